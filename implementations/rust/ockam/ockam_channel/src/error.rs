@@ -14,6 +14,18 @@ pub enum SecureChannelError {
     InvalidHubResponse,
     /// Invalid LocalInfo type
     InvalidLocalInfoType,
+    /// Message was encrypted with a key that is no longer available.
+    InvalidKeyEpoch,
+    /// Message was received before, or is too old to tell.
+    ReplayedMessage,
+    /// Metadata is longer than fits in a frame.
+    MetadataTooLong,
+    /// Frame metadata is truncated.
+    InvalidMetadata,
+    /// Key exchange message is larger than the responder accepts.
+    HandshakeMessageTooLarge,
+    /// Nonces of the key ran out before the other side answered the rekey.
+    NonceExhausted,
 }
 
 impl SecureChannelError {
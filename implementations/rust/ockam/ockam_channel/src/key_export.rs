@@ -0,0 +1,45 @@
+use core::fmt;
+use ockam_core::compat::vec::Vec;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+/// Key material exported from an established SecureChannel, for out-of-band use such as
+/// encrypting bulk data over another transport without going through the channel.
+///
+/// Both ends of a channel export the same bytes. They are derived one-way from the key the
+/// initiator encrypts with at the end of the handshake, so they don't reveal the channel
+/// keys and can't be used to read or forge channel messages. They do however stand in for
+/// the channel: whatever is protected with them is only as secret as every place they end
+/// up in, and nothing ties their use to the peer identity the way channel messages are.
+///
+/// Besides, before enabling the `key_export` feature that provides it:
+///  - The key doesn't follow rekeying. It stays the same for the lifetime of the channel,
+///    whatever [`RekeyOptions`](crate::RekeyOptions) say, so it loses the forward secrecy
+///    rekeying gives the channel itself.
+///  - The bytes leave the vault, which may have kept the channel keys in hardware.
+///    They are wiped from memory on drop, but not from copies made of them.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExportedChannelKey(Vec<u8>);
+
+impl ExportedChannelKey {
+    pub(crate) fn new(key: Vec<u8>) -> Self {
+        Self(key)
+    }
+
+    /// The key bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ExportedChannelKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ExportedChannelKey(..)")
+    }
+}
+
+impl Drop for ExportedChannelKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
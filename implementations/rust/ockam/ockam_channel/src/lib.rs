@@ -23,14 +23,26 @@ extern crate core;
 extern crate alloc;
 
 mod error;
+#[cfg(feature = "key_export")]
+mod key_export;
 mod local_info;
+mod metadata;
+mod pending_handshakes;
+mod rekey_options;
+mod replay_window;
 mod secure_channel;
 mod secure_channel_listener;
 mod secure_channel_worker;
 mod traits;
 
 pub use error::*;
+#[cfg(feature = "key_export")]
+pub use key_export::*;
 pub use local_info::*;
+pub use metadata::*;
+pub use pending_handshakes::*;
+pub use rekey_options::*;
+pub use replay_window::*;
 pub use secure_channel::*;
 pub use secure_channel_listener::*;
 pub use secure_channel_worker::*;
@@ -38,9 +50,18 @@ pub use traits::*;
 
 #[cfg(test)]
 mod tests {
-    use crate::SecureChannel;
+    use crate::{
+        RekeyOptions, SecureChannel, SecureChannelMetadata, UndeliveredMessage,
+        DEFAULT_REPLAY_WINDOW,
+    };
+    use core::sync::atomic::{AtomicU16, Ordering};
     use ockam_core::compat::string::{String, ToString};
-    use ockam_core::{AsyncTryClone, Result, Route};
+    use ockam_core::compat::sync::Arc;
+    use ockam_core::compat::vec::Vec;
+    use ockam_core::{
+        async_trait, Address, Any, AsyncTryClone, Decodable, Encodable, LocalMessage, Result,
+        Route, Routed, Worker,
+    };
     use ockam_key_exchange_core::NewKeyExchanger;
     use ockam_key_exchange_xx::XXNewKeyExchanger;
     use ockam_node::Context;
@@ -76,4 +97,346 @@ mod tests {
         assert_eq!(ctx.receive::<String>().await?, test_msg);
         ctx.stop().await
     }
+
+    #[ockam_macros::test]
+    async fn rekeying_channel(ctx: &mut Context) -> Result<()> {
+        let vault_sync = VaultSync::create(ctx, SoftwareVault::default()).await?;
+        let new_key_exchanger = XXNewKeyExchanger::new(vault_sync.async_try_clone().await?);
+        SecureChannel::create_listener_extended(
+            ctx,
+            "secure_channel_listener".to_string(),
+            new_key_exchanger.async_try_clone().await?,
+            vault_sync.async_try_clone().await?,
+        )
+        .await?;
+        let tap = Tap::default();
+        let epoch = tap.epoch.clone();
+        ctx.start_worker(vec!["tap", "tap_replay", "tap_record"], tap)
+            .await?;
+        let initiator = SecureChannel::create_extended_with_rekey(
+            ctx,
+            Route::new().append("tap").append("secure_channel_listener"),
+            None,
+            new_key_exchanger.initiator().await?,
+            vault_sync,
+            RekeyOptions::new().with_after_messages(2),
+        )
+        .await?;
+        ctx.send("tap_record", ()).await?;
+
+        // Crosses several rekey boundaries
+        for i in 0..7 {
+            ctx.send(
+                Route::new().append(initiator.address()).append("app"),
+                format!("Hello, channel {}", i),
+            )
+            .await?;
+        }
+
+        for i in 0..7 {
+            assert_eq!(
+                ctx.receive::<String>().await?,
+                format!("Hello, channel {}", i)
+            );
+        }
+
+        // The key goes on changing as long as messages are sent. The peer answers rekey
+        // requests when it gets them, so the messages are spread out
+        let mut i = 7;
+        while epoch.load(Ordering::Relaxed) < 3 && i < 100 {
+            ctx.send(
+                Route::new().append(initiator.address()).append("app"),
+                format!("Hello, channel {}", i),
+            )
+            .await?;
+            assert_eq!(
+                ctx.receive::<String>().await?,
+                format!("Hello, channel {}", i)
+            );
+            i += 1;
+        }
+        assert!(epoch.load(Ordering::Relaxed) >= 3);
+
+        ctx.stop().await
+    }
+
+    /// Forwards messages like a transport would, capturing the last one so that
+    /// it can be sent again by messaging "tap_replay". Once messaged at "tap_record", which
+    /// is only done after the handshake, keeps the highest epoch of the frames it forwards
+    #[derive(Default)]
+    struct Tap {
+        last: Option<LocalMessage>,
+        recording: bool,
+        epoch: Arc<AtomicU16>,
+    }
+
+    #[async_trait]
+    impl Worker for Tap {
+        type Message = Any;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            ctx: &mut Self::Context,
+            msg: Routed<Self::Message>,
+        ) -> Result<()> {
+            if msg.msg_addr() == Address::from("tap_replay") {
+                if let Some(last) = self.last.clone() {
+                    ctx.forward(last).await?;
+                }
+                return Ok(());
+            }
+            if msg.msg_addr() == Address::from("tap_record") {
+                self.recording = true;
+                return Ok(());
+            }
+
+            let mut local_msg = msg.into_local_message();
+            let transport_msg = local_msg.transport_mut();
+            transport_msg.onward_route.step()?;
+            transport_msg.return_route.modify().prepend(ctx.address());
+
+            if self.recording {
+                let frame = Vec::<u8>::decode(&transport_msg.payload)?;
+                self.epoch
+                    .fetch_max(u16::from_be_bytes([frame[0], frame[1]]), Ordering::Relaxed);
+            }
+            self.last = Some(local_msg.clone());
+
+            ctx.forward(local_msg).await
+        }
+    }
+
+    #[ockam_macros::test]
+    async fn replayed_frame_is_dropped(ctx: &mut Context) -> Result<()> {
+        let vault_sync = VaultSync::create(ctx, SoftwareVault::default()).await?;
+        let new_key_exchanger = XXNewKeyExchanger::new(vault_sync.async_try_clone().await?);
+        SecureChannel::create_listener_extended(
+            ctx,
+            "secure_channel_listener".to_string(),
+            new_key_exchanger.async_try_clone().await?,
+            vault_sync.async_try_clone().await?,
+        )
+        .await?;
+        ctx.start_worker(vec!["tap", "tap_replay"], Tap::default())
+            .await?;
+        let initiator = SecureChannel::create_extended(
+            ctx,
+            Route::new().append("tap").append("secure_channel_listener"),
+            None,
+            new_key_exchanger.initiator().await?,
+            vault_sync,
+        )
+        .await?;
+
+        ctx.send(
+            Route::new().append(initiator.address()).append("app"),
+            "Hello, channel".to_string(),
+        )
+        .await?;
+        assert_eq!(ctx.receive::<String>().await?, "Hello, channel");
+
+        // Same ciphertext again, as if captured and injected by an attacker
+        ctx.send("tap_replay", ()).await?;
+
+        ctx.send(
+            Route::new().append(initiator.address()).append("app"),
+            "Hello again, channel".to_string(),
+        )
+        .await?;
+        assert_eq!(ctx.receive::<String>().await?, "Hello again, channel");
+        assert!(ctx.receive_timeout::<String>(1).await.is_err());
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn undelivered_message_is_returned(ctx: &mut Context) -> Result<()> {
+        let vault_sync = VaultSync::create(ctx, SoftwareVault::default()).await?;
+        let new_key_exchanger = XXNewKeyExchanger::new(vault_sync.async_try_clone().await?);
+        SecureChannel::create_listener_extended(
+            ctx,
+            "secure_channel_listener".to_string(),
+            new_key_exchanger.async_try_clone().await?,
+            vault_sync.async_try_clone().await?,
+        )
+        .await?;
+        ctx.start_worker(vec!["tap", "tap_replay"], Tap::default())
+            .await?;
+        let mut undelivered_ctx = ctx.new_context(Address::random(0)).await?;
+        let initiator = SecureChannel::create_extended_with_undelivered_address(
+            ctx,
+            Route::new().append("tap").append("secure_channel_listener"),
+            None,
+            new_key_exchanger.initiator().await?,
+            vault_sync,
+            RekeyOptions::default(),
+            DEFAULT_REPLAY_WINDOW,
+            Some(undelivered_ctx.address()),
+        )
+        .await?;
+
+        // The transport connection drops
+        ctx.stop_worker("tap").await?;
+        for i in 0..2 {
+            ctx.send(
+                Route::new().append(initiator.address()).append("app"),
+                format!("Hello, channel {}", i),
+            )
+            .await?;
+        }
+
+        for i in 0..2 {
+            let msg = undelivered_ctx
+                .receive::<UndeliveredMessage>()
+                .await?
+                .take();
+            assert_eq!(msg.return_route().next()?, &initiator.address());
+            let undelivered = msg.body();
+            assert_eq!(undelivered.onward_route(), &Route::new().append("app"));
+            assert_eq!(
+                String::decode(undelivered.payload())?,
+                format!("Hello, channel {}", i)
+            );
+        }
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn metadata_is_delivered(ctx: &mut Context) -> Result<()> {
+        let vault_sync = VaultSync::create(ctx, SoftwareVault::default()).await?;
+        let new_key_exchanger = XXNewKeyExchanger::new(vault_sync.async_try_clone().await?);
+        SecureChannel::create_listener_extended(
+            ctx,
+            "secure_channel_listener".to_string(),
+            new_key_exchanger.async_try_clone().await?,
+            vault_sync.async_try_clone().await?,
+        )
+        .await?;
+        let initiator = SecureChannel::create_extended(
+            ctx,
+            Route::new().append("secure_channel_listener"),
+            None,
+            new_key_exchanger.initiator().await?,
+            vault_sync,
+        )
+        .await?;
+
+        let metadata = SecureChannelMetadata::new(b"type:hello".to_vec())?;
+        metadata
+            .send(
+                ctx,
+                Route::new().append(initiator.address()).append("app"),
+                "Hello, channel".to_string(),
+            )
+            .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert_eq!(
+            SecureChannelMetadata::find_info(msg.local_message())?,
+            metadata
+        );
+        assert_eq!(msg.body(), "Hello, channel");
+
+        // Messages without metadata are delivered without it
+        ctx.send(
+            Route::new().append(initiator.address()).append("app"),
+            "Hello again, channel".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert!(SecureChannelMetadata::find_info(msg.local_message()).is_err());
+        assert_eq!(msg.body(), "Hello again, channel");
+
+        ctx.stop().await
+    }
+
+    /// Forwards messages like a transport would. Once enabled by messaging "flipper_enable",
+    /// flips the first metadata byte of every frame that has metadata
+    #[derive(Default)]
+    struct MetadataFlipper {
+        enabled: bool,
+    }
+
+    #[async_trait]
+    impl Worker for MetadataFlipper {
+        type Message = Any;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            ctx: &mut Self::Context,
+            msg: Routed<Self::Message>,
+        ) -> Result<()> {
+            if msg.msg_addr() == Address::from("flipper_enable") {
+                self.enabled = true;
+                return Ok(());
+            }
+
+            let mut local_msg = msg.into_local_message();
+            let transport_msg = local_msg.transport_mut();
+            transport_msg.onward_route.step()?;
+            transport_msg.return_route.modify().prepend(ctx.address());
+
+            let has_metadata = matches!(
+                SecureChannelMetadata::from_frame(&transport_msg.payload),
+                Ok(Some(_))
+            );
+            if self.enabled && has_metadata {
+                let mut frame = Vec::<u8>::decode(&transport_msg.payload)?;
+                frame[5] ^= 0xFF;
+                transport_msg.payload = frame.encode()?;
+            }
+
+            ctx.forward(local_msg).await
+        }
+    }
+
+    #[ockam_macros::test]
+    async fn tampered_metadata_is_dropped(ctx: &mut Context) -> Result<()> {
+        let vault_sync = VaultSync::create(ctx, SoftwareVault::default()).await?;
+        let new_key_exchanger = XXNewKeyExchanger::new(vault_sync.async_try_clone().await?);
+        SecureChannel::create_listener_extended(
+            ctx,
+            "secure_channel_listener".to_string(),
+            new_key_exchanger.async_try_clone().await?,
+            vault_sync.async_try_clone().await?,
+        )
+        .await?;
+        ctx.start_worker(
+            vec!["flipper", "flipper_enable"],
+            MetadataFlipper::default(),
+        )
+        .await?;
+        let initiator = SecureChannel::create_extended(
+            ctx,
+            Route::new()
+                .append("flipper")
+                .append("secure_channel_listener"),
+            None,
+            new_key_exchanger.initiator().await?,
+            vault_sync,
+        )
+        .await?;
+        ctx.send("flipper_enable", ()).await?;
+
+        SecureChannelMetadata::new(b"type:hello".to_vec())?
+            .send(
+                ctx,
+                Route::new().append(initiator.address()).append("app"),
+                "Hello, channel".to_string(),
+            )
+            .await?;
+        assert!(ctx.receive_timeout::<String>(1).await.is_err());
+
+        // The channel itself is still fine
+        ctx.send(
+            Route::new().append(initiator.address()).append("app"),
+            "Hello again, channel".to_string(),
+        )
+        .await?;
+        assert_eq!(ctx.receive::<String>().await?, "Hello again, channel");
+
+        ctx.stop().await
+    }
 }
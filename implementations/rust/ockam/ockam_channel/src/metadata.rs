@@ -0,0 +1,169 @@
+use crate::SecureChannelError;
+use ockam_core::compat::vec::Vec;
+use ockam_core::{
+    Decodable, Encodable, LocalInfo, LocalMessage, Message, Result, Route, TransportMessage,
+};
+use ockam_node::Context;
+use serde::{Deserialize, Serialize};
+
+/// SecureChannel metadata LocalInfo unique Identifier
+pub const SECURE_CHANNEL_METADATA_IDENTIFIER: &str = "SECURE_CHANNEL_METADATA";
+
+/// Maximum length of [`SecureChannelMetadata`]
+pub const MAX_SECURE_CHANNEL_METADATA_LEN: usize = u8::MAX as usize;
+
+/// Length of epoch and nonce at the start of every encrypted frame
+const FRAME_NONCE_LEN: usize = 4;
+
+/// Application data sent in clear along with an encrypted message, e.g. a message type tag.
+///
+/// The metadata is authenticated together with the message, so it can be read from the
+/// frame for routing decisions, but can't be changed without the message being dropped.
+/// Attach it as LocalInfo to a message sent through a SecureChannel, the other end attaches it
+/// to the decrypted message
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecureChannelMetadata {
+    data: Vec<u8>,
+}
+
+impl SecureChannelMetadata {
+    /// Constructor. Fails if data is longer than [`MAX_SECURE_CHANNEL_METADATA_LEN`]
+    pub fn new(data: impl Into<Vec<u8>>) -> Result<Self> {
+        let data = data.into();
+        if data.len() > MAX_SECURE_CHANNEL_METADATA_LEN {
+            return Err(SecureChannelError::MetadataTooLong.into());
+        }
+
+        Ok(Self { data })
+    }
+
+    /// Metadata bytes
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Read metadata of an encrypted frame without decrypting it, given the payload of the
+    /// TransportMessage between two SecureChannel workers. It is not authenticated until the
+    /// frame is decrypted
+    pub fn from_frame(payload: &[u8]) -> Result<Option<Self>> {
+        let frame = Vec::<u8>::decode(payload)?;
+        let (header, _) = split_frame(&frame)?;
+        let metadata = &header[FRAME_NONCE_LEN + 1..];
+        if metadata.is_empty() {
+            Ok(None)
+        } else {
+            Self::new(metadata).map(Some)
+        }
+    }
+
+    /// Send a message with this metadata attached, to be picked up by the first SecureChannel
+    /// on the route
+    pub async fn send<R, M>(&self, ctx: &Context, route: R, msg: M) -> Result<()>
+    where
+        R: Into<Route>,
+        M: Message + Send + 'static,
+    {
+        let transport_msg = TransportMessage::v1(route, ctx.address(), msg.encode()?);
+        ctx.forward(LocalMessage::new(
+            transport_msg,
+            vec![self.to_local_info()?],
+        ))
+        .await
+    }
+
+    /// Create SecureChannel metadata object using Ockam Routing LocalInfo
+    pub fn from_local_info(value: &LocalInfo) -> Result<Self> {
+        if value.type_identifier() != SECURE_CHANNEL_METADATA_IDENTIFIER {
+            return Err(SecureChannelError::InvalidLocalInfoType.into());
+        }
+
+        if let Ok(info) = SecureChannelMetadata::decode(value.data()) {
+            return Ok(info);
+        }
+
+        Err(SecureChannelError::InvalidLocalInfoType.into())
+    }
+
+    /// Create Ockam Routing LocalInfo object using SecureChannel metadata
+    pub fn to_local_info(&self) -> Result<LocalInfo> {
+        Ok(LocalInfo::new(
+            SECURE_CHANNEL_METADATA_IDENTIFIER.into(),
+            self.encode()?,
+        ))
+    }
+
+    /// Find SecureChannel metadata in a LocalMessage
+    pub fn find_info(local_msg: &LocalMessage) -> Result<Self> {
+        if let Some(local_info) = local_msg
+            .local_info()
+            .iter()
+            .find(|x| x.type_identifier() == SECURE_CHANNEL_METADATA_IDENTIFIER)
+        {
+            Self::from_local_info(local_info)
+        } else {
+            Err(SecureChannelError::InvalidLocalInfoType.into())
+        }
+    }
+}
+
+/// Build the part of a frame that is sent in clear: epoch, nonce and metadata.
+/// It is authenticated as associated data of the ciphertext that follows
+pub(crate) fn frame_header(
+    epoch: u16,
+    small_nonce: &[u8; 2],
+    metadata: Option<&SecureChannelMetadata>,
+) -> Vec<u8> {
+    let metadata = metadata.map(|m| m.data()).unwrap_or(&[]);
+
+    let mut header = Vec::with_capacity(FRAME_NONCE_LEN + 1 + metadata.len());
+    header.extend_from_slice(&epoch.to_be_bytes());
+    header.extend_from_slice(small_nonce);
+    header.push(metadata.len() as u8);
+    header.extend_from_slice(metadata);
+
+    header
+}
+
+/// Split a frame into its header and ciphertext
+pub(crate) fn split_frame(frame: &[u8]) -> Result<(&[u8], &[u8])> {
+    if frame.len() <= FRAME_NONCE_LEN {
+        return Err(SecureChannelError::InvalidNonce.into());
+    }
+
+    let header_len = FRAME_NONCE_LEN + 1 + frame[FRAME_NONCE_LEN] as usize;
+    if frame.len() < header_len {
+        return Err(SecureChannelError::InvalidMetadata.into());
+    }
+
+    Ok(frame.split_at(header_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_frame() {
+        let metadata = SecureChannelMetadata::new(b"tag".to_vec()).unwrap();
+        let mut frame = frame_header(1, &[0, 2], Some(&metadata));
+        frame.extend_from_slice(b"ciphertext");
+        let payload = frame.encode().unwrap();
+
+        assert_eq!(
+            SecureChannelMetadata::from_frame(&payload).unwrap(),
+            Some(metadata)
+        );
+        let (header, cipher_text) = split_frame(&frame).unwrap();
+        assert_eq!(header, &[0, 1, 0, 2, 3, b't', b'a', b'g']);
+        assert_eq!(cipher_text, b"ciphertext");
+
+        let mut frame = frame_header(1, &[0, 2], None);
+        frame.extend_from_slice(b"ciphertext");
+        let payload = frame.encode().unwrap();
+        assert_eq!(SecureChannelMetadata::from_frame(&payload).unwrap(), None);
+
+        // Metadata length beyond the end of the frame
+        assert!(split_frame(&[0, 1, 0, 2, 3, b't']).is_err());
+        assert!(SecureChannelMetadata::new(vec![0; MAX_SECURE_CHANNEL_METADATA_LEN + 1]).is_err());
+    }
+}
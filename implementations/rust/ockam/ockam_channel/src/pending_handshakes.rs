@@ -0,0 +1,51 @@
+use ockam_core::compat::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+    vec::Vec,
+};
+use ockam_core::{Address, Result};
+use ockam_node::Context;
+
+/// Responders of a [`SecureChannelListener`](crate::SecureChannelListener) that didn't
+/// complete their key exchange yet. Shared with whoever needs to cancel them.
+///
+/// A responder leaves the set once its key exchange completes. A responder reporting to a
+/// completed callback address stays until the owner of the callback address removes it,
+/// so that it can't be missed while the completion is on its way
+#[derive(Clone, Default)]
+pub struct PendingHandshakes {
+    addresses: Arc<Mutex<BTreeSet<Address>>>,
+}
+
+impl PendingHandshakes {
+    /// Addresses of the pending responders
+    pub fn addresses(&self) -> Vec<Address> {
+        self.addresses.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Whether no handshake is pending
+    pub fn is_empty(&self) -> bool {
+        self.addresses.lock().unwrap().is_empty()
+    }
+
+    /// Track a responder, by any of its addresses
+    pub fn insert(&self, address: Address) {
+        self.addresses.lock().unwrap().insert(address);
+    }
+
+    /// Stop tracking a responder, e.g. once its handshake completed
+    pub fn remove(&self, address: &Address) -> bool {
+        self.addresses.lock().unwrap().remove(address)
+    }
+
+    /// Stop every pending responder. Returns once they are all stopped
+    pub async fn cancel(&self, ctx: &Context) -> Result<()> {
+        let addresses = core::mem::take(&mut *self.addresses.lock().unwrap());
+        for address in addresses {
+            // The responder may have stopped on its own in the meantime
+            let _ = ctx.stop_worker(address).await;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+use core::time::Duration;
+use serde::{Deserialize, Serialize};
+
+/// Conditions under which a SecureChannel replaces its encryption key.
+///
+/// Whichever limit is reached first triggers the rekey, a new Diffie-Hellman exchange with the
+/// other side over the channel, so that a leaked key doesn't reveal the messages of other
+/// epochs. The current key stays in use until the other side answers. Regardless of these
+/// options, the rekey starts once half of the nonce space is used.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct RekeyOptions {
+    after_messages: Option<u16>,
+    interval: Option<Duration>,
+}
+
+impl RekeyOptions {
+    /// Only rekey when half of the nonce space is used.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rekey after given number of messages were encrypted with the same key.
+    /// Zero is treated as one.
+    pub fn with_after_messages(mut self, messages: u16) -> Self {
+        self.after_messages = Some(messages);
+        self
+    }
+
+    /// Rekey once the same key has been used for given duration.
+    /// Requires a clock, so this is ignored without the `std` feature.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Number of messages encrypted with the same key before rekey
+    pub fn after_messages(&self) -> Option<u16> {
+        self.after_messages
+    }
+
+    /// Maximum lifetime of the same key
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+}
@@ -0,0 +1,116 @@
+use ockam_core::compat::vec::Vec;
+
+/// Default number of recent nonces a SecureChannel remembers to detect replayed messages
+pub const DEFAULT_REPLAY_WINDOW: u16 = 64;
+
+/// Sliding window over the nonces received with one decryption key.
+///
+/// Nonces above the highest one received so far are always fresh. Nonces less than `size`
+/// below it are accepted once, so that messages delivered out of order still pass.
+/// Anything older is rejected.
+pub(crate) struct ReplayWindow {
+    highest: Option<u16>,
+    // Indexed by nonce modulo size
+    received: Vec<bool>,
+}
+
+impl ReplayWindow {
+    /// Zero is treated as one, which only accepts increasing nonces
+    pub fn new(size: u16) -> Self {
+        Self {
+            highest: None,
+            received: vec![false; size.max(1) as usize],
+        }
+    }
+
+    fn size(&self) -> u16 {
+        self.received.len() as u16
+    }
+
+    fn index(&self, nonce: u16) -> usize {
+        (nonce % self.size()) as usize
+    }
+
+    /// Check the nonce without remembering it, as the message isn't authenticated yet
+    pub fn is_fresh(&self, nonce: u16) -> bool {
+        match self.highest {
+            None => true,
+            Some(highest) if nonce > highest => true,
+            Some(highest) if highest - nonce >= self.size() => false,
+            Some(_) => !self.received[self.index(nonce)],
+        }
+    }
+
+    /// Remember a nonce of an authenticated message
+    pub fn mark(&mut self, nonce: u16) {
+        match self.highest {
+            Some(highest) if nonce <= highest => {}
+            Some(highest) if nonce - highest < self.size() => {
+                // Forget the nonces that slid out of the window
+                for skipped in highest + 1..=nonce {
+                    let index = self.index(skipped);
+                    self.received[index] = false;
+                }
+                self.highest = Some(nonce);
+            }
+            _ => {
+                self.received.iter_mut().for_each(|r| *r = false);
+                self.highest = Some(nonce);
+            }
+        }
+
+        let index = self.index(nonce);
+        self.received[index] = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receive(window: &mut ReplayWindow, nonce: u16) -> bool {
+        let fresh = window.is_fresh(nonce);
+        if fresh {
+            window.mark(nonce);
+        }
+        fresh
+    }
+
+    #[test]
+    fn test_replay_window() {
+        let mut window = ReplayWindow::new(4);
+
+        assert!(receive(&mut window, 0));
+        assert!(!receive(&mut window, 0));
+
+        // Out of order within the window
+        assert!(receive(&mut window, 3));
+        assert!(receive(&mut window, 1));
+        assert!(receive(&mut window, 2));
+        assert!(!receive(&mut window, 1));
+        assert!(!receive(&mut window, 3));
+
+        // 2 is still within the window, 1 is not
+        assert!(receive(&mut window, 5));
+        assert!(!receive(&mut window, 2));
+        assert!(!receive(&mut window, 1));
+        assert!(receive(&mut window, 4));
+
+        // Jump further than the window size
+        assert!(receive(&mut window, 100));
+        assert!(!receive(&mut window, 96));
+        assert!(receive(&mut window, 97));
+        assert!(!receive(&mut window, 100));
+    }
+
+    #[test]
+    fn test_replay_window_zero_size() {
+        let mut window = ReplayWindow::new(0);
+
+        assert!(receive(&mut window, 0));
+        assert!(receive(&mut window, 2));
+        assert!(!receive(&mut window, 1));
+        assert!(!receive(&mut window, 2));
+        assert!(receive(&mut window, 3));
+    }
+}
@@ -1,6 +1,8 @@
+#[cfg(feature = "key_export")]
+use crate::ExportedChannelKey;
 use crate::{
-    KeyExchangeCompleted, SecureChannelKeyExchanger, SecureChannelListener,
-    SecureChannelNewKeyExchanger, SecureChannelVault, SecureChannelWorker,
+    KeyExchangeCompleted, RekeyOptions, SecureChannelKeyExchanger, SecureChannelListener,
+    SecureChannelNewKeyExchanger, SecureChannelVault, SecureChannelWorker, DEFAULT_REPLAY_WINDOW,
 };
 use ockam_core::compat::rand::random;
 use ockam_core::{Address, Result, Route};
@@ -15,6 +17,8 @@ use tracing::{debug, info};
 pub struct SecureChannelInfo {
     worker_address: Address,
     auth_hash: [u8; 32],
+    #[cfg(feature = "key_export")]
+    exported_key: ExportedChannelKey,
 }
 
 impl SecureChannelInfo {
@@ -26,6 +30,11 @@ impl SecureChannelInfo {
     pub fn auth_hash(&self) -> [u8; 32] {
         self.auth_hash
     }
+    /// Return the key material exported from the channel.
+    #[cfg(feature = "key_export")]
+    pub fn exported_key(&self) -> &ExportedChannelKey {
+        &self.exported_key
+    }
 }
 
 /// Secure Channel
@@ -96,6 +105,77 @@ impl SecureChannel {
         first_responder_address: Option<Address>,
         key_exchanger: impl SecureChannelKeyExchanger,
         vault: impl SecureChannelVault,
+    ) -> Result<SecureChannelInfo> {
+        Self::create_extended_with_rekey(
+            ctx,
+            route,
+            first_responder_address,
+            key_exchanger,
+            vault,
+            RekeyOptions::default(),
+        )
+        .await
+    }
+
+    /// Create initiator channel with given route to a remote channel listener,
+    /// rekeying its outgoing messages according to given options.
+    pub async fn create_extended_with_rekey(
+        ctx: &Context,
+        route: impl Into<Route>,
+        first_responder_address: Option<Address>,
+        key_exchanger: impl SecureChannelKeyExchanger,
+        vault: impl SecureChannelVault,
+        rekey_options: RekeyOptions,
+    ) -> Result<SecureChannelInfo> {
+        Self::create_extended_with_replay_window(
+            ctx,
+            route,
+            first_responder_address,
+            key_exchanger,
+            vault,
+            rekey_options,
+            DEFAULT_REPLAY_WINDOW,
+        )
+        .await
+    }
+
+    /// Create initiator channel with given route to a remote channel listener,
+    /// remembering given number of recent nonces to detect replayed messages.
+    pub async fn create_extended_with_replay_window(
+        ctx: &Context,
+        route: impl Into<Route>,
+        first_responder_address: Option<Address>,
+        key_exchanger: impl SecureChannelKeyExchanger,
+        vault: impl SecureChannelVault,
+        rekey_options: RekeyOptions,
+        replay_window: u16,
+    ) -> Result<SecureChannelInfo> {
+        Self::create_extended_with_undelivered_address(
+            ctx,
+            route,
+            first_responder_address,
+            key_exchanger,
+            vault,
+            rekey_options,
+            replay_window,
+            None,
+        )
+        .await
+    }
+
+    /// Create initiator channel with given route to a remote channel listener, returning
+    /// messages it can't send to the other side as [`UndeliveredMessage`](crate::UndeliveredMessage)s to
+    /// `undelivered_address`, if any. Otherwise they are dropped
+    #[allow(clippy::too_many_arguments)]
+    pub async fn create_extended_with_undelivered_address(
+        ctx: &Context,
+        route: impl Into<Route>,
+        first_responder_address: Option<Address>,
+        key_exchanger: impl SecureChannelKeyExchanger,
+        vault: impl SecureChannelVault,
+        rekey_options: RekeyOptions,
+        replay_window: u16,
+        undelivered_address: Option<Address>,
     ) -> Result<SecureChannelInfo> {
         let address_remote: Address = random();
         let address_local: Address = random();
@@ -118,8 +198,11 @@ impl SecureChannel {
             first_responder_address,
             key_exchanger,
             vault,
+            rekey_options,
+            replay_window,
         )
-        .await?;
+        .await?
+        .with_undelivered_address(undelivered_address);
 
         ctx.start_worker(vec![address_remote.clone(), address_local.clone()], channel)
             .await?;
@@ -133,6 +216,8 @@ impl SecureChannel {
         let info = SecureChannelInfo {
             worker_address: address_local,
             auth_hash: resp.auth_hash(),
+            #[cfg(feature = "key_export")]
+            exported_key: resp.exported_key().clone(),
         };
 
         Ok(info)
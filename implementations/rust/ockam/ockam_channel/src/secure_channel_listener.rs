@@ -1,4 +1,7 @@
-use crate::{SecureChannelNewKeyExchanger, SecureChannelVault, SecureChannelWorker};
+use crate::{
+    PendingHandshakes, RekeyOptions, SecureChannelError, SecureChannelNewKeyExchanger,
+    SecureChannelVault, SecureChannelWorker, DEFAULT_REPLAY_WINDOW,
+};
 use ockam_core::async_trait;
 use ockam_core::compat::rand::random;
 use ockam_core::compat::{boxed::Box, vec::Vec};
@@ -7,13 +10,20 @@ use ockam_core::{
 };
 use ockam_node::Context;
 use serde::{Deserialize, Serialize};
-use tracing::debug;
+use tracing::{debug, warn};
+
+/// Largest key exchange message responders accept by default, well above what any supported
+/// key exchange sends
+pub const DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE: usize = 64 * 1024;
 
 /// SecureChannelListener listens for messages from SecureChannel initiators
 /// and creates responder SecureChannels
 pub struct SecureChannelListener<V: SecureChannelVault, N: SecureChannelNewKeyExchanger> {
     new_key_exchanger: N,
     vault: V,
+    replay_window: u16,
+    pending: PendingHandshakes,
+    max_handshake_message_size: usize,
 }
 
 impl<V: SecureChannelVault, N: SecureChannelNewKeyExchanger> SecureChannelListener<V, N> {
@@ -22,8 +32,32 @@ impl<V: SecureChannelVault, N: SecureChannelNewKeyExchanger> SecureChannelListen
         Self {
             new_key_exchanger,
             vault,
+            replay_window: DEFAULT_REPLAY_WINDOW,
+            pending: PendingHandshakes::default(),
+            max_handshake_message_size: DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE,
         }
     }
+
+    /// Number of recent nonces responders remember to detect replayed messages.
+    /// Messages delivered out of order by more than that are dropped as well.
+    pub fn with_replay_window(mut self, replay_window: u16) -> Self {
+        self.replay_window = replay_window;
+        self
+    }
+
+    /// Track responders in given set while their key exchange is in progress.
+    /// They are stopped along with the listener
+    pub fn with_pending_handshakes(mut self, pending: PendingHandshakes) -> Self {
+        self.pending = pending;
+        self
+    }
+
+    /// Reject key exchange messages larger than given size, in bytes, before they are parsed.
+    /// Responders stop once they receive one
+    pub fn with_max_handshake_message_size(mut self, max_handshake_message_size: usize) -> Self {
+        self.max_handshake_message_size = max_handshake_message_size;
+        self
+    }
 }
 
 /// SecureChannelListener message wrapper.
@@ -61,6 +95,11 @@ impl<V: SecureChannelVault, N: SecureChannelNewKeyExchanger> Worker
     type Message = CreateResponderChannelMessage;
     type Context = Context;
 
+    async fn shutdown(&mut self, ctx: &mut Self::Context) -> Result<()> {
+        // Nobody would take over the channels of handshakes in progress
+        self.pending.cancel(ctx).await
+    }
+
     async fn handle_message(
         &mut self,
         ctx: &mut Self::Context,
@@ -69,6 +108,15 @@ impl<V: SecureChannelVault, N: SecureChannelNewKeyExchanger> Worker
         let reply = msg.return_route().clone();
         let msg = msg.body();
 
+        if msg.payload().len() > self.max_handshake_message_size {
+            warn!(
+                "Rejecting SecureChannel at: {}, key exchange message of {} bytes",
+                ctx.address(),
+                msg.payload().len()
+            );
+            return Err(SecureChannelError::HandshakeMessageTooLarge.into());
+        }
+
         let address_remote: Address = random();
         let address_local: Address = random();
 
@@ -88,9 +136,14 @@ impl<V: SecureChannelVault, N: SecureChannelNewKeyExchanger> Worker
             None,
             responder,
             vault,
+            RekeyOptions::default(),
+            self.replay_window,
         )
-        .await?;
+        .await?
+        .with_pending_handshakes(self.pending.clone())
+        .with_max_handshake_message_size(self.max_handshake_message_size);
 
+        self.pending.insert(address_local.clone());
         ctx.start_worker(vec![address_remote.clone(), address_local], channel)
             .await?;
 
@@ -1,22 +1,166 @@
+use crate::metadata::{frame_header, split_frame};
 use crate::{
-    CreateResponderChannelMessage, SecureChannelError, SecureChannelKeyExchanger,
-    SecureChannelLocalInfo, SecureChannelVault,
+    CreateResponderChannelMessage, PendingHandshakes, RekeyOptions, ReplayWindow,
+    SecureChannelError, SecureChannelKeyExchanger, SecureChannelLocalInfo, SecureChannelMetadata,
+    SecureChannelVault,
 };
+use core::mem;
+use core::time::Duration;
 use ockam_core::async_trait;
 use ockam_core::compat::{boxed::Box, string::String, vec::Vec};
-use ockam_core::vault::Secret;
+use ockam_core::vault::{
+    Buffer, PublicKey, Secret, SecretAttributes, SecretPersistence, SecretType, SecretVault,
+    CURVE25519_SECRET_LENGTH,
+};
 use ockam_core::{
     Address, Any, Decodable, Encodable, LocalMessage, Message, Result, Route, Routed,
     TransportMessage, Worker,
 };
 use ockam_node::Context;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
+
+#[cfg(feature = "key_export")]
+use crate::ExportedChannelKey;
+#[cfg(feature = "key_export")]
+use zeroize::Zeroizing;
+
+/// Messages encrypted with the same key before a rekey starts at the latest. The other half of
+/// the nonce space is left for the messages sent until the peer answers
+const MAX_MESSAGES_PER_KEY: u16 = u16::MAX / 2;
+
+/// HKDF info of the keys agreed on by a rekey, followed by the epoch of the key
+const REKEY_INFO: &[u8] = b"OCKAM_SECURE_CHANNEL_REKEY";
+
+/// Plaintext of an encrypted frame
+#[derive(Serialize, Deserialize)]
+enum FramePayload {
+    /// Message sent through the channel
+    Message(TransportMessage),
+    /// Ephemeral public key of the sender, to agree on its key of the next epoch
+    RekeyRequest(PublicKey),
+    /// Answer to a [`FramePayload::RekeyRequest`] with the ephemeral public key of the sender,
+    /// for the key of given epoch of the receiver
+    RekeyResponse(u16, PublicKey),
+}
+
+/// Rekey started by this side, waiting for the peer to answer
+struct PendingRekey {
+    secret: Secret,
+    public_key: PublicKey,
+    // Nonce of the last request, which is sent again if the answer takes too long
+    requested_at: u16,
+}
+
+/// Key the peer is going to encrypt with in the next epoch
+struct NextDecryptKey {
+    their_public_key: PublicKey,
+    // Sent again if the request is repeated, e.g. because the answer was lost
+    public_key: PublicKey,
+    key: Secret,
+}
 
 pub(crate) struct ChannelKeys {
     encrypt_key: Secret,
-    decrypt_key: Secret,
+    encrypt_epoch: u16,
+    #[cfg(feature = "std")]
+    encrypt_key_created: std::time::Instant,
     nonce: u16,
+    decrypt_key: Secret,
+    decrypt_epoch: u16,
+    decrypt_window: ReplayWindow,
+    // Key of the previous epoch, kept for messages that were in flight during rekey
+    previous_decrypt_key: Option<(Secret, ReplayWindow)>,
+    pending_rekey: Option<PendingRekey>,
+    next_decrypt_key: Option<NextDecryptKey>,
+    replay_window: u16,
+}
+
+impl ChannelKeys {
+    fn new(encrypt_key: Secret, decrypt_key: Secret, replay_window: u16) -> Self {
+        Self {
+            encrypt_key,
+            encrypt_epoch: 0,
+            #[cfg(feature = "std")]
+            encrypt_key_created: std::time::Instant::now(),
+            nonce: 0,
+            decrypt_key,
+            decrypt_epoch: 0,
+            decrypt_window: ReplayWindow::new(replay_window),
+            previous_decrypt_key: None,
+            pending_rekey: None,
+            next_decrypt_key: None,
+            replay_window,
+        }
+    }
+
+    fn max_messages(options: &RekeyOptions) -> u16 {
+        options
+            .after_messages()
+            .unwrap_or(MAX_MESSAGES_PER_KEY)
+            .clamp(1, MAX_MESSAGES_PER_KEY)
+    }
+
+    fn encrypt_key_expired(&self, options: &RekeyOptions) -> bool {
+        self.nonce >= Self::max_messages(options) || self.encrypt_key_outlived(options.interval())
+    }
+
+    /// Whether a rekey request is to be sent, either to start a rekey or because the
+    /// peer didn't answer the last one within as many messages as a key is used for
+    fn rekey_request_due(&self, options: &RekeyOptions) -> bool {
+        match &self.pending_rekey {
+            Some(pending) => self.nonce - pending.requested_at >= Self::max_messages(options),
+            None => self.encrypt_key_expired(options),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn encrypt_key_outlived(&self, interval: Option<Duration>) -> bool {
+        interval.map_or(false, |i| self.encrypt_key_created.elapsed() >= i)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn encrypt_key_outlived(&self, _interval: Option<Duration>) -> bool {
+        false
+    }
+
+    /// Switch to the next epoch, removing the old key from the vault
+    async fn replace_encrypt_key(
+        &mut self,
+        vault: &mut impl SecretVault,
+        encrypt_key: Secret,
+    ) -> Result<()> {
+        self.encrypt_epoch += 1;
+        self.nonce = 0;
+        #[cfg(feature = "std")]
+        {
+            self.encrypt_key_created = std::time::Instant::now();
+        }
+
+        let old_key = mem::replace(&mut self.encrypt_key, encrypt_key);
+        vault.secret_destroy(old_key).await
+    }
+
+    /// Remove every key from the vault, so that no key material outlives the channel.
+    /// Keys are removed even if removing one of them fails, the first error is returned
+    async fn destroy(self, vault: &mut impl SecretVault) -> Result<()> {
+        let mut keys = vec![self.encrypt_key, self.decrypt_key];
+        keys.extend(self.previous_decrypt_key.map(|(key, _)| key));
+        keys.extend(self.pending_rekey.map(|pending| pending.secret));
+        keys.extend(self.next_decrypt_key.map(|next| next.key));
+
+        let mut first_err = None;
+        for key in keys {
+            if let Err(err) = vault.secret_destroy(key).await {
+                first_err.get_or_insert(err);
+            }
+        }
+
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
 }
 
 /// SecureChannel is an abstraction responsible for sending messages (usually over the network) in
@@ -28,6 +172,8 @@ pub struct SecureChannelWorker<V: SecureChannelVault, K: SecureChannelKeyExchang
     address_remote: Address,
     address_local: Address,
     keys: Option<ChannelKeys>,
+    rekey_options: RekeyOptions,
+    replay_window: u16,
     // Optional address to which message is sent after SecureChannel is created
     key_exchange_completed_callback_route: Option<Address>,
     // Optional address to which responder can talk to after SecureChannel is created
@@ -35,6 +181,12 @@ pub struct SecureChannelWorker<V: SecureChannelVault, K: SecureChannelKeyExchang
     vault: V,
     key_exchanger: Option<K>,
     key_exchange_name: String,
+    // Set of the listener that started this responder, left once the key exchange completes
+    pending_handshakes: Option<PendingHandshakes>,
+    // Larger key exchange messages stop the responder, see SecureChannelListener::with_max_handshake_message_size
+    max_handshake_message_size: Option<usize>,
+    // Messages that can't be sent to the other side are returned there, see UndeliveredMessage
+    undelivered_address: Option<Address>,
 }
 
 impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> SecureChannelWorker<V, K> {
@@ -48,6 +200,8 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> SecureChannelWorker<V,
         first_responder_address: Option<Address>,
         key_exchanger: K,
         vault: V,
+        rekey_options: RekeyOptions,
+        replay_window: u16,
     ) -> Result<Self> {
         let key_exchange_name = key_exchanger.name().await?;
         Ok(SecureChannelWorker {
@@ -56,14 +210,34 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> SecureChannelWorker<V,
             address_remote,
             address_local,
             keys: None,
+            rekey_options,
+            replay_window,
             key_exchange_completed_callback_route,
             first_responder_address,
             key_exchanger: Some(key_exchanger),
             vault,
             key_exchange_name,
+            pending_handshakes: None,
+            max_handshake_message_size: None,
+            undelivered_address: None,
         })
     }
 
+    pub(crate) fn with_pending_handshakes(mut self, pending_handshakes: PendingHandshakes) -> Self {
+        self.pending_handshakes = Some(pending_handshakes);
+        self
+    }
+
+    pub(crate) fn with_max_handshake_message_size(mut self, max_size: usize) -> Self {
+        self.max_handshake_message_size = Some(max_size);
+        self
+    }
+
+    pub(crate) fn with_undelivered_address(mut self, undelivered_address: Option<Address>) -> Self {
+        self.undelivered_address = undelivered_address;
+        self
+    }
+
     fn convert_nonce_u16(nonce: u16) -> ([u8; 2], [u8; 12]) {
         let mut n: [u8; 12] = [0; 12];
         let b: [u8; 2] = nonce.to_be_bytes();
@@ -84,6 +258,126 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> SecureChannelWorker<V,
         Ok(n)
     }
 
+    /// Generate the ephemeral key of one side of a rekey
+    async fn generate_rekey_secret(vault: &mut V) -> Result<(Secret, PublicKey)> {
+        let secret = vault
+            .secret_generate(SecretAttributes::new(
+                SecretType::X25519,
+                SecretPersistence::Ephemeral,
+                CURVE25519_SECRET_LENGTH,
+            ))
+            .await?;
+        let public_key = vault.secret_public_key_get(&secret).await?;
+
+        Ok((secret, public_key))
+    }
+
+    /// Derive the key of given epoch from a new Diffie-Hellman exchange, so that it doesn't
+    /// depend on any earlier key. It has the attributes of the key it replaces
+    async fn agree_on_key(
+        vault: &mut V,
+        secret: &Secret,
+        their_public_key: &PublicKey,
+        epoch: u16,
+        attributes: SecretAttributes,
+    ) -> Result<Secret> {
+        let shared_secret = vault.ec_diffie_hellman(secret, their_public_key).await?;
+
+        let mut info = REKEY_INFO.to_vec();
+        info.extend_from_slice(&epoch.to_be_bytes());
+        let keys = vault
+            .hkdf_sha256(&shared_secret, &info, None, vec![attributes])
+            .await;
+        vault.secret_destroy(shared_secret).await?;
+
+        match keys?.pop() {
+            Some(key) => Ok(key),
+            None => Err(SecureChannelError::InvalidInternalState.into()),
+        }
+    }
+
+    /// Derive the key material the channel exports from the initial initiator to responder
+    /// key. The nonce has its first bytes set, unlike the ones of messages, so the result is
+    /// unrelated to anything the channel uses
+    #[cfg(feature = "key_export")]
+    async fn derive_exported_key(vault: &mut V, key: &Secret) -> Result<ExportedChannelKey> {
+        let exported = Zeroizing::new(
+            vault
+                .aead_aes_gcm_encrypt(key, &[0u8; 32], &[0xFF; 12], &[])
+                .await?,
+        );
+
+        Ok(ExportedChannelKey::new(exported[..32].to_vec()))
+    }
+
+    /// Decrypt message of the next epoch of the peer. Keys are only switched once the message
+    /// is authenticated, so that a forged epoch can't break the channel
+    async fn decrypt_with_next_key(
+        vault: &mut V,
+        keys: &mut ChannelKeys,
+        cipher_text: &[u8],
+        nonce: &[u8],
+        small_nonce: u16,
+        aad: &[u8],
+    ) -> Result<Buffer<u8>> {
+        let plaintext = match &keys.next_decrypt_key {
+            Some(next) => {
+                vault
+                    .aead_aes_gcm_decrypt(&next.key, cipher_text, nonce, aad)
+                    .await?
+            }
+            None => return Err(SecureChannelError::InvalidKeyEpoch.into()),
+        };
+
+        let next = match keys.next_decrypt_key.take() {
+            Some(next) => next,
+            None => return Err(SecureChannelError::InvalidInternalState.into()),
+        };
+
+        // Every key starts with a fresh window, as nonces start over at every epoch
+        let mut window = ReplayWindow::new(keys.replay_window);
+        window.mark(small_nonce);
+
+        let old_key = mem::replace(&mut keys.decrypt_key, next.key);
+        let old_window = mem::replace(&mut keys.decrypt_window, window);
+        let retired = keys.previous_decrypt_key.replace((old_key, old_window));
+        keys.decrypt_epoch += 1;
+
+        debug!(
+            "SecureChannel decrypt key moved to epoch {}",
+            keys.decrypt_epoch
+        );
+
+        if let Some((old_previous, _)) = retired {
+            vault.secret_destroy(old_previous).await?;
+        }
+
+        Ok(plaintext)
+    }
+
+    /// Decrypt message unless its nonce was seen before. The nonce is only remembered
+    /// once the message is authenticated, so that a forged message can't block a real one
+    async fn decrypt_fresh(
+        vault: &mut V,
+        key: &Secret,
+        window: &mut ReplayWindow,
+        cipher_text: &[u8],
+        nonce: &[u8],
+        small_nonce: u16,
+        aad: &[u8],
+    ) -> Result<Buffer<u8>> {
+        if !window.is_fresh(small_nonce) {
+            return Err(SecureChannelError::ReplayedMessage.into());
+        }
+
+        let plaintext = vault
+            .aead_aes_gcm_decrypt(key, cipher_text, nonce, aad)
+            .await?;
+        window.mark(small_nonce);
+
+        Ok(plaintext)
+    }
+
     fn get_keys(keys: &mut Option<ChannelKeys>) -> Result<&mut ChannelKeys> {
         if let Some(k) = keys.as_mut() {
             Ok(k)
@@ -117,6 +411,183 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> SecureChannelWorker<V,
         }
     }
 
+    /// Encrypt frame with the current key
+    async fn encrypt_frame(
+        vault: &mut V,
+        keys: &mut ChannelKeys,
+        payload: &FramePayload,
+        metadata: Option<&SecureChannelMetadata>,
+    ) -> Result<Vec<u8>> {
+        // The peer didn't answer the rekey before the nonces ran out, see MAX_MESSAGES_PER_KEY
+        if keys.nonce == u16::max_value() {
+            return Err(SecureChannelError::NonceExhausted.into());
+        }
+
+        let nonce = keys.nonce;
+
+        keys.nonce += 1;
+
+        let (small_nonce, nonce) = Self::convert_nonce_u16(nonce);
+
+        let mut res = frame_header(keys.encrypt_epoch, &small_nonce, metadata);
+
+        let mut cipher_text = vault
+            .aead_aes_gcm_encrypt(&keys.encrypt_key, &payload.encode()?, &nonce, &res)
+            .await?;
+
+        res.append(&mut cipher_text);
+
+        Ok(res)
+    }
+
+    /// Rekey request frame, if the encrypt key is due to be replaced. The key of the next
+    /// epoch is agreed on once the peer answers, until then the current key stays in use
+    async fn rekey_request(
+        vault: &mut V,
+        keys: &mut ChannelKeys,
+        options: &RekeyOptions,
+    ) -> Result<Option<Vec<u8>>> {
+        if !keys.rekey_request_due(options) {
+            return Ok(None);
+        }
+
+        if keys.encrypt_epoch == u16::max_value() {
+            return Err(SecureChannelError::InvalidKeyEpoch.into());
+        }
+
+        let public_key = match keys.pending_rekey.as_mut() {
+            // The peer didn't answer yet, the request may have been lost
+            Some(pending) => {
+                pending.requested_at = keys.nonce;
+                pending.public_key.clone()
+            }
+            None => {
+                let (secret, public_key) = Self::generate_rekey_secret(vault).await?;
+                keys.pending_rekey = Some(PendingRekey {
+                    secret,
+                    public_key: public_key.clone(),
+                    requested_at: keys.nonce,
+                });
+                public_key
+            }
+        };
+
+        debug!(
+            "SecureChannel requesting encrypt key of epoch {}",
+            keys.encrypt_epoch + 1
+        );
+
+        let frame =
+            Self::encrypt_frame(vault, keys, &FramePayload::RekeyRequest(public_key), None).await?;
+
+        Ok(Some(frame))
+    }
+
+    /// Agree on the key the peer encrypts with in the next epoch and send it our half
+    async fn handle_rekey_request(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        frame_epoch: u16,
+        their_public_key: PublicKey,
+    ) -> Result<()> {
+        let keys = Self::get_keys(&mut self.keys)?;
+
+        // Sent before the peer moved on to the requested epoch
+        if frame_epoch != keys.decrypt_epoch || keys.decrypt_epoch == u16::max_value() {
+            return Ok(());
+        }
+        let epoch = keys.decrypt_epoch + 1;
+
+        // The same request again, e.g. because the answer was lost
+        let repeated = match &keys.next_decrypt_key {
+            Some(next) if next.their_public_key == their_public_key => {
+                Some(next.public_key.clone())
+            }
+            _ => None,
+        };
+
+        let public_key = match repeated {
+            Some(public_key) => public_key,
+            None => {
+                let attributes = self.vault.secret_attributes_get(&keys.decrypt_key).await?;
+                let (secret, public_key) = Self::generate_rekey_secret(&mut self.vault).await?;
+                let key = Self::agree_on_key(
+                    &mut self.vault,
+                    &secret,
+                    &their_public_key,
+                    epoch,
+                    attributes,
+                )
+                .await;
+                self.vault.secret_destroy(secret).await?;
+
+                let next = NextDecryptKey {
+                    their_public_key,
+                    public_key: public_key.clone(),
+                    key: key?,
+                };
+                if let Some(old_next) = keys.next_decrypt_key.replace(next) {
+                    self.vault.secret_destroy(old_next.key).await?;
+                }
+
+                public_key
+            }
+        };
+
+        let frame = Self::encrypt_frame(
+            &mut self.vault,
+            keys,
+            &FramePayload::RekeyResponse(epoch, public_key),
+            None,
+        )
+        .await?;
+
+        ctx.send_from_address(
+            self.remote_route.clone(),
+            frame,
+            self.address_remote.clone(),
+        )
+        .await
+    }
+
+    /// Switch to the key agreed on with the peer, which is ready to decrypt with it
+    async fn handle_rekey_response(
+        &mut self,
+        epoch: u16,
+        their_public_key: PublicKey,
+    ) -> Result<()> {
+        let keys = Self::get_keys(&mut self.keys)?;
+
+        // Answer to a request that was answered before
+        if keys.encrypt_epoch.checked_add(1) != Some(epoch) {
+            return Ok(());
+        }
+        let pending = match keys.pending_rekey.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+
+        let attributes = self.vault.secret_attributes_get(&keys.encrypt_key).await?;
+        let key = Self::agree_on_key(
+            &mut self.vault,
+            &pending.secret,
+            &their_public_key,
+            epoch,
+            attributes,
+        )
+        .await;
+        self.vault.secret_destroy(pending.secret).await?;
+
+        keys.replace_encrypt_key(&mut self.vault, key?).await?;
+
+        debug!(
+            "SecureChannel encrypt key moved to epoch {}",
+            keys.encrypt_epoch
+        );
+
+        Ok(())
+    }
+
     async fn handle_encrypt(
         &mut self,
         ctx: &mut <Self as Worker>::Context,
@@ -124,6 +595,7 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> SecureChannelWorker<V,
     ) -> Result<()> {
         debug!("SecureChannel received Encrypt");
 
+        let metadata = SecureChannelMetadata::find_info(msg.local_message()).ok();
         let reply = msg.return_route();
         let mut onward_route = msg.onward_route();
         let transport_message = msg.into_transport_message();
@@ -132,39 +604,72 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> SecureChannelWorker<V,
         let _ = onward_route.step();
 
         let msg = TransportMessage::v1(onward_route, reply, payload.to_vec());
-        let payload = msg.encode()?;
+        // Only kept if it's returned when it can't be sent
+        let undelivered = self.undelivered_address.as_ref().map(|_| msg.clone());
 
-        let payload = {
+        let (rekey_request, payload) = {
             let keys = Self::get_keys(&mut self.keys)?;
 
-            let nonce = keys.nonce;
-
-            if nonce == u16::max_value() {
-                return Err(SecureChannelError::InvalidNonce.into());
-            }
+            let rekey_request =
+                Self::rekey_request(&mut self.vault, keys, &self.rekey_options).await?;
 
-            keys.nonce += 1;
-
-            let (small_nonce, nonce) = Self::convert_nonce_u16(nonce);
+            let payload = Self::encrypt_frame(
+                &mut self.vault,
+                keys,
+                &FramePayload::Message(msg),
+                metadata.as_ref(),
+            )
+            .await?;
 
-            let mut cipher_text = self
-                .vault
-                .aead_aes_gcm_encrypt(&keys.encrypt_key, payload.as_slice(), &nonce, &[])
-                .await?;
+            (rekey_request, payload)
+        };
 
-            let mut res = Vec::new();
-            res.extend_from_slice(&small_nonce);
-            res.append(&mut cipher_text);
+        if let Some(rekey_request) = rekey_request {
+            // The request is sent again with a later message if it's lost
+            if let Err(err) = ctx
+                .send_from_address(
+                    self.remote_route.clone(),
+                    rekey_request,
+                    self.address_remote.clone(),
+                )
+                .await
+            {
+                debug!("{} sending SecureChannel rekey request", err);
+            }
+        }
 
-            res
+        let err = match ctx
+            .send_from_address(
+                self.remote_route.clone(),
+                payload,
+                self.address_remote.clone(),
+            )
+            .await
+        {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
         };
 
-        ctx.send_from_address(
-            self.remote_route.clone(),
-            payload,
-            self.address_remote.clone(),
-        )
-        .await
+        // The remote route is gone, e.g. the transport connection dropped
+        match (&self.undelivered_address, undelivered) {
+            (Some(undelivered_address), Some(msg)) => {
+                warn!(
+                    "{} sending encrypted message of SecureChannel at local: {}, returning it",
+                    err, self.address_local
+                );
+                ctx.send_from_address(
+                    undelivered_address.clone(),
+                    UndeliveredMessage {
+                        onward_route: msg.onward_route,
+                        return_route: msg.return_route,
+                        payload: msg.payload,
+                    },
+                    self.address_local.clone(),
+                )
+                .await
+            }
+            _ => Err(err),
+        }
     }
 
     async fn handle_decrypt(
@@ -176,23 +681,80 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> SecureChannelWorker<V,
 
         let transport_message = msg.into_transport_message();
         let payload = transport_message.payload;
-        let payload = Vec::<u8>::decode(&payload)?;
+        let frame = Vec::<u8>::decode(&payload)?;
 
-        let payload = {
+        let (payload, metadata, epoch) = {
             let keys = Self::get_keys(&mut self.keys)?;
 
-            if payload.len() < 2 {
-                return Err(SecureChannelError::InvalidNonce.into());
-            }
+            // Epoch, nonce and metadata are authenticated as associated data
+            let (header, cipher_text) = split_frame(&frame)?;
 
-            let nonce = Self::convert_nonce_small(&payload.as_slice()[..2])?;
+            let epoch = u16::from_be_bytes([header[0], header[1]]);
+            let small_nonce = u16::from_be_bytes([header[2], header[3]]);
+            let nonce = Self::convert_nonce_small(&header[2..4])?;
+            let metadata = &header[5..];
 
-            self.vault
-                .aead_aes_gcm_decrypt(&keys.decrypt_key, &payload[2..], &nonce, &[])
+            let payload = if epoch == keys.decrypt_epoch {
+                Self::decrypt_fresh(
+                    &mut self.vault,
+                    &keys.decrypt_key,
+                    &mut keys.decrypt_window,
+                    cipher_text,
+                    &nonce,
+                    small_nonce,
+                    header,
+                )
+                .await?
+            } else if epoch < keys.decrypt_epoch {
+                match &mut keys.previous_decrypt_key {
+                    Some((key, window)) if epoch + 1 == keys.decrypt_epoch => {
+                        Self::decrypt_fresh(
+                            &mut self.vault,
+                            key,
+                            window,
+                            cipher_text,
+                            &nonce,
+                            small_nonce,
+                            header,
+                        )
+                        .await?
+                    }
+                    _ => return Err(SecureChannelError::InvalidKeyEpoch.into()),
+                }
+            } else if epoch - keys.decrypt_epoch == 1 {
+                Self::decrypt_with_next_key(
+                    &mut self.vault,
+                    keys,
+                    cipher_text,
+                    &nonce,
+                    small_nonce,
+                    header,
+                )
                 .await?
+            } else {
+                return Err(SecureChannelError::InvalidKeyEpoch.into());
+            };
+
+            let metadata = if metadata.is_empty() {
+                None
+            } else {
+                Some(SecureChannelMetadata::new(metadata)?)
+            };
+
+            (payload, metadata, epoch)
         };
 
-        let mut transport_message = TransportMessage::decode(&payload)?;
+        let mut transport_message = match FramePayload::decode(&payload)? {
+            FramePayload::Message(transport_message) => transport_message,
+            FramePayload::RekeyRequest(their_public_key) => {
+                return self
+                    .handle_rekey_request(ctx, epoch, their_public_key)
+                    .await
+            }
+            FramePayload::RekeyResponse(epoch, their_public_key) => {
+                return self.handle_rekey_response(epoch, their_public_key).await
+            }
+        };
 
         transport_message
             .return_route
@@ -201,7 +763,12 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> SecureChannelWorker<V,
 
         let local_info = SecureChannelLocalInfo::new(self.key_exchange_name.clone());
 
-        let local_msg = LocalMessage::new(transport_message, vec![local_info.to_local_info()?]);
+        let mut local_info = vec![local_info.to_local_info()?];
+        if let Some(metadata) = metadata {
+            local_info.push(metadata.to_local_info()?);
+        }
+
+        let local_msg = LocalMessage::new(transport_message, local_info);
 
         ctx.forward(local_msg).await
     }
@@ -217,6 +784,20 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> SecureChannelWorker<V,
         let reply = msg.return_route();
         let transport_message = msg.into_transport_message();
         let payload = transport_message.payload;
+
+        // The peer isn't authenticated yet, so don't let it make us process anything larger
+        if let Some(max_size) = self.max_handshake_message_size {
+            if payload.len() > max_size {
+                warn!(
+                    "Stopping SecureChannel at local: {}, key exchange message of {} bytes",
+                    self.address_local,
+                    payload.len()
+                );
+                ctx.stop_worker(self.address_local.clone()).await?;
+                return Err(SecureChannelError::HandshakeMessageTooLarge.into());
+            }
+        }
+
         let payload = Vec::<u8>::decode(&payload)?;
 
         // Update route to a remote
@@ -250,11 +831,21 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> SecureChannelWorker<V,
             }
             let keys = key_exchanger.finalize().await?;
 
-            self.keys = Some(ChannelKeys {
-                encrypt_key: keys.encrypt_key().clone(),
-                decrypt_key: keys.decrypt_key().clone(),
-                nonce: 0,
-            });
+            #[cfg(feature = "key_export")]
+            let exported_key = {
+                let initiator_key = if self.is_initiator {
+                    keys.encrypt_key()
+                } else {
+                    keys.decrypt_key()
+                };
+                Self::derive_exported_key(&mut self.vault, initiator_key).await?
+            };
+
+            self.keys = Some(ChannelKeys::new(
+                keys.encrypt_key().clone(),
+                keys.decrypt_key().clone(),
+                self.replay_window,
+            ));
 
             let role_str = if self.is_initiator {
                 "initiator"
@@ -267,17 +858,32 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> SecureChannelWorker<V,
                 role_str, &self.address_local, &self.address_remote
             );
 
-            // Notify interested worker about finished key exchange
+            // Notify interested worker about finished key exchange. It takes over the
+            // pending handshake, otherwise the handshake is over
             if let Some(r) = self.key_exchange_completed_callback_route.take() {
-                ctx.send_from_address(
-                    r,
-                    KeyExchangeCompleted {
-                        address: self.address_local.clone(),
-                        auth_hash: *keys.h(),
-                    },
-                    self.address_local.clone(),
-                )
-                .await?;
+                if let Err(err) = ctx
+                    .send_from_address(
+                        r,
+                        KeyExchangeCompleted {
+                            address: self.address_local.clone(),
+                            auth_hash: *keys.h(),
+                            #[cfg(feature = "key_export")]
+                            exported_key,
+                        },
+                        self.address_local.clone(),
+                    )
+                    .await
+                {
+                    // Nobody is waiting for the channel anymore, e.g. the handshake was cancelled
+                    warn!(
+                        "{} reporting completed key exchange, stopping SecureChannel at local: {}",
+                        err, self.address_local
+                    );
+                    ctx.stop_worker(self.address_local.clone()).await?;
+                    return Err(err);
+                }
+            } else if let Some(pending_handshakes) = &self.pending_handshakes {
+                pending_handshakes.remove(&self.address_local);
             }
         }
 
@@ -290,6 +896,8 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> SecureChannelWorker<V,
 pub struct KeyExchangeCompleted {
     address: Address,
     auth_hash: [u8; 32],
+    #[cfg(feature = "key_export")]
+    exported_key: ExportedChannelKey,
 }
 
 impl KeyExchangeCompleted {
@@ -301,6 +909,40 @@ impl KeyExchangeCompleted {
     pub fn auth_hash(&self) -> [u8; 32] {
         self.auth_hash
     }
+    /// Key material exported from the channel
+    #[cfg(feature = "key_export")]
+    pub fn exported_key(&self) -> &ExportedChannelKey {
+        &self.exported_key
+    }
+}
+
+/// Message a SecureChannel couldn't send to the other side, e.g. because the transport
+/// connection dropped. Returned, from the local address of the channel, to the address given with
+/// [`SecureChannel::create_extended_with_undelivered_address`](crate::SecureChannel::create_extended_with_undelivered_address),
+/// in the order the messages were sent. The channel keeps running, so it has to be stopped
+/// by whoever takes over its messages
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Message)]
+pub struct UndeliveredMessage {
+    onward_route: Route,
+    return_route: Route,
+    payload: Vec<u8>,
+}
+
+impl UndeliveredMessage {
+    /// Route of the message past the channel
+    pub fn onward_route(&self) -> &Route {
+        &self.onward_route
+    }
+    pub fn return_route(&self) -> &Route {
+        &self.return_route
+    }
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+    /// Onward route, return route and payload
+    pub fn into_parts(self) -> (Route, Route, Vec<u8>) {
+        (self.onward_route, self.return_route, self.payload)
+    }
 }
 
 #[async_trait]
@@ -308,6 +950,18 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> Worker for SecureChann
     type Message = Any;
     type Context = Context;
 
+    async fn shutdown(&mut self, _ctx: &mut Self::Context) -> Result<()> {
+        if let Some(pending_handshakes) = &self.pending_handshakes {
+            pending_handshakes.remove(&self.address_local);
+        }
+
+        if let Some(keys) = self.keys.take() {
+            keys.destroy(&mut self.vault).await?;
+        }
+
+        Ok(())
+    }
+
     async fn initialize(&mut self, ctx: &mut Self::Context) -> Result<()> {
         if self.is_initiator {
             if let Some(initiator) = self.key_exchanger.as_mut() {
@@ -341,3 +995,65 @@ impl<V: SecureChannelVault, K: SecureChannelKeyExchanger> Worker for SecureChann
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ockam_core::vault::AES256_SECRET_LENGTH;
+    use ockam_vault::SoftwareVault;
+
+    fn aes_attributes() -> SecretAttributes {
+        SecretAttributes::new(
+            SecretType::Aes,
+            SecretPersistence::Ephemeral,
+            AES256_SECRET_LENGTH,
+        )
+    }
+
+    #[ockam_macros::test]
+    async fn test_keys_are_destroyed(ctx: &mut Context) -> Result<()> {
+        let mut vault = SoftwareVault::default();
+
+        let encrypt_key = vault.secret_generate(aes_attributes()).await?;
+        let decrypt_key = vault.secret_generate(aes_attributes()).await?;
+        let mut keys = ChannelKeys::new(encrypt_key.clone(), decrypt_key.clone(), 4);
+
+        // Rekey superseding the encrypt key
+        let next_key = vault.secret_generate(aes_attributes()).await?;
+        keys.replace_encrypt_key(&mut vault, next_key.clone())
+            .await?;
+        assert_eq!(keys.encrypt_epoch, 1);
+        assert!(vault.secret_export(&encrypt_key).await.is_err());
+
+        let previous_decrypt_key = vault.secret_generate(aes_attributes()).await?;
+        keys.previous_decrypt_key = Some((previous_decrypt_key.clone(), ReplayWindow::new(4)));
+
+        keys.destroy(&mut vault).await?;
+        for key in [next_key, decrypt_key, previous_decrypt_key] {
+            assert!(vault.secret_export(&key).await.is_err());
+        }
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_keys_are_destroyed_after_error(ctx: &mut Context) -> Result<()> {
+        let mut vault = SoftwareVault::default();
+
+        let encrypt_key = vault.secret_generate(aes_attributes()).await?;
+        let decrypt_key = vault.secret_generate(aes_attributes()).await?;
+        let mut keys = ChannelKeys::new(encrypt_key.clone(), decrypt_key.clone(), 4);
+        let previous_decrypt_key = vault.secret_generate(aes_attributes()).await?;
+        keys.previous_decrypt_key = Some((previous_decrypt_key.clone(), ReplayWindow::new(4)));
+
+        // Destroying this one again fails, which doesn't keep the others in the vault
+        vault.secret_destroy(decrypt_key).await?;
+
+        assert!(keys.destroy(&mut vault).await.is_err());
+        for key in [encrypt_key, previous_decrypt_key] {
+            assert!(vault.secret_export(&key).await.is_err());
+        }
+
+        ctx.stop().await
+    }
+}
@@ -1,11 +1,24 @@
-use ockam_core::vault::SymmetricVault;
+use ockam_core::vault::{AsymmetricVault, Hasher, SecretVault, SymmetricVault};
 use ockam_core::AsyncTryClone;
 use ockam_key_exchange_core::{KeyExchanger, NewKeyExchanger};
 
-/// Vault with XX required functionality
-pub trait SecureChannelVault: SymmetricVault + AsyncTryClone + Send + Sync + 'static {}
+/// Vault with SecureChannel required functionality
+pub trait SecureChannelVault:
+    SymmetricVault + SecretVault + AsymmetricVault + Hasher + AsyncTryClone + Send + Sync + 'static
+{
+}
 
-impl<D> SecureChannelVault for D where D: SymmetricVault + AsyncTryClone + Send + Sync + 'static {}
+impl<D> SecureChannelVault for D where
+    D: SymmetricVault
+        + SecretVault
+        + AsymmetricVault
+        + Hasher
+        + AsyncTryClone
+        + Send
+        + Sync
+        + 'static
+{
+}
 
 /// KeyExchanger with extra constraints
 pub trait SecureChannelKeyExchanger: KeyExchanger + Send + Sync + 'static {}
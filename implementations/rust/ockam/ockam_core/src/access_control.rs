@@ -1,13 +1,49 @@
 use crate::compat::boxed::Box;
 use crate::{LocalMessage, Result};
 
+mod all_access_control;
+pub use all_access_control::*;
+mod any_access_control;
+pub use any_access_control::*;
+mod local_origin_only;
+pub use local_origin_only::*;
+
 /// Access control
 #[async_trait]
 pub trait AccessControl: Send + Sync + 'static {
     /// Returns true if message is allowed to pass, and false if not
     async fn msg_is_authorized(&mut self, local_msg: &LocalMessage) -> Result<bool>;
+
+    /// Synchronous version of [`AccessControl::msg_is_authorized`].
+    ///
+    /// Returns `None` if the decision requires the async version, which is the default.
+    /// Implementations that can decide without awaiting should override this, so that
+    /// message dispatch doesn't need to allocate a future for every message.
+    fn msg_is_authorized_sync(&mut self, _local_msg: &LocalMessage) -> Option<Result<bool>> {
+        None
+    }
+}
+
+/// Combine access controls with [`AllAccessControl`]
+pub trait ConjunctionAccessControl: AccessControl + Sized {
+    /// Allow messages allowed by both access controls
+    fn and<O: AccessControl>(self, other: O) -> AllAccessControl<Self, O> {
+        AllAccessControl::new(self, other)
+    }
 }
 
+impl<T> ConjunctionAccessControl for T where T: AccessControl {}
+
+/// Combine access controls with [`AnyAccessControl`]
+pub trait DisjunctionAccessControl: AccessControl + Sized {
+    /// Allow messages allowed by any of both access controls
+    fn or<O: AccessControl>(self, other: O) -> AnyAccessControl<Self, O> {
+        AnyAccessControl::new(self, other)
+    }
+}
+
+impl<T> DisjunctionAccessControl for T where T: AccessControl {}
+
 /// Access Control that allows any message to pass through
 pub struct Passthrough;
 
@@ -16,6 +52,10 @@ impl AccessControl for Passthrough {
     async fn msg_is_authorized(&mut self, _local_msg: &LocalMessage) -> Result<bool> {
         Ok(true)
     }
+
+    fn msg_is_authorized_sync(&mut self, _local_msg: &LocalMessage) -> Option<Result<bool>> {
+        Some(Ok(true))
+    }
 }
 
 /// Access Control that doesn't allow all messages to pass through
@@ -26,4 +66,38 @@ impl AccessControl for NoAccess {
     async fn msg_is_authorized(&mut self, _local_msg: &LocalMessage) -> Result<bool> {
         Ok(false)
     }
+
+    fn msg_is_authorized_sync(&mut self, _local_msg: &LocalMessage) -> Option<Result<bool>> {
+        Some(Ok(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{route, TransportMessage};
+
+    struct AsyncOnly;
+
+    #[async_trait]
+    impl AccessControl for AsyncOnly {
+        async fn msg_is_authorized(&mut self, _local_msg: &LocalMessage) -> Result<bool> {
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn sync_fast_path() {
+        let msg = LocalMessage::new(TransportMessage::v1(route!["a"], route![], vec![]), vec![]);
+
+        assert!(matches!(
+            Passthrough.msg_is_authorized_sync(&msg),
+            Some(Ok(true))
+        ));
+        assert!(matches!(
+            NoAccess.msg_is_authorized_sync(&msg),
+            Some(Ok(false))
+        ));
+        assert!(AsyncOnly.msg_is_authorized_sync(&msg).is_none());
+    }
 }
@@ -0,0 +1,74 @@
+use crate::compat::boxed::Box;
+use crate::{AccessControl, LocalMessage, Result};
+
+/// Access control that allows a message if any of both access controls allows it.
+/// The second one isn't asked if the first one allows the message
+pub struct AnyAccessControl<F: AccessControl, S: AccessControl> {
+    first: F,
+    second: S,
+}
+
+impl<F: AccessControl, S: AccessControl> AnyAccessControl<F, S> {
+    /// Constructor
+    pub fn new(first: F, second: S) -> Self {
+        Self { first, second }
+    }
+}
+
+#[async_trait]
+impl<F: AccessControl, S: AccessControl> AccessControl for AnyAccessControl<F, S> {
+    async fn msg_is_authorized(&mut self, local_msg: &LocalMessage) -> Result<bool> {
+        Ok(self.first.msg_is_authorized(local_msg).await?
+            || self.second.msg_is_authorized(local_msg).await?)
+    }
+
+    fn msg_is_authorized_sync(&mut self, local_msg: &LocalMessage) -> Option<Result<bool>> {
+        match self.first.msg_is_authorized_sync(local_msg)? {
+            Ok(false) => self.second.msg_is_authorized_sync(local_msg),
+            res => Some(res),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compat::boxed::Box;
+    use crate::{
+        route, AccessControl, DisjunctionAccessControl, LocalMessage, NoAccess, Passthrough,
+        Result, TransportMessage,
+    };
+
+    struct AsyncOnly(bool);
+
+    #[async_trait]
+    impl AccessControl for AsyncOnly {
+        async fn msg_is_authorized(&mut self, _local_msg: &LocalMessage) -> Result<bool> {
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn test() {
+        let msg = LocalMessage::new(TransportMessage::v1(route!["a"], route![], vec![]), vec![]);
+
+        assert!(matches!(
+            NoAccess.or(NoAccess).msg_is_authorized_sync(&msg),
+            Some(Ok(false))
+        ));
+        assert!(matches!(
+            NoAccess.or(Passthrough).msg_is_authorized_sync(&msg),
+            Some(Ok(true))
+        ));
+        // Decided without the second one
+        assert!(matches!(
+            Passthrough
+                .or(AsyncOnly(false))
+                .msg_is_authorized_sync(&msg),
+            Some(Ok(true))
+        ));
+        assert!(NoAccess
+            .or(AsyncOnly(true))
+            .msg_is_authorized_sync(&msg)
+            .is_none());
+    }
+}
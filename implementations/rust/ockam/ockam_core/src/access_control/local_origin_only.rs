@@ -0,0 +1,61 @@
+use crate::compat::boxed::Box;
+use crate::{AccessControl, LocalMessage, Result};
+
+/// Access control that allows messages whose return route doesn't go through a transport,
+/// i.e. that were sent by a worker on this node. Messages from other nodes only pass
+/// through a channel that terminates on this node, such as a secure channel, as that
+/// replaces the return route with its own local address
+pub struct LocalOriginOnly;
+
+impl LocalOriginOnly {
+    fn is_local_origin(local_msg: &LocalMessage) -> bool {
+        // Transports use non-zero address types
+        local_msg
+            .transport()
+            .return_route
+            .iter()
+            .all(|address| address.tt == 0)
+    }
+}
+
+#[async_trait]
+impl AccessControl for LocalOriginOnly {
+    async fn msg_is_authorized(&mut self, local_msg: &LocalMessage) -> Result<bool> {
+        Ok(Self::is_local_origin(local_msg))
+    }
+
+    fn msg_is_authorized_sync(&mut self, local_msg: &LocalMessage) -> Option<Result<bool>> {
+        Some(Ok(Self::is_local_origin(local_msg)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{route, Address, TransportMessage};
+
+    #[test]
+    fn test() {
+        let local = LocalMessage::new(
+            TransportMessage::v1(route!["a"], route!["b", "c"], vec![]),
+            vec![],
+        );
+        let remote = LocalMessage::new(
+            TransportMessage::v1(
+                route!["a"],
+                route![Address::new(1, "127.0.0.1:4000"), "c"],
+                vec![],
+            ),
+            vec![],
+        );
+
+        assert!(matches!(
+            LocalOriginOnly.msg_is_authorized_sync(&local),
+            Some(Ok(true))
+        ));
+        assert!(matches!(
+            LocalOriginOnly.msg_is_authorized_sync(&remote),
+            Some(Ok(false))
+        ));
+    }
+}
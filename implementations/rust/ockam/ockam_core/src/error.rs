@@ -15,6 +15,8 @@ use serde::{Deserialize, Serialize};
 ///
 /// 1. __Error Code__: A `u32` representing the the presise error.
 /// 2. __Error Domain__: An error domain string.
+/// 3. __Error Reason__: An optional human readable detail, see [`Error::with_reason`].
+///    It isn't serialized, so that errors encode the same with and without it.
 ///
 /// # no_std
 /// When the `"std"` feature is not enabled we assume that the Rust Standard
@@ -22,12 +24,16 @@ use serde::{Deserialize, Serialize};
 ///
 /// 1. __Error Code__: A `u32` representing the the presise error.
 ///
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Error {
     code: u32,
 
     #[cfg(feature = "alloc")]
     domain: String,
+
+    #[cfg(feature = "alloc")]
+    #[serde(skip)]
+    reason: Option<String>,
 }
 
 /// The type returned by Ockam functions.
@@ -56,9 +62,25 @@ impl Error {
         Self {
             code,
             domain: domain.into(),
+            reason: None,
         }
     }
 
+    /// Attach a human readable detail, e.g. why a peer was rejected.
+    /// The code stays the same, so errors are still told apart by it
+    #[cfg(feature = "alloc")]
+    pub fn with_reason<S: Into<String>>(mut self, reason: S) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    /// Returns an error's reason, if it has one.
+    #[inline]
+    #[cfg(feature = "alloc")]
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
     /// Returns an error's domain.
     #[inline]
     #[cfg(feature = "alloc")]
@@ -77,11 +99,18 @@ impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         #[cfg(feature = "alloc")]
         {
-            write!(
-                f,
-                "Error {{ code: {}, domain: \"{}\" }}",
-                self.code, self.domain
-            )
+            match &self.reason {
+                Some(reason) => write!(
+                    f,
+                    "Error {{ code: {}, domain: \"{}\", reason: \"{}\" }}",
+                    self.code, self.domain, reason
+                ),
+                None => write!(
+                    f,
+                    "Error {{ code: {}, domain: \"{}\" }}",
+                    self.code, self.domain
+                ),
+            }
         }
         #[cfg(not(feature = "alloc"))]
         {
@@ -90,6 +119,22 @@ impl Display for Error {
     }
 }
 
+// Leaves out a missing reason, so that most errors print as before reasons were added
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Error");
+        debug.field("code", &self.code);
+        #[cfg(feature = "alloc")]
+        {
+            debug.field("domain", &self.domain);
+            if let Some(reason) = &self.reason {
+                debug.field("reason", reason);
+            }
+        }
+        debug.finish()
+    }
+}
+
 impl crate::compat::error::Error for Error {}
 
 #[cfg(feature = "alloc")]
@@ -97,6 +142,13 @@ impl crate::compat::error::Error for Error {}
 mod std_test {
     use super::*;
 
+    /// [`Error`] as it was encoded before it had a reason
+    #[derive(Serialize, Deserialize)]
+    struct ErrorWithoutReason {
+        code: u32,
+        domain: String,
+    }
+
     #[test]
     fn can_be_created() {
         let _error = Error::new(1000, "SOME_ERROR_DOMAIN");
@@ -133,6 +185,44 @@ mod std_test {
             "Error { code: 1000, domain: \"SOME_ERROR_DOMAIN\" }"
         );
     }
+
+    #[test]
+    fn can_carry_reason() {
+        let error = Error::new(1000, "SOME_ERROR_DOMAIN").with_reason("not on the list");
+        assert_eq!(error.code(), 1000);
+        assert_eq!(error.reason(), Some("not on the list"));
+        assert_eq!(
+            format!("{}", error),
+            "Error { code: 1000, domain: \"SOME_ERROR_DOMAIN\", reason: \"not on the list\" }"
+        );
+        assert_eq!(
+            format!("{:?}", error),
+            "Error { code: 1000, domain: \"SOME_ERROR_DOMAIN\", reason: \"not on the list\" }"
+        );
+        assert!(Error::new(1000, "SOME_ERROR_DOMAIN").reason().is_none());
+    }
+
+    #[test]
+    fn encodes_without_reason() {
+        let error = Error::new(1000, "SOME_ERROR_DOMAIN").with_reason("not on the list");
+        let old = ErrorWithoutReason {
+            code: 1000,
+            domain: "SOME_ERROR_DOMAIN".into(),
+        };
+
+        let encoded = serde_bare::to_vec(&error).unwrap();
+        assert_eq!(encoded, serde_bare::to_vec(&old).unwrap());
+
+        let decoded: Error = serde_bare::from_slice(&encoded).unwrap();
+        assert_eq!(decoded.code(), 1000);
+        assert_eq!(decoded.domain(), "SOME_ERROR_DOMAIN");
+        assert!(decoded.reason().is_none());
+
+        let reencoded: ErrorWithoutReason =
+            serde_bare::from_slice(&serde_bare::to_vec(&decoded).unwrap()).unwrap();
+        assert_eq!(reencoded.code, 1000);
+        assert_eq!(reencoded.domain, "SOME_ERROR_DOMAIN");
+    }
 }
 
 #[cfg(not(feature = "alloc"))]
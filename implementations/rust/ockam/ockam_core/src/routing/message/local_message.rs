@@ -63,6 +63,16 @@ impl LocalMessage {
     pub fn local_info(&self) -> &[LocalInfo] {
         &self.local_info
     }
+    /// Underlying transport message and LocalInfo, in the order it was added.
+    /// [`LocalMessage::new`] puts them back together, e.g. to forward a message
+    /// with all of its LocalInfo, including types the forwarding Worker doesn't know
+    pub fn into_parts(self) -> (TransportMessage, Vec<LocalInfo>) {
+        (self.transport_message, self.local_info)
+    }
+    /// Add LocalInfo after the one already attached
+    pub fn append_local_info(&mut self, local_info: LocalInfo) {
+        self.local_info.push(local_info);
+    }
 }
 
 impl LocalMessage {
@@ -73,6 +73,11 @@ impl Route {
             .ok_or_else(|| RouteError::IncompleteRoute.into())
     }
 
+    /// Iterate over the addresses of this route, starting with the next one
+    pub fn iter(&self) -> impl Iterator<Item = &Address> {
+        self.inner.iter()
+    }
+
     /// Get the final recipient address
     pub fn recipient(&self) -> Address {
         self.inner
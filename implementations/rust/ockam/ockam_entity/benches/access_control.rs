@@ -0,0 +1,61 @@
+//! Cost of entity access controls per message, through the synchronous fast path message
+//! dispatch prefers and through the async fallback, whose future is boxed for every message.
+//!
+//! Run with `cargo bench -p ockam_entity --bench access_control`. That the fast path allocates
+//! less is checked by the `access_control_allocations` test
+
+use criterion::Criterion;
+use futures::executor::block_on;
+use ockam_core::{route, AccessControl, LocalMessage, TransportMessage};
+use ockam_entity::{EntityAccessControlBuilder, EntitySecureChannelLocalInfo, ProfileIdentifier};
+use std::convert::TryInto;
+
+const PROFILE_ID: &str = "P79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b6";
+
+/// Message as it leaves a secure channel with given peer
+fn channel_message(their_profile_id: ProfileIdentifier) -> LocalMessage {
+    let local_info = EntitySecureChannelLocalInfo::new(their_profile_id)
+        .to_local_info()
+        .unwrap();
+
+    LocalMessage::new(
+        TransportMessage::v1(route!["receiver"], route!["channel", "sender"], vec![]),
+        vec![local_info],
+    )
+}
+
+fn bench_access_control<A: AccessControl>(
+    c: &mut Criterion,
+    name: &str,
+    mut access_control: A,
+    msg: &LocalMessage,
+) {
+    let mut group = c.benchmark_group("access_control");
+    group.bench_function(format!("{}/sync", name), |b| {
+        b.iter(|| access_control.msg_is_authorized_sync(msg))
+    });
+    group.bench_function(format!("{}/async", name), |b| {
+        b.iter(|| block_on(access_control.msg_is_authorized(msg)))
+    });
+    group.finish();
+}
+
+fn main() {
+    let their_profile_id: ProfileIdentifier = PROFILE_ID.try_into().unwrap();
+    let msg = channel_message(their_profile_id.clone());
+
+    let mut c = Criterion::default().configure_from_args();
+    bench_access_control(
+        &mut c,
+        "id",
+        EntityAccessControlBuilder::new_with_id(their_profile_id),
+        &msg,
+    );
+    bench_access_control(
+        &mut c,
+        "any_id",
+        EntityAccessControlBuilder::new_with_any_id(),
+        &msg,
+    );
+    c.final_summary();
+}
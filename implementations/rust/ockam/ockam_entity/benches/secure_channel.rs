@@ -0,0 +1,167 @@
+//! Handshake latency and message throughput of secure channels between two entities of the
+//! same node, so that routing is in memory and the cost measured is that of the channel.
+//!
+//! Run with `cargo bench -p ockam_entity --bench secure_channel`. Criterion arguments, e.g. a
+//! filter like `-- throughput/batched`, work as usual
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+use ockam_core::{route, Address, Result};
+use ockam_entity::{
+    Entity, Identity, KeyExchangePattern, SecureChannelOptions, TrustEveryonePolicy,
+};
+use ockam_node::tokio::runtime::Runtime;
+use ockam_node::Context;
+use ockam_vault_sync_core::Vault;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const LISTENER: &str = "bench_listener";
+
+/// Message sizes of the throughput benchmarks, in bytes
+const MESSAGE_SIZES: [usize; 3] = [64, 1024, 64 * 1024];
+
+/// Messages sent before waiting for them to arrive, so that the channel is kept busy
+/// without piling up an unbounded number of messages in the mailbox
+const WINDOW: usize = 64;
+
+struct Bench {
+    rt: Arc<Runtime>,
+    ctx: Context,
+    alice: Entity,
+}
+
+fn handshake_latency(c: &mut Criterion, bench: &mut Bench) {
+    let rt = bench.rt.clone();
+    let mut group = c.benchmark_group("handshake");
+
+    for (name, pattern) in [
+        ("xx", KeyExchangePattern::Xx),
+        #[cfg(feature = "x3dh")]
+        ("x3dh", KeyExchangePattern::X3dh),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter_custom(|iters| {
+                rt.block_on(async {
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iters {
+                        let options = SecureChannelOptions::new().with_key_exchange(pattern);
+                        let start = Instant::now();
+                        let channel = bench
+                            .alice
+                            .create_secure_channel_with_options(
+                                route![LISTENER],
+                                TrustEveryonePolicy,
+                                options,
+                            )
+                            .await
+                            .unwrap();
+                        elapsed += start.elapsed();
+                        bench.alice.stop_secure_channel(&channel).await.unwrap();
+                    }
+                    elapsed
+                })
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Send `count` messages of `size` bytes through `channel` back to `ctx`, in windows of
+/// [`WINDOW`] messages
+async fn send_through(ctx: &mut Context, channel: &Address, size: usize, count: u64) -> Result<()> {
+    let payload = vec![0xAB; size];
+    let mut remaining = count as usize;
+
+    while remaining > 0 {
+        let window = remaining.min(WINDOW);
+        for _ in 0..window {
+            ctx.send(route![channel.clone(), ctx.address()], payload.clone())
+                .await?;
+        }
+        for _ in 0..window {
+            let msg = ctx.receive::<Vec<u8>>().await?.take().body();
+            assert_eq!(msg.len(), size);
+        }
+        remaining -= window;
+    }
+
+    Ok(())
+}
+
+fn throughput(c: &mut Criterion, bench: &mut Bench) {
+    let rt = bench.rt.clone();
+    let mut group = c.benchmark_group("throughput");
+
+    for (name, options) in [
+        ("default", SecureChannelOptions::new()),
+        ("batched", SecureChannelOptions::new().with_max_batch(16)),
+        (
+            "backpressure",
+            SecureChannelOptions::new().with_backpressure(WINDOW, WINDOW * 4),
+        ),
+    ] {
+        let channel = rt
+            .block_on(bench.alice.create_secure_channel_with_options(
+                route![LISTENER],
+                TrustEveryonePolicy,
+                options,
+            ))
+            .unwrap();
+
+        for size in MESSAGE_SIZES {
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(BenchmarkId::new(name, size), &size, |b, &size| {
+                b.iter_custom(|iters| {
+                    rt.block_on(async {
+                        let start = Instant::now();
+                        send_through(&mut bench.ctx, &channel, size, iters)
+                            .await
+                            .unwrap();
+                        start.elapsed()
+                    })
+                })
+            });
+        }
+
+        rt.block_on(bench.alice.stop_secure_channel(&channel))
+            .unwrap();
+    }
+
+    group.finish();
+}
+
+async fn setup(ctx: &mut Context) -> Result<Entity> {
+    let vault = Vault::create(ctx).await?;
+
+    let alice = Entity::create(ctx, &vault).await?;
+    let mut bob = Entity::create(ctx, &vault).await?;
+    bob.create_secure_channel_listener(LISTENER, TrustEveryonePolicy)
+        .await?;
+
+    Ok(alice)
+}
+
+fn main() {
+    let (ctx, mut executor) = ockam_node::start_node();
+    let rt = executor.runtime();
+
+    executor
+        .execute(async move {
+            // Criterion blocks, and the benchmarks block on the runtime in turn, which only
+            // works from outside of it
+            std::thread::spawn(move || {
+                let mut ctx = ctx;
+                let alice = rt.block_on(setup(&mut ctx)).unwrap();
+                let mut bench = Bench { rt, ctx, alice };
+
+                let mut c = Criterion::default().configure_from_args();
+                handshake_latency(&mut c, &mut bench);
+                throughput(&mut c, &mut bench);
+                c.final_summary();
+
+                bench.rt.block_on(bench.ctx.stop()).unwrap();
+            });
+        })
+        .unwrap();
+}
@@ -0,0 +1,33 @@
+use ockam_core::compat::rand::distributions::{Distribution, Standard};
+use ockam_core::compat::rand::RngCore;
+use ockam_core::compat::{
+    boxed::Box,
+    sync::{Arc, Mutex},
+};
+use ockam_core::Address;
+
+/// Source of the addresses an [`Entity`](crate::Entity) gives its worker, listeners and channels.
+/// Uses the thread RNG, unless built with [`EntityBuilder::with_rng`](crate::EntityBuilder::with_rng)
+#[derive(Clone, Default)]
+pub(crate) struct AddressGenerator {
+    rng: Option<Arc<Mutex<Box<dyn RngCore + Send>>>>,
+}
+
+impl AddressGenerator {
+    pub fn new(rng: impl RngCore + Send + 'static) -> Self {
+        Self {
+            rng: Some(Arc::new(Mutex::new(Box::new(rng)))),
+        }
+    }
+
+    pub fn generate(&self) -> Address {
+        match &self.rng {
+            Some(rng) => {
+                let mut rng = rng.lock().unwrap();
+                let rng: &mut (dyn RngCore + Send) = &mut **rng;
+                Standard.sample(rng)
+            }
+            None => Address::random(0),
+        }
+    }
+}
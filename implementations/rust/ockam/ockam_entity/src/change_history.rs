@@ -70,6 +70,16 @@ impl ProfileChangeHistory {
         Self::find_last_key_event_public_key(existing_events, Profile::ROOT_LABEL)
     }
 
+    /// Every root key the profile had, oldest first
+    pub(crate) fn get_root_public_keys(existing_events: &[ProfileChangeEvent]) -> Vec<PublicKey> {
+        existing_events
+            .iter()
+            .map(|e| e.change_block().change())
+            .filter(|c| c.has_label(Profile::ROOT_LABEL))
+            .filter_map(|c| c.public_key().ok())
+            .collect()
+    }
+
     pub(crate) fn get_first_root_public_key(&self) -> Result<PublicKey> {
         // TODO: Support root key rotation
         let root_event;
@@ -153,7 +163,28 @@ impl ProfileChangeHistory {
                     root_sign,
                 }
             }
-            RotateKey(_) => {
+            RotateKey(change) => {
+                // New key data should be signed by both the new key and the key it replaces
+                let data_binary = change.data().encode().map_err(|_| EntityError::BareError)?;
+                let data_hash = vault.sha256(&data_binary).await?;
+                let prev_public_key = Self::get_public_key_static(
+                    existing_events,
+                    change.data().key_attributes().label(),
+                )?;
+                if !vault
+                    .verify(change.prev_signature(), &prev_public_key, &data_hash)
+                    .await?
+                    || !vault
+                        .verify(
+                            change.self_signature(),
+                            change.data().public_key(),
+                            &data_hash,
+                        )
+                        .await?
+                {
+                    return deny();
+                }
+
                 // Should have self signature, root signature, and previous key signature
                 SignaturesCheck {
                     self_sign: 1,
@@ -201,6 +232,14 @@ impl ProfileChangeHistory {
             *counter -= 1;
         }
 
+        // Every expected signature should be present
+        if signatures_check.self_sign != 0
+            || signatures_check.prev_sign != 0
+            || signatures_check.root_sign != 0
+        {
+            return deny();
+        }
+
         allow()
     }
 
@@ -1,5 +1,8 @@
 use crate::ProfileIdentifier;
-use ockam_core::{async_trait, compat::boxed::Box};
+use ockam_core::{
+    async_trait,
+    compat::{boxed::Box, string::String},
+};
 use ockam_core::{AccessControl, LocalMessage, Result};
 
 mod secure_channel_worker;
@@ -12,6 +15,60 @@ mod trust_policy;
 pub use trust_policy::*;
 mod local_info;
 pub use local_info::*;
+mod secure_channel_options;
+pub use secure_channel_options::*;
+mod secure_channel_events;
+pub use secure_channel_events::*;
+mod key_exchange_pattern;
+pub use key_exchange_pattern::*;
+mod channel_counter;
+pub(crate) use channel_counter::*;
+mod secure_channel_handle;
+pub use secure_channel_handle::*;
+mod cbor_context;
+pub use cbor_context::*;
+mod authority_credential;
+pub use authority_credential::*;
+mod secure_channel_services;
+pub(crate) use secure_channel_services::*;
+mod secure_channel_handshakes;
+pub(crate) use secure_channel_handshakes::*;
+mod backpressure;
+pub use backpressure::*;
+mod cipher_suite;
+pub use cipher_suite::*;
+mod bounded_decoder;
+pub(crate) use bounded_decoder::*;
+#[cfg(feature = "compression")]
+mod compression;
+#[cfg(feature = "compression")]
+pub(crate) use compression::*;
+#[cfg(test)]
+mod loopback;
+#[cfg(test)]
+pub(crate) use loopback::*;
+mod channel_stream;
+pub use channel_stream::*;
+mod priority;
+pub use priority::*;
+mod bridge;
+pub use bridge::*;
+#[cfg(feature = "std")]
+mod rate_limit;
+#[cfg(feature = "std")]
+pub use rate_limit::*;
+mod peer_receiver;
+pub use peer_receiver::*;
+mod pool;
+pub use pool::*;
+mod psk;
+pub use psk::*;
+mod secure_channel_error_kind;
+pub use secure_channel_error_kind::*;
+
+#[cfg(feature = "unsafe_channel_key_export")]
+pub use ockam_channel::ExportedChannelKey;
+pub use ockam_channel::DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE;
 
 pub struct EntityAccessControlBuilder;
 
@@ -20,17 +77,53 @@ impl EntityAccessControlBuilder {
         EntityIdAccessControl { their_profile_id }
     }
 
+    /// Allows messages from any authenticated peer. Anonymous channels are rejected,
+    /// unless allowed with [`EntityAnyIdAccessControl::allow_anonymous`]
     pub fn new_with_any_id() -> EntityAnyIdAccessControl {
-        EntityAnyIdAccessControl
+        EntityAnyIdAccessControl {
+            allow_anonymous: false,
+        }
+    }
+
+    pub fn new_with_attribute(
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> EntityAttributeAccessControl {
+        EntityAttributeAccessControl {
+            key: key.into(),
+            value: value.into(),
+        }
     }
 }
 
-pub struct EntityAnyIdAccessControl;
+pub struct EntityAnyIdAccessControl {
+    allow_anonymous: bool,
+}
+
+impl EntityAnyIdAccessControl {
+    /// Also allow messages from channels created with
+    /// [`SecureChannelOptions::with_anonymous`]
+    pub fn allow_anonymous(mut self) -> Self {
+        self.allow_anonymous = true;
+        self
+    }
+
+    fn is_authorized(&self, local_msg: &LocalMessage) -> bool {
+        match EntitySecureChannelLocalInfo::find_info(local_msg) {
+            Ok(local_info) => self.allow_anonymous || !local_info.is_anonymous(),
+            Err(_) => false,
+        }
+    }
+}
 
 #[async_trait]
 impl AccessControl for EntityAnyIdAccessControl {
     async fn msg_is_authorized(&mut self, local_msg: &LocalMessage) -> Result<bool> {
-        Ok(EntitySecureChannelLocalInfo::find_info(local_msg).is_ok())
+        Ok(self.is_authorized(local_msg))
+    }
+
+    fn msg_is_authorized_sync(&mut self, local_msg: &LocalMessage) -> Option<Result<bool>> {
+        Some(Ok(self.is_authorized(local_msg)))
     }
 }
 
@@ -42,22 +135,72 @@ pub struct EntityIdAccessControl {
 impl AccessControl for EntityIdAccessControl {
     async fn msg_is_authorized(&mut self, local_msg: &LocalMessage) -> Result<bool> {
         if let Ok(msg_profile_id) = EntitySecureChannelLocalInfo::find_info(local_msg) {
-            Ok(msg_profile_id.their_profile_id() == &self.their_profile_id)
+            Ok(!msg_profile_id.is_anonymous()
+                && msg_profile_id.their_profile_id() == &self.their_profile_id)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn msg_is_authorized_sync(&mut self, local_msg: &LocalMessage) -> Option<Result<bool>> {
+        if let Ok(msg_profile_id) = EntitySecureChannelLocalInfo::find_info(local_msg) {
+            Some(Ok(!msg_profile_id.is_anonymous()
+                && msg_profile_id.their_profile_id()
+                    == &self.their_profile_id))
+        } else {
+            Some(Ok(false))
+        }
+    }
+}
+
+/// Allows messages from peers with a verified credential attribute of given value, e.g. one
+/// checked by [`TrustCredentialPolicy`]. Attributes the peer only advertised don't count
+pub struct EntityAttributeAccessControl {
+    key: String,
+    value: String,
+}
+
+#[async_trait]
+impl AccessControl for EntityAttributeAccessControl {
+    async fn msg_is_authorized(&mut self, local_msg: &LocalMessage) -> Result<bool> {
+        if let Ok(local_info) = EntitySecureChannelLocalInfo::find_info(local_msg) {
+            Ok(local_info.their_attribute(&self.key) == Some(self.value.as_str()))
         } else {
             Ok(false)
         }
     }
+
+    fn msg_is_authorized_sync(&mut self, local_msg: &LocalMessage) -> Option<Result<bool>> {
+        if let Ok(local_info) = EntitySecureChannelLocalInfo::find_info(local_msg) {
+            Some(Ok(
+                local_info.their_attribute(&self.key) == Some(self.value.as_str())
+            ))
+        } else {
+            Some(Ok(false))
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{Entity, Identity};
-    use core::sync::atomic::{AtomicU8, Ordering};
-    use ockam_core::compat::sync::Arc;
-    use ockam_core::{route, Any, Route, Routed, Worker};
+    use crate::{Entity, EntityError, Identity};
+    #[cfg(feature = "compression")]
+    use core::sync::atomic::AtomicUsize;
+    use core::sync::atomic::{AtomicU16, AtomicU8, Ordering};
+    use ockam_channel::{CreateResponderChannelMessage, SecureChannel};
+    use ockam_core::compat::{
+        collections::{BTreeMap, HashSet},
+        sync::Arc,
+    };
+    use ockam_core::{
+        route, Address, Any, AsyncTryClone, ConjunctionAccessControl, Decodable, LocalOriginOnly,
+        Route, Routed, TransportMessage, Worker,
+    };
+    use ockam_key_exchange_core::NewKeyExchanger;
+    use ockam_key_exchange_xx::XXNewKeyExchanger;
     use ockam_node::Context;
-    use ockam_vault_sync_core::Vault;
+    use ockam_vault_sync_core::{Vault, VaultSync};
     use std::convert::TryInto;
     use std::time::Duration;
     use tokio::time::sleep;
@@ -89,6 +232,10 @@ mod test {
 
         let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
         assert_eq!(local_info.their_profile_id(), &alice.identifier().await?);
+        assert_eq!(
+            local_info.their_public_key(),
+            Some(&alice.get_root_public_key().await?)
+        );
 
         let return_route = msg.return_route();
         assert_eq!("Hello, Bob!", msg.body());
@@ -99,6 +246,10 @@ mod test {
 
         let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
         assert_eq!(local_info.their_profile_id(), &bob.identifier().await?);
+        assert_eq!(
+            local_info.their_public_key(),
+            Some(&bob.get_root_public_key().await?)
+        );
 
         assert_eq!("Hello, Alice!", msg.body());
 
@@ -106,264 +257,2786 @@ mod test {
     }
 
     #[ockam_macros::test]
-    async fn test_tunneled_secure_channel_works(ctx: &mut Context) -> Result<()> {
+    async fn test_channel_as_named_profile(ctx: &mut Context) -> Result<()> {
         let vault = Vault::create(ctx).await?;
 
         let mut alice = Entity::create(ctx, &vault).await?;
         let mut bob = Entity::create(ctx, &vault).await?;
-
-        let alice_trust_policy = TrustIdentifierPolicy::new(bob.identifier().await?);
-        let bob_trust_policy = TrustIdentifierPolicy::new(alice.identifier().await?);
-
-        bob.create_secure_channel_listener("bob_listener", bob_trust_policy.clone())
+        let alice_work_id = alice
+            .create_named_profile("work", &vault)
+            .await?
+            .identifier()
             .await?;
-
-        let alice_channel = alice
-            .create_secure_channel(route!["bob_listener"], alice_trust_policy.clone())
+        let alice_personal_id = alice
+            .create_named_profile("personal", &vault)
+            .await?
+            .identifier()
             .await?;
+        assert_ne!(alice_work_id, alice_personal_id);
+        // The default profile stays the current one
+        assert_ne!(alice.identifier().await?, alice_work_id);
 
-        bob.create_secure_channel_listener("bob_another_listener", bob_trust_policy)
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
             .await?;
 
-        let alice_another_channel = alice
-            .create_secure_channel(
-                route![alice_channel, "bob_another_listener"],
-                alice_trust_policy,
+        for (profile_name, profile_id) in [("work", alice_work_id), ("personal", alice_personal_id)]
+        {
+            let alice_channel = alice
+                .create_secure_channel_as(profile_name, route!["bob_listener"], TrustEveryonePolicy)
+                .await?;
+
+            ctx.send(
+                route![alice_channel, ctx.address()],
+                "Hello, Bob!".to_string(),
             )
             .await?;
+            let msg = ctx.receive::<String>().await?.take();
 
-        ctx.send(
-            route![alice_another_channel, ctx.address()],
-            "Hello, Bob!".to_string(),
-        )
-        .await?;
-        let msg = ctx.receive::<String>().await?.take();
-        let return_route = msg.return_route();
-        assert_eq!("Hello, Bob!", msg.body());
+            let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+            assert_eq!(local_info.their_profile_id(), &profile_id);
+        }
 
-        ctx.send(return_route, "Hello, Alice!".to_string()).await?;
+        let err = alice
+            .create_secure_channel_as("unknown", route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
         assert_eq!(
-            "Hello, Alice!",
-            ctx.receive::<String>().await?.take().body()
+            err.code(),
+            ockam_core::Error::from(EntityError::ProfileNotFound).code()
         );
+        assert!(alice.create_named_profile("work", &vault).await.is_err());
 
         ctx.stop().await
     }
 
     #[ockam_macros::test]
-    async fn test_double_tunneled_secure_channel_works(ctx: &mut Context) -> Result<()> {
-        let vault = Vault::create(ctx).await?;
+    async fn test_channel_after_reload_from_file(ctx: &mut Context) -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "ockam_entity_{}",
+            ockam_core::compat::rand::random::<u64>()
+        ));
+
+        let alice_vault =
+            Vault::create_with_storage(ctx, ockam_vault::FileVaultStorage::new(&path)).await?;
+        let mut alice = Entity::create(ctx, &alice_vault).await?;
+        alice.rotate_root_secret_key().await?;
+        let alice_id = alice.identifier().await?;
+        let data = alice.export().await?;
 
-        let mut alice = Entity::create(ctx, &vault).await?;
-        let mut bob = Entity::create(ctx, &vault).await?;
+        // Everything is gone but the file and the exported profile, without secrets
+        alice.shutdown().await?;
+        ctx.stop_worker(alice_vault).await?;
 
-        let alice_trust_policy = TrustIdentifierPolicy::new(bob.identifier().await?);
-        let bob_trust_policy = TrustIdentifierPolicy::new(alice.identifier().await?);
+        let alice_vault =
+            Vault::create_with_storage(ctx, ockam_vault::FileVaultStorage::new(&path)).await?;
+        let mut alice = Entity::import(ctx, &alice_vault, &data).await?;
+        assert_eq!(alice.identifier().await?, alice_id);
 
-        bob.create_secure_channel_listener("bob_listener", bob_trust_policy.clone())
+        let bob_vault = Vault::create(ctx).await?;
+        let mut bob = Entity::create(ctx, &bob_vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustIdentifierPolicy::new(alice_id))
             .await?;
-
         let alice_channel = alice
-            .create_secure_channel(route!["bob_listener"], alice_trust_policy.clone())
-            .await?;
-
-        bob.create_secure_channel_listener("bob_another_listener", bob_trust_policy.clone())
-            .await?;
-
-        let alice_another_channel = alice
-            .create_secure_channel(
-                route![alice_channel, "bob_another_listener"],
-                alice_trust_policy.clone(),
-            )
-            .await?;
-
-        bob.create_secure_channel_listener("bob_yet_another_listener", bob_trust_policy)
-            .await?;
-
-        let alice_yet_another_channel = alice
-            .create_secure_channel(
-                route![alice_another_channel, "bob_yet_another_listener"],
-                alice_trust_policy,
-            )
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
             .await?;
 
         ctx.send(
-            route![alice_yet_another_channel, ctx.address()],
+            route![alice_channel, ctx.address()],
             "Hello, Bob!".to_string(),
         )
         .await?;
-        let msg = ctx.receive::<String>().await?.take();
-        let return_route = msg.return_route();
-        assert_eq!("Hello, Bob!", msg.body());
-
-        ctx.send(return_route, "Hello, Alice!".to_string()).await?;
-        assert_eq!(
-            "Hello, Alice!",
-            ctx.receive::<String>().await?.take().body()
-        );
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
 
+        std::fs::remove_file(&path).unwrap();
         ctx.stop().await
     }
 
     #[ockam_macros::test]
-    async fn test_many_times_tunneled_secure_channel_works(ctx: &mut Context) -> Result<()> {
-        let vault = Vault::create(ctx).await?;
+    async fn test_channel_after_import(ctx: &mut Context) -> Result<()> {
+        let alice_vault = Vault::create(ctx).await.expect("failed to create vault");
+        let bob_vault = Vault::create(ctx).await.expect("failed to create vault");
 
-        let mut alice = Entity::create(ctx, &vault).await?;
-        let mut bob = Entity::create(ctx, &vault).await?;
+        let mut alice = Entity::create(ctx, &alice_vault).await?;
+        let mut bob = Entity::create(ctx, &bob_vault).await?;
+
+        alice.rotate_root_secret_key().await?;
+        let alice_id = alice.identifier().await?;
+        let alice_public_key = alice.get_root_public_key().await?;
+
+        // In-memory vault doesn't survive a restart, so secrets are exported as well
+        let data = alice.export_with_secrets().await?;
+        let new_alice_vault = Vault::create(ctx).await.expect("failed to create vault");
+        let mut alice = Entity::import(ctx, &new_alice_vault, &data).await?;
+        assert_eq!(alice.identifier().await?, alice_id);
+        assert_eq!(alice.get_root_public_key().await?, alice_public_key);
+
+        // Secrets are already in the vault
+        let data = alice.export().await?;
+        let reloaded_alice = Entity::import(ctx, &new_alice_vault, &data).await?;
+        assert_eq!(reloaded_alice.identifier().await?, alice_id);
+
+        // Secrets are neither in the data nor in the vault
+        let empty_vault = Vault::create(ctx).await.expect("failed to create vault");
+        assert!(Entity::import(ctx, &empty_vault, &data).await.is_err());
 
         let alice_trust_policy = TrustIdentifierPolicy::new(bob.identifier().await?);
-        let bob_trust_policy = TrustIdentifierPolicy::new(alice.identifier().await?);
+        let bob_trust_policy = TrustIdentifierPolicy::new(alice_id.clone());
 
-        let n = rand::random::<u8>() % 5 + 4;
-        let mut channels = vec![];
-        for i in 0..n {
-            bob.create_secure_channel_listener(i.to_string(), bob_trust_policy.clone())
-                .await?;
-            let channel_route: Route;
-            if i > 0 {
-                channel_route = route![channels.pop().unwrap(), i.to_string()];
-            } else {
-                channel_route = route![i.to_string()];
-            }
-            let alice_channel = alice
-                .create_secure_channel(channel_route, alice_trust_policy.clone())
-                .await?;
-            channels.push(alice_channel);
-        }
+        bob.create_secure_channel_listener("bob_listener", bob_trust_policy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], alice_trust_policy)
+            .await?;
 
         ctx.send(
-            route![channels.pop().unwrap(), ctx.address()],
+            route![alice_channel, ctx.address()],
             "Hello, Bob!".to_string(),
         )
         .await?;
         let msg = ctx.receive::<String>().await?.take();
-        let return_route = msg.return_route();
-        assert_eq!("Hello, Bob!", msg.body());
 
-        ctx.send(return_route, "Hello, Alice!".to_string()).await?;
-        assert_eq!(
-            "Hello, Alice!",
-            ctx.receive::<String>().await?.take().body()
-        );
+        let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert_eq!(local_info.their_profile_id(), &alice_id);
+        assert_eq!("Hello, Bob!", msg.body());
 
         ctx.stop().await
     }
 
-    struct Receiver {
-        received_count: Arc<AtomicU8>,
-    }
-
-    #[ockam_core::async_trait]
-    impl Worker for Receiver {
-        type Message = Any;
-        type Context = Context;
-
-        async fn handle_message(
-            &mut self,
-            _context: &mut Self::Context,
-            _msg: Routed<Self::Message>,
-        ) -> Result<()> {
-            self.received_count.fetch_add(1, Ordering::Relaxed);
-
-            Ok(())
-        }
-    }
-
-    #[allow(non_snake_case)]
     #[ockam_macros::test]
-    async fn access_control__known_participant__should_pass_messages(
-        ctx: &mut Context,
-    ) -> Result<()> {
-        let received_count = Arc::new(AtomicU8::new(0));
-        let receiver = Receiver {
-            received_count: received_count.clone(),
-        };
+    async fn test_channel_after_key_rotation(ctx: &mut Context) -> Result<()> {
+        let alice_vault = Vault::create(ctx).await.expect("failed to create vault");
+        let bob_vault = Vault::create(ctx).await.expect("failed to create vault");
 
-        let vault = Vault::create(&ctx).await?;
+        let mut alice = Entity::create(ctx, &alice_vault).await?;
+        let mut bob = Entity::create(ctx, &bob_vault).await?;
 
-        let mut alice = Entity::create(&ctx, &vault).await?;
-        let mut bob = Entity::create(&ctx, &vault).await?;
+        let alice_trust_policy = TrustIdentifierPolicy::new(bob.identifier().await?);
+        let bob_trust_policy = TrustIdentifierPolicy::new(alice.identifier().await?);
 
-        let access_control = EntityAccessControlBuilder::new_with_id(alice.identifier().await?);
-        ctx.start_worker_with_access_control("receiver", receiver, access_control)
+        bob.create_secure_channel_listener("bob_listener", bob_trust_policy)
             .await?;
 
-        bob.create_secure_channel_listener("listener", TrustEveryonePolicy)
+        let old_channel = alice
+            .create_secure_channel(route!["bob_listener"], alice_trust_policy.clone())
             .await?;
 
-        let alice_channel = alice
-            .create_secure_channel("listener", TrustEveryonePolicy)
-            .await?;
+        alice.rotate_profile_key().await?;
+        let new_public_key = alice.get_root_public_key().await?;
 
-        ctx.send(route![alice_channel, "receiver"], "Hello, Bob!".to_string())
+        // Existing channel isn't affected by the rotation
+        ctx.send(route![old_channel, ctx.address()], "Old".to_string())
             .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert_eq!("Old", msg.body());
 
-        sleep(Duration::from_secs(1)).await;
+        // Bob knows Alice's old key, the new one is accepted after verifying the change
+        let new_channel = alice
+            .create_secure_channel(route!["bob_listener"], alice_trust_policy)
+            .await?;
+        ctx.send(route![new_channel, ctx.address()], "New".to_string())
+            .await?;
+        let msg = ctx.receive::<String>().await?.take();
 
-        assert_eq!(received_count.load(Ordering::Relaxed), 1);
+        let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert_eq!(local_info.their_profile_id(), &alice.identifier().await?);
+        assert_eq!(local_info.their_public_key(), Some(&new_public_key));
+        assert_eq!("New", msg.body());
 
         ctx.stop().await
     }
 
-    #[allow(non_snake_case)]
     #[ockam_macros::test]
-    async fn access_control__unknown_participant__should_not_pass_messages(
-        ctx: &mut Context,
-    ) -> Result<()> {
-        let received_count = Arc::new(AtomicU8::new(0));
-        let receiver = Receiver {
-            received_count: received_count.clone(),
-        };
+    async fn test_channel_rekey(ctx: &mut Context) -> Result<()> {
+        let alice_vault = Vault::create(ctx).await.expect("failed to create vault");
+        let bob_vault = Vault::create(ctx).await.expect("failed to create vault");
 
-        let vault = Vault::create(&ctx).await?;
+        let mut alice = Entity::create(ctx, &alice_vault).await?;
+        let mut bob = Entity::create(ctx, &bob_vault).await?;
 
-        let mut alice = Entity::create(&ctx, &vault).await?;
-        let mut bob = Entity::create(&ctx, &vault).await?;
+        let alice_trust_policy = TrustIdentifierPolicy::new(bob.identifier().await?);
+        let bob_trust_policy = TrustIdentifierPolicy::new(alice.identifier().await?);
 
-        let access_control = EntityAccessControlBuilder::new_with_id(bob.identifier().await?);
-        ctx.start_worker_with_access_control("receiver", receiver, access_control)
+        bob.create_secure_channel_listener("bob_listener", bob_trust_policy)
             .await?;
 
-        bob.create_secure_channel_listener("listener", TrustEveryonePolicy)
-            .await?;
+        let epoch = Arc::new(AtomicU16::new(0));
+        ctx.start_worker(
+            "epoch_link",
+            EpochLink {
+                epoch: epoch.clone(),
+            },
+        )
+        .await?;
 
         let alice_channel = alice
-            .create_secure_channel("listener", TrustEveryonePolicy)
+            .create_secure_channel_with_options(
+                route!["epoch_link", "bob_listener"],
+                alice_trust_policy,
+                SecureChannelOptions::new().with_rekey_after_messages(2),
+            )
             .await?;
+        // Key exchange messages passed the link as well, their first bytes aren't an epoch
+        epoch.store(0, Ordering::Relaxed);
 
-        ctx.send(route![alice_channel, "receiver"], "Hello, Bob!".to_string())
+        for i in 0..5 {
+            ctx.send(
+                route![alice_channel.clone(), ctx.address()],
+                format!("Hello, Bob! {}", i),
+            )
             .await?;
+        }
 
-        sleep(Duration::from_secs(1)).await;
+        for i in 0..5 {
+            let msg = ctx.receive::<String>().await?.take();
+            let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+            assert_eq!(local_info.their_profile_id(), &alice.identifier().await?);
+            assert_eq!(format!("Hello, Bob! {}", i), msg.body());
+        }
 
-        assert_eq!(received_count.load(Ordering::Relaxed), 0);
+        // Bob answers the rekey requests of Alice as he gets them, so the key only goes on
+        // changing while messages go back and forth
+        let mut i = 5;
+        while epoch.load(Ordering::Relaxed) < 3 && i < 100 {
+            ctx.send(
+                route![alice_channel.clone(), ctx.address()],
+                format!("Hello, Bob! {}", i),
+            )
+            .await?;
+            let msg = ctx.receive::<String>().await?.take();
+            assert_eq!(format!("Hello, Bob! {}", i), msg.body());
+            i += 1;
+        }
+        assert!(epoch.load(Ordering::Relaxed) >= 3);
 
         ctx.stop().await
     }
 
-    #[allow(non_snake_case)]
+    #[cfg(feature = "x3dh")]
     #[ockam_macros::test]
-    async fn access_control__no_secure_channel__should_not_pass_messages(
-        ctx: &mut Context,
-    ) -> Result<()> {
-        let received_count = Arc::new(AtomicU8::new(0));
-        let receiver = Receiver {
-            received_count: received_count.clone(),
-        };
+    async fn test_channel_x3dh(ctx: &mut Context) -> Result<()> {
+        let alice_vault = Vault::create(ctx).await?;
+        let bob_vault = Vault::create(ctx).await?;
 
-        let access_control = EntityAccessControlBuilder::new_with_id(
-            "P79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b6".try_into()?,
+        let mut alice = Entity::create(ctx, &alice_vault).await?;
+        let mut bob = Entity::create(ctx, &bob_vault).await?;
+
+        let alice_trust_policy = TrustIdentifierPolicy::new(bob.identifier().await?);
+        let bob_trust_policy = TrustIdentifierPolicy::new(alice.identifier().await?);
+
+        bob.create_secure_channel_listener("bob_listener", bob_trust_policy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                alice_trust_policy,
+                SecureChannelOptions::new().with_key_exchange(KeyExchangePattern::X3dh),
+            )
+            .await?;
+
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert_eq!(local_info.their_profile_id(), &alice.identifier().await?);
+        let inner_local_info =
+            ockam_channel::SecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert_eq!(inner_local_info.key_exchange(), "X3DH");
+        assert_eq!("Hello, Bob!", msg.body());
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_batching(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new()
+                    .with_max_batch(4)
+                    .with_max_batch_delay(Duration::from_millis(100)),
+            )
+            .await?;
+
+        let other_sender = ctx.new_context(Address::random(0)).await?;
+
+        // Message from another sender in the middle of a batch has a different return route
+        for i in 0..11 {
+            let sender = if i == 5 { &other_sender } else { &*ctx };
+            sender
+                .send(
+                    route![alice_channel.clone(), ctx.address()],
+                    format!("Hello, Bob! {}", i),
+                )
+                .await?;
+        }
+
+        // The last partial batch is sent by the timer
+        for i in 0..11 {
+            let msg = ctx.receive::<String>().await?.take();
+            let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+            assert_eq!(local_info.their_profile_id(), &alice.identifier().await?);
+
+            let expected_sender = if i == 5 {
+                other_sender.address()
+            } else {
+                ctx.address()
+            };
+            assert_eq!(msg.return_route().recipient(), expected_sender);
+            assert_eq!(format!("Hello, Bob! {}", i), msg.body());
+        }
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_events(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let mut events_ctx = ctx.new_context(Address::random(0)).await?;
+
+        alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_events_address(events_ctx.address()),
+            )
+            .await?;
+
+        let event = events_ctx.receive::<SecureChannelEvent>().await?.take();
+        assert_eq!(event.body(), SecureChannelEvent::HandshakeStarted);
+        let event = events_ctx.receive::<SecureChannelEvent>().await?.take();
+        if let SecureChannelEvent::HandshakeCompleted { peer_id, .. } = event.body() {
+            assert_eq!(peer_id, bob.identifier().await?);
+        } else {
+            panic!("Expected HandshakeCompleted");
+        }
+
+        let res = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustIdentifierPolicy::new(ProfileIdentifier::random()),
+                SecureChannelOptions::new().with_events_address(events_ctx.address()),
+            )
+            .await;
+        assert!(res.is_err());
+
+        let event = events_ctx.receive::<SecureChannelEvent>().await?.take();
+        assert_eq!(event.body(), SecureChannelEvent::HandshakeStarted);
+        let event = events_ctx.receive::<SecureChannelEvent>().await?.take();
+        assert!(matches!(
+            event.body(),
+            SecureChannelEvent::HandshakeFailed { .. }
+        ));
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_stop(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        ctx.send(
+            route![alice_channel.clone(), ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let bob_channel = msg.return_route().next()?.clone();
+
+        alice.stop_secure_channel(&alice_channel).await?;
+
+        sleep(Duration::from_secs(1)).await;
+
+        let workers = ctx.list_workers().await?;
+        assert!(!workers.contains(&alice_channel));
+        assert!(!workers.contains(&bob_channel));
+
+        assert!(ctx
+            .send(
+                route![alice_channel, ctx.address()],
+                "Hello again, Bob!".to_string()
+            )
+            .await
+            .is_err());
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_list(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let alice_id = alice.identifier().await?;
+        let bob_id = bob.identifier().await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let first_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        let second_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        let mut bob_channels = Vec::new();
+        for channel in [&first_channel, &second_channel] {
+            ctx.send(
+                route![channel.clone(), ctx.address()],
+                "Hello, Bob!".to_string(),
+            )
+            .await?;
+            let msg = ctx.receive::<String>().await?.take();
+            bob_channels.push(msg.return_route().next()?.clone());
+        }
+
+        let channels = alice.secure_channels().await?;
+        assert_eq!(channels.len(), 2);
+        for channel in [&first_channel, &second_channel] {
+            let handle = alice.secure_channel_info(channel).await?.unwrap();
+            assert_eq!(handle.their_profile_id(), &bob_id);
+            assert!(handle.is_initiator());
+            assert!(channels.contains(&handle));
+        }
+
+        let channels = bob.secure_channels().await?;
+        assert_eq!(channels.len(), 2);
+        for channel in &bob_channels {
+            let handle = bob.secure_channel_info(channel).await?.unwrap();
+            assert_eq!(handle.their_profile_id(), &alice_id);
+            assert!(!handle.is_initiator());
+        }
+
+        alice.stop_secure_channel(&first_channel).await?;
+        sleep(Duration::from_secs(1)).await;
+
+        let channels = alice.secure_channels().await?;
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].address(), &second_channel);
+        assert!(alice.secure_channel_info(&first_channel).await?.is_none());
+
+        let channels = bob.secure_channels().await?;
+        assert_eq!(channels.len(), 1);
+        assert_eq!(channels[0].address(), &bob_channels[1]);
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_cipher_suite(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        ctx.send(
+            route![alice_channel.clone(), ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let bob_channel = msg.return_route().next()?.clone();
+
+        let expected = SecureChannelCipherSuite::new(KeyExchangePattern::default());
+        assert_eq!(expected.key_exchange(), KeyExchangePattern::Xx);
+        assert_eq!(expected.dh(), DhCurve::X25519);
+        assert_eq!(expected.aead(), AeadAlgorithm::Aes256Gcm);
+        assert_eq!(expected.hash(), HashAlgorithm::Sha256);
+
+        assert_eq!(
+            alice.secure_channel_cipher_suite(&alice_channel).await?,
+            Some(expected)
+        );
+        assert_eq!(
+            bob.secure_channel_cipher_suite(&bob_channel).await?,
+            Some(expected)
+        );
+        assert_eq!(
+            alice
+                .secure_channel_cipher_suite(&Address::random(0))
+                .await?,
+            None
+        );
+
+        #[cfg(feature = "x3dh")]
+        {
+            let x3dh_channel = alice
+                .create_secure_channel_with_options(
+                    route!["bob_listener"],
+                    TrustEveryonePolicy,
+                    SecureChannelOptions::new().with_key_exchange(KeyExchangePattern::X3dh),
+                )
+                .await?;
+            let suite = alice
+                .secure_channel_cipher_suite(&x3dh_channel)
+                .await?
+                .unwrap();
+            assert_eq!(suite.key_exchange(), KeyExchangePattern::X3dh);
+        }
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_protocol_version(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        // A newer Alice falls back to the version Bob speaks
+        let alice_channel = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new()
+                    .with_protocol_version(SECURE_CHANNEL_PROTOCOL_VERSION + 1),
+            )
+            .await?;
+        ctx.send(
+            route![alice_channel.clone(), ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let bob_channel = msg.return_route().next()?.clone();
+
+        let alice_handle = alice.secure_channel_info(&alice_channel).await?.unwrap();
+        assert_eq!(
+            alice_handle.protocol_version(),
+            SECURE_CHANNEL_PROTOCOL_VERSION
+        );
+        let bob_handle = bob.secure_channel_info(&bob_channel).await?.unwrap();
+        assert_eq!(
+            bob_handle.protocol_version(),
+            SECURE_CHANNEL_PROTOCOL_VERSION
+        );
+
+        let too_old =
+            ockam_core::Error::from(EntityError::SecureChannelProtocolVersionTooOld).code();
+
+        // Bob is below the minimum of Alice
+        let err = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new()
+                    .with_protocol_version(SECURE_CHANNEL_PROTOCOL_VERSION + 1)
+                    .with_min_protocol_version(SECURE_CHANNEL_PROTOCOL_VERSION + 1),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), too_old);
+
+        // Alice is older than anything Bob still speaks, and Bob rejects her
+        let err = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new()
+                    .with_protocol_version(MIN_SECURE_CHANNEL_PROTOCOL_VERSION - 1)
+                    .with_min_protocol_version(0),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), too_old);
+
+        // Alice is below the minimum of the listener
+        bob.create_secure_channel_listener_with_options(
+            "bob_newer_listener",
+            TrustEveryonePolicy,
+            SecureChannelListenerOptions::new()
+                .with_min_protocol_version(SECURE_CHANNEL_PROTOCOL_VERSION + 1),
+        )
+        .await?;
+        let err = alice
+            .create_secure_channel(route!["bob_newer_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), too_old);
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_listener_at_derived_address(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let listener = bob
+            .create_secure_channel_listener_at_derived_address("listener", TrustEveryonePolicy)
+            .await?;
+
+        // Alice only needs to know who Bob is
+        let bob_id = bob.identifier().await?;
+        assert_eq!(listener, bob_id.derive_address("listener"));
+        let alice_channel = alice
+            .create_secure_channel(
+                route![bob_id.derive_address("listener")],
+                TrustEveryonePolicy,
+            )
+            .await?;
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+
+        // Another label is another listener
+        let other = bob
+            .create_secure_channel_listener_at_derived_address("other", TrustEveryonePolicy)
+            .await?;
+        assert_ne!(other, listener);
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_listener_max_channels(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let mut eve = Entity::create(ctx, &vault).await?;
+
+        let bob_trust_policy = TrustIdentifierPolicy::new(alice.identifier().await?);
+        bob.create_secure_channel_listener_with_max_channels("bob_listener", bob_trust_policy, 2)
+            .await?;
+
+        // Bob rejects Eve after the key exchange, which releases the slot
+        let _ = eve
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await;
+        sleep(Duration::from_millis(250)).await;
+
+        let first_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        let _second_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        let err = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .expect("listener should be at capacity");
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelListenerAtCapacity).code()
+        );
+
+        alice.stop_secure_channel(&first_channel).await?;
+        sleep(Duration::from_secs(1)).await;
+
+        let third_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        ctx.send(
+            route![third_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert_eq!("Hello, Bob!", msg.body());
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_listener_max_handshake_message_size(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let mut eve = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener_with_max_handshake_message_size(
+            "bob_listener",
+            TrustEveryonePolicy,
+            2048,
+        )
+        .await?;
+        let workers_before: HashSet<Address> = ctx.list_workers().await?.into_iter().collect();
+
+        // Dropped before a responder is started
+        ctx.send(
+            route!["bob_listener"],
+            CreateResponderChannelMessage::new(vec![0; 4096], None),
+        )
+        .await?;
+        sleep(Duration::from_millis(250)).await;
+        let workers_after: HashSet<Address> = ctx.list_workers().await?.into_iter().collect();
+        assert_eq!(workers_after, workers_before);
+
+        // Eve's profile doesn't fit, which she's told once the key exchange completed
+        eve.set_attribute("padding".to_string(), "x".repeat(4096))
+            .await?;
+        let err = eve
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelHandshakeMessageTooLarge).code()
+        );
+
+        let channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        ctx.send(route![channel, ctx.address()], "Hello, Bob!".to_string())
+            .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert_eq!("Hello, Bob!", msg.body());
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_untagged_key_exchange(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener_with_options(
+            "bob_listener",
+            TrustEveryonePolicy,
+            SecureChannelListenerOptions::new().with_untagged_key_exchange(),
+        )
+        .await?;
+
+        let alice_channel = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_untagged_key_exchange(),
+            )
+            .await?;
+
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert_eq!(local_info.their_profile_id(), &alice.identifier().await?);
+        assert_eq!("Hello, Bob!", msg.body());
+
+        // Without a tag there's no way to name a service
+        let err = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new()
+                    .with_untagged_key_exchange()
+                    .with_service("printer"),
+            )
+            .await
+            .err()
+            .expect("service can't be sent untagged");
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::KeyExchangePatternMismatch).code()
+        );
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_listener_with_options(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let options = SecureChannelListenerOptions::new()
+            .with_max_channels(1)
+            .with_max_handshake_message_size(2 * DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE);
+        assert_eq!(options.max_channels(), Some(1));
+        assert!(!options.strict_trust() && !options.allow_anonymous());
+        bob.create_secure_channel_listener_with_options(
+            "bob_listener",
+            TrustIdentifierPolicy::new(alice.identifier().await?),
+            options,
+        )
+        .await?;
+
+        let channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        let err = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .expect("listener should be at capacity");
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelListenerAtCapacity).code()
+        );
+
+        alice.stop_secure_channel(&channel).await?;
+        sleep(Duration::from_secs(1)).await;
+
+        let channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        ctx.send(route![channel, ctx.address()], "Hello, Bob!".to_string())
+            .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert_eq!("Hello, Bob!", msg.body());
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_listener_handshake_timeout(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let options = SecureChannelListenerOptions::new()
+            .with_max_channels(1)
+            .with_handshake_timeout(Duration::from_millis(500));
+        bob.create_secure_channel_listener_with_options(
+            "bob_listener",
+            TrustEveryonePolicy,
+            options,
+        )
+        .await?;
+
+        // Only the first key exchange message reaches Bob, whose responder then waits for more
+        ctx.start_worker("stalling_link", StallingLink { remaining: 1 })
+            .await?;
+        let err = alice
+            .create_secure_channel_with_options(
+                route!["stalling_link", "bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_timeout(Duration::from_millis(100)),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelTimeout).code()
+        );
+
+        let err = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .expect("stalled handshake should hold the slot");
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelListenerAtCapacity).code()
+        );
+
+        // The stalled responder gave up
+        sleep(Duration::from_millis(750)).await;
+        let channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        ctx.send(route![channel, ctx.address()], "Hello, Bob!".to_string())
+            .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert_eq!("Hello, Bob!", msg.body());
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_strict_trust(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let alice_id = alice.identifier().await?;
+        let bob_id = bob.identifier().await?;
+
+        bob.create_secure_channel_listener_with_strict_trust(
+            "strict_listener",
+            TrustIdentifierPolicy::new(alice_id.clone()),
+        )
+        .await?;
+        bob.create_secure_channel_listener("listener", TrustEveryonePolicy)
+            .await?;
+
+        let not_enforced =
+            ockam_core::Error::from(EntityError::SecureChannelTrustNotEnforced).code();
+
+        // Alice didn't opt in
+        let err = alice
+            .create_secure_channel("strict_listener", TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), not_enforced);
+
+        // Bob didn't opt in
+        let err = alice
+            .create_secure_channel_with_options(
+                "listener",
+                TrustIdentifierPolicy::new(bob_id.clone()),
+                SecureChannelOptions::new().with_strict_trust(),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), not_enforced);
+
+        let alice_channel = alice
+            .create_secure_channel_with_options(
+                "strict_listener",
+                TrustIdentifierPolicy::new(bob_id),
+                SecureChannelOptions::new().with_strict_trust(),
+            )
+            .await?;
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_public_key_policy(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let mut eve = Entity::create(ctx, &vault).await?;
+
+        let alice_trust_policy = TrustPublicKeyPolicy::new(bob.get_root_public_key().await?);
+        let bob_trust_policy = TrustPublicKeyPolicy::new(alice.get_root_public_key().await?);
+
+        bob.create_secure_channel_listener("bob_listener", bob_trust_policy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], alice_trust_policy)
+            .await?;
+
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert_eq!("Hello, Bob!", msg.body());
+
+        let eve_channel = eve
+            .create_secure_channel_with_timeout(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                Duration::from_secs(2),
+            )
+            .await;
+        assert!(eve_channel.is_err());
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_concurrent_channel_creation(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let mut tasks = Vec::new();
+        for _ in 0..50 {
+            let mut alice = alice.async_try_clone().await?;
+            tasks.push(tokio::spawn(async move {
+                alice
+                    .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+                    .await
+            }));
+        }
+
+        let mut channels = Vec::new();
+        for task in tasks {
+            channels.push(task.await.expect("task failed")?);
+        }
+
+        let unique: HashSet<_> = channels.iter().collect();
+        assert_eq!(unique.len(), 50);
+
+        for (i, channel) in channels.into_iter().enumerate() {
+            ctx.send(route![channel, ctx.address()], i.to_string())
+                .await?;
+            let msg = ctx.receive::<String>().await?.take();
+            assert_eq!(i.to_string(), msg.body());
+        }
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_tunneled_secure_channel_works(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let alice_trust_policy = TrustIdentifierPolicy::new(bob.identifier().await?);
+        let bob_trust_policy = TrustIdentifierPolicy::new(alice.identifier().await?);
+
+        bob.create_secure_channel_listener("bob_listener", bob_trust_policy.clone())
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], alice_trust_policy.clone())
+            .await?;
+
+        bob.create_secure_channel_listener("bob_another_listener", bob_trust_policy)
+            .await?;
+
+        let alice_another_channel = alice
+            .create_secure_channel(
+                route![alice_channel, "bob_another_listener"],
+                alice_trust_policy,
+            )
+            .await?;
+
+        ctx.send(
+            route![alice_another_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let return_route = msg.return_route();
+        assert_eq!("Hello, Bob!", msg.body());
+
+        ctx.send(return_route, "Hello, Alice!".to_string()).await?;
+        assert_eq!(
+            "Hello, Alice!",
+            ctx.receive::<String>().await?.take().body()
+        );
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_tunneled_secure_channel_ordering(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        for listener in ["first", "second", "third"] {
+            bob.create_secure_channel_listener(listener, TrustEveryonePolicy)
+                .await?;
+        }
+
+        // The middle hop batches, so that batched and unbatched frames mix
+        let first = alice
+            .create_secure_channel(route!["first"], TrustEveryonePolicy)
+            .await?;
+        let second = alice
+            .create_secure_channel_with_options(
+                route![first, "second"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new()
+                    .with_max_batch(8)
+                    .with_max_batch_delay(Duration::from_millis(5)),
+            )
+            .await?;
+        let third = alice
+            .create_secure_channel(route![second, "third"], TrustEveryonePolicy)
+            .await?;
+
+        // Every 97th message is too big to be batched
+        let message = |i: usize| {
+            if i % 97 == 0 {
+                format!("{} {}", i, "x".repeat(20 * 1024))
+            } else {
+                i.to_string()
+            }
+        };
+        let sequence = |msg: &str| msg.split(' ').next().unwrap().parse::<usize>().unwrap();
+
+        for i in 0..1000 {
+            ctx.send(route![third.clone(), ctx.address()], message(i))
+                .await?;
+        }
+        for i in 0..1000 {
+            let msg = ctx.receive::<String>().await?.take();
+            assert_eq!(sequence(msg.body().as_str()), i);
+        }
+
+        // Concurrent senders each see their own order kept
+        let mut tasks = Vec::new();
+        for sender in 0..4 {
+            let sender_ctx = ctx.new_context(Address::random(0)).await?;
+            let route = route![third.clone(), ctx.address()];
+            tasks.push(tokio::spawn(async move {
+                for i in 0..250 {
+                    sender_ctx
+                        .send(route.clone(), format!("{} {}", i, sender))
+                        .await?;
+                }
+                sender_ctx.stop_worker(sender_ctx.address()).await
+            }));
+        }
+
+        let mut next = [0; 4];
+        for _ in 0..1000 {
+            let msg = ctx.receive::<String>().await?.take();
+            let sender: usize = msg.body().split(' ').nth(1).unwrap().parse().unwrap();
+            assert_eq!(sequence(msg.body().as_str()), next[sender]);
+            next[sender] += 1;
+        }
+        assert_eq!(next, [250; 4]);
+        for task in tasks {
+            task.await.expect("task failed")?;
+        }
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_tunneled_secure_channel_inherited_trust(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let alice_id = alice.identifier().await?;
+        let bob_id = bob.identifier().await?;
+
+        bob.create_secure_channel_listener(
+            "bob_listener",
+            TrustIdentifierPolicy::new(alice_id.clone()),
+        )
+        .await?;
+        bob.create_secure_channel_listener(
+            "bob_another_listener",
+            TrustIdentifierPolicy::new(alice_id.clone()),
+        )
+        .await?;
+
+        let count = Arc::new(AtomicU8::new(0));
+        ctx.start_worker(
+            "link",
+            CountingLink {
+                count: count.clone(),
+            },
+        )
+        .await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["link", "bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        // Counts messages passing the outer channel until one sent over the inner channel arrives
+        let mut counts = Vec::new();
+        for options in [
+            SecureChannelOptions::new(),
+            SecureChannelOptions::new().with_inherited_trust(),
+        ] {
+            count.store(0, Ordering::Relaxed);
+            let inner_channel = alice
+                .create_secure_channel_with_options(
+                    route![alice_channel.clone(), "bob_another_listener"],
+                    TrustIdentifierPolicy::new(bob_id.clone()),
+                    options,
+                )
+                .await?;
+
+            ctx.send(
+                route![inner_channel, ctx.address()],
+                "Hello, Bob!".to_string(),
+            )
+            .await?;
+            assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+            counts.push(count.load(Ordering::Relaxed));
+        }
+        // The confirmation is skipped
+        assert_eq!(counts[1] + 1, counts[0]);
+
+        // Both channels are bound to the same identity as the outer one
+        let bob_channels = bob.secure_channels().await?;
+        assert_eq!(bob_channels.len(), 3);
+        assert!(bob_channels
+            .iter()
+            .all(|c| c.their_profile_id() == &alice_id));
+
+        // The route has to start with a channel of ours
+        let err = alice
+            .create_secure_channel_with_options(
+                route!["link", "bob_another_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_inherited_trust(),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::InheritedTrustUnavailable).code()
+        );
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_inherited_trust_listener_rejects(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let bob_id = bob.identifier().await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+        // Trusts nobody, even peers of other channels
+        bob.create_secure_channel_listener(
+            "bob_another_listener",
+            TrustIdentifierPolicy::new(bob_id.clone()),
+        )
+        .await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        let err = alice
+            .create_secure_channel_with_options(
+                route![alice_channel, "bob_another_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_inherited_trust(),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelTrustCheckFailed).code()
+        );
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_double_tunneled_secure_channel_works(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let alice_trust_policy = TrustIdentifierPolicy::new(bob.identifier().await?);
+        let bob_trust_policy = TrustIdentifierPolicy::new(alice.identifier().await?);
+
+        bob.create_secure_channel_listener("bob_listener", bob_trust_policy.clone())
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], alice_trust_policy.clone())
+            .await?;
+
+        bob.create_secure_channel_listener("bob_another_listener", bob_trust_policy.clone())
+            .await?;
+
+        let alice_another_channel = alice
+            .create_secure_channel(
+                route![alice_channel, "bob_another_listener"],
+                alice_trust_policy.clone(),
+            )
+            .await?;
+
+        bob.create_secure_channel_listener("bob_yet_another_listener", bob_trust_policy)
+            .await?;
+
+        let alice_yet_another_channel = alice
+            .create_secure_channel(
+                route![alice_another_channel, "bob_yet_another_listener"],
+                alice_trust_policy,
+            )
+            .await?;
+
+        ctx.send(
+            route![alice_yet_another_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let return_route = msg.return_route();
+        assert_eq!("Hello, Bob!", msg.body());
+
+        ctx.send(return_route, "Hello, Alice!".to_string()).await?;
+        assert_eq!(
+            "Hello, Alice!",
+            ctx.receive::<String>().await?.take().body()
+        );
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_many_times_tunneled_secure_channel_works(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let alice_trust_policy = TrustIdentifierPolicy::new(bob.identifier().await?);
+        let bob_trust_policy = TrustIdentifierPolicy::new(alice.identifier().await?);
+
+        let n = rand::random::<u8>() % 5 + 4;
+        let mut channels = vec![];
+        for i in 0..n {
+            bob.create_secure_channel_listener(i.to_string(), bob_trust_policy.clone())
+                .await?;
+            let channel_route: Route;
+            if i > 0 {
+                channel_route = route![channels.pop().unwrap(), i.to_string()];
+            } else {
+                channel_route = route![i.to_string()];
+            }
+            let alice_channel = alice
+                .create_secure_channel(channel_route, alice_trust_policy.clone())
+                .await?;
+            channels.push(alice_channel);
+        }
+
+        ctx.send(
+            route![channels.pop().unwrap(), ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let return_route = msg.return_route();
+        assert_eq!("Hello, Bob!", msg.body());
+
+        ctx.send(return_route, "Hello, Alice!".to_string()).await?;
+        assert_eq!(
+            "Hello, Alice!",
+            ctx.receive::<String>().await?.take().body()
+        );
+
+        ctx.stop().await
+    }
+
+    struct Receiver {
+        received_count: Arc<AtomicU8>,
+    }
+
+    #[ockam_core::async_trait]
+    impl Worker for Receiver {
+        type Message = Any;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            _context: &mut Self::Context,
+            _msg: Routed<Self::Message>,
+        ) -> Result<()> {
+            self.received_count.fetch_add(1, Ordering::Relaxed);
+
+            Ok(())
+        }
+    }
+
+    /// Passes messages to the next hop, like a transport connection would
+    struct Link;
+
+    #[ockam_core::async_trait]
+    impl Worker for Link {
+        type Message = Any;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            ctx: &mut Self::Context,
+            msg: Routed<Self::Message>,
+        ) -> Result<()> {
+            let mut local_msg = msg.into_local_message();
+            let transport_msg = local_msg.transport_mut();
+            transport_msg.onward_route.step()?;
+            transport_msg.return_route.modify().prepend(ctx.address());
+
+            ctx.forward(local_msg).await
+        }
+    }
+
+    /// [`Link`] that counts the messages passing it
+    struct CountingLink {
+        count: Arc<AtomicU8>,
+    }
+
+    #[ockam_core::async_trait]
+    impl Worker for CountingLink {
+        type Message = Any;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            ctx: &mut Self::Context,
+            msg: Routed<Self::Message>,
+        ) -> Result<()> {
+            self.count.fetch_add(1, Ordering::Relaxed);
+            Link.handle_message(ctx, msg).await
+        }
+    }
+
+    /// [`Link`] that keeps the highest key epoch of the secure channel frames passing it
+    struct EpochLink {
+        epoch: Arc<AtomicU16>,
+    }
+
+    #[ockam_core::async_trait]
+    impl Worker for EpochLink {
+        type Message = Any;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            ctx: &mut Self::Context,
+            msg: Routed<Self::Message>,
+        ) -> Result<()> {
+            if let Ok(frame) = Vec::<u8>::decode(msg.payload()) {
+                if frame.len() >= 2 {
+                    let epoch = u16::from_be_bytes([frame[0], frame[1]]);
+                    self.epoch.fetch_max(epoch, Ordering::Relaxed);
+                }
+            }
+
+            Link.handle_message(ctx, msg).await
+        }
+    }
+
+    /// [`Link`] that adds up the payload sizes of the messages passing it
+    #[cfg(feature = "compression")]
+    struct MeteringLink {
+        bytes: Arc<AtomicUsize>,
+    }
+
+    #[cfg(feature = "compression")]
+    #[ockam_core::async_trait]
+    impl Worker for MeteringLink {
+        type Message = Any;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            ctx: &mut Self::Context,
+            msg: Routed<Self::Message>,
+        ) -> Result<()> {
+            self.bytes.fetch_add(msg.payload().len(), Ordering::Relaxed);
+            Link.handle_message(ctx, msg).await
+        }
+    }
+
+    /// [`Link`] that drops every message after the first few, like a peer that stopped answering
+    struct StallingLink {
+        remaining: usize,
+    }
+
+    #[ockam_core::async_trait]
+    impl Worker for StallingLink {
+        type Message = Any;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            ctx: &mut Self::Context,
+            msg: Routed<Self::Message>,
+        ) -> Result<()> {
+            if self.remaining == 0 {
+                return Ok(());
+            }
+            self.remaining -= 1;
+            Link.handle_message(ctx, msg).await
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    #[ockam_macros::test]
+    async fn test_channel_compression(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let bytes = Arc::new(AtomicUsize::new(0));
+        ctx.start_worker(
+            "meter",
+            MeteringLink {
+                bytes: bytes.clone(),
+            },
+        )
+        .await?;
+
+        let payload =
+            r#"{"sensor":"greenhouse-3","temperature":21.5,"humidity":40,"ok":true}"#.repeat(300);
+
+        for (compression, max_batch) in [(false, 1), (true, 1), (true, 4)].iter() {
+            let options = SecureChannelOptions::new()
+                .with_max_batch(*max_batch)
+                .with_max_batch_delay(Duration::from_millis(10));
+            let options = if *compression {
+                options.with_compression()
+            } else {
+                options
+            };
+            let alice_channel = alice
+                .create_secure_channel_with_options(
+                    route!["meter", "bob_listener"],
+                    TrustEveryonePolicy,
+                    options,
+                )
+                .await?;
+
+            // Batched as well as not, small messages aren't compressed
+            bytes.store(0, Ordering::Relaxed);
+            ctx.send(
+                route![alice_channel.clone(), ctx.address()],
+                payload.clone(),
+            )
+            .await?;
+            ctx.send(
+                route![alice_channel.clone(), ctx.address()],
+                "ok".to_string(),
+            )
+            .await?;
+            assert_eq!(ctx.receive::<String>().await?.take().body(), payload);
+            assert_eq!(ctx.receive::<String>().await?.take().body(), "ok");
+
+            let sent = bytes.load(Ordering::Relaxed);
+            if *compression {
+                assert!(sent < payload.len() / 4, "{} bytes sent", sent);
+            } else {
+                assert!(sent > payload.len(), "{} bytes sent", sent);
+            }
+
+            alice.stop_secure_channel(&alice_channel).await?;
+        }
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_their_route(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        ctx.start_worker("link", Link).await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["link", "bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+
+        // Bob sees the handshake coming in over the link
+        let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert_eq!(local_info.their_route().next()?, &Address::from("link"));
+
+        ctx.send(msg.return_route(), "Hello, Alice!".to_string())
+            .await?;
+        let msg = ctx.receive::<String>().await?.take();
+
+        // Alice reaches Bob's listener over the link
+        let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert_eq!(local_info.their_route().next()?, &Address::from("link"));
+        assert_eq!(
+            local_info.their_route().recipient(),
+            Address::from("bob_listener")
+        );
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_reconnect(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        ctx.start_worker("link", Link).await?;
+
+        let alice_channel = alice
+            .create_secure_channel_with_options(
+                route!["link", "bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new()
+                    .with_reconnect(5, Duration::from_millis(100))
+                    .with_timeout(Duration::from_millis(500)),
+            )
+            .await?;
+
+        ctx.send(
+            route![alice_channel.clone(), ctx.address()],
+            "Hello, Bob! 1".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert_eq!("Hello, Bob! 1", msg.body());
+
+        // Drop the link. The message that runs into it is sent again after reconnecting
+        ctx.stop_worker("link").await?;
+        ctx.send(
+            route![alice_channel.clone(), ctx.address()],
+            "Hello, Bob! 2".to_string(),
+        )
+        .await?;
+        sleep(Duration::from_secs(1)).await;
+
+        ctx.start_worker("link", Link).await?;
+
+        ctx.send(
+            route![alice_channel.clone(), ctx.address()],
+            "Hello, Bob! 3".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert_eq!("Hello, Bob! 2", msg.body());
+        let msg = ctx.receive::<String>().await?.take();
+        assert_eq!("Hello, Bob! 3", msg.body());
+        let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert_eq!(local_info.their_profile_id(), &alice.identifier().await?);
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_keepalive(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        ctx.start_worker("link", Link).await?;
+
+        let alice_channel = alice
+            .create_secure_channel_with_options(
+                route!["link", "bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new()
+                    .with_keepalive(Duration::from_millis(100), Duration::from_millis(300)),
+            )
+            .await?;
+
+        // Answered pings keep the channel up
+        sleep(Duration::from_millis(600)).await;
+        assert!(ctx.list_workers().await?.contains(&alice_channel));
+
+        // Bob vanishes without closing: the link swallows everything from now on
+        ctx.stop_worker("link").await?;
+        let received_count = Arc::new(AtomicU8::new(0));
+        let black_hole = Receiver {
+            received_count: received_count.clone(),
+        };
+        ctx.start_worker("link", black_hole).await?;
+
+        // Next ping goes out within the interval, and expires after the deadline
+        sleep(Duration::from_millis(600)).await;
+        assert!(received_count.load(Ordering::Relaxed) > 0);
+        assert!(!ctx.list_workers().await?.contains(&alice_channel));
+
+        assert!(ctx
+            .send(
+                route![alice_channel, ctx.address()],
+                "Hello, Bob!".to_string()
+            )
+            .await
+            .is_err());
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_creation_timeout(ctx: &mut Context) -> Result<()> {
+        let received_count = Arc::new(AtomicU8::new(0));
+        let black_hole = Receiver {
+            received_count: received_count.clone(),
+        };
+        ctx.start_worker("black_hole", black_hole).await?;
+
+        let vault = Vault::create(ctx).await?;
+        let mut alice = Entity::create(ctx, &vault).await?;
+
+        let res = alice
+            .create_secure_channel_with_timeout(
+                route!["black_hole"],
+                TrustEveryonePolicy,
+                Duration::from_secs(1),
+            )
+            .await;
+
+        let err = res.err().expect("handshake should time out");
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelTimeout).code()
+        );
+        assert_eq!(received_count.load(Ordering::Relaxed), 1);
+
+        ctx.stop().await
+    }
+
+    #[allow(non_snake_case)]
+    #[ockam_macros::test]
+    async fn access_control__known_participant__should_pass_messages(
+        ctx: &mut Context,
+    ) -> Result<()> {
+        let received_count = Arc::new(AtomicU8::new(0));
+        let receiver = Receiver {
+            received_count: received_count.clone(),
+        };
+
+        let vault = Vault::create(&ctx).await?;
+
+        let mut alice = Entity::create(&ctx, &vault).await?;
+        let mut bob = Entity::create(&ctx, &vault).await?;
+
+        let access_control = EntityAccessControlBuilder::new_with_id(alice.identifier().await?);
+        ctx.start_worker_with_access_control("receiver", receiver, access_control)
+            .await?;
+
+        bob.create_secure_channel_listener("listener", TrustEveryonePolicy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel("listener", TrustEveryonePolicy)
+            .await?;
+
+        ctx.send(route![alice_channel, "receiver"], "Hello, Bob!".to_string())
+            .await?;
+
+        sleep(Duration::from_secs(1)).await;
+
+        assert_eq!(received_count.load(Ordering::Relaxed), 1);
+
+        ctx.stop().await
+    }
+
+    #[allow(non_snake_case)]
+    #[ockam_macros::test]
+    async fn access_control__revoked_participant__should_not_pass_messages(
+        ctx: &mut Context,
+    ) -> Result<()> {
+        let received_count = Arc::new(AtomicU8::new(0));
+        let receiver = Receiver {
+            received_count: received_count.clone(),
+        };
+
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let access_control = EntityAccessControlBuilder::new_with_id(alice.identifier().await?);
+        ctx.start_worker_with_access_control("receiver", receiver, access_control)
+            .await?;
+
+        bob.create_secure_channel_listener("listener", TrustEveryonePolicy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel("listener", TrustEveryonePolicy)
+            .await?;
+
+        ctx.send(
+            route![alice_channel.clone(), "receiver"],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        sleep(Duration::from_secs(1)).await;
+        assert_eq!(received_count.load(Ordering::Relaxed), 1);
+
+        ctx.set_worker_access_control(
+            "receiver",
+            EntityAccessControlBuilder::new_with_id(bob.identifier().await?),
+        )
+        .await?;
+
+        ctx.send(
+            route![alice_channel, "receiver"],
+            "Hello again!".to_string(),
+        )
+        .await?;
+        sleep(Duration::from_secs(1)).await;
+        assert_eq!(received_count.load(Ordering::Relaxed), 1);
+
+        assert!(ctx
+            .set_worker_access_control("unknown", LocalOriginOnly)
+            .await
+            .is_err());
+
+        ctx.stop().await
+    }
+
+    #[allow(non_snake_case)]
+    #[ockam_macros::test]
+    async fn access_control__unknown_participant__should_not_pass_messages(
+        ctx: &mut Context,
+    ) -> Result<()> {
+        let received_count = Arc::new(AtomicU8::new(0));
+        let receiver = Receiver {
+            received_count: received_count.clone(),
+        };
+
+        let vault = Vault::create(&ctx).await?;
+
+        let mut alice = Entity::create(&ctx, &vault).await?;
+        let mut bob = Entity::create(&ctx, &vault).await?;
+
+        let access_control = EntityAccessControlBuilder::new_with_id(bob.identifier().await?);
+        ctx.start_worker_with_access_control("receiver", receiver, access_control)
+            .await?;
+
+        bob.create_secure_channel_listener("listener", TrustEveryonePolicy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel("listener", TrustEveryonePolicy)
+            .await?;
+
+        ctx.send(route![alice_channel, "receiver"], "Hello, Bob!".to_string())
+            .await?;
+
+        sleep(Duration::from_secs(1)).await;
+
+        assert_eq!(received_count.load(Ordering::Relaxed), 0);
+
+        ctx.stop().await
+    }
+
+    #[allow(non_snake_case)]
+    #[ockam_macros::test]
+    async fn access_control__no_secure_channel__should_not_pass_messages(
+        ctx: &mut Context,
+    ) -> Result<()> {
+        let received_count = Arc::new(AtomicU8::new(0));
+        let receiver = Receiver {
+            received_count: received_count.clone(),
+        };
+
+        let access_control = EntityAccessControlBuilder::new_with_id(
+            "P79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b6".try_into()?,
+        );
+        ctx.start_worker_with_access_control("receiver", receiver, access_control)
+            .await?;
+
+        ctx.send(route!["receiver"], "Hello, Bob!".to_string())
+            .await?;
+
+        sleep(Duration::from_secs(1)).await;
+
+        assert_eq!(received_count.load(Ordering::Relaxed), 0);
+
+        ctx.stop().await
+    }
+
+    #[allow(non_snake_case)]
+    #[test]
+    fn access_control__known_participant_from_transport__should_not_pass_local_only() -> Result<()>
+    {
+        let their_profile_id: ProfileIdentifier =
+            "P79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b6".try_into()?;
+        let local_info =
+            EntitySecureChannelLocalInfo::new(their_profile_id.clone()).to_local_info()?;
+
+        let remote_msg = LocalMessage::new(
+            TransportMessage::v1(
+                route!["receiver"],
+                route![Address::new(1, "127.0.0.1:4000"), "sender"],
+                vec![],
+            ),
+            vec![local_info.clone()],
+        );
+        let local_msg = LocalMessage::new(
+            TransportMessage::v1(route!["receiver"], route!["channel", "sender"], vec![]),
+            vec![local_info],
+        );
+
+        let mut entity_only = EntityAccessControlBuilder::new_with_id(their_profile_id.clone());
+        assert!(matches!(
+            entity_only.msg_is_authorized_sync(&remote_msg),
+            Some(Ok(true))
+        ));
+
+        let mut access_control =
+            EntityAccessControlBuilder::new_with_id(their_profile_id).and(LocalOriginOnly);
+        assert!(matches!(
+            access_control.msg_is_authorized_sync(&remote_msg),
+            Some(Ok(false))
+        ));
+        assert!(matches!(
+            access_control.msg_is_authorized_sync(&local_msg),
+            Some(Ok(true))
+        ));
+
+        Ok(())
+    }
+
+    async fn attribute_access_control_test(ctx: &mut Context, attribute_value: &str) -> Result<u8> {
+        let received_count = Arc::new(AtomicU8::new(0));
+        let receiver = Receiver {
+            received_count: received_count.clone(),
+        };
+
+        let vault = Vault::create(ctx).await?;
+
+        let mut authority = Entity::create(ctx, &vault).await?;
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let access_control = EntityAccessControlBuilder::new_with_attribute("role", "admin");
+        ctx.start_worker_with_access_control("receiver", receiver, access_control)
+            .await?;
+
+        let policy = TrustCredentialPolicy::new(
+            VaultSync::create_with_worker(ctx, &vault).await?,
+            authority.get_root_public_key().await?,
+        );
+        bob.create_secure_channel_listener("listener", policy)
+            .await?;
+
+        let mut attributes = BTreeMap::new();
+        attributes.insert("role".to_string(), attribute_value.to_string());
+        let credential = authority
+            .issue_authority_credential(&alice.identifier().await?, attributes)
+            .await?;
+        let alice_channel = alice
+            .create_secure_channel_with_options(
+                "listener",
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_credential(credential),
+            )
+            .await?;
+
+        ctx.send(route![alice_channel, "receiver"], "Hello, Bob!".to_string())
+            .await?;
+
+        sleep(Duration::from_secs(1)).await;
+
+        Ok(received_count.load(Ordering::Relaxed))
+    }
+
+    #[allow(non_snake_case)]
+    #[ockam_macros::test]
+    async fn access_control__matching_attribute__should_pass_messages(
+        ctx: &mut Context,
+    ) -> Result<()> {
+        assert_eq!(attribute_access_control_test(ctx, "admin").await?, 1);
+
+        ctx.stop().await
+    }
+
+    #[allow(non_snake_case)]
+    #[ockam_macros::test]
+    async fn access_control__mismatching_attribute__should_not_pass_messages(
+        ctx: &mut Context,
+    ) -> Result<()> {
+        assert_eq!(attribute_access_control_test(ctx, "guest").await?, 0);
+
+        ctx.stop().await
+    }
+
+    #[allow(non_snake_case)]
+    #[ockam_macros::test]
+    async fn access_control__no_attribute__should_not_pass_messages(
+        ctx: &mut Context,
+    ) -> Result<()> {
+        let received_count = Arc::new(AtomicU8::new(0));
+        let receiver = Receiver {
+            received_count: received_count.clone(),
+        };
+
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let access_control = EntityAccessControlBuilder::new_with_attribute("role", "admin");
+        ctx.start_worker_with_access_control("receiver", receiver, access_control)
+            .await?;
+
+        // Advertised, but not vouched for by anyone
+        alice
+            .set_attribute("role".to_string(), "admin".to_string())
+            .await?;
+
+        bob.create_secure_channel_listener("listener", TrustEveryonePolicy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel("listener", TrustEveryonePolicy)
+            .await?;
+
+        ctx.send(route![alice_channel, "receiver"], "Hello, Bob!".to_string())
+            .await?;
+
+        sleep(Duration::from_secs(1)).await;
+
+        assert_eq!(received_count.load(Ordering::Relaxed), 0);
+
+        ctx.stop().await
+    }
+
+    #[allow(non_snake_case)]
+    #[ockam_macros::test]
+    async fn access_control__inherited_attribute__should_pass_messages(
+        ctx: &mut Context,
+    ) -> Result<()> {
+        let received_count = Arc::new(AtomicU8::new(0));
+        let receiver = Receiver {
+            received_count: received_count.clone(),
+        };
+
+        let vault = Vault::create(ctx).await?;
+
+        let mut authority = Entity::create(ctx, &vault).await?;
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let access_control = EntityAccessControlBuilder::new_with_attribute("role", "admin");
+        ctx.start_worker_with_access_control("receiver", receiver, access_control)
+            .await?;
+
+        let policy = TrustCredentialPolicy::new(
+            VaultSync::create_with_worker(ctx, &vault).await?,
+            authority.get_root_public_key().await?,
+        );
+        bob.create_secure_channel_listener("listener", policy)
+            .await?;
+        bob.create_secure_channel_listener(
+            "inner_listener",
+            TrustIdentifierPolicy::new(alice.identifier().await?),
+        )
+        .await?;
+
+        let mut attributes = BTreeMap::new();
+        attributes.insert("role".to_string(), "admin".to_string());
+        let credential = authority
+            .issue_authority_credential(&alice.identifier().await?, attributes)
+            .await?;
+        let alice_channel = alice
+            .create_secure_channel_with_options(
+                "listener",
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_credential(credential),
+            )
+            .await?;
+
+        // No credential is presented again, the attributes come from the outer channel
+        let inner_channel = alice
+            .create_secure_channel_with_options(
+                route![alice_channel, "inner_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_inherited_trust(),
+            )
+            .await?;
+
+        ctx.send(route![inner_channel, "receiver"], "Hello, Bob!".to_string())
+            .await?;
+
+        sleep(Duration::from_secs(1)).await;
+
+        assert_eq!(received_count.load(Ordering::Relaxed), 1);
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_anonymous_channel(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let accepted_count = Arc::new(AtomicU8::new(0));
+        let receiver = Receiver {
+            received_count: accepted_count.clone(),
+        };
+        let access_control = EntityAccessControlBuilder::new_with_any_id().allow_anonymous();
+        ctx.start_worker_with_access_control("accepting", receiver, access_control)
+            .await?;
+
+        let rejected_count = Arc::new(AtomicU8::new(0));
+        let receiver = Receiver {
+            received_count: rejected_count.clone(),
+        };
+        let access_control = EntityAccessControlBuilder::new_with_any_id();
+        ctx.start_worker_with_access_control("rejecting", receiver, access_control)
+            .await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+        bob.create_secure_channel_listener_with_anonymous(
+            "bob_anonymous_listener",
+            TrustEveryonePolicy,
+        )
+        .await?;
+
+        // Listeners have to opt in
+        let err = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_anonymous(),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::AnonymousSecureChannelRejected).code()
         );
-        ctx.start_worker_with_access_control("receiver", receiver, access_control)
+
+        let alice_channel = alice
+            .create_secure_channel_with_options(
+                route!["bob_anonymous_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_anonymous(),
+            )
             .await?;
+        assert!(alice.secure_channels().await?.is_empty());
 
-        ctx.send(route!["receiver"], "Hello, Bob!".to_string())
+        ctx.send(
+            route![alice_channel.clone(), ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert!(local_info.is_anonymous());
+        assert!(local_info.their_public_key().is_none());
+
+        // Replies are anonymous as well
+        ctx.send(msg.return_route(), "Hello, Alice!".to_string())
             .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert!(local_info.is_anonymous());
+
+        ctx.send(
+            route![alice_channel.clone(), "accepting"],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        ctx.send(
+            route![alice_channel, "rejecting"],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
 
         sleep(Duration::from_secs(1)).await;
 
-        assert_eq!(received_count.load(Ordering::Relaxed), 0);
+        assert_eq!(accepted_count.load(Ordering::Relaxed), 1);
+        assert_eq!(rejected_count.load(Ordering::Relaxed), 0);
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_listener_stop(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        let workers_before: HashSet<Address> = ctx.list_workers().await?.into_iter().collect();
+
+        // Complete the key exchange and never answer Bob's profile, so that he keeps waiting
+        let vault_sync = VaultSync::create_with_worker(ctx, &vault).await?;
+        let initiator = XXNewKeyExchanger::new(vault_sync.async_try_clone().await?)
+            .initiator()
+            .await?;
+        let mut stalled_ctx = ctx.new_context(Address::random(0)).await?;
+        SecureChannel::create_extended(
+            ctx,
+            route!["bob_listener"],
+            Some(stalled_ctx.address()),
+            TaggedInitiator::new(initiator, &KeyExchangeHeader::new(KeyExchangePattern::Xx))?,
+            vault_sync,
+        )
+        .await?;
+        let msg = stalled_ctx.receive::<EntityChannelMessage>().await?.take();
+        let bob_responder = msg.return_route().recipient();
+        assert!(matches!(msg.body(), EntityChannelMessage::Request { .. }));
+
+        let workers_during: HashSet<Address> = ctx.list_workers().await?.into_iter().collect();
+
+        bob.stop_secure_channel_listener("bob_listener").await?;
+
+        // Bob's responder and its regular SecureChannel are gone
+        let workers_after: HashSet<Address> = ctx.list_workers().await?.into_iter().collect();
+        let stopped: Vec<_> = workers_during
+            .difference(&workers_before)
+            .filter(|address| !workers_after.contains(address))
+            .collect();
+        assert_eq!(stopped.len(), 2);
+        assert!(ctx
+            .send(route![bob_responder], "Hello, Bob!".to_string())
+            .await
+            .is_err());
+
+        // Established channels stay open
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+
+        let err = bob
+            .stop_secure_channel_listener("bob_listener")
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelListenerNotFound).code()
+        );
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_listener_address_in_use(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let in_use = ockam_core::Error::from(EntityError::SecureChannelListenerAddressInUse).code();
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+        let err = bob
+            .create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), in_use);
+
+        // Taken by another entity's listener
+        let err = alice
+            .create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), in_use);
+
+        bob.stop_secure_channel_listener("bob_listener").await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_listener_services(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let mut carol = Entity::create(ctx, &vault).await?;
+        let alice_id = alice.identifier().await?;
+        let carol_id = carol.identifier().await?;
+
+        // Nobody is trusted without naming a service
+        let bob_id = bob.identifier().await?;
+        bob.create_secure_channel_listener("bob_listener", TrustIdentifierPolicy::new(bob_id))
+            .await?;
+        bob.add_secure_channel_service(
+            "bob_listener",
+            "printer",
+            TrustIdentifierPolicy::new(alice_id),
+        )
+        .await?;
+        bob.add_secure_channel_service(
+            "bob_listener",
+            "storage",
+            TrustIdentifierPolicy::new(carol_id),
+        )
+        .await?;
+
+        let options = |service: &str| SecureChannelOptions::new().with_service(service);
+        let code = |err: EntityError| ockam_core::Error::from(err).code();
+
+        let alice_channel = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                options("printer"),
+            )
+            .await?;
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, printer!".to_string(),
+        )
+        .await?;
+        assert_eq!(
+            ctx.receive::<String>().await?.take().body(),
+            "Hello, printer!"
+        );
+
+        let carol_channel = carol
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                options("storage"),
+            )
+            .await?;
+        ctx.send(
+            route![carol_channel, ctx.address()],
+            "Hello, storage!".to_string(),
+        )
+        .await?;
+        assert_eq!(
+            ctx.receive::<String>().await?.take().body(),
+            "Hello, storage!"
+        );
+
+        let err = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                options("storage"),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), code(EntityError::SecureChannelTrustCheckFailed));
+
+        let err = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), code(EntityError::SecureChannelTrustCheckFailed));
+
+        let err = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                options("scanner"),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), code(EntityError::UnknownSecureChannelService));
+
+        let err = bob
+            .add_secure_channel_service("no_listener", "printer", TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), code(EntityError::SecureChannelListenerNotFound));
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_listener_service_patterns(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let alice_id = alice.identifier().await?;
+
+        let bob_id = bob.identifier().await?;
+        bob.create_secure_channel_listener("bob_listener", TrustIdentifierPolicy::new(bob_id))
+            .await?;
+        bob.add_secure_channel_service("bob_listener", "sensor.*", TrustEveryonePolicy)
+            .await?;
+        // More specific than the pattern above
+        bob.add_secure_channel_service(
+            "bob_listener",
+            "sensor.vault.*",
+            TrustIdentifierPolicy::new(ProfileIdentifier::random()),
+        )
+        .await?;
+
+        let options = |service: &str| SecureChannelOptions::new().with_service(service);
+        let code = |err: EntityError| ockam_core::Error::from(err).code();
+
+        for service in ["sensor.kitchen", "sensor.garage.door"] {
+            let channel = alice
+                .create_secure_channel_with_options(
+                    route!["bob_listener"],
+                    TrustEveryonePolicy,
+                    options(service),
+                )
+                .await?;
+            ctx.send(route![channel, ctx.address()], service.to_string())
+                .await?;
+            let msg = ctx.receive::<String>().await?.take();
+            let info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+            assert_eq!(info.their_profile_id(), &alice_id);
+            assert_eq!(msg.body(), service);
+        }
+
+        let err = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                options("sensor.vault.door"),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), code(EntityError::SecureChannelTrustCheckFailed));
+
+        for service in ["printer", "sensor"] {
+            let err = alice
+                .create_secure_channel_with_options(
+                    route!["bob_listener"],
+                    TrustEveryonePolicy,
+                    options(service),
+                )
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(err.code(), code(EntityError::UnknownSecureChannelService));
+        }
+
+        ctx.stop().await
+    }
+
+    /// Trusts everyone, after a while
+    #[derive(Clone)]
+    struct SlowTrustPolicy;
+
+    #[ockam_core::async_trait]
+    impl TrustPolicy for SlowTrustPolicy {
+        async fn check(&self, _trust_info: &SecureChannelTrustInfo) -> Result<bool> {
+            sleep(Duration::from_secs(2)).await;
+            Ok(true)
+        }
+    }
+
+    #[ockam_macros::test]
+    async fn test_create_secure_channel_dropped(ctx: &mut Context) -> Result<()> {
+        use ockam_core::compat::rand::distributions::{Distribution, Standard};
+        use rand::SeedableRng;
+        use rand_xorshift::XorShiftRng;
+
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = crate::EntityBuilder::new(ctx, &vault)
+            .await?
+            .with_rng(XorShiftRng::seed_from_u64(7))
+            .build()
+            .await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+        bob.create_secure_channel_listener("slow_listener", SlowTrustPolicy)
+            .await?;
+
+        // Addresses of Alice's worker and her first channels
+        let mut rng = XorShiftRng::seed_from_u64(7);
+        let alice_addresses: HashSet<Address> =
+            (0..16).map(|_| Standard.sample(&mut rng)).collect();
+        let workers_before: HashSet<Address> = ctx.list_workers().await?.into_iter().collect();
+        let assert_cleaned_up = |workers: Vec<Address>| {
+            let orphaned: Vec<_> = workers
+                .into_iter()
+                .filter(|address| {
+                    alice_addresses.contains(address) && !workers_before.contains(address)
+                })
+                .collect();
+            assert!(orphaned.is_empty(), "orphaned {:?}", orphaned);
+        };
+
+        // Dropped while Bob decides whether to trust Alice
+        let res = ockam_node::tokio::time::timeout(
+            Duration::from_millis(500),
+            alice.create_secure_channel(route!["slow_listener"], TrustEveryonePolicy),
+        )
+        .await;
+        assert!(res.is_err());
+        sleep(Duration::from_millis(250)).await;
+        assert_cleaned_up(ctx.list_workers().await?);
+
+        // Dropped before the entity worker got the request
+        let res = ockam_node::tokio::time::timeout(
+            Duration::ZERO,
+            alice.create_secure_channel(route!["bob_listener"], TrustEveryonePolicy),
+        )
+        .await;
+        assert!(res.is_err());
+        sleep(Duration::from_millis(250)).await;
+        assert_cleaned_up(ctx.list_workers().await?);
+        assert!(alice.secure_channels().await?.is_empty());
+
+        // Bob's listener still accepts channels
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_bound_to_transport(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        // Two adapters reaching the same listener
+        let counts = [Arc::new(AtomicU8::new(0)), Arc::new(AtomicU8::new(0))];
+        for (adapter, count) in ["adapter_a", "adapter_b"].iter().zip(&counts) {
+            ctx.start_worker(
+                *adapter,
+                CountingLink {
+                    count: count.clone(),
+                },
+            )
+            .await?;
+        }
+
+        let options = SecureChannelOptions::new().with_transport("adapter_b");
+        assert_eq!(options.transport(), Some(&Address::from("adapter_b")));
+        let channel = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                options,
+            )
+            .await?;
+        let handshake = counts[1].load(Ordering::Relaxed);
+        assert!(handshake > 0);
+
+        ctx.send(route![channel, ctx.address()], "Hello, Bob!".to_string())
+            .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+        assert!(counts[1].load(Ordering::Relaxed) > handshake);
+        assert_eq!(counts[0].load(Ordering::Relaxed), 0);
+
+        for transport in [Address::from("adapter_c"), Address::new(42, "adapter_a")] {
+            let err = alice
+                .create_secure_channel_with_options(
+                    route!["bob_listener"],
+                    TrustEveryonePolicy,
+                    SecureChannelOptions::new().with_transport(transport),
+                )
+                .await
+                .err()
+                .expect("transport should be missing");
+            assert_eq!(
+                err.code(),
+                ockam_core::Error::from(EntityError::SecureChannelTransportNotFound).code()
+            );
+        }
+        assert_eq!(counts[0].load(Ordering::Relaxed), 0);
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_drain_channel_listener(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        // Bob registers the channel once he confirmed it
+        sleep(Duration::from_millis(250)).await;
+        let accepted = bob.secure_channels().await?;
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].listener(), Some(&Address::from("bob_listener")));
+        assert_eq!(
+            alice
+                .secure_channel_info(&channel)
+                .await?
+                .unwrap()
+                .listener(),
+            None
+        );
+
+        bob.drain_secure_channel_listener("bob_listener").await?;
+        let err = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .expect("listener should be draining");
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelListenerDraining).code()
+        );
+
+        // Established channels keep working meanwhile
+        ctx.send(
+            route![channel.clone(), ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+
+        // The channel doesn't close on its own, so it's stopped after the deadline
+        bob.drain_secure_channel_listener_then_stop("bob_listener", Duration::from_millis(250))
+            .await?;
+        sleep(Duration::from_millis(250)).await;
+        assert!(bob.secure_channels().await?.is_empty());
+        let err = bob
+            .drain_secure_channel_listener("bob_listener")
+            .await
+            .err()
+            .expect("listener should be stopped");
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelListenerNotFound).code()
+        );
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_peer_history_after_key_rotation(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let old_public_key = alice.get_root_public_key().await?;
+        alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        // Bob registers the channel once he confirmed it
+        sleep(Duration::from_millis(250)).await;
+        let old_channel = bob.secure_channels().await?[0].address().clone();
+
+        alice.rotate_profile_key().await?;
+        let new_public_key = alice.get_root_public_key().await?;
+        alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        sleep(Duration::from_millis(250)).await;
+        let new_channel = bob
+            .secure_channels()
+            .await?
+            .into_iter()
+            .map(|channel| channel.address().clone())
+            .find(|address| address != &old_channel)
+            .unwrap();
+
+        let mut vault_sync = VaultSync::create_with_worker(ctx, &vault).await?;
+        let history = bob.secure_channel_peer_history(&new_channel).await?;
+        assert_eq!(history.identifier(), &alice.identifier().await?);
+        assert!(history.verify(&mut vault_sync).await?);
+        let public_keys: Vec<_> = history
+            .change_events()
+            .iter()
+            .map(|event| event.change_block().change().public_key())
+            .collect::<Result<_>>()?;
+        assert_eq!(public_keys, vec![old_public_key.clone(), new_public_key]);
+
+        // The channel created before keeps the history it verified
+        let history = bob.secure_channel_peer_history(&old_channel).await?;
+        assert!(history.verify(&mut vault_sync).await?);
+        assert_eq!(history.change_events().len(), 1);
+        assert_eq!(history.get_profile_update_public_key()?, old_public_key);
 
         ctx.stop().await
     }
@@ -0,0 +1,65 @@
+use crate::ProfileIdentifier;
+use ockam_core::compat::{collections::BTreeMap, string::String, vec::Vec};
+use ockam_core::{Encodable, Result};
+use serde::{Deserialize, Serialize};
+
+/// Prefixed to the data an authority signs, so that the signature can't pass for a channel
+/// authentication proof or an [`Entity::sign`](crate::Entity::sign) signature
+const CREDENTIAL_LABEL: &[u8] = b"OCKAM_ENTITY_AUTHORITY_CREDENTIAL";
+/// Version of the signed data, bumped whenever its layout changes
+const CREDENTIAL_VERSION: u8 = 1;
+
+/// Attributes of a profile, signed by an authority the other side trusts.
+/// Issued with [`Entity::issue_authority_credential`](crate::Entity::issue_authority_credential),
+/// presented during the handshake with [`SecureChannelOptions::with_credential`](crate::SecureChannelOptions::with_credential)
+/// and verified by [`TrustCredentialPolicy`](crate::TrustCredentialPolicy)
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuthorityCredential {
+    subject: ProfileIdentifier,
+    attributes: BTreeMap<String, String>,
+    proof: Vec<u8>,
+}
+
+impl AuthorityCredential {
+    pub(crate) fn new(
+        subject: ProfileIdentifier,
+        attributes: BTreeMap<String, String>,
+        proof: Vec<u8>,
+    ) -> Self {
+        Self {
+            subject,
+            attributes,
+            proof,
+        }
+    }
+
+    /// Data the authority signs: label, version, then the encoded subject and attributes
+    pub(crate) fn signed_data(
+        subject: &ProfileIdentifier,
+        attributes: &BTreeMap<String, String>,
+    ) -> Result<Vec<u8>> {
+        let mut data = CREDENTIAL_LABEL.to_vec();
+        data.push(CREDENTIAL_VERSION);
+        data.extend((subject, attributes).encode()?);
+
+        Ok(data)
+    }
+
+    /// Profile the attributes belong to
+    pub fn subject(&self) -> &ProfileIdentifier {
+        &self.subject
+    }
+
+    pub fn attributes(&self) -> &BTreeMap<String, String> {
+        &self.attributes
+    }
+
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key).map(String::as_str)
+    }
+
+    /// Signature of the authority over the subject and attributes
+    pub fn proof(&self) -> &[u8] {
+        &self.proof
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{request_channel, EntityChannelMessage, EntityError};
+use ockam_core::{async_trait, compat::boxed::Box};
+use ockam_core::{Address, Result};
+use ockam_node::Context;
+
+/// Wait for a secure channel created with
+/// [`SecureChannelOptions::with_backpressure`](crate::SecureChannelOptions::with_backpressure)
+/// to catch up, rather than having it hold or drop messages
+#[async_trait]
+pub trait BackpressureContext {
+    /// Wait until the channel at given address sends the next message right away.
+    /// Returns immediately for channels without backpressure
+    async fn wait_for_capacity(&self, channel: &Address) -> Result<()>;
+}
+
+#[async_trait]
+impl BackpressureContext for Context {
+    async fn wait_for_capacity(&self, channel: &Address) -> Result<()> {
+        let mut ctx = request_channel(self, channel, EntityChannelMessage::ReserveCapacity).await?;
+
+        match ctx
+            .receive_block::<EntityChannelMessage>()
+            .await?
+            .take()
+            .body()
+        {
+            EntityChannelMessage::CapacityReserved => Ok(()),
+            // The channel stopped, e.g. the other side went away
+            EntityChannelMessage::Reject(err) => Err(err.into()),
+            _ => Err(EntityError::InvalidSecureChannelInternalState.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BackpressureContext, Entity, SecureChannelOptions, TrustEveryonePolicy};
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use core::time::Duration;
+    use ockam_core::compat::{string::String, sync::Arc, vec::Vec};
+    use ockam_core::{async_trait, route, Address, Any, LocalMessage, Result, Routed, Worker};
+    use ockam_node::tokio::time::timeout;
+    use ockam_node::Context;
+    use ockam_vault_sync_core::Vault;
+    use tokio::time::sleep;
+
+    /// Transport stand-in, which holds everything while stalled
+    struct Tap {
+        stalled: Arc<AtomicBool>,
+        held_count: Arc<AtomicUsize>,
+        held: Vec<LocalMessage>,
+    }
+
+    #[async_trait]
+    impl Worker for Tap {
+        type Message = Any;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            ctx: &mut Self::Context,
+            msg: Routed<Self::Message>,
+        ) -> Result<()> {
+            if msg.msg_addr() == Address::from("tap_release") {
+                self.stalled.store(false, Ordering::SeqCst);
+                for held in self.held.drain(..) {
+                    ctx.forward(held).await?;
+                }
+                return Ok(());
+            }
+
+            let mut local_msg = msg.into_local_message();
+            let transport_msg = local_msg.transport_mut();
+            transport_msg.onward_route.step()?;
+            transport_msg
+                .return_route
+                .modify()
+                .prepend(Address::from("tap"));
+
+            if self.stalled.load(Ordering::SeqCst) {
+                self.held.push(local_msg);
+                self.held_count.fetch_add(1, Ordering::SeqCst);
+                return Ok(());
+            }
+
+            ctx.forward(local_msg).await
+        }
+    }
+
+    struct Counter {
+        received: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Worker for Counter {
+        type Message = String;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            _ctx: &mut Self::Context,
+            _msg: Routed<Self::Message>,
+        ) -> Result<()> {
+            self.received.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[ockam_macros::test]
+    async fn test_stalled_transport(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let stalled = Arc::new(AtomicBool::new(false));
+        let held_count = Arc::new(AtomicUsize::new(0));
+        let tap = Tap {
+            stalled: stalled.clone(),
+            held_count: held_count.clone(),
+            held: Vec::new(),
+        };
+        ctx.start_worker(vec!["tap", "tap_release"], tap).await?;
+
+        let received = Arc::new(AtomicUsize::new(0));
+        let counter = Counter {
+            received: received.clone(),
+        };
+        ctx.start_worker("counter", counter).await?;
+
+        let channel = alice
+            .create_secure_channel_with_options(
+                route!["tap", "bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_backpressure(2, 3),
+            )
+            .await?;
+        // Let the handshake leftovers through before stalling
+        sleep(Duration::from_millis(100)).await;
+        stalled.store(true, Ordering::SeqCst);
+
+        for i in 0..10 {
+            ctx.send(route![channel.clone(), "counter"], i.to_string())
+                .await?;
+        }
+        sleep(Duration::from_millis(250)).await;
+
+        // Only the window reached the transport, 3 more are held by the channel, the rest was dropped
+        assert_eq!(held_count.load(Ordering::SeqCst), 2);
+        assert!(
+            timeout(Duration::from_millis(250), ctx.wait_for_capacity(&channel))
+                .await
+                .is_err()
+        );
+
+        ctx.send(route!["tap_release"], String::new()).await?;
+        sleep(Duration::from_millis(250)).await;
+
+        assert_eq!(received.load(Ordering::SeqCst), 5);
+        timeout(Duration::from_secs(1), ctx.wait_for_capacity(&channel))
+            .await
+            .expect("channel didn't catch up")?;
+
+        ctx.stop().await
+    }
+}
@@ -0,0 +1,458 @@
+use crate::EntityError;
+use core::convert::TryFrom;
+use core::fmt::{Display, Formatter};
+use ockam_core::compat::{string::String, vec::Vec};
+use ockam_core::Result;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+
+/// Decode data in the same format as [`Decodable`](ockam_core::Decodable), without trusting
+/// any length it contains. Lengths of strings and byte arrays beyond the end of the data fail
+/// before anything is allocated, as do more sequence or map entries than there are bytes left.
+/// Every entry is assumed to take at least one byte, which holds for every handshake message.
+/// Trailing bytes fail as well.
+///
+/// Used for messages a peer sends before it's authenticated
+pub(crate) fn decode_bounded<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+    let mut deserializer = BoundedDeserializer { data };
+    let value = T::deserialize(&mut deserializer).map_err(|_| malformed())?;
+    if !deserializer.data.is_empty() {
+        return Err(malformed());
+    }
+
+    Ok(value)
+}
+
+fn malformed() -> ockam_core::Error {
+    EntityError::MalformedHandshakeMessage.into()
+}
+
+#[derive(Debug)]
+struct DecodeError;
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.write_str("malformed message")
+    }
+}
+
+impl de::StdError for DecodeError {}
+
+impl de::Error for DecodeError {
+    fn custom<T: Display>(_msg: T) -> Self {
+        DecodeError
+    }
+}
+
+type DecodeResult<T> = core::result::Result<T, DecodeError>;
+
+struct BoundedDeserializer<'de> {
+    data: &'de [u8],
+}
+
+impl<'de> BoundedDeserializer<'de> {
+    fn take(&mut self, len: usize) -> DecodeResult<&'de [u8]> {
+        if len > self.data.len() {
+            return Err(DecodeError);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+
+        Ok(taken)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> DecodeResult<[u8; N]> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.take(N)?);
+
+        Ok(array)
+    }
+
+    /// LEB128 encoded integer
+    fn uint(&mut self) -> DecodeResult<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(DecodeError)
+    }
+
+    /// Length of a string or byte array, which has to fit into the remaining data
+    fn len(&mut self) -> DecodeResult<usize> {
+        let len = self.uint()?;
+        if len > self.data.len() as u64 {
+            return Err(DecodeError);
+        }
+
+        Ok(len as usize)
+    }
+
+    fn bytes(&mut self) -> DecodeResult<&'de [u8]> {
+        let len = self.len()?;
+        self.take(len)
+    }
+
+    fn str(&mut self) -> DecodeResult<&'de str> {
+        core::str::from_utf8(self.bytes()?).map_err(|_| DecodeError)
+    }
+}
+
+macro_rules! deserialize_fixed {
+    ($method:ident, $visit:ident, $ty:ty) => {
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> DecodeResult<V::Value> {
+            visitor.$visit(<$ty>::from_le_bytes(self.take_array()?))
+        }
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for &'a mut BoundedDeserializer<'de> {
+    type Error = DecodeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> DecodeResult<V::Value> {
+        // The format isn't self-describing
+        Err(DecodeError)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> DecodeResult<V::Value> {
+        match self.take(1)?[0] {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            _ => Err(DecodeError),
+        }
+    }
+
+    deserialize_fixed!(deserialize_i8, visit_i8, i8);
+    deserialize_fixed!(deserialize_i16, visit_i16, i16);
+    deserialize_fixed!(deserialize_i32, visit_i32, i32);
+    deserialize_fixed!(deserialize_i64, visit_i64, i64);
+    deserialize_fixed!(deserialize_u8, visit_u8, u8);
+    deserialize_fixed!(deserialize_u16, visit_u16, u16);
+    deserialize_fixed!(deserialize_u32, visit_u32, u32);
+    deserialize_fixed!(deserialize_u64, visit_u64, u64);
+    deserialize_fixed!(deserialize_f32, visit_f32, f32);
+    deserialize_fixed!(deserialize_f64, visit_f64, f64);
+
+    fn deserialize_char<V: Visitor<'de>>(self, _visitor: V) -> DecodeResult<V::Value> {
+        Err(DecodeError)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> DecodeResult<V::Value> {
+        visitor.visit_borrowed_str(self.str()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> DecodeResult<V::Value> {
+        visitor.visit_string(String::from(self.str()?))
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> DecodeResult<V::Value> {
+        visitor.visit_borrowed_bytes(self.bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> DecodeResult<V::Value> {
+        visitor.visit_byte_buf(Vec::from(self.bytes()?))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> DecodeResult<V::Value> {
+        match self.take(1)?[0] {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            _ => Err(DecodeError),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> DecodeResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> DecodeResult<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> DecodeResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> DecodeResult<V::Value> {
+        let len = self.len()?;
+        visitor.visit_seq(Entries {
+            deserializer: self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> DecodeResult<V::Value> {
+        visitor.visit_seq(Entries {
+            deserializer: self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> DecodeResult<V::Value> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> DecodeResult<V::Value> {
+        let len = self.len()?;
+        visitor.visit_map(Entries {
+            deserializer: self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> DecodeResult<V::Value> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> DecodeResult<V::Value> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _visitor: V) -> DecodeResult<V::Value> {
+        Err(DecodeError)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, _visitor: V) -> DecodeResult<V::Value> {
+        Err(DecodeError)
+    }
+}
+
+/// Elements of a sequence, tuple or struct, or entries of a map
+struct Entries<'a, 'de> {
+    deserializer: &'a mut BoundedDeserializer<'de>,
+    remaining: usize,
+}
+
+impl<'a, 'de> SeqAccess<'de> for Entries<'a, 'de> {
+    type Error = DecodeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> DecodeResult<Option<T::Value>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+
+        seed.deserialize(&mut *self.deserializer).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'a, 'de> MapAccess<'de> for Entries<'a, 'de> {
+    type Error = DecodeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> DecodeResult<Option<K::Value>> {
+        self.next_element_seed(seed)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> DecodeResult<V::Value> {
+        seed.deserialize(&mut *self.deserializer)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'a, 'de> EnumAccess<'de> for &'a mut BoundedDeserializer<'de> {
+    type Error = DecodeError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> DecodeResult<(V::Value, Self)> {
+        let index = u32::try_from(self.uint()?).map_err(|_| DecodeError)?;
+        let value = seed.deserialize(index.into_deserializer())?;
+
+        Ok((value, self))
+    }
+}
+
+impl<'a, 'de> VariantAccess<'de> for &'a mut BoundedDeserializer<'de> {
+    type Error = DecodeError;
+
+    fn unit_variant(self) -> DecodeResult<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> DecodeResult<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> DecodeResult<V::Value> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> DecodeResult<V::Value> {
+        de::Deserializer::deserialize_tuple(self, fields.len(), visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Entity, EntityChannelMessage, Identity, SECURE_CHANNEL_PROTOCOL_VERSION};
+    use ockam_core::compat::{collections::BTreeMap, string::ToString};
+    use ockam_core::Encodable;
+    use ockam_node::Context;
+    use ockam_vault_sync_core::Vault;
+    use rand::{Rng, SeedableRng};
+    use rand_xorshift::XorShiftRng;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Sample {
+        Empty,
+        Fields {
+            name: String,
+            data: Vec<u8>,
+            array: [u8; 4],
+            optional: Option<u16>,
+            map: BTreeMap<String, i64>,
+            flag: bool,
+        },
+        Tuple(u32, (u8, u64)),
+    }
+
+    fn samples() -> Vec<Sample> {
+        let mut map = BTreeMap::new();
+        map.insert("key".into(), -42);
+        vec![
+            Sample::Empty,
+            Sample::Fields {
+                name: "name".into(),
+                data: vec![1, 2, 3],
+                array: [4, 5, 6, 7],
+                optional: Some(300),
+                map,
+                flag: true,
+            },
+            Sample::Tuple(u32::MAX, (8, 1 << 40)),
+        ]
+    }
+
+    #[test]
+    fn test_same_format_as_decodable() {
+        for sample in samples() {
+            let encoded = sample.encode().unwrap();
+            let decoded: Sample = decode_bounded(&encoded).unwrap();
+            assert_eq!(decoded, sample);
+
+            // Truncated
+            for len in 0..encoded.len() {
+                assert!(decode_bounded::<Sample>(&encoded[..len]).is_err());
+            }
+        }
+
+        let mut trailing = Sample::Empty.encode().unwrap();
+        trailing.push(0);
+        assert!(decode_bounded::<Sample>(&trailing).is_err());
+    }
+
+    #[test]
+    fn test_oversized_length() {
+        // Variant 1, then a name of u64::MAX bytes
+        let mut data = vec![1];
+        data.extend_from_slice(&[0xFF; 9]);
+        data.push(0x01);
+        assert!(decode_bounded::<Sample>(&data).is_err());
+
+        // Length longer than 64 bits
+        let mut data = vec![1];
+        data.extend_from_slice(&[0xFF; 16]);
+        assert!(decode_bounded::<Sample>(&data).is_err());
+    }
+
+    #[test]
+    fn test_random_data() {
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        let encoded: Vec<Vec<u8>> = samples().iter().map(|s| s.encode().unwrap()).collect();
+
+        for _ in 0..10_000 {
+            let len = rng.gen_range(0..64);
+            let data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            let _ = decode_bounded::<Sample>(&data);
+            let _ = decode_bounded::<EntityChannelMessage>(&data);
+
+            // Valid data with a byte flipped
+            let mut data = encoded[rng.gen_range(0..encoded.len())].clone();
+            let index = rng.gen_range(0..data.len());
+            data[index] ^= rng.gen::<u8>() | 1;
+            let _ = decode_bounded::<Sample>(&data);
+        }
+    }
+
+    #[ockam_macros::test]
+    async fn test_handshake_message(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+        let mut alice = Entity::create(ctx, &vault).await?;
+
+        let encoded = EntityChannelMessage::Request {
+            contact: alice.as_contact().await?,
+            proof: vec![1, 2, 3],
+            credential: None,
+            attributes: vec![("role".to_string(), "sensor".to_string())]
+                .into_iter()
+                .collect(),
+            strict_trust: true,
+            compression: false,
+            protocol_version: SECURE_CHANNEL_PROTOCOL_VERSION,
+        }
+        .encode()?;
+        let decoded: EntityChannelMessage = decode_bounded(&encoded)?;
+        assert_eq!(decoded.encode()?, encoded);
+
+        for len in 0..encoded.len() {
+            assert!(decode_bounded::<EntityChannelMessage>(&encoded[..len]).is_err());
+        }
+
+        let mut rng = XorShiftRng::seed_from_u64(0);
+        for _ in 0..1_000 {
+            let mut data = encoded.clone();
+            let index = rng.gen_range(0..data.len());
+            data[index] = rng.gen();
+            let _ = decode_bounded::<EntityChannelMessage>(&data);
+        }
+
+        ctx.stop().await
+    }
+}
@@ -0,0 +1,278 @@
+use crate::{Entity, EntityError, EntitySecureChannelLocalInfo, ProfileIdentifier};
+use ockam_core::compat::boxed::Box;
+use ockam_core::{
+    async_trait, AccessControl, Address, Any, Decodable, Encodable, LocalInfo, LocalMessage,
+    Result, Routed, Worker,
+};
+use ockam_node::Context;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Channel bridge LocalInfo unique Identifier
+pub const ENTITY_CHANNEL_BRIDGE_IDENTIFIER: &str = "ENTITY_CHANNEL_BRIDGE_IDENTIFIER";
+
+/// Added by a [`ChannelBridge`] to the messages it hands to its [`AccessControl`],
+/// next to the [`EntitySecureChannelLocalInfo`] of the channel they came through
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChannelBridgeLocalInfo {
+    from: ProfileIdentifier,
+    to: ProfileIdentifier,
+}
+
+impl ChannelBridgeLocalInfo {
+    pub fn to_local_info(&self) -> Result<LocalInfo> {
+        Ok(LocalInfo::new(
+            ENTITY_CHANNEL_BRIDGE_IDENTIFIER.into(),
+            self.encode()?,
+        ))
+    }
+
+    /// Fails with [`EntityError::LocalInfoMissing`] if the message wasn't checked by
+    /// a bridge, and with [`EntityError::LocalInfoMalformed`] if its info can't be decoded
+    pub fn find_info(local_msg: &LocalMessage) -> Result<Self> {
+        match local_msg
+            .local_info()
+            .iter()
+            .find(|x| x.type_identifier() == ENTITY_CHANNEL_BRIDGE_IDENTIFIER)
+        {
+            Some(local_info) => {
+                Self::decode(local_info.data()).map_err(|_| EntityError::LocalInfoMalformed.into())
+            }
+            None => Err(EntityError::LocalInfoMissing.into()),
+        }
+    }
+
+    /// Peer of the channel the message came through
+    pub fn from(&self) -> &ProfileIdentifier {
+        &self.from
+    }
+
+    /// Peer of the channel the message is forwarded to
+    pub fn to(&self) -> &ProfileIdentifier {
+        &self.to
+    }
+}
+
+/// Worker forwarding messages between two secure channels of the same [`Entity`],
+/// e.g. for a gateway that authenticates both sides on its own.
+/// Messages that came through one of the channels and are addressed to the bridge
+/// are sent through the other one, in the order they arrived. The other side sees
+/// the bridge as the sender, and replies to the return route find their way back.
+/// Anything else sent to the bridge is dropped
+pub struct ChannelBridge<A: AccessControl> {
+    first: (Address, ProfileIdentifier),
+    second: (Address, ProfileIdentifier),
+    access_control: A,
+}
+
+impl<A: AccessControl> ChannelBridge<A> {
+    /// Start a bridge at `address` between two channels of the current profile of `entity`,
+    /// see [`Entity::secure_channels`]. Messages in either direction are only forwarded if
+    /// `access_control` allows them, given the [`EntitySecureChannelLocalInfo`] of the channel
+    /// they came through and a [`ChannelBridgeLocalInfo`] naming both peers.
+    /// Fails with [`EntityError::SecureChannelNotFound`] for unknown channels
+    pub async fn create(
+        ctx: &Context,
+        address: impl Into<Address>,
+        entity: &Entity,
+        first: &Address,
+        second: &Address,
+        access_control: A,
+    ) -> Result<()> {
+        let bridge = Self {
+            first: (first.clone(), Self::peer(entity, first).await?),
+            second: (second.clone(), Self::peer(entity, second).await?),
+            access_control,
+        };
+
+        ctx.start_worker(address.into(), bridge).await
+    }
+
+    async fn peer(entity: &Entity, channel: &Address) -> Result<ProfileIdentifier> {
+        match entity.secure_channel_info(channel).await? {
+            Some(handle) => Ok(handle.their_profile_id().clone()),
+            None => Err(EntityError::SecureChannelNotFound.into()),
+        }
+    }
+}
+
+#[async_trait]
+impl<A: AccessControl> Worker for ChannelBridge<A> {
+    type Message = Any;
+    type Context = Context;
+
+    async fn handle_message(&mut self, ctx: &mut Context, msg: Routed<Any>) -> Result<()> {
+        let came_through = msg.return_route().next().ok().cloned();
+        let (from, to) = match came_through {
+            Some(channel) if channel == self.first.0 => (&self.first, &self.second),
+            Some(channel) if channel == self.second.0 => (&self.second, &self.first),
+            _ => {
+                warn!(
+                    "ChannelBridge at {} dropped a message of another sender",
+                    ctx.address()
+                );
+                return Ok(());
+            }
+        };
+
+        let mut local_msg = msg.into_local_message();
+        if EntitySecureChannelLocalInfo::find_info(&local_msg).is_err() {
+            warn!(
+                "ChannelBridge at {} dropped a message of another sender",
+                ctx.address()
+            );
+            return Ok(());
+        }
+
+        local_msg.append_local_info(
+            ChannelBridgeLocalInfo {
+                from: from.1.clone(),
+                to: to.1.clone(),
+            }
+            .to_local_info()?,
+        );
+        if !self.access_control.msg_is_authorized(&local_msg).await? {
+            warn!(
+                "ChannelBridge at {} dropped a message from {} to {}",
+                ctx.address(),
+                from.1,
+                to.1
+            );
+            return Ok(());
+        }
+
+        let mut transport_msg = local_msg.into_transport_message();
+        transport_msg.onward_route.step()?;
+        // Replies already name the channel they go back through
+        if transport_msg.onward_route.next().ok() != Some(&to.0) {
+            transport_msg.onward_route.modify().prepend(to.0.clone());
+        }
+        transport_msg.return_route.modify().prepend(ctx.address());
+
+        ctx.forward(LocalMessage::new(transport_msg, vec![])).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Identity, TrustEveryonePolicy};
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use ockam_core::compat::{
+        string::{String, ToString},
+        sync::{Arc, Mutex},
+        vec::Vec,
+    };
+    use ockam_core::route;
+    use ockam_vault_sync_core::Vault;
+
+    /// Records what the bridge asked about, and allows everything while open
+    struct Recorder {
+        open: Arc<AtomicBool>,
+        seen: Arc<Mutex<Vec<(ProfileIdentifier, ChannelBridgeLocalInfo)>>>,
+    }
+
+    #[async_trait]
+    impl AccessControl for Recorder {
+        async fn msg_is_authorized(&mut self, local_msg: &LocalMessage) -> Result<bool> {
+            let sender = EntitySecureChannelLocalInfo::find_info(local_msg)?;
+            let bridge = ChannelBridgeLocalInfo::find_info(local_msg)?;
+            self.seen
+                .lock()
+                .unwrap()
+                .push((sender.their_profile_id().clone(), bridge));
+            Ok(self.open.load(Ordering::SeqCst))
+        }
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel_bridge(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut gateway = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        gateway
+            .create_secure_channel_listener("gateway_listener", TrustEveryonePolicy)
+            .await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+        let alice_id = alice.identifier().await?;
+        let gateway_id = gateway.identifier().await?;
+        let bob_id = bob.identifier().await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["gateway_listener"], TrustEveryonePolicy)
+            .await?;
+        ctx.send(
+            route![alice_channel.clone(), ctx.address()],
+            "Hello, gateway!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let gateway_to_alice = msg.return_route().next()?.clone();
+        let gateway_to_bob = gateway
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        let open = Arc::new(AtomicBool::new(true));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder {
+            open: open.clone(),
+            seen: seen.clone(),
+        };
+        ChannelBridge::create(
+            ctx,
+            "bridge",
+            &gateway,
+            &gateway_to_alice,
+            &gateway_to_bob,
+            recorder,
+        )
+        .await?;
+
+        // This context stands in for the applications of both alice and bob
+        for i in 0..5 {
+            ctx.send(
+                route![alice_channel.clone(), "bridge", ctx.address()],
+                i.to_string(),
+            )
+            .await?;
+        }
+        let mut return_route = route![];
+        for i in 0..5 {
+            let msg = ctx.receive::<String>().await?.take();
+            assert_eq!(
+                EntitySecureChannelLocalInfo::find_info(msg.local_message())?.their_profile_id(),
+                &gateway_id
+            );
+            return_route = msg.return_route();
+            assert_eq!(msg.body(), i.to_string());
+        }
+
+        ctx.send(return_route, "Hello, Alice!".to_string()).await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert_eq!(msg.return_route().next()?, &alice_channel);
+        assert_eq!(msg.body(), "Hello, Alice!");
+
+        let seen = seen.lock().unwrap().clone();
+        assert_eq!(seen.len(), 6);
+        for (sender, info) in &seen[..5] {
+            assert_eq!(sender, &alice_id);
+            assert_eq!((info.from(), info.to()), (&alice_id, &bob_id));
+        }
+        assert_eq!(seen[5].0, bob_id);
+        assert_eq!((seen[5].1.from(), seen[5].1.to()), (&bob_id, &alice_id));
+
+        // Denied messages don't reach the other side
+        open.store(false, Ordering::SeqCst);
+        ctx.send(
+            route![alice_channel, "bridge", ctx.address()],
+            "denied".to_string(),
+        )
+        .await?;
+        assert!(ctx.receive_timeout::<String>(1).await.is_err());
+
+        ctx.stop().await
+    }
+}
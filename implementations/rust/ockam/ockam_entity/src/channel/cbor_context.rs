@@ -0,0 +1,151 @@
+use crate::EntityError;
+use ockam_core::compat::{boxed::Box, vec::Vec};
+use ockam_core::{async_trait, Address, LocalMessage, Message, Result, Route};
+use ockam_node::Context;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::warn;
+
+/// Wire format of [`CborContext`] messages
+#[derive(Serialize, Deserialize, Message)]
+struct CborMessage(Vec<u8>);
+
+/// Send and receive any serde type, encoded with CBOR.
+/// Messages that went through a secure channel keep its local info,
+/// see [`EntitySecureChannelLocalInfo::find_info`](crate::EntitySecureChannelLocalInfo::find_info)
+#[async_trait]
+pub trait CborContext {
+    /// Encode `msg` with CBOR and send it to `route`
+    async fn send_encoded<R, T>(&self, route: R, msg: &T) -> Result<()>
+    where
+        R: Into<Route> + Send,
+        T: Serialize + Sync;
+
+    /// Receive the next message and decode it from CBOR. Fails with
+    /// [`EntityError::CborDecodeFailed`] and drops the message if it isn't a `T`
+    async fn receive_decoded<T>(&mut self) -> Result<Decoded<T>>
+    where
+        T: DeserializeOwned + Send;
+}
+
+#[async_trait]
+impl CborContext for Context {
+    async fn send_encoded<R, T>(&self, route: R, msg: &T) -> Result<()>
+    where
+        R: Into<Route> + Send,
+        T: Serialize + Sync,
+    {
+        let data = serde_cbor::to_vec(msg).map_err(|_| EntityError::CborEncodeFailed)?;
+        self.send(route, CborMessage(data)).await
+    }
+
+    async fn receive_decoded<T>(&mut self) -> Result<Decoded<T>>
+    where
+        T: DeserializeOwned + Send,
+    {
+        let msg = self.receive::<CborMessage>().await?.take();
+        let msg_addr = msg.msg_addr();
+        let local_msg = msg.local_message().clone();
+        let body = serde_cbor::from_slice(&msg.body().0).map_err(|err| {
+            warn!("{} decoding CBOR message at: {}", err, msg_addr);
+            EntityError::CborDecodeFailed
+        })?;
+
+        Ok(Decoded {
+            body,
+            msg_addr,
+            local_msg,
+        })
+    }
+}
+
+/// Message decoded by [`CborContext::receive_decoded`], with its routing information
+pub struct Decoded<T> {
+    body: T,
+    msg_addr: Address,
+    local_msg: LocalMessage,
+}
+
+impl<T> Decoded<T> {
+    /// Address the message was received at
+    pub fn msg_addr(&self) -> &Address {
+        &self.msg_addr
+    }
+
+    pub fn return_route(&self) -> Route {
+        self.local_msg.transport().return_route.clone()
+    }
+
+    pub fn local_message(&self) -> &LocalMessage {
+        &self.local_msg
+    }
+
+    pub fn as_body(&self) -> &T {
+        &self.body
+    }
+
+    pub fn body(self) -> T {
+        self.body
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Entity, EntitySecureChannelLocalInfo, Identity, TrustEveryonePolicy};
+    use ockam_core::compat::string::{String, ToString};
+    use ockam_core::route;
+    use ockam_vault_sync_core::Vault;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Reading {
+        sensor: String,
+        values: Vec<f64>,
+        sequence: u64,
+        calibrated: bool,
+        note: Option<String>,
+    }
+
+    #[ockam_macros::test]
+    async fn test_cbor_through_channel(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        let reading = Reading {
+            sensor: "thermometer".to_string(),
+            values: vec![21.5, 21.7, -3.25],
+            sequence: 42,
+            calibrated: true,
+            note: None,
+        };
+        ctx.send_encoded(route![alice_channel.clone(), ctx.address()], &reading)
+            .await?;
+
+        let msg = ctx.receive_decoded::<Reading>().await?;
+        let local_info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert_eq!(local_info.their_profile_id(), &alice.identifier().await?);
+        assert_eq!(msg.as_body(), &reading);
+
+        // Not CBOR, so it can't be decoded
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let err = ctx.receive_decoded::<Reading>().await.err().unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::CborDecodeFailed).code()
+        );
+
+        ctx.stop().await
+    }
+}
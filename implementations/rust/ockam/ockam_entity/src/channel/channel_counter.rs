@@ -0,0 +1,60 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use ockam_core::compat::sync::Arc;
+
+/// Number of channels a listener has open, shared with the responders it started
+#[derive(Clone, Default)]
+pub(crate) struct ChannelCounter(Arc<AtomicUsize>);
+
+impl ChannelCounter {
+    /// Take a slot, unless `max_channels` are open already
+    pub fn acquire(&self, max_channels: Option<usize>) -> Option<ChannelSlot> {
+        self.0
+            .fetch_update(
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+                |count| match max_channels {
+                    Some(max_channels) if count >= max_channels => None,
+                    _ => Some(count + 1),
+                },
+            )
+            .ok()
+            .map(|_| ChannelSlot(self.0.clone()))
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Taken by a responder for its whole lifetime. Released on drop, so that the slot
+/// is freed however the channel ends
+pub(crate) struct ChannelSlot(Arc<AtomicUsize>);
+
+impl Drop for ChannelSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_acquire() {
+        let counter = ChannelCounter::default();
+
+        let first = counter.acquire(Some(2)).unwrap();
+        let second = counter.acquire(Some(2)).unwrap();
+        assert!(counter.acquire(Some(2)).is_none());
+        assert_eq!(counter.count(), 2);
+
+        drop(first);
+        assert_eq!(counter.count(), 1);
+        let _third = counter.acquire(Some(2)).unwrap();
+
+        drop(second);
+        let _unlimited = counter.acquire(None).unwrap();
+        assert_eq!(counter.count(), 2);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::{request_channel, EntityChannelMessage, EntityError, EntitySecureChannelLocalInfo};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context as TaskContext, Poll};
+use futures_core::Stream;
+use ockam_core::compat::boxed::Box;
+use ockam_core::{Address, Any, Decodable, Message, Result, Routed};
+use ockam_node::Context;
+use tracing::warn;
+
+type NextMessage<M> = Pin<Box<dyn Future<Output = (Context, Option<Result<Routed<M>>>)> + Send>>;
+
+enum StreamState<M> {
+    /// Waiting to be polled, nothing is taken from the mailbox meanwhile
+    Idle(Context),
+    Receiving(NextMessage<M>),
+    Done,
+}
+
+/// Messages arriving through a secure channel, as a [`Stream`].
+/// Items keep their [`LocalMessage`](ockam_core::LocalMessage), so that
+/// [`EntitySecureChannelLocalInfo::find_info`] works on them as on messages received by a worker.
+/// Messages are only taken from the mailbox when the stream is polled, and the stream ends
+/// once the channel closes, after the messages that arrived before
+pub struct ChannelStream<M: Message> {
+    address: Address,
+    channel: Address,
+    state: StreamState<M>,
+}
+
+impl<M: Message> ChannelStream<M> {
+    /// Follow the channel at given address, e.g. one of
+    /// [`Entity::secure_channels`](crate::Entity::secure_channels).
+    /// The other side has to send to [`ChannelStream::address`] through that channel
+    pub async fn create(ctx: &Context, channel: &Address) -> Result<Self> {
+        let mut ctx = request_channel(ctx, channel, EntityChannelMessage::WatchClose).await?;
+
+        match ctx.receive::<EntityChannelMessage>().await?.take().body() {
+            EntityChannelMessage::WatchingClose => Ok(Self {
+                address: ctx.address(),
+                channel: channel.clone(),
+                state: StreamState::Idle(ctx),
+            }),
+            _ => Err(EntityError::InvalidSecureChannelInternalState.into()),
+        }
+    }
+
+    /// Address messages have to be sent to
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// Channel the stream follows
+    pub fn channel(&self) -> &Address {
+        &self.channel
+    }
+
+    async fn next_message(
+        mut ctx: Context,
+        channel: Address,
+    ) -> (Context, Option<Result<Routed<M>>>) {
+        let item = loop {
+            let msg = match ctx.receive_block::<Any>().await {
+                Ok(msg) => msg.take(),
+                // The context is gone, e.g. the node shut down
+                Err(_) => break None,
+            };
+
+            if EntitySecureChannelLocalInfo::find_info(msg.local_message()).is_err() {
+                if msg.sender() == channel
+                    && matches!(
+                        EntityChannelMessage::decode(msg.payload()),
+                        Ok(EntityChannelMessage::Close)
+                    )
+                {
+                    break None;
+                }
+                warn!(
+                    "ChannelStream at {} dropped a message that didn't come through a secure channel",
+                    ctx.address()
+                );
+                continue;
+            }
+
+            let msg_addr = msg.msg_addr();
+            let local_msg = msg.into_local_message();
+            break Some(
+                M::decode(&local_msg.transport().payload)
+                    .map(|body| Routed::new(body, msg_addr, local_msg)),
+            );
+        };
+
+        (ctx, item)
+    }
+}
+
+// Nothing is pinned in place, the pending receive is boxed
+impl<M: Message> Unpin for ChannelStream<M> {}
+
+impl<M: Message> Stream for ChannelStream<M> {
+    type Item = Result<Routed<M>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match core::mem::replace(&mut this.state, StreamState::Done) {
+                StreamState::Idle(ctx) => {
+                    this.state = StreamState::Receiving(Box::pin(Self::next_message(
+                        ctx,
+                        this.channel.clone(),
+                    )));
+                }
+                StreamState::Receiving(mut next) => {
+                    return match next.as_mut().poll(cx) {
+                        Poll::Ready((ctx, Some(item))) => {
+                            this.state = StreamState::Idle(ctx);
+                            Poll::Ready(Some(item))
+                        }
+                        Poll::Ready((_, None)) => Poll::Ready(None),
+                        Poll::Pending => {
+                            this.state = StreamState::Receiving(next);
+                            Poll::Pending
+                        }
+                    }
+                }
+                StreamState::Done => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ChannelStream, Entity, EntitySecureChannelLocalInfo, Identity, TrustEveryonePolicy,
+    };
+    use core::time::Duration;
+    use futures::StreamExt;
+    use ockam_core::compat::{string::String, vec::Vec};
+    use ockam_core::{route, Result};
+    use ockam_node::tokio::time::timeout;
+    use ockam_node::Context;
+    use ockam_vault_sync_core::Vault;
+
+    #[ockam_macros::test]
+    async fn test_channel_stream(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let alice_id = alice.identifier().await?;
+
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        ctx.send(route![alice_channel.clone(), ctx.address()], String::new())
+            .await?;
+        ctx.receive::<String>().await?;
+        let bob_channel = bob.secure_channels().await?[0].address().clone();
+
+        let mut stream = ChannelStream::<String>::create(ctx, &bob_channel).await?;
+        for i in 0..5 {
+            ctx.send(
+                route![alice_channel.clone(), stream.address().clone()],
+                i.to_string(),
+            )
+            .await?;
+        }
+
+        let received: Vec<_> = stream.by_ref().take(5).collect().await;
+        for (i, msg) in received.into_iter().enumerate() {
+            let msg = msg?;
+            let info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+            assert_eq!(info.their_profile_id(), &alice_id);
+            assert_eq!(msg.body(), i.to_string());
+        }
+
+        alice.stop_secure_channel(&alice_channel).await?;
+        let end = timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("stream didn't end");
+        assert!(end.is_none());
+        assert!(stream.next().await.is_none());
+
+        ctx.stop().await
+    }
+}
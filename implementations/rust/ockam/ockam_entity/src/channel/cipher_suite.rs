@@ -0,0 +1,57 @@
+use crate::KeyExchangePattern;
+use serde::{Deserialize, Serialize};
+
+/// Curve of the Diffie-Hellman key agreement
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DhCurve {
+    X25519,
+}
+
+/// Cipher encrypting and authenticating the messages
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AeadAlgorithm {
+    Aes256Gcm,
+}
+
+/// Hash of the handshake transcript and key derivation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    Sha256,
+}
+
+/// Primitives a secure channel negotiated, see [`SecureChannelHandle::cipher_suite`](crate::SecureChannelHandle::cipher_suite).
+/// The key exchange pattern is the only choice so far, both patterns use the same primitives
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecureChannelCipherSuite {
+    key_exchange: KeyExchangePattern,
+    dh: DhCurve,
+    aead: AeadAlgorithm,
+    hash: HashAlgorithm,
+}
+
+impl SecureChannelCipherSuite {
+    pub(crate) fn new(key_exchange: KeyExchangePattern) -> Self {
+        Self {
+            key_exchange,
+            dh: DhCurve::X25519,
+            aead: AeadAlgorithm::Aes256Gcm,
+            hash: HashAlgorithm::Sha256,
+        }
+    }
+
+    pub fn key_exchange(&self) -> KeyExchangePattern {
+        self.key_exchange
+    }
+
+    pub fn dh(&self) -> DhCurve {
+        self.dh
+    }
+
+    pub fn aead(&self) -> AeadAlgorithm {
+        self.aead
+    }
+
+    pub fn hash(&self) -> HashAlgorithm {
+        self.hash
+    }
+}
@@ -0,0 +1,101 @@
+use crate::EntityError;
+use core::convert::TryInto;
+use ockam_core::compat::vec::Vec;
+use ockam_core::Result;
+
+/// Payloads shorter than that are sent as they are, LZ4 rarely gets them smaller
+const MIN_COMPRESSED_SIZE: usize = 64;
+/// Decompressed payloads can't be bigger than that, so that a peer can't have us allocate more
+const MAX_DECOMPRESSED_SIZE: usize = 16 * 1024 * 1024;
+
+const UNCOMPRESSED_TAG: u8 = 0;
+/// Followed by the decompressed length as u32 LE, then the LZ4 block
+const LZ4_TAG: u8 = 1;
+const LZ4_HEADER_LEN: usize = 5;
+
+/// Frame a payload of a channel that negotiated compression, compressing it unless
+/// that would make it bigger
+pub(crate) fn compress_payload(payload: Vec<u8>) -> Vec<u8> {
+    if payload.len() >= MIN_COMPRESSED_SIZE && payload.len() <= MAX_DECOMPRESSED_SIZE {
+        let compressed = lz4_flex::compress(&payload);
+        if compressed.len() + LZ4_HEADER_LEN < payload.len() + 1 {
+            let mut frame = Vec::with_capacity(compressed.len() + LZ4_HEADER_LEN);
+            frame.push(LZ4_TAG);
+            frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            frame.extend_from_slice(&compressed);
+            return frame;
+        }
+    }
+
+    let mut frame = Vec::with_capacity(payload.len() + 1);
+    frame.push(UNCOMPRESSED_TAG);
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+/// Reverse [`compress_payload`]. Fails with [`EntityError::MalformedCompressedPayload`]
+pub(crate) fn decompress_payload(frame: &[u8]) -> Result<Vec<u8>> {
+    match frame.split_first() {
+        Some((&UNCOMPRESSED_TAG, payload)) => Ok(payload.to_vec()),
+        Some((&LZ4_TAG, rest)) if rest.len() >= LZ4_HEADER_LEN - 1 => {
+            let (len, compressed) = rest.split_at(LZ4_HEADER_LEN - 1);
+            let len = u32::from_le_bytes(len.try_into().map_err(|_| malformed())?) as usize;
+            if len > MAX_DECOMPRESSED_SIZE {
+                return Err(malformed());
+            }
+
+            let payload = lz4_flex::decompress(compressed, len).map_err(|_| malformed())?;
+            if payload.len() != len {
+                return Err(malformed());
+            }
+
+            Ok(payload)
+        }
+        _ => Err(malformed()),
+    }
+}
+
+fn malformed() -> ockam_core::Error {
+    EntityError::MalformedCompressedPayload.into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compression_round_trip() -> Result<()> {
+        let json = br#"{"temperature":21.5,"humidity":40,"unit":"celsius"}"#.repeat(100);
+        let frame = compress_payload(json.clone());
+        assert_eq!(frame[0], LZ4_TAG);
+        assert!(frame.len() < json.len() / 4);
+        assert_eq!(decompress_payload(&frame)?, json);
+
+        // Small and incompressible payloads don't grow by more than the tag
+        for payload in [b"ping".to_vec(), (0..=255u8).collect()].iter() {
+            let frame = compress_payload(payload.clone());
+            assert_eq!(frame[0], UNCOMPRESSED_TAG);
+            assert_eq!(frame.len(), payload.len() + 1);
+            assert_eq!(&decompress_payload(&frame)?, payload);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_malformed() {
+        let mut too_big = vec![LZ4_TAG];
+        too_big.extend_from_slice(&u32::MAX.to_le_bytes());
+        too_big.extend_from_slice(&[0; 16]);
+
+        let mut bad_len = compress_payload(b"a".repeat(1000));
+        bad_len[1] = 0xff;
+
+        for frame in [vec![], vec![2, 0], vec![LZ4_TAG, 0], too_big, bad_len].iter() {
+            assert_eq!(
+                decompress_payload(frame).err().unwrap().code(),
+                ockam_core::Error::from(EntityError::MalformedCompressedPayload).code()
+            );
+        }
+    }
+}
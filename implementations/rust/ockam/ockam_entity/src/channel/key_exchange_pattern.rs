@@ -0,0 +1,359 @@
+use crate::{EntityError, SECURE_CHANNEL_PROTOCOL_VERSION};
+use ockam_core::compat::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use ockam_core::{async_trait, Result};
+use ockam_key_exchange_core::{CompletedKeyExchange, KeyExchanger};
+#[cfg(feature = "x3dh")]
+use ockam_key_exchange_x3dh::X3dhVault;
+use ockam_key_exchange_xx::XXVault;
+use serde::{Deserialize, Serialize};
+
+/// Key agreement pattern of the regular SecureChannel underneath an entity secure channel.
+/// Listeners accept every pattern, the initiator picks one
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyExchangePattern {
+    /// Noise XX
+    Xx,
+    /// X3DH
+    #[cfg(feature = "x3dh")]
+    X3dh,
+}
+
+impl Default for KeyExchangePattern {
+    fn default() -> Self {
+        Self::Xx
+    }
+}
+
+/// Marks a service name ahead of the pattern tag
+const SERVICE_TAG: u8 = 0;
+/// Asks the listener to derive trust from the channel the message came through, goes first
+const INHERITED_TAG: u8 = 0xff;
+/// Asks the listener to skip the identity exchange, goes first
+const ANONYMOUS_TAG: u8 = 0xfe;
+/// Marks the protocol version of the initiator, 2 bytes big endian. Goes ahead of
+/// everything else, so that it's read whatever follows it in later versions
+const VERSION_TAG: u8 = 0xfd;
+/// Version of initiators whose header doesn't start with [`VERSION_TAG`]
+const UNVERSIONED_PROTOCOL_VERSION: u16 = 1;
+
+impl KeyExchangePattern {
+    fn tag(&self) -> u8 {
+        match self {
+            Self::Xx => 1,
+            #[cfg(feature = "x3dh")]
+            Self::X3dh => 2,
+        }
+    }
+}
+
+/// What the initiator tells the listener ahead of the first key exchange message
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct KeyExchangeHeader {
+    /// Highest protocol version the initiator speaks. The listener settles on a version
+    /// from it, before decoding anything whose layout depends on the version
+    pub protocol_version: u16,
+    pub pattern: KeyExchangePattern,
+    /// Service the initiator asks for
+    pub service: Option<String>,
+    /// Trust is derived from the channel the message came through
+    pub inherited_trust: bool,
+    /// Neither side proves an identity
+    pub anonymous: bool,
+    /// Nothing is sent, for listeners that don't expect a tag. Only Noise XX without any of the above
+    pub untagged: bool,
+}
+
+impl KeyExchangeHeader {
+    pub fn new(pattern: KeyExchangePattern) -> Self {
+        Self {
+            protocol_version: SECURE_CHANNEL_PROTOCOL_VERSION,
+            pattern,
+            service: None,
+            inherited_trust: false,
+            anonymous: false,
+            untagged: false,
+        }
+    }
+
+    /// Tag of the first key exchange message
+    fn encode(&self) -> Result<Vec<u8>> {
+        if self.untagged {
+            if self.pattern != KeyExchangePattern::Xx
+                || self.service.is_some()
+                || self.inherited_trust
+                || self.anonymous
+            {
+                return Err(EntityError::KeyExchangePatternMismatch.into());
+            }
+            return Ok(Vec::new());
+        }
+
+        let mut header = vec![VERSION_TAG];
+        header.extend_from_slice(&self.protocol_version.to_be_bytes());
+        if self.inherited_trust {
+            header.push(INHERITED_TAG);
+        }
+        if self.anonymous {
+            header.push(ANONYMOUS_TAG);
+        }
+        if let Some(service) = &self.service {
+            if service.is_empty() || service.len() > u8::MAX as usize {
+                return Err(EntityError::InvalidSecureChannelService.into());
+            }
+            header.push(SERVICE_TAG);
+            header.push(service.len() as u8);
+            header.extend_from_slice(service.as_bytes());
+        }
+        header.push(self.pattern.tag());
+
+        Ok(header)
+    }
+
+    /// Split the header off the first key exchange message
+    pub fn untag(payload: &[u8]) -> Result<(Self, &[u8])> {
+        let (protocol_version, payload) = match payload.split_first() {
+            Some((&VERSION_TAG, payload)) => {
+                if payload.len() < 2 {
+                    return Err(EntityError::KeyExchangePatternMismatch.into());
+                }
+                let (version, payload) = payload.split_at(2);
+                (u16::from_be_bytes([version[0], version[1]]), payload)
+            }
+            _ => (UNVERSIONED_PROTOCOL_VERSION, payload),
+        };
+
+        let (inherited_trust, payload) = match payload.split_first() {
+            Some((&INHERITED_TAG, payload)) => (true, payload),
+            _ => (false, payload),
+        };
+
+        let (anonymous, payload) = match payload.split_first() {
+            Some((&ANONYMOUS_TAG, payload)) => (true, payload),
+            _ => (false, payload),
+        };
+
+        let (service, payload) = match payload.split_first() {
+            Some((&SERVICE_TAG, payload)) => {
+                let (len, payload) = payload
+                    .split_first()
+                    .ok_or(EntityError::InvalidSecureChannelService)?;
+                let len = *len as usize;
+                if len == 0 || payload.len() < len {
+                    return Err(EntityError::InvalidSecureChannelService.into());
+                }
+                let (service, payload) = payload.split_at(len);
+                let service = core::str::from_utf8(service)
+                    .map_err(|_| EntityError::InvalidSecureChannelService)?;
+                (Some(service.to_string()), payload)
+            }
+            _ => (None, payload),
+        };
+
+        let (tag, payload) = payload
+            .split_first()
+            .ok_or(EntityError::KeyExchangePatternMismatch)?;
+
+        let pattern = match tag {
+            1 => KeyExchangePattern::Xx,
+            #[cfg(feature = "x3dh")]
+            2 => KeyExchangePattern::X3dh,
+            _ => return Err(EntityError::KeyExchangePatternMismatch.into()),
+        };
+
+        Ok((
+            Self {
+                protocol_version,
+                pattern,
+                service,
+                inherited_trust,
+                anonymous,
+                untagged: false,
+            },
+            payload,
+        ))
+    }
+
+    /// Header of listeners that don't expect a tag. It carries no version,
+    /// the listener speaks its own
+    pub fn untagged() -> Self {
+        Self {
+            untagged: true,
+            ..Self::new(KeyExchangePattern::Xx)
+        }
+    }
+}
+
+/// Vault supporting every [`KeyExchangePattern`]
+#[cfg(feature = "x3dh")]
+pub(crate) trait EntityChannelVault: XXVault + X3dhVault {}
+
+#[cfg(feature = "x3dh")]
+impl<D> EntityChannelVault for D where D: XXVault + X3dhVault {}
+
+/// Vault supporting every [`KeyExchangePattern`]
+#[cfg(not(feature = "x3dh"))]
+pub(crate) trait EntityChannelVault: XXVault {}
+
+#[cfg(not(feature = "x3dh"))]
+impl<D> EntityChannelVault for D where D: XXVault {}
+
+/// Tags the first message of the initiator, so that the listener can pick the matching responder
+pub(crate) struct TaggedInitiator<K: KeyExchanger> {
+    inner: K,
+    tag: Option<Vec<u8>>,
+}
+
+impl<K: KeyExchanger> TaggedInitiator<K> {
+    pub fn new(inner: K, header: &KeyExchangeHeader) -> Result<Self> {
+        Ok(Self {
+            inner,
+            tag: Some(header.encode()?),
+        })
+    }
+}
+
+#[async_trait]
+impl<K: KeyExchanger + Send + Sync> KeyExchanger for TaggedInitiator<K> {
+    async fn name(&self) -> Result<String> {
+        self.inner.name().await
+    }
+
+    async fn generate_request(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut request = self.inner.generate_request(payload).await?;
+        if let Some(mut tag) = self.tag.take() {
+            tag.append(&mut request);
+            request = tag;
+        }
+
+        Ok(request)
+    }
+
+    async fn handle_response(&mut self, response: &[u8]) -> Result<Vec<u8>> {
+        self.inner.handle_response(response).await
+    }
+
+    async fn is_complete(&self) -> Result<bool> {
+        self.inner.is_complete().await
+    }
+
+    async fn finalize(self) -> Result<CompletedKeyExchange> {
+        self.inner.finalize().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{KeyExchangeHeader, KeyExchangePattern, SECURE_CHANNEL_PROTOCOL_VERSION};
+    use ockam_core::compat::vec::Vec;
+
+    fn tagged(header: &KeyExchangeHeader) -> Vec<u8> {
+        let mut tagged = header.encode().unwrap();
+        tagged.push(42);
+        tagged
+    }
+
+    #[test]
+    fn test_untag() {
+        for pattern in [
+            KeyExchangePattern::Xx,
+            #[cfg(feature = "x3dh")]
+            KeyExchangePattern::X3dh,
+        ] {
+            let header = KeyExchangeHeader::new(pattern);
+            let (untagged, payload) = KeyExchangeHeader::untag(&tagged(&header)).unwrap();
+            assert_eq!(untagged, header);
+            assert_eq!(payload, &[42]);
+        }
+
+        assert!(KeyExchangeHeader::untag(&[]).is_err());
+        assert!(KeyExchangeHeader::untag(&[3, 42]).is_err());
+    }
+
+    #[cfg(feature = "x3dh")]
+    #[test]
+    fn test_untag_service() {
+        let mut header = KeyExchangeHeader::new(KeyExchangePattern::X3dh);
+        header.service = Some("printer".into());
+        let tagged = tagged(&header);
+        let (untagged, payload) = KeyExchangeHeader::untag(&tagged).unwrap();
+        assert_eq!(untagged, header);
+        assert_eq!(payload, &[42]);
+
+        header.service = Some("".into());
+        assert!(header.encode().is_err());
+        // Name longer than the remaining payload
+        assert!(KeyExchangeHeader::untag(&[0, 8, b'a', 1, 42]).is_err());
+    }
+
+    #[test]
+    fn test_untag_inherited_trust() {
+        let mut header = KeyExchangeHeader::new(KeyExchangePattern::Xx);
+        header.service = Some("printer".into());
+        header.inherited_trust = true;
+        let tagged = tagged(&header);
+        let (untagged, payload) = KeyExchangeHeader::untag(&tagged).unwrap();
+        assert_eq!(untagged, header);
+        assert_eq!(payload, &[42]);
+
+        // The tag only goes first
+        assert!(
+            !KeyExchangeHeader::untag(&[1, 0xff, 42])
+                .unwrap()
+                .0
+                .inherited_trust
+        );
+        assert!(KeyExchangeHeader::untag(&[0xff]).is_err());
+    }
+
+    #[test]
+    fn test_untag_protocol_version() {
+        let mut header = KeyExchangeHeader::new(KeyExchangePattern::Xx);
+        header.protocol_version = 0x0102;
+        let tagged = tagged(&header);
+        assert_eq!(tagged, vec![0xfd, 1, 2, 1, 42]);
+        let (untagged, payload) = KeyExchangeHeader::untag(&tagged).unwrap();
+        assert_eq!(untagged, header);
+        assert_eq!(payload, &[42]);
+
+        // Initiators that predate the version tag speak the first version
+        let (untagged, payload) = KeyExchangeHeader::untag(&[1, 42]).unwrap();
+        assert_eq!(untagged.protocol_version, 1);
+        assert_eq!(payload, &[42]);
+
+        assert!(KeyExchangeHeader::untag(&[0xfd, 0]).is_err());
+        assert_eq!(
+            KeyExchangeHeader::untagged().protocol_version,
+            SECURE_CHANNEL_PROTOCOL_VERSION
+        );
+    }
+
+    #[test]
+    fn test_untag_anonymous() {
+        let mut header = KeyExchangeHeader::new(KeyExchangePattern::Xx);
+        header.anonymous = true;
+        let tagged = tagged(&header);
+        assert_eq!(tagged[3..], [0xfe, 1, 42]);
+        let (untagged, payload) = KeyExchangeHeader::untag(&tagged).unwrap();
+        assert_eq!(untagged, header);
+        assert_eq!(payload, &[42]);
+
+        assert!(KeyExchangeHeader::untag(&[0xfe]).is_err());
+    }
+
+    #[test]
+    fn test_untagged() {
+        let header = KeyExchangeHeader::untagged();
+        assert_eq!(tagged(&header), vec![42]);
+
+        let mut header = KeyExchangeHeader::untagged();
+        header.service = Some("printer".into());
+        assert!(header.encode().is_err());
+        let mut header = KeyExchangeHeader::untagged();
+        header.anonymous = true;
+        assert!(header.encode().is_err());
+    }
+}
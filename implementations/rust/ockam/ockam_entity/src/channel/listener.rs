@@ -1,52 +1,154 @@
-use crate::{Identity, SecureChannelWorker, TrustPolicy};
-use ockam_channel::{CreateResponderChannelMessage, SecureChannel};
-use ockam_core::compat::boxed::Box;
-use ockam_core::compat::rand::random;
+use crate::{
+    handshake_digest, AddressGenerator, ChannelCounter, EntityChannelVault, EntityError,
+    EntitySecureChannelLocalInfo, Identity, KeyExchangeHeader, KeyExchangePattern, PreSharedKey,
+    PskMismatches, PskNewKeyExchanger, ResponderSetup, SecureChannelHandshakes,
+    SecureChannelListenerOptions, SecureChannelRegistry, SecureChannelServices,
+    SecureChannelTrustInfo, SecureChannelWorker, TrustPolicy, TrustPolicyImpl,
+    SECURE_CHANNEL_PROTOCOL_VERSION,
+};
+use core::time::Duration;
+use ockam_channel::{CreateResponderChannelMessage, SecureChannelListener};
+use ockam_core::compat::{boxed::Box, collections::VecDeque};
 use ockam_core::{Address, Result, Routed, Worker};
-use ockam_key_exchange_xx::{XXNewKeyExchanger, XXVault};
+#[cfg(feature = "x3dh")]
+use ockam_key_exchange_x3dh::X3dhNewKeyExchanger;
+use ockam_key_exchange_xx::XXNewKeyExchanger;
 use ockam_node::Context;
+use tracing::{debug, warn};
 
-pub(crate) struct ProfileChannelListener<T: TrustPolicy, P: Identity, V: XXVault> {
+/// First messages of handshakes a listener remembers, to drop them if they arrive again
+const RECENT_HANDSHAKES: usize = 64;
+
+pub(crate) struct ProfileChannelListener<T: TrustPolicy, P: Identity, V: EntityChannelVault> {
     trust_policy: T,
     profile: P,
     vault: V,
-    listener_address: Address,
+    xx_listener_address: Address,
+    #[cfg(feature = "x3dh")]
+    x3dh_listener_address: Address,
+    max_channels: Option<usize>,
+    strict_trust: bool,
+    /// Accept channels created with [`SecureChannelOptions::with_anonymous`](crate::SecureChannelOptions::with_anonymous)
+    allow_anonymous: bool,
+    /// Larger handshake messages are rejected before they are parsed
+    max_handshake_message_size: usize,
+    /// Mixed into the keys of every channel, see [`SecureChannelOptions::with_psk`](crate::SecureChannelOptions::with_psk)
+    psk: Option<PreSharedKey>,
+    /// See [`SecureChannelListenerOptions::with_unstamped_local_hops`]
+    unstamped_local_hops: bool,
+    /// See [`SecureChannelListenerOptions::with_handshake_timeout`]
+    handshake_timeout: Duration,
+    /// See [`SecureChannelListenerOptions::with_untagged_key_exchange`]
+    untagged_key_exchange: bool,
+    /// See [`SecureChannelListenerOptions::with_min_protocol_version`]
+    min_protocol_version: u16,
+    channels: ChannelCounter,
+    registry: SecureChannelRegistry,
+    /// Trust policies of the services initiators may name, instead of the default one
+    services: SecureChannelServices,
+    handshakes: SecureChannelHandshakes,
+    addresses: AddressGenerator,
+    /// [`handshake_digest`] of the first messages of recent handshakes, oldest first
+    recent_handshakes: VecDeque<[u8; 32]>,
+    /// Filled by the regular SecureChannel listeners, looked up by the responders
+    psk_mismatches: PskMismatches,
 }
 
-impl<T: TrustPolicy, P: Identity, V: XXVault> ProfileChannelListener<T, P, V> {
-    pub fn new(trust_policy: T, profile: P, vault: V) -> Self {
-        let listener_address: Address = random();
+impl<T: TrustPolicy, P: Identity, V: EntityChannelVault> ProfileChannelListener<T, P, V> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        trust_policy: T,
+        profile: P,
+        vault: V,
+        options: SecureChannelListenerOptions,
+        registry: SecureChannelRegistry,
+        services: SecureChannelServices,
+        handshakes: SecureChannelHandshakes,
+        addresses: AddressGenerator,
+    ) -> Self {
         ProfileChannelListener {
             trust_policy,
             profile,
             vault,
-            listener_address,
+            xx_listener_address: addresses.generate(),
+            #[cfg(feature = "x3dh")]
+            x3dh_listener_address: addresses.generate(),
+            max_channels: options.max_channels(),
+            strict_trust: options.strict_trust(),
+            allow_anonymous: options.allow_anonymous(),
+            max_handshake_message_size: options.max_handshake_message_size(),
+            psk: options.psk().cloned(),
+            unstamped_local_hops: options.unstamped_local_hops(),
+            handshake_timeout: options.handshake_timeout(),
+            untagged_key_exchange: options.untagged_key_exchange(),
+            min_protocol_version: options.min_protocol_version(),
+            channels: ChannelCounter::default(),
+            registry,
+            services,
+            handshakes,
+            addresses,
+            recent_handshakes: VecDeque::new(),
+            psk_mismatches: PskMismatches::default(),
+        }
+    }
+
+    fn listener_address(&self, pattern: KeyExchangePattern) -> Address {
+        match pattern {
+            KeyExchangePattern::Xx => self.xx_listener_address.clone(),
+            #[cfg(feature = "x3dh")]
+            KeyExchangePattern::X3dh => self.x3dh_listener_address.clone(),
         }
     }
 }
 
 #[ockam_core::worker]
-impl<T: TrustPolicy, P: Identity, V: XXVault> Worker for ProfileChannelListener<T, P, V> {
+impl<T: TrustPolicy, P: Identity, V: EntityChannelVault> Worker
+    for ProfileChannelListener<T, P, V>
+{
     type Message = CreateResponderChannelMessage;
     type Context = Context;
 
     async fn initialize(&mut self, ctx: &mut Self::Context) -> Result<()> {
-        let new_key_exchanger = XXNewKeyExchanger::new(self.vault.async_try_clone().await?);
+        let new_key_exchanger = PskNewKeyExchanger::new(
+            XXNewKeyExchanger::new(self.vault.async_try_clone().await?),
+            self.vault.async_try_clone().await?,
+            self.psk.clone(),
+            self.psk_mismatches.clone(),
+        );
         let vault = self.vault.async_try_clone().await?;
-        SecureChannel::create_listener_extended(
-            ctx,
-            self.listener_address.clone(),
-            new_key_exchanger,
-            vault,
-        )
-        .await?;
+        let listener = SecureChannelListener::new(new_key_exchanger, vault)
+            .with_pending_handshakes(self.handshakes.key_exchanges.clone())
+            .with_max_handshake_message_size(self.max_handshake_message_size);
+        ctx.start_worker(self.xx_listener_address.clone(), listener)
+            .await?;
+
+        #[cfg(feature = "x3dh")]
+        {
+            let new_key_exchanger = PskNewKeyExchanger::new(
+                X3dhNewKeyExchanger::new(self.vault.async_try_clone().await?),
+                self.vault.async_try_clone().await?,
+                self.psk.clone(),
+                self.psk_mismatches.clone(),
+            );
+            let vault = self.vault.async_try_clone().await?;
+            let listener = SecureChannelListener::new(new_key_exchanger, vault)
+                .with_pending_handshakes(self.handshakes.key_exchanges.clone())
+                .with_max_handshake_message_size(self.max_handshake_message_size);
+            ctx.start_worker(self.x3dh_listener_address.clone(), listener)
+                .await?;
+        }
 
         Ok(())
     }
 
     async fn shutdown(&mut self, ctx: &mut Self::Context) -> Result<()> {
+        // Usually done by the entity worker already, unless the node is shutting down
+        self.handshakes.cancel(ctx).await?;
+
         // Ignore the error in case node is shutting down and this listener was stopped already
-        let _ = ctx.stop_worker(self.listener_address.clone()).await;
+        let _ = ctx.stop_worker(self.xx_listener_address.clone()).await;
+        #[cfg(feature = "x3dh")]
+        let _ = ctx.stop_worker(self.x3dh_listener_address.clone()).await;
 
         Ok(())
     }
@@ -56,15 +158,174 @@ impl<T: TrustPolicy, P: Identity, V: XXVault> Worker for ProfileChannelListener<
         ctx: &mut Self::Context,
         msg: Routed<Self::Message>,
     ) -> Result<()> {
-        let trust_policy = self.trust_policy.async_try_clone().await?;
+        // Before anything else looks at it, the initiator isn't authenticated
+        if msg.as_body().payload().len() > self.max_handshake_message_size {
+            warn!(
+                "Rejecting SecureChannel at: {}, handshake message of {} bytes",
+                ctx.address(),
+                msg.as_body().payload().len()
+            );
+            return Err(EntityError::SecureChannelHandshakeMessageTooLarge.into());
+        }
+
+        // A lossy transport delivering the first message again would start a second responder,
+        // which never completes
+        let digest = handshake_digest(msg.as_body().payload());
+        if self.recent_handshakes.contains(&digest) {
+            debug!(
+                "Ignored repeated SecureChannel handshake at: {}",
+                ctx.address()
+            );
+            return Ok(());
+        }
+        if self.recent_handshakes.len() >= RECENT_HANDSHAKES {
+            self.recent_handshakes.pop_front();
+        }
+        self.recent_handshakes.push_back(digest);
+
+        let header = if self.untagged_key_exchange {
+            Ok((KeyExchangeHeader::untagged(), msg.as_body().payload()))
+        } else {
+            KeyExchangeHeader::untag(msg.as_body().payload())
+        };
+        let (header, payload) = match header {
+            Ok((header, payload)) => (header, payload.to_vec()),
+            Err(err) => {
+                warn!(
+                    "{} rejecting SecureChannel with unsupported key exchange at: {}",
+                    err,
+                    ctx.address()
+                );
+                return Err(err);
+            }
+        };
+
+        let mut slot = if self.handshakes.is_draining() {
+            warn!("Rejecting SecureChannel at: {}, draining", ctx.address());
+            Err(EntityError::SecureChannelListenerDraining.into())
+        } else {
+            match self.channels.acquire(self.max_channels) {
+                Some(slot) => Ok(slot),
+                None => {
+                    warn!(
+                        "Rejecting SecureChannel at: {}, {} channels are open already",
+                        ctx.address(),
+                        self.channels.count()
+                    );
+                    Err(EntityError::SecureChannelListenerAtCapacity.into())
+                }
+            }
+        };
+
+        let KeyExchangeHeader {
+            protocol_version,
+            pattern,
+            service,
+            inherited_trust,
+            anonymous,
+            ..
+        } = header;
+
+        // Versions newer than ours are unknown to us, but the initiator speaks ours too
+        let protocol_version = SECURE_CHANNEL_PROTOCOL_VERSION.min(protocol_version);
+        if protocol_version < self.min_protocol_version {
+            warn!(
+                "Rejecting SecureChannel at: {}, protocol version {} is too old",
+                ctx.address(),
+                protocol_version
+            );
+            slot = Err(EntityError::SecureChannelProtocolVersionTooOld.into());
+        }
+
+        if anonymous && (!self.allow_anonymous || inherited_trust) {
+            warn!("Rejecting anonymous SecureChannel at: {}", ctx.address());
+            slot = Err(EntityError::AnonymousSecureChannelRejected.into());
+        }
+
+        let service_trust_policy = match &service {
+            Some(service) => match self.services.get(service) {
+                Some(address) => Some(TrustPolicyImpl::create_using_worker(ctx, &address).await?),
+                None => {
+                    warn!(
+                        "Rejecting SecureChannel at: {}, unknown service: {}",
+                        ctx.address(),
+                        service
+                    );
+                    slot = Err(EntityError::UnknownSecureChannelService.into());
+                    None
+                }
+            },
+            None => None,
+        };
+
+        // Only entity secure channels tell who sent the messages they deliver
+        let inherited_trust = if inherited_trust {
+            match EntitySecureChannelLocalInfo::find_info(msg.local_message()) {
+                // Nothing to inherit from an anonymous channel
+                Ok(info) if !info.is_anonymous() => Some(
+                    SecureChannelTrustInfo::new_with_public_key(
+                        info.their_profile_id().clone(),
+                        info.their_public_key().cloned(),
+                    )
+                    .with_inherited_attributes(info.their_attributes().clone()),
+                ),
+                _ => {
+                    warn!(
+                        "Rejecting SecureChannel at: {}, no channel to inherit trust from",
+                        ctx.address()
+                    );
+                    slot = Err(EntityError::InheritedTrustUnavailable.into());
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         let profile = self.profile.async_try_clone().await?;
-        SecureChannelWorker::create_responder(
-            ctx,
-            profile,
-            trust_policy,
-            self.listener_address.clone(),
-            msg,
-        )
-        .await
+        let setup = ResponderSetup {
+            listener_address: self.listener_address(pattern),
+            slot,
+            registry: self.registry.clone(),
+            handshakes: self.handshakes.clone(),
+            strict_trust: self.strict_trust,
+            key_exchange: pattern,
+            inherited_trust,
+            anonymous,
+            protocol_version,
+            min_protocol_version: self.min_protocol_version,
+            addresses: self.addresses.clone(),
+            listener: ctx.address(),
+            service,
+            max_handshake_message_size: self.max_handshake_message_size,
+            unstamped_local_hops: self.unstamped_local_hops,
+            handshake_timeout: self.handshake_timeout,
+            psk_mismatches: self.psk_mismatches.clone(),
+        };
+        match service_trust_policy {
+            Some(trust_policy) => {
+                SecureChannelWorker::create_responder(
+                    ctx,
+                    profile,
+                    trust_policy,
+                    msg,
+                    &payload,
+                    setup,
+                )
+                .await
+            }
+            None => {
+                let trust_policy = self.trust_policy.async_try_clone().await?;
+                SecureChannelWorker::create_responder(
+                    ctx,
+                    profile,
+                    trust_policy,
+                    msg,
+                    &payload,
+                    setup,
+                )
+                .await
+            }
+        }
     }
 }
@@ -1,5 +1,7 @@
 use crate::{EntityError, ProfileIdentifier};
-use ockam_core::{Decodable, Encodable, LocalInfo, LocalMessage, Result};
+use ockam_core::compat::{collections::BTreeMap, string::String};
+use ockam_core::vault::PublicKey;
+use ockam_core::{Decodable, Encodable, LocalInfo, LocalMessage, Result, Route};
 use serde::{Deserialize, Serialize};
 
 /// Entity SecureChannel LocalInfo unique Identifier
@@ -9,9 +11,15 @@ pub const ENTITY_SECURE_CHANNEL_IDENTIFIER: &str = "ENTITY_SECURE_CHANNEL_IDENTI
 #[derive(Serialize, Deserialize)]
 pub struct EntitySecureChannelLocalInfo {
     their_profile_id: ProfileIdentifier,
+    their_public_key: Option<PublicKey>,
+    their_attributes: BTreeMap<String, String>,
+    their_profile_attributes: BTreeMap<String, String>,
+    their_route: Route,
+    anonymous: bool,
 }
 
 impl EntitySecureChannelLocalInfo {
+    /// Fails with [`EntityError::LocalInfoMalformed`] if the data can't be decoded
     pub fn from_local_info(value: &LocalInfo) -> Result<Self> {
         if value.type_identifier() != ENTITY_SECURE_CHANNEL_IDENTIFIER {
             return Err(EntityError::InvalidLocalInfoType.into());
@@ -21,7 +29,7 @@ impl EntitySecureChannelLocalInfo {
             return Ok(info);
         }
 
-        Err(EntityError::InvalidLocalInfoType.into())
+        Err(EntityError::LocalInfoMalformed.into())
     }
 
     pub fn to_local_info(&self) -> Result<LocalInfo> {
@@ -31,6 +39,8 @@ impl EntitySecureChannelLocalInfo {
         ))
     }
 
+    /// Fails with [`EntityError::LocalInfoMissing`] if the message didn't come through
+    /// a secure channel, and with [`EntityError::LocalInfoMalformed`] if its info can't be decoded
     pub fn find_info(local_msg: &LocalMessage) -> Result<Self> {
         if let Some(local_info) = local_msg
             .local_info()
@@ -39,21 +49,298 @@ impl EntitySecureChannelLocalInfo {
         {
             Self::from_local_info(local_info)
         } else {
-            Err(EntityError::InvalidLocalInfoType.into())
+            Err(EntityError::LocalInfoMissing.into())
         }
     }
 }
 
 impl EntitySecureChannelLocalInfo {
-    /// Key exchange name
+    /// Key exchange name. Empty for anonymous channels
     pub fn their_profile_id(&self) -> &ProfileIdentifier {
         &self.their_profile_id
     }
+
+    /// Profile public key the peer proved possession of during the handshake
+    pub fn their_public_key(&self) -> Option<&PublicKey> {
+        self.their_public_key.as_ref()
+    }
+
+    /// Attributes of the peer the trust policy verified, see [`TrustDecision::verified_attributes`](crate::TrustDecision::verified_attributes),
+    /// e.g. those of a credential checked by [`TrustCredentialPolicy`](crate::TrustCredentialPolicy)
+    pub fn their_attributes(&self) -> &BTreeMap<String, String> {
+        &self.their_attributes
+    }
+
+    /// Verified credential attribute of the peer with given key
+    pub fn their_attribute(&self, key: &str) -> Option<&str> {
+        self.their_attributes.get(key).map(String::as_str)
+    }
+
+    /// Attributes the peer advertised during the handshake, as in
+    /// [`SecureChannelTrustInfo::their_profile_attributes`](crate::SecureChannelTrustInfo::their_profile_attributes)
+    pub fn their_profile_attributes(&self) -> &BTreeMap<String, String> {
+        &self.their_profile_attributes
+    }
+
+    /// Advertised attribute of the peer with given key
+    pub fn their_profile_attribute(&self, key: &str) -> Option<&str> {
+        self.their_profile_attributes.get(key).map(String::as_str)
+    }
+
+    /// Transport route towards the peer: the route the channel was created with for
+    /// the initiator, the route the handshake arrived on for the responder
+    pub fn their_route(&self) -> &Route {
+        &self.their_route
+    }
+
+    /// The channel was created with [`SecureChannelOptions::with_anonymous`](crate::SecureChannelOptions::with_anonymous),
+    /// so nothing is known about the peer
+    pub fn is_anonymous(&self) -> bool {
+        self.anonymous
+    }
 }
 
 impl EntitySecureChannelLocalInfo {
     /// Constructor
     pub fn new(their_profile_id: ProfileIdentifier) -> Self {
-        Self { their_profile_id }
+        Self::new_with_public_key(their_profile_id, None)
+    }
+
+    /// Constructor with verified public key
+    pub fn new_with_public_key(
+        their_profile_id: ProfileIdentifier,
+        their_public_key: Option<PublicKey>,
+    ) -> Self {
+        Self {
+            their_profile_id,
+            their_public_key,
+            their_attributes: BTreeMap::new(),
+            their_profile_attributes: BTreeMap::new(),
+            their_route: Route::new().into(),
+            anonymous: false,
+        }
+    }
+
+    /// Constructor for anonymous channels
+    pub fn anonymous() -> Self {
+        Self {
+            anonymous: true,
+            ..Self::new(ProfileIdentifier::default())
+        }
+    }
+
+    /// Attach verified credential attributes
+    pub fn with_attributes(mut self, their_attributes: BTreeMap<String, String>) -> Self {
+        self.their_attributes = their_attributes;
+        self
+    }
+
+    /// Attach attributes the peer advertised
+    pub fn with_profile_attributes(
+        mut self,
+        their_profile_attributes: BTreeMap<String, String>,
+    ) -> Self {
+        self.their_profile_attributes = their_profile_attributes;
+        self
+    }
+
+    /// Attach transport route towards the peer
+    pub fn with_route(mut self, their_route: Route) -> Self {
+        self.their_route = their_route;
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Entity, Identity, SecureChannelListenerOptions, TrustEveryonePolicy};
+    use ockam_core::compat::{string::ToString, vec::Vec};
+    use ockam_core::{route, Address, Any, Routed, RouterMessage, TransportMessage, Worker};
+    use ockam_node::Context;
+    use ockam_vault_sync_core::Vault;
+    use std::convert::TryInto;
+
+    fn local_message(local_info: Vec<LocalInfo>) -> LocalMessage {
+        LocalMessage::new(
+            TransportMessage::v1(route!["receiver"], route!["sender"], vec![]),
+            local_info,
+        )
+    }
+
+    fn code(err: EntityError) -> u32 {
+        ockam_core::Error::from(err).code()
+    }
+
+    #[test]
+    fn test_find_info() -> Result<()> {
+        let their_profile_id: ProfileIdentifier =
+            "P79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b6".try_into()?;
+        let local_info =
+            EntitySecureChannelLocalInfo::new(their_profile_id.clone()).to_local_info()?;
+        let info = EntitySecureChannelLocalInfo::find_info(&local_message(vec![local_info]))?;
+        assert_eq!(info.their_profile_id(), &their_profile_id);
+
+        let err = EntitySecureChannelLocalInfo::find_info(&local_message(vec![]))
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), code(EntityError::LocalInfoMissing));
+
+        let corrupted = LocalInfo::new(ENTITY_SECURE_CHANNEL_IDENTIFIER.into(), vec![0xFF; 3]);
+        let err = EntitySecureChannelLocalInfo::find_info(&local_message(vec![corrupted]))
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), code(EntityError::LocalInfoMalformed));
+
+        Ok(())
+    }
+
+    const TRACE_IDENTIFIER: &str = "TRACE_IDENTIFIER";
+
+    /// Middleware passing messages on with their LocalInfo, optionally adding a trace block
+    struct Proxy {
+        trace: Option<Vec<u8>>,
+    }
+
+    #[ockam_core::async_trait]
+    impl Worker for Proxy {
+        type Message = Any;
+        type Context = Context;
+
+        async fn handle_message(&mut self, ctx: &mut Context, msg: Routed<Any>) -> Result<()> {
+            let (mut transport_msg, mut local_info) = msg.into_local_message().into_parts();
+            transport_msg.onward_route.step()?;
+            if let Some(trace) = &self.trace {
+                local_info.push(LocalInfo::new(TRACE_IDENTIFIER.into(), trace.clone()));
+            }
+
+            ctx.forward(LocalMessage::new(transport_msg, local_info))
+                .await
+        }
+    }
+
+    #[ockam_macros::test]
+    async fn test_forward_all_local_info(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+        let channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        ctx.start_worker(
+            "tracer",
+            Proxy {
+                trace: Some(vec![0, 1, 0xFF]),
+            },
+        )
+        .await?;
+        ctx.start_worker("passthrough", Proxy { trace: None })
+            .await?;
+
+        // What the channel attaches, which the proxies know nothing about
+        ctx.send(route![channel.clone(), ctx.address()], "Hello".to_string())
+            .await?;
+        let direct = ctx.receive::<String>().await?.take();
+        let attached = direct.local_message().local_info().to_vec();
+        assert!(attached
+            .iter()
+            .any(|info| info.type_identifier() == ENTITY_SECURE_CHANNEL_IDENTIFIER));
+
+        ctx.send(
+            route![channel, "tracer", "passthrough", ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+
+        let (trace, forwarded) = msg.local_message().local_info().split_last().unwrap();
+        assert_eq!(forwarded, &attached[..]);
+        assert_eq!(trace.type_identifier(), TRACE_IDENTIFIER);
+        assert_eq!(trace.data(), &[0, 1, 0xFF]);
+        let info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert_eq!(info.their_profile_id(), &alice.identifier().await?);
+        assert_eq!(msg.body(), "Hello, Bob!");
+
+        ctx.stop().await
+    }
+    /// Stands in for a transport to another node, which gets messages with their LocalInfo
+    struct FakeTransport;
+
+    #[ockam_core::async_trait]
+    impl Worker for FakeTransport {
+        type Message = RouterMessage;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            ctx: &mut Context,
+            msg: Routed<RouterMessage>,
+        ) -> Result<()> {
+            if let RouterMessage::Route(msg) = msg.body() {
+                let (mut transport_msg, local_info) = msg.into_parts();
+                transport_msg.onward_route.step()?;
+                ctx.forward(LocalMessage::new(transport_msg, local_info))
+                    .await?;
+            }
+
+            Ok(())
+        }
+    }
+
+    #[ockam_macros::test]
+    async fn test_unstamped_local_hops(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener_with_options(
+            "bob_listener",
+            TrustEveryonePolicy,
+            SecureChannelListenerOptions::new().with_unstamped_local_hops(),
+        )
+        .await?;
+        let channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        const FAKE_TRANSPORT: u8 = 42;
+        ctx.start_worker("fake_transport", FakeTransport).await?;
+        ctx.register(FAKE_TRANSPORT, "fake_transport").await?;
+
+        ctx.send(route![channel.clone(), ctx.address()], "local".to_string())
+            .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert_eq!(msg.body(), "local");
+        assert!(EntitySecureChannelLocalInfo::find_info(msg.local_message()).is_err());
+
+        ctx.send(
+            route![
+                channel.clone(),
+                Address::new(FAKE_TRANSPORT, "other_node"),
+                ctx.address()
+            ],
+            "remote".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert_eq!(msg.body(), "remote");
+        let info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert_eq!(info.their_profile_id(), &alice.identifier().await?);
+
+        // Off by default, also on the initiator side
+        let bob_channel = bob.secure_channels().await?[0].clone();
+        ctx.send(
+            route![bob_channel.address().clone(), ctx.address()],
+            "Hello, Alice!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        assert!(EntitySecureChannelLocalInfo::find_info(msg.local_message()).is_ok());
+
+        ctx.stop().await
     }
 }
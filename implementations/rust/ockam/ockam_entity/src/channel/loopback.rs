@@ -0,0 +1,463 @@
+use core::time::Duration;
+use ockam_core::compat::{
+    sync::{Arc, Mutex},
+    vec::Vec,
+};
+use ockam_core::{
+    async_trait, route, Address, Any, Decodable, LocalMessage, Message, Result, Routed, Worker,
+};
+use ockam_node::Context;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+
+/// Held messages are let through once nothing else arrived for that long, even if the
+/// reorder window isn't full
+const REORDER_FLUSH_DELAY: Duration = Duration::from_millis(50);
+
+/// Faults a [`Loopback`] applies to the messages passing it. Changed while the loopback
+/// runs through [`LoopbackControl`], so that e.g. the handshake goes through unharmed
+#[derive(Clone, Copy, Default)]
+pub(crate) struct LoopbackFaults {
+    /// Every message is let through after that long
+    pub delay: Duration,
+    /// Chance of a message to be dropped, from 0 to 1
+    pub drop_probability: f64,
+    /// The first that many messages arriving after the faults were set are dropped
+    pub drop_first: usize,
+    /// Messages are held until that many arrived, then let through in random order.
+    /// Values below 2 keep the order
+    pub reorder_window: usize,
+    /// Message let through twice, by its index among the messages forwarded so far,
+    /// see [`LoopbackControl::forwarded`]
+    pub duplicate: Option<usize>,
+}
+
+/// Sent by the flush timer of the reorder window
+#[derive(Serialize, Deserialize, Message)]
+struct FlushHeld(u64);
+
+#[derive(Default)]
+struct LoopbackState {
+    faults: LoopbackFaults,
+    /// Messages arrived since the faults were set
+    arrived: usize,
+    forwarded: usize,
+    dropped: usize,
+}
+
+/// Test side of a [`Loopback`]
+#[derive(Clone)]
+pub(crate) struct LoopbackControl {
+    state: Arc<Mutex<LoopbackState>>,
+}
+
+impl LoopbackControl {
+    pub fn set_faults(&self, faults: LoopbackFaults) {
+        let mut state = self.state.lock().unwrap();
+        state.faults = faults;
+        state.arrived = 0;
+    }
+
+    /// Messages let through so far
+    pub fn forwarded(&self) -> usize {
+        self.state.lock().unwrap().forwarded
+    }
+
+    /// Messages dropped so far
+    pub fn dropped(&self) -> usize {
+        self.state.lock().unwrap().dropped
+    }
+}
+
+/// In-memory stand-in for a transport connection, for channel tests. It passes messages
+/// to the next hop of their route and prepends itself to the return route, like a
+/// transport does, after applying the configured [`LoopbackFaults`]. Faults are drawn
+/// from a seeded RNG, so that a test sees the same ones on every run
+pub(crate) struct Loopback {
+    state: Arc<Mutex<LoopbackState>>,
+    rng: XorShiftRng,
+    /// Messages held back to be reordered
+    held: Vec<LocalMessage>,
+    /// Address the flush timer of the reorder window sends to
+    flush_address: Address,
+    /// Only the last flush timer counts, earlier ones were overtaken by newer messages
+    flush_id: u64,
+}
+
+impl Loopback {
+    /// Start a loopback without faults at given address. Routes go through it as through
+    /// a transport, e.g. `route![address, "bob_listener"]`
+    pub async fn create(ctx: &Context, address: Address, seed: u64) -> Result<LoopbackControl> {
+        let state = Arc::new(Mutex::new(LoopbackState::default()));
+        let flush_address = Address::random(0);
+        let loopback = Self {
+            state: state.clone(),
+            rng: XorShiftRng::seed_from_u64(seed),
+            held: Vec::new(),
+            flush_address: flush_address.clone(),
+            flush_id: 0,
+        };
+        ctx.start_worker(vec![address, flush_address], loopback)
+            .await?;
+
+        Ok(LoopbackControl { state })
+    }
+
+    async fn forward_after(&self, ctx: &Context, delay: Duration, msg: LocalMessage) -> Result<()> {
+        if delay == Duration::default() {
+            return ctx.forward(msg).await;
+        }
+
+        let child_ctx = ctx.new_context(Address::random(0)).await?;
+        ctx.runtime().spawn(async move {
+            child_ctx.sleep(delay).await;
+            let _ = child_ctx.forward(msg).await;
+        });
+
+        Ok(())
+    }
+
+    async fn release_held(&mut self, ctx: &Context, delay: Duration) -> Result<()> {
+        let mut held = core::mem::take(&mut self.held);
+        held.shuffle(&mut self.rng);
+        for msg in held {
+            self.forward_after(ctx, delay, msg).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Worker for Loopback {
+    type Message = Any;
+    type Context = Context;
+
+    async fn handle_message(&mut self, ctx: &mut Context, msg: Routed<Any>) -> Result<()> {
+        let faults = self.state.lock().unwrap().faults;
+
+        if msg.msg_addr() == self.flush_address {
+            if FlushHeld::decode(msg.payload())?.0 == self.flush_id {
+                self.release_held(ctx, faults.delay).await?;
+            }
+            return Ok(());
+        }
+
+        let arrived = {
+            let mut state = self.state.lock().unwrap();
+            state.arrived += 1;
+            state.arrived
+        };
+        if arrived <= faults.drop_first
+            || self.rng.gen_bool(faults.drop_probability.max(0.0).min(1.0))
+        {
+            self.state.lock().unwrap().dropped += 1;
+            return Ok(());
+        }
+        let index = {
+            let mut state = self.state.lock().unwrap();
+            state.forwarded += 1;
+            state.forwarded - 1
+        };
+
+        let mut local_msg = msg.into_local_message();
+        let transport_msg = local_msg.transport_mut();
+        transport_msg.onward_route.step()?;
+        transport_msg.return_route.modify().prepend(ctx.address());
+
+        if faults.duplicate == Some(index) {
+            self.forward_after(ctx, faults.delay, local_msg.clone())
+                .await?;
+        }
+
+        if faults.reorder_window < 2 && self.held.is_empty() {
+            return self.forward_after(ctx, faults.delay, local_msg).await;
+        }
+
+        self.held.push(local_msg);
+        if self.held.len() >= faults.reorder_window {
+            return self.release_held(ctx, faults.delay).await;
+        }
+
+        self.flush_id += 1;
+        let id = self.flush_id;
+        let child_ctx = ctx.new_context(Address::random(0)).await?;
+        let flush_address = self.flush_address.clone();
+        ctx.runtime().spawn(async move {
+            child_ctx.sleep(REORDER_FLUSH_DELAY).await;
+            let _ = child_ctx.send(route![flush_address], FlushHeld(id)).await;
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Entity, EntityError, SecureChannelOptions, TrustEveryonePolicy};
+    use ockam_core::compat::string::{String, ToString};
+    use ockam_node::tokio::time::timeout;
+    use ockam_vault_sync_core::Vault;
+
+    async fn receive_all(ctx: &mut Context) -> Vec<String> {
+        let mut received = Vec::new();
+        while let Ok(msg) = timeout(Duration::from_millis(500), ctx.receive::<String>()).await {
+            received.push(msg.unwrap().take().body());
+        }
+        received
+    }
+
+    #[ockam_macros::test]
+    async fn test_loopback_drop(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let loopback = Loopback::create(ctx, "loopback".into(), 42).await?;
+        let channel = alice
+            .create_secure_channel_with_options(
+                route!["loopback", "bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new(),
+            )
+            .await?;
+
+        let forwarded = loopback.forwarded();
+        loopback.set_faults(LoopbackFaults {
+            drop_probability: 0.5,
+            ..Default::default()
+        });
+        let sent: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        for msg in &sent {
+            ctx.send(route![channel.clone(), ctx.address()], msg.clone())
+                .await?;
+        }
+
+        // What's left arrives in order, and the channel keeps working after the losses
+        let received = receive_all(ctx).await;
+        assert!(loopback.dropped() > 0);
+        assert_eq!(
+            loopback.forwarded() - forwarded + loopback.dropped(),
+            sent.len()
+        );
+        assert_eq!(received.len(), sent.len() - loopback.dropped());
+        assert!(received
+            .windows(2)
+            .all(|w| w[0].parse::<u32>().unwrap() < w[1].parse::<u32>().unwrap()));
+
+        loopback.set_faults(LoopbackFaults::default());
+        ctx.send(route![channel.clone(), ctx.address()], "last".to_string())
+            .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "last");
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_loopback_reorder(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let loopback = Loopback::create(ctx, "loopback".into(), 7).await?;
+        let channel = alice
+            .create_secure_channel_with_options(
+                route!["loopback", "bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new(),
+            )
+            .await?;
+
+        loopback.set_faults(LoopbackFaults {
+            delay: Duration::from_millis(10),
+            reorder_window: 5,
+            ..Default::default()
+        });
+        // The last window is only partly filled, the flush timer lets it through
+        let sent: Vec<String> = (0..12).map(|i| i.to_string()).collect();
+        for msg in &sent {
+            ctx.send(route![channel.clone(), ctx.address()], msg.clone())
+                .await?;
+        }
+
+        // Reordering within the replay window loses nothing
+        let mut received = receive_all(ctx).await;
+        assert_ne!(received, sent);
+        received.sort_by_key(|msg| msg.parse::<u32>().unwrap());
+        assert_eq!(received, sent);
+        assert_eq!(loopback.dropped(), 0);
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_loopback_duplicate_handshake(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let loopback = Loopback::create(ctx, "loopback".into(), 3).await?;
+        let route = route!["loopback", "bob_listener"];
+
+        // What a handshake takes when nothing goes wrong
+        let workers = ctx.list_workers().await?.len();
+        let channel = alice
+            .create_secure_channel(route.clone(), TrustEveryonePolicy)
+            .await?;
+        let handshake_messages = loopback.forwarded();
+        ctx.send(route![channel, ctx.address()], "0".to_string())
+            .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "0");
+        ctx.sleep(Duration::from_millis(250)).await;
+        let handshake_workers = ctx.list_workers().await?.len() - workers;
+
+        for i in 0..handshake_messages {
+            let workers = ctx.list_workers().await?.len();
+            loopback.set_faults(LoopbackFaults {
+                duplicate: Some(loopback.forwarded() + i),
+                ..Default::default()
+            });
+
+            let channel = alice
+                .create_secure_channel(route.clone(), TrustEveryonePolicy)
+                .await?;
+            ctx.send(route![channel, ctx.address()], i.to_string())
+                .await?;
+            assert_eq!(receive_all(ctx).await, [i.to_string()]);
+
+            // Neither side started anything for the repeated message
+            assert_eq!(ctx.list_workers().await?.len() - workers, handshake_workers);
+        }
+        assert_eq!(bob.secure_channels().await?.len(), handshake_messages + 1);
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_loopback_handshake_retry(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let loopback = Loopback::create(ctx, "loopback".into(), 11).await?;
+        let route = route!["loopback", "bob_listener"];
+        let options = SecureChannelOptions::new().with_timeout(Duration::from_secs(1));
+
+        // The first handshake message is lost, the second attempt goes through
+        loopback.set_faults(LoopbackFaults {
+            drop_first: 1,
+            ..Default::default()
+        });
+        let channel = alice
+            .create_secure_channel_with_options(
+                route.clone(),
+                TrustEveryonePolicy,
+                options
+                    .clone()
+                    .with_handshake_retry(3, Duration::from_millis(100)),
+            )
+            .await?;
+        assert_eq!(loopback.dropped(), 1);
+        ctx.send(route![channel, ctx.address()], "Hello, Bob!".to_string())
+            .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+        assert_eq!(bob.secure_channels().await?.len(), 1);
+
+        // Without retries the handshake fails
+        loopback.set_faults(LoopbackFaults {
+            drop_first: 1,
+            ..Default::default()
+        });
+        let err = alice
+            .create_secure_channel_with_options(route, TrustEveryonePolicy, options)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelTimeout).code()
+        );
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_loopback_probe(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let loopback = Loopback::create(ctx, "loopback".into(), 5).await?;
+        let channel = alice
+            .create_secure_channel(route!["loopback", "bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        // Both ways pass the loopback
+        loopback.set_faults(LoopbackFaults {
+            delay: Duration::from_millis(50),
+            ..Default::default()
+        });
+        let latency = alice
+            .probe_secure_channel(&channel, Duration::from_secs(1))
+            .await?;
+        assert!(latency >= Duration::from_millis(100));
+        assert!(latency < Duration::from_secs(1));
+        // Either side probes, and neither delivers anything
+        let bob_channel = bob.secure_channels().await?[0].address().clone();
+        bob.probe_secure_channel(&bob_channel, Duration::from_secs(1))
+            .await?;
+        assert!(receive_all(ctx).await.is_empty());
+
+        // The peer is gone, the channel stays until the caller decides otherwise
+        loopback.set_faults(LoopbackFaults {
+            drop_probability: 1.0,
+            ..Default::default()
+        });
+        let err = alice
+            .probe_secure_channel(&channel, Duration::from_millis(500))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelTimeout).code()
+        );
+        assert!(alice.secure_channel_info(&channel).await?.is_some());
+
+        let err = alice
+            .probe_secure_channel(&"unknown".into(), Duration::from_millis(500))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelNotFound).code()
+        );
+
+        // Probes go through again once the peer is back
+        loopback.set_faults(LoopbackFaults::default());
+        alice
+            .probe_secure_channel(&channel, Duration::from_secs(1))
+            .await?;
+
+        ctx.stop().await
+    }
+}
@@ -1,11 +1,163 @@
-use crate::Contact;
-use ockam_core::compat::vec::Vec;
-use ockam_core::Message;
+use crate::{AuthorityCredential, Contact, EntityError, ProfileIdentifier};
+#[cfg(feature = "unsafe_channel_key_export")]
+use ockam_channel::ExportedChannelKey;
+use ockam_core::compat::{collections::BTreeMap, string::String, vec::Vec};
+use ockam_core::{route, Address, Encodable, Error, Message, Result, Route};
+use ockam_node::Context;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 #[derive(Serialize, Deserialize, Message)]
 pub(crate) enum EntityChannelMessage {
-    Request { contact: Contact, proof: Vec<u8> },
-    Response { contact: Contact, proof: Vec<u8> },
+    // The protocol version goes first, so that it's decoded ahead of the fields
+    // whose layout depends on it
+    Request {
+        /// Protocol version the listener settled on from the key exchange header
+        protocol_version: u16,
+        contact: Contact,
+        proof: Vec<u8>,
+        credential: Option<AuthorityCredential>,
+        /// Profile attributes of the sender, covered by the proof
+        attributes: BTreeMap<String, String>,
+        /// Whether the sender checks a trust policy and requires the same from us
+        strict_trust: bool,
+        /// Whether the sender decompresses messages
+        compression: bool,
+    },
+    Response {
+        /// Same as the one of the [`EntityChannelMessage::Request`]
+        protocol_version: u16,
+        contact: Contact,
+        proof: Vec<u8>,
+        credential: Option<AuthorityCredential>,
+        attributes: BTreeMap<String, String>,
+        strict_trust: bool,
+        /// Whether both sides compress messages from now on
+        compression: bool,
+    },
+    /// Sent by the responder once it verified and trusts the initiator
     Confirm,
+    Close,
+    /// Several messages coalesced into one encrypted frame
+    Batch(Vec<BatchedMessage>),
+    /// Local only, sent by the batch timer
+    FlushBatch(u64),
+    /// Sent by the initiator to have the other side acknowledge the messages it receives
+    EnableAcks,
+    /// Number of messages received since acknowledgements were enabled
+    Ack(u64),
+    /// Local only, sent by the channel to itself once it processed the messages it had so far
+    SendAck,
+    /// Local only, asks for a reply once the channel accepts another message without queueing it
+    ReserveCapacity,
+    /// Local only, reply to [`EntityChannelMessage::ReserveCapacity`]
+    CapacityReserved,
+    /// Local only, asks for [`EntityChannelMessage::Close`] once the channel closed
+    WatchClose,
+    /// Local only, reply to [`EntityChannelMessage::WatchClose`]
+    WatchingClose,
+    /// Sent by the initiator every keepalive interval, answered with [`EntityChannelMessage::Pong`]
+    Ping(u64),
+    Pong(u64),
+    /// Local only, sent by the keepalive timer when the next ping is due
+    KeepaliveTick,
+    /// Local only, sent by the keepalive timer once the other side had time to answer given ping
+    KeepaliveDeadline(u64),
+    /// Sent by the responder instead of its profile when the listener refused the channel,
+    /// or instead of [`EntityChannelMessage::Confirm`] when it doesn't trust the initiator
+    Reject(RejectError),
+    /// Local only, asks for [`EntityChannelMessage::Ready`] once the channel takes messages
+    AwaitReady,
+    /// Local only, reply to [`EntityChannelMessage::AwaitReady`] with the peer's identifier
+    Ready(ProfileIdentifier),
+    /// Local only, asks for a round trip to the other side, see [`crate::Entity::probe_secure_channel`]
+    Probe,
+    /// Local only, reply to [`EntityChannelMessage::Probe`] once the other side answered
+    Probed,
+    /// Sent for a [`EntityChannelMessage::Probe`], answered with [`EntityChannelMessage::ProbeResponse`]
+    ProbeRequest(u64),
+    ProbeResponse(u64),
+    /// Local only, asks for the change history of the other side
+    GetPeerHistory,
+    /// Local only, reply to [`EntityChannelMessage::GetPeerHistory`]
+    PeerHistory(Contact),
+    /// Local only, sent once a reconnected channel stops waiting for the messages the replaced
+    /// regular SecureChannel returns
+    FinishRecovery(u64),
+    // Feature gated variants come last, so that they don't shift the ones above
+    /// Local only, asks for the key material of the channel
+    #[cfg(feature = "unsafe_channel_key_export")]
+    ExportKey,
+    /// Local only, reply to [`EntityChannelMessage::ExportKey`]
+    #[cfg(feature = "unsafe_channel_key_export")]
+    ExportedKey(ExportedChannelKey),
+}
+
+/// Send a local only request to the secure channel at given address. Replies come to the
+/// returned context, at a fresh address, so that they don't mix with other messages
+pub(crate) async fn request_channel(
+    ctx: &Context,
+    channel: &Address,
+    request: EntityChannelMessage,
+) -> Result<Context> {
+    let reply_ctx = ctx.new_context(Address::random(0)).await?;
+    reply_ctx.send(route![channel.clone()], request).await?;
+    Ok(reply_ctx)
+}
+
+/// Identifies a handshake message, so that the same message delivered again,
+/// e.g. retried by a lossy transport, isn't taken for the next step
+pub(crate) fn handshake_digest(payload: &[u8]) -> [u8; 32] {
+    Sha256::digest(payload).into()
+}
+
+/// What the profile proof of a [`EntityChannelMessage::Request`] or [`EntityChannelMessage::Response`]
+/// signs: the channel, and the attributes advertised with it, so that they are as the profile
+/// holder set them. Without attributes it's the channel alone
+pub(crate) fn auth_proof_data(
+    auth_hash: &[u8; 32],
+    attributes: &BTreeMap<String, String>,
+) -> Result<Vec<u8>> {
+    if attributes.is_empty() {
+        return Ok(auth_hash.to_vec());
+    }
+
+    (auth_hash, attributes).encode()
+}
+
+/// Single message of a [`EntityChannelMessage::Batch`]. All messages of a batch share the return route
+#[derive(Serialize, Deserialize)]
+pub(crate) struct BatchedMessage {
+    pub onward_route: Route,
+    pub payload: Vec<u8>,
+}
+
+/// Error of a [`EntityChannelMessage::Reject`]. [`Error`] doesn't encode its reason,
+/// so it's sent alongside, for the other side to tell why it was turned down
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RejectError {
+    error: Error,
+    reason: Option<String>,
+}
+
+impl From<Error> for RejectError {
+    fn from(error: Error) -> Self {
+        let reason = error.reason().map(String::from);
+        Self { error, reason }
+    }
+}
+
+impl From<EntityError> for RejectError {
+    fn from(error: EntityError) -> Self {
+        Error::from(error).into()
+    }
+}
+
+impl From<RejectError> for Error {
+    fn from(rejection: RejectError) -> Self {
+        match rejection.reason {
+            Some(reason) => rejection.error.with_reason(reason),
+            None => rejection.error,
+        }
+    }
 }
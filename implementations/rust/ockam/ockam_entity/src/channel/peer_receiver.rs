@@ -0,0 +1,158 @@
+use crate::{EntitySecureChannelLocalInfo, ProfileIdentifier};
+use ockam_core::compat::collections::VecDeque;
+use ockam_core::{Any, Decodable, Message, Result, Routed};
+use ockam_node::Context;
+
+/// Receives the messages of one peer at a time, for a context that secure channels to several
+/// peers deliver to. Peers are told apart by the [`EntitySecureChannelLocalInfo`] of the messages.
+/// Messages that aren't asked for yet are kept in the order they arrived.
+///
+/// Receiving is cancellation safe: a message is kept as soon as it's taken from the mailbox,
+/// so dropping a pending receive, e.g. on a timeout, loses nothing
+#[derive(Default)]
+pub struct PeerReceiver {
+    buffered: VecDeque<Routed<Any>>,
+}
+
+impl PeerReceiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Next message from given peer. Messages kept earlier come first, then `ctx` is waited on
+    /// without a timeout. Fails if the message isn't an `M`, which is kept then
+    pub async fn receive_from<M: Message>(
+        &mut self,
+        ctx: &mut Context,
+        peer: &ProfileIdentifier,
+    ) -> Result<Routed<M>> {
+        if let Some(index) = self
+            .buffered
+            .iter()
+            .position(|msg| Self::is_from(msg, peer))
+        {
+            return self.take(index);
+        }
+
+        loop {
+            let msg = ctx.receive_block::<Any>().await?.take();
+            let is_from = Self::is_from(&msg, peer);
+            self.buffered.push_back(msg);
+            if is_from {
+                return self.take(self.buffered.len() - 1);
+            }
+        }
+    }
+
+    /// Next message of any sender, the ones kept earlier first
+    pub async fn receive<M: Message>(&mut self, ctx: &mut Context) -> Result<Routed<M>> {
+        if self.buffered.is_empty() {
+            let msg = ctx.receive_block::<Any>().await?.take();
+            self.buffered.push_back(msg);
+        }
+
+        self.take(0)
+    }
+
+    /// Messages kept until they're asked for
+    pub fn buffered(&self) -> usize {
+        self.buffered.len()
+    }
+
+    fn is_from(msg: &Routed<Any>, peer: &ProfileIdentifier) -> bool {
+        match EntitySecureChannelLocalInfo::find_info(msg.local_message()) {
+            Ok(info) => !info.is_anonymous() && info.their_profile_id() == peer,
+            Err(_) => false,
+        }
+    }
+
+    fn take<M: Message>(&mut self, index: usize) -> Result<Routed<M>> {
+        let body = M::decode(self.buffered[index].payload())?;
+        // Decoded right above
+        let msg = self.buffered.remove(index).unwrap();
+
+        Ok(Routed::new(body, msg.msg_addr(), msg.into_local_message()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Entity, Identity, TrustEveryonePolicy};
+    use core::time::Duration;
+    use ockam_core::compat::string::{String, ToString};
+    use ockam_core::route;
+    use ockam_node::tokio::time::timeout;
+    use ockam_vault_sync_core::Vault;
+
+    #[ockam_macros::test]
+    async fn test_receive_from(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut carol = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+        let alice_id = alice.identifier().await?;
+        let carol_id = carol.identifier().await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        let carol_channel = carol
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        // This context stands in for Bob's worker serving both peers
+        for i in 0..3 {
+            ctx.send(
+                route![alice_channel.clone(), ctx.address()],
+                format!("alice {}", i),
+            )
+            .await?;
+            ctx.send(
+                route![carol_channel.clone(), ctx.address()],
+                format!("carol {}", i),
+            )
+            .await?;
+        }
+
+        let mut receiver = PeerReceiver::new();
+        for i in 0..2 {
+            let msg = receiver.receive_from::<String>(ctx, &alice_id).await?;
+            assert_eq!(msg.body(), format!("alice {}", i));
+        }
+
+        // Waiting for a peer that sends nothing takes in, and keeps, everything else
+        let nobody = ProfileIdentifier::random();
+        let res = timeout(
+            Duration::from_millis(500),
+            receiver.receive_from::<String>(ctx, &nobody),
+        )
+        .await;
+        assert!(res.is_err());
+        assert_eq!(receiver.buffered(), 4);
+
+        for i in 0..3 {
+            let msg = receiver.receive_from::<String>(ctx, &carol_id).await?;
+            assert_eq!(msg.body(), format!("carol {}", i));
+        }
+        let msg = receiver.receive_from::<String>(ctx, &alice_id).await?;
+        assert_eq!(msg.body(), "alice 2");
+        assert_eq!(receiver.buffered(), 0);
+
+        // Messages without a peer are only left to receive
+        ctx.send(route![ctx.address()], "local".to_string()).await?;
+        assert!(timeout(
+            Duration::from_millis(500),
+            receiver.receive_from::<String>(ctx, &alice_id)
+        )
+        .await
+        .is_err());
+        let msg = receiver.receive::<String>(ctx).await?;
+        assert_eq!(msg.body(), "local");
+
+        ctx.stop().await
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{Entity, SecureChannelOptions, TrustPolicy};
+use core::time::Duration;
+use ockam_core::compat::{collections::VecDeque, string::String};
+use ockam_core::{Address, AsyncTryClone, Result, Route};
+use tracing::debug;
+
+/// Channels a [`SecureChannelPool`] keeps open by default
+pub const DEFAULT_SECURE_CHANNEL_POOL_SIZE: usize = 16;
+
+struct PooledChannel {
+    route: Route,
+    trust_policy: String,
+    address: Address,
+}
+
+/// Secure channels of an [`Entity`] kept open for reuse, e.g. by clients sending many
+/// requests to the same few listeners. Channels are told apart by the route to the listener
+/// and the name of the trust policy they were created with. Channels that died are created
+/// again, and once the pool is full the least recently used channel is closed
+pub struct SecureChannelPool {
+    entity: Entity,
+    max_channels: usize,
+    options: SecureChannelOptions,
+    health_check: Option<Duration>,
+    /// Least recently used first
+    channels: VecDeque<PooledChannel>,
+    created: usize,
+}
+
+impl SecureChannelPool {
+    /// Pool channels of the current profile of `entity`, up to [`DEFAULT_SECURE_CHANNEL_POOL_SIZE`]
+    pub async fn new(entity: &Entity) -> Result<Self> {
+        Ok(Self {
+            entity: entity.async_try_clone().await?,
+            max_channels: DEFAULT_SECURE_CHANNEL_POOL_SIZE,
+            options: SecureChannelOptions::new(),
+            health_check: None,
+            channels: VecDeque::new(),
+            created: 0,
+        })
+    }
+
+    /// Keep up to given number of channels open, at least one
+    pub fn with_max_channels(mut self, max_channels: usize) -> Self {
+        self.max_channels = max_channels.max(1);
+        self
+    }
+
+    /// Create channels with given options
+    pub fn with_options(mut self, options: SecureChannelOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Before handing out a channel, wait up to `timeout` for it to take messages, see
+    /// [`Entity::await_secure_channel_ready`]. Channels that don't are created again.
+    /// Otherwise a channel is handed out as long as it's open
+    pub fn with_health_check(mut self, timeout: Duration) -> Self {
+        self.health_check = Some(timeout);
+        self
+    }
+
+    /// Open channel to the listener at `route`, created with the trust policy named
+    /// `trust_policy_name`. If there's none, one is created with `trust_policy`, which
+    /// has to be the policy of that name
+    pub async fn get(
+        &mut self,
+        route: impl Into<Route>,
+        trust_policy_name: &str,
+        trust_policy: impl TrustPolicy,
+    ) -> Result<Address> {
+        let route = route.into();
+
+        let pooled = self
+            .channels
+            .iter()
+            .position(|channel| channel.route == route && channel.trust_policy == trust_policy_name)
+            .and_then(|index| self.channels.remove(index));
+        if let Some(channel) = pooled {
+            if self.is_healthy(&channel.address).await {
+                let address = channel.address.clone();
+                self.channels.push_back(channel);
+                return Ok(address);
+            }
+            debug!(
+                "Pooled SecureChannel at {} to {} died, creating it again",
+                channel.address, route
+            );
+            // It may still be around, e.g. reconnecting
+            let _ = self.entity.stop_secure_channel(&channel.address).await;
+        }
+
+        let address = self
+            .entity
+            .create_secure_channel_with_options(route.clone(), trust_policy, self.options.clone())
+            .await?;
+        self.created += 1;
+
+        if self.channels.len() >= self.max_channels {
+            if let Some(evicted) = self.channels.pop_front() {
+                debug!(
+                    "Closing pooled SecureChannel at {} to {}",
+                    evicted.address, evicted.route
+                );
+                let _ = self.entity.stop_secure_channel(&evicted.address).await;
+            }
+        }
+        self.channels.push_back(PooledChannel {
+            route,
+            trust_policy: trust_policy_name.into(),
+            address: address.clone(),
+        });
+
+        Ok(address)
+    }
+
+    /// Channels in the pool
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// Channels the pool created so far, including the ones it no longer holds
+    pub fn created(&self) -> usize {
+        self.created
+    }
+
+    /// Close every channel in the pool
+    pub async fn close(&mut self) -> Result<()> {
+        for channel in core::mem::take(&mut self.channels) {
+            // Closed on its own meanwhile
+            let _ = self.entity.stop_secure_channel(&channel.address).await;
+        }
+
+        Ok(())
+    }
+
+    async fn is_healthy(&self, address: &Address) -> bool {
+        match self.health_check {
+            Some(timeout) => self
+                .entity
+                .await_secure_channel_ready(address, timeout)
+                .await
+                .is_ok(),
+            None => matches!(self.entity.secure_channel_info(address).await, Ok(Some(_))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::TrustEveryonePolicy;
+    use ockam_core::compat::{string::ToString, vec::Vec};
+    use ockam_core::route;
+    use ockam_node::Context;
+    use ockam_vault_sync_core::Vault;
+
+    #[ockam_macros::test]
+    async fn test_secure_channel_pool(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        for listener in ["first", "second", "third"] {
+            bob.create_secure_channel_listener(listener, TrustEveryonePolicy)
+                .await?;
+        }
+
+        let mut pool = SecureChannelPool::new(&alice)
+            .await?
+            .with_max_channels(2)
+            .with_health_check(Duration::from_secs(1));
+
+        let mut channels = Vec::new();
+        for i in 0..20 {
+            let channel = pool.get("first", "everyone", TrustEveryonePolicy).await?;
+            ctx.send(route![channel.clone(), ctx.address()], i.to_string())
+                .await?;
+            assert_eq!(ctx.receive::<String>().await?.take().body(), i.to_string());
+            channels.push(channel);
+        }
+        assert_eq!(pool.created(), 1);
+        assert!(channels.iter().all(|channel| channel == &channels[0]));
+
+        // Another trust policy is another channel
+        let other = pool.get("first", "anyone", TrustEveryonePolicy).await?;
+        assert_ne!(other, channels[0]);
+        assert_eq!((pool.len(), pool.created()), (2, 2));
+
+        // The least recently used channel is closed
+        pool.get("first", "everyone", TrustEveryonePolicy).await?;
+        pool.get("second", "everyone", TrustEveryonePolicy).await?;
+        assert_eq!((pool.len(), pool.created()), (2, 3));
+        ctx.sleep(Duration::from_millis(250)).await;
+        assert!(alice.secure_channel_info(&other).await?.is_none());
+        assert!(alice.secure_channel_info(&channels[0]).await?.is_some());
+
+        // Dead channels are created again
+        alice.stop_secure_channel(&channels[0]).await?;
+        ctx.sleep(Duration::from_millis(250)).await;
+        let channel = pool.get("first", "everyone", TrustEveryonePolicy).await?;
+        assert_ne!(channel, channels[0]);
+        assert_eq!((pool.len(), pool.created()), (2, 4));
+
+        pool.close().await?;
+        ctx.sleep(Duration::from_millis(250)).await;
+        assert!(alice.secure_channels().await?.is_empty());
+
+        ctx.stop().await
+    }
+}
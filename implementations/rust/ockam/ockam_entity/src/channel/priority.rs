@@ -0,0 +1,219 @@
+use ockam_core::compat::boxed::Box;
+use ockam_core::{
+    async_trait, route, Decodable, Encodable, LocalInfo, LocalMessage, Message, Result, Route,
+    TransportMessage,
+};
+use ockam_node::Context;
+use serde::{Deserialize, Serialize};
+
+/// Message priority LocalInfo unique Identifier
+pub const ENTITY_SECURE_CHANNEL_PRIORITY: &str = "ENTITY_SECURE_CHANNEL_PRIORITY";
+
+/// How a secure channel orders a message of a local worker among the ones it holds.
+/// [`MessagePriority::High`] messages are sent before the messages held by
+/// [`SecureChannelOptions::with_backpressure`](crate::SecureChannelOptions::with_backpressure)
+/// and aren't batched. Messages keep their order within a priority
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MessagePriority {
+    Normal,
+    High,
+}
+
+impl Default for MessagePriority {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
+impl MessagePriority {
+    pub fn to_local_info(&self) -> Result<LocalInfo> {
+        Ok(LocalInfo::new(
+            ENTITY_SECURE_CHANNEL_PRIORITY.into(),
+            self.encode()?,
+        ))
+    }
+
+    /// Priority the sender gave the message, [`MessagePriority::Normal`] if none or malformed
+    pub fn find(local_msg: &LocalMessage) -> Self {
+        local_msg
+            .local_info()
+            .iter()
+            .find(|x| x.type_identifier() == ENTITY_SECURE_CHANNEL_PRIORITY)
+            .and_then(|x| Self::decode(x.data()).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Send messages to a secure channel with a [`MessagePriority`]
+#[async_trait]
+pub trait PriorityContext {
+    /// Send `msg` to `route`, whose first hop is a secure channel. Replies come back
+    /// to this context as for [`Context::send`]
+    async fn send_with_priority<R, M>(
+        &self,
+        route: R,
+        msg: M,
+        priority: MessagePriority,
+    ) -> Result<()>
+    where
+        R: Into<Route> + Send,
+        M: Message + Send + 'static;
+}
+
+#[async_trait]
+impl PriorityContext for Context {
+    async fn send_with_priority<R, M>(
+        &self,
+        route: R,
+        msg: M,
+        priority: MessagePriority,
+    ) -> Result<()>
+    where
+        R: Into<Route> + Send,
+        M: Message + Send + 'static,
+    {
+        let transport_msg = TransportMessage::v1(route, route![self.address()], msg.encode()?);
+        self.forward(LocalMessage::new(
+            transport_msg,
+            vec![priority.to_local_info()?],
+        ))
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Entity, MessagePriority, PriorityContext, SecureChannelOptions, TrustEveryonePolicy,
+    };
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use core::time::Duration;
+    use ockam_core::compat::{
+        string::{String, ToString},
+        sync::Arc,
+        vec::Vec,
+    };
+    use ockam_core::{async_trait, route, Address, Any, LocalMessage, Result, Routed, Worker};
+    use ockam_node::Context;
+    use ockam_vault_sync_core::Vault;
+    use tokio::time::sleep;
+
+    /// Transport stand-in, which holds everything while stalled
+    struct Tap {
+        stalled: Arc<AtomicBool>,
+        held: Vec<LocalMessage>,
+    }
+
+    #[async_trait]
+    impl Worker for Tap {
+        type Message = Any;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            ctx: &mut Self::Context,
+            msg: Routed<Self::Message>,
+        ) -> Result<()> {
+            if msg.msg_addr() == Address::from("tap_release") {
+                self.stalled.store(false, Ordering::SeqCst);
+                for held in self.held.drain(..) {
+                    ctx.forward(held).await?;
+                }
+                return Ok(());
+            }
+
+            let mut local_msg = msg.into_local_message();
+            let transport_msg = local_msg.transport_mut();
+            transport_msg.onward_route.step()?;
+            transport_msg
+                .return_route
+                .modify()
+                .prepend(Address::from("tap"));
+
+            if self.stalled.load(Ordering::SeqCst) {
+                self.held.push(local_msg);
+                return Ok(());
+            }
+
+            ctx.forward(local_msg).await
+        }
+    }
+
+    #[test]
+    fn test_find_priority() -> Result<()> {
+        let local_msg = |local_info| {
+            LocalMessage::new(
+                ockam_core::TransportMessage::v1(route![], route![], Vec::new()),
+                local_info,
+            )
+        };
+
+        assert_eq!(
+            MessagePriority::find(&local_msg(Vec::new())),
+            MessagePriority::Normal
+        );
+        assert_eq!(
+            MessagePriority::find(&local_msg(vec![MessagePriority::High.to_local_info()?])),
+            MessagePriority::High
+        );
+
+        Ok(())
+    }
+
+    #[ockam_macros::test]
+    async fn test_high_priority_first(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let stalled = Arc::new(AtomicBool::new(false));
+        let tap = Tap {
+            stalled: stalled.clone(),
+            held: Vec::new(),
+        };
+        ctx.start_worker(vec!["tap", "tap_release"], tap).await?;
+
+        let channel = alice
+            .create_secure_channel_with_options(
+                route!["tap", "bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_backpressure(1, 10),
+            )
+            .await?;
+        // Let the handshake leftovers through before stalling
+        sleep(Duration::from_millis(100)).await;
+        stalled.store(true, Ordering::SeqCst);
+
+        // The first one takes the window, the others are held by the channel
+        for i in 0..4 {
+            ctx.send(route![channel.clone(), ctx.address()], i.to_string())
+                .await?;
+        }
+        ctx.send_with_priority(
+            route![channel.clone(), ctx.address()],
+            "urgent".to_string(),
+            MessagePriority::High,
+        )
+        .await?;
+        ctx.send_with_priority(
+            route![channel.clone(), ctx.address()],
+            "4".to_string(),
+            MessagePriority::Normal,
+        )
+        .await?;
+        sleep(Duration::from_millis(100)).await;
+
+        ctx.send(route!["tap_release"], String::new()).await?;
+
+        let mut received = Vec::new();
+        for _ in 0..6 {
+            received.push(ctx.receive::<String>().await?.take().body());
+        }
+        assert_eq!(received, ["0", "urgent", "1", "2", "3", "4"]);
+
+        ctx.stop().await
+    }
+}
@@ -0,0 +1,436 @@
+use crate::{EntityChannelVault, EntityError};
+use core::fmt;
+use ockam_core::compat::{
+    boxed::Box,
+    collections::BTreeSet,
+    string::String,
+    sync::{Arc, Mutex},
+    vec::Vec,
+};
+use ockam_core::vault::{Secret, SecretAttributes, SecretPersistence, SecretType};
+use ockam_core::{async_trait, AsyncTryClone, Result};
+use ockam_key_exchange_core::{CompletedKeyExchange, KeyExchanger, NewKeyExchanger};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Hashed with the pre-shared key, so that the result isn't used anywhere else
+const PSK_LABEL: &[u8] = b"OCKAM_ENTITY_CHANNEL_PSK";
+/// HKDF info of the keys mixed with the pre-shared key
+const PSK_INFO: &[u8] = b"psk";
+/// Additional data of the key confirmations
+const CONFIRMATION_LABEL: &[u8] = b"OCKAM_ENTITY_CHANNEL_PSK_CONFIRMATION";
+/// Nonce of the key confirmations, which messages, rekeying, key export and the PRF never use
+const CONFIRMATION_NONCE: [u8; 12] = [0xFE, 0xFE, 0xFE, 0xFE, 0, 0, 0, 0, 0, 0, 0, 0];
+
+/// Secret shared by both sides of a channel out of band, see
+/// [`SecureChannelOptions::with_psk`](crate::SecureChannelOptions::with_psk).
+/// Wiped from memory when dropped, and never printed
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Zeroize)]
+#[zeroize(drop)]
+pub struct PreSharedKey(Vec<u8>);
+
+impl PreSharedKey {
+    pub fn new(psk: impl Into<Vec<u8>>) -> Self {
+        Self(psk.into())
+    }
+}
+
+impl From<&[u8]> for PreSharedKey {
+    fn from(psk: &[u8]) -> Self {
+        Self::new(psk)
+    }
+}
+
+impl fmt::Debug for PreSharedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PreSharedKey(..)")
+    }
+}
+
+/// Handshakes whose key confirmation failed, by handshake hash, until their channel looks
+/// them up. Those still complete, so that the channel can fail with
+/// [`EntityError::PreSharedKeyMismatch`] instead of waiting for messages it can't decrypt
+#[derive(Clone, Default)]
+pub(crate) struct PskMismatches {
+    hashes: Arc<Mutex<BTreeSet<[u8; 32]>>>,
+}
+
+impl PskMismatches {
+    fn insert(&self, h: [u8; 32]) {
+        self.hashes.lock().unwrap().insert(h);
+    }
+
+    /// Whether the handshake with given hash failed, forgetting about it
+    pub fn take(&self, h: &[u8; 32]) -> bool {
+        self.hashes.lock().unwrap().remove(h)
+    }
+}
+
+/// Mixes the pre-shared key, if any, into the keys of a completed key exchange.
+/// Each key `k` is replaced by `HKDF(salt: SHA256(label || psk), ikm: PRF(k))`, where the PRF
+/// encrypts zeros with a nonce messages, rekeying and key export never use.
+/// Both sides derive the same keys only if they hold the same pre-shared key, which they
+/// confirm with one more message each, the responder's first: an AEAD tag over a label with
+/// their mixed encryption key. A tag that doesn't verify is recorded in [`PskMismatches`]
+pub(crate) struct PskKeyExchanger<K: KeyExchanger, V: EntityChannelVault> {
+    /// Taken once it completes, to mix the keys before confirming them
+    inner: Option<K>,
+    name: Option<String>,
+    vault: V,
+    psk: Option<PreSharedKey>,
+    mismatches: PskMismatches,
+    mixed: Option<CompletedKeyExchange>,
+    confirmation_sent: bool,
+    confirmation_received: bool,
+}
+
+impl<K: KeyExchanger, V: EntityChannelVault> PskKeyExchanger<K, V> {
+    pub fn new(inner: K, vault: V, psk: Option<PreSharedKey>, mismatches: PskMismatches) -> Self {
+        Self {
+            inner: Some(inner),
+            name: None,
+            vault,
+            psk,
+            mismatches,
+            mixed: None,
+            confirmation_sent: false,
+            confirmation_received: false,
+        }
+    }
+
+    /// Mix the pre-shared key into the keys once the key exchange completed
+    async fn mix_if_complete(&mut self) -> Result<()> {
+        let psk = match &self.psk {
+            Some(psk) => psk.clone(),
+            None => return Ok(()),
+        };
+        let complete = match &self.inner {
+            Some(inner) => inner.is_complete().await?,
+            None => false,
+        };
+        let inner = match self.inner.take() {
+            Some(inner) if complete => inner,
+            inner => {
+                self.inner = inner;
+                return Ok(());
+            }
+        };
+
+        self.name = Some(inner.name().await?);
+        let completed = inner.finalize().await?;
+
+        let mut labeled = Zeroizing::new(PSK_LABEL.to_vec());
+        labeled.extend_from_slice(&psk.0);
+        let hashed = Zeroizing::new(self.vault.sha256(&labeled).await?);
+        let salt = self
+            .vault
+            .secret_import(
+                &hashed[..],
+                SecretAttributes::new(SecretType::Buffer, SecretPersistence::Ephemeral, 32),
+            )
+            .await?;
+
+        let encrypt_key = Self::mix(&mut self.vault, &salt, completed.encrypt_key()).await;
+        let decrypt_key = Self::mix(&mut self.vault, &salt, completed.decrypt_key()).await;
+        self.vault.secret_destroy(salt).await?;
+
+        self.mixed = Some(CompletedKeyExchange::new(
+            *completed.h(),
+            encrypt_key?,
+            decrypt_key?,
+        ));
+
+        Ok(())
+    }
+
+    fn mixed(&self) -> Result<&CompletedKeyExchange> {
+        self.mixed
+            .as_ref()
+            .ok_or_else(|| EntityError::InvalidInternalState.into())
+    }
+
+    /// Prove we derived the same keys, without revealing anything about them
+    async fn confirmation(&mut self) -> Result<Vec<u8>> {
+        let encrypt_key = self.mixed()?.encrypt_key().clone();
+        let confirmation = self
+            .vault
+            .aead_aes_gcm_encrypt(&encrypt_key, &[], &CONFIRMATION_NONCE, CONFIRMATION_LABEL)
+            .await?;
+        self.confirmation_sent = true;
+
+        Ok(confirmation)
+    }
+
+    async fn check_confirmation(&mut self, confirmation: &[u8]) -> Result<()> {
+        let mixed = self.mixed()?;
+        let (h, decrypt_key) = (*mixed.h(), mixed.decrypt_key().clone());
+        let confirmed = self
+            .vault
+            .aead_aes_gcm_decrypt(
+                &decrypt_key,
+                confirmation,
+                &CONFIRMATION_NONCE,
+                CONFIRMATION_LABEL,
+            )
+            .await
+            .is_ok();
+        if !confirmed {
+            self.mismatches.insert(h);
+        }
+        self.confirmation_received = true;
+
+        Ok(())
+    }
+
+    async fn mix(vault: &mut V, salt: &Secret, key: &Secret) -> Result<Secret> {
+        let attributes = vault.secret_attributes_get(key).await?;
+
+        let mut nonce = [0u8; 12];
+        nonce[..4].copy_from_slice(&[0xFF; 4]);
+        // This is plain key material, which must not linger in memory once it's imported
+        let prf = Zeroizing::new(
+            vault
+                .aead_aes_gcm_encrypt(key, &[0u8; 32], &nonce, &[])
+                .await?,
+        );
+        let ikm = vault
+            .secret_import(
+                &prf[..32],
+                SecretAttributes::new(SecretType::Buffer, SecretPersistence::Ephemeral, 32),
+            )
+            .await?;
+
+        let mixed = vault
+            .hkdf_sha256(salt, PSK_INFO, Some(&ikm), vec![attributes])
+            .await;
+        vault.secret_destroy(ikm).await?;
+        vault.secret_destroy(key.clone()).await?;
+
+        mixed?
+            .pop()
+            .ok_or_else(|| EntityError::InvalidInternalState.into())
+    }
+}
+
+#[async_trait]
+impl<K, V> KeyExchanger for PskKeyExchanger<K, V>
+where
+    K: KeyExchanger + Send + Sync,
+    V: EntityChannelVault,
+{
+    async fn name(&self) -> Result<String> {
+        match (&self.inner, &self.name) {
+            (Some(inner), _) => inner.name().await,
+            (None, Some(name)) => Ok(name.clone()),
+            (None, None) => Err(EntityError::InvalidInternalState.into()),
+        }
+    }
+
+    async fn generate_request(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        let request = match &mut self.inner {
+            Some(inner) => inner.generate_request(payload).await?,
+            None => return self.confirmation().await,
+        };
+        self.mix_if_complete().await?;
+
+        Ok(request)
+    }
+
+    async fn handle_response(&mut self, response: &[u8]) -> Result<Vec<u8>> {
+        let payload = match &mut self.inner {
+            Some(inner) => inner.handle_response(response).await?,
+            None => {
+                self.check_confirmation(response).await?;
+                return Ok(Vec::new());
+            }
+        };
+        self.mix_if_complete().await?;
+
+        Ok(payload)
+    }
+
+    async fn is_complete(&self) -> Result<bool> {
+        match &self.inner {
+            // Not mixed yet, which only happens without a pre-shared key
+            Some(inner) => Ok(self.psk.is_none() && inner.is_complete().await?),
+            None => Ok(self.confirmation_sent && self.confirmation_received),
+        }
+    }
+
+    async fn finalize(self) -> Result<CompletedKeyExchange> {
+        match (self.inner, self.mixed) {
+            (Some(inner), None) => inner.finalize().await,
+            (None, Some(mixed)) => Ok(mixed),
+            _ => Err(EntityError::InvalidInternalState.into()),
+        }
+    }
+}
+
+/// Creates key exchangers that mix in the pre-shared key, if any, see [`PskKeyExchanger`]
+pub(crate) struct PskNewKeyExchanger<N: NewKeyExchanger, V: EntityChannelVault> {
+    inner: N,
+    vault: V,
+    psk: Option<PreSharedKey>,
+    mismatches: PskMismatches,
+}
+
+impl<N: NewKeyExchanger, V: EntityChannelVault> PskNewKeyExchanger<N, V> {
+    pub fn new(inner: N, vault: V, psk: Option<PreSharedKey>, mismatches: PskMismatches) -> Self {
+        Self {
+            inner,
+            vault,
+            psk,
+            mismatches,
+        }
+    }
+}
+
+#[async_trait]
+impl<N, V> NewKeyExchanger for PskNewKeyExchanger<N, V>
+where
+    N: NewKeyExchanger + Send + Sync,
+    V: EntityChannelVault + 'static,
+{
+    type Initiator = PskKeyExchanger<N::Initiator, V>;
+    type Responder = PskKeyExchanger<N::Responder, V>;
+
+    async fn initiator(&self) -> Result<Self::Initiator> {
+        Ok(PskKeyExchanger::new(
+            self.inner.initiator().await?,
+            self.vault.async_try_clone().await?,
+            self.psk.clone(),
+            self.mismatches.clone(),
+        ))
+    }
+
+    async fn responder(&self) -> Result<Self::Responder> {
+        Ok(PskKeyExchanger::new(
+            self.inner.responder().await?,
+            self.vault.async_try_clone().await?,
+            self.psk.clone(),
+            self.mismatches.clone(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        Entity, Identity, SecureChannelListenerOptions, SecureChannelOptions, TrustEveryonePolicy,
+    };
+    use core::time::Duration;
+    use ockam_core::compat::string::ToString;
+    use ockam_core::route;
+    use ockam_node::Context;
+    use ockam_vault_sync_core::Vault;
+
+    #[ockam_macros::test]
+    async fn test_psk(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let psk = PreSharedKey::new(b"correct horse battery staple".to_vec());
+        assert_eq!(format!("{:?}", psk), "PreSharedKey(..)");
+
+        bob.create_secure_channel_listener_with_psk(
+            "bob_listener",
+            TrustEveryonePolicy,
+            psk.clone(),
+        )
+        .await?;
+        bob.create_secure_channel_listener("bob_plain_listener", TrustEveryonePolicy)
+            .await?;
+
+        let channel = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_psk(psk.clone()),
+            )
+            .await?;
+        ctx.send(route![channel, ctx.address()], "Hello, Bob!".to_string())
+            .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+
+        // Neither side learns whose key was wrong. Without a key of its own, the initiator
+        // can't tell why the handshake doesn't complete
+        let attempts = [
+            (
+                "bob_listener",
+                Some(PreSharedKey::from(&b"correct horse battery stapler"[..])),
+                EntityError::PreSharedKeyMismatch,
+            ),
+            ("bob_listener", None, EntityError::SecureChannelTimeout),
+            (
+                "bob_plain_listener",
+                Some(psk),
+                EntityError::PreSharedKeyMismatch,
+            ),
+        ];
+        for (listener, psk, expected) in attempts {
+            let mut options = SecureChannelOptions::new().with_timeout(Duration::from_millis(500));
+            if let Some(psk) = psk {
+                options = options.with_psk(psk);
+            }
+            let err = alice
+                .create_secure_channel_with_options(route![listener], TrustEveryonePolicy, options)
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(err.code(), ockam_core::Error::from(expected).code());
+        }
+        assert_eq!(alice.secure_channels().await?.len(), 1);
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_psk_mismatch_stops_responder(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let psk = PreSharedKey::new(b"correct horse battery staple".to_vec());
+        bob.create_secure_channel_listener_with_options(
+            "bob_listener",
+            TrustEveryonePolicy,
+            SecureChannelListenerOptions::new()
+                .with_psk(psk.clone())
+                .with_max_channels(1),
+        )
+        .await?;
+
+        let err = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_psk(&b"correct horse battery stapler"[..]),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::PreSharedKeyMismatch).code()
+        );
+
+        // The responder found out as well, and released its slot long before its handshake timeout
+        ctx.sleep(Duration::from_millis(200)).await;
+        let channel = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_psk(psk),
+            )
+            .await?;
+        ctx.send(route![channel, ctx.address()], "Hello, Bob!".to_string())
+            .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+
+        ctx.stop().await
+    }
+}
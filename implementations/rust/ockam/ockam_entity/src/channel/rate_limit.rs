@@ -0,0 +1,273 @@
+use crate::{EntitySecureChannelLocalInfo, ProfileIdentifier};
+use ockam_core::compat::{boxed::Box, collections::HashMap};
+use ockam_core::{async_trait, AccessControl, LocalMessage, Result};
+use std::time::Instant;
+
+/// Peers a [`RateLimitAccessControl`] keeps track of by default
+pub const DEFAULT_RATE_LIMIT_MAX_PEERS: usize = 1024;
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    seen_at: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant, rate: f64, burst: f64) {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.refilled_at = now;
+    }
+}
+
+/// Allows the messages another [`AccessControl`] allows, as long as their sender doesn't
+/// go over a rate. Each peer, told apart by its [`EntitySecureChannelLocalInfo`], gets a
+/// token bucket, which allows bursts and then refills at the rate.
+/// Messages without that info or from anonymous channels are left to the other
+/// access control, unless denied with [`RateLimitAccessControl::deny_unidentified`].
+///
+/// Only the buckets of so many peers are kept. Peers whose bucket is full again are
+/// forgotten first, as they start over the same, then the ones seen least recently
+pub struct RateLimitAccessControl<A: AccessControl> {
+    inner: A,
+    rate: f64,
+    burst: f64,
+    max_peers: usize,
+    deny_unidentified: bool,
+    buckets: HashMap<ProfileIdentifier, Bucket>,
+}
+
+impl<A: AccessControl> RateLimitAccessControl<A> {
+    /// Allow each peer `rate` messages per second, and up to `burst` at once
+    pub fn new(inner: A, rate: u32, burst: u32) -> Self {
+        Self {
+            inner,
+            rate: rate as f64,
+            burst: burst.max(1) as f64,
+            max_peers: DEFAULT_RATE_LIMIT_MAX_PEERS,
+            deny_unidentified: false,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Keep track of up to given number of peers, at least one
+    pub fn with_max_peers(mut self, max_peers: usize) -> Self {
+        self.max_peers = max_peers.max(1);
+        self
+    }
+
+    /// Deny messages that didn't come through an authenticated secure channel
+    pub fn deny_unidentified(mut self) -> Self {
+        self.deny_unidentified = true;
+        self
+    }
+
+    fn decide(&mut self, local_msg: &LocalMessage, inner_allows: bool, now: Instant) -> bool {
+        if !inner_allows {
+            return false;
+        }
+
+        match EntitySecureChannelLocalInfo::find_info(local_msg) {
+            Ok(info) if !info.is_anonymous() => {
+                self.take_token(info.their_profile_id().clone(), now)
+            }
+            _ => !self.deny_unidentified,
+        }
+    }
+
+    fn take_token(&mut self, peer: ProfileIdentifier, now: Instant) -> bool {
+        if !self.buckets.contains_key(&peer) && self.buckets.len() >= self.max_peers {
+            self.evict(now);
+        }
+
+        let (rate, burst) = (self.rate, self.burst);
+        let bucket = self.buckets.entry(peer).or_insert(Bucket {
+            tokens: burst,
+            refilled_at: now,
+            seen_at: now,
+        });
+        bucket.refill(now, rate, burst);
+        bucket.seen_at = now;
+
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+
+    fn evict(&mut self, now: Instant) {
+        let (rate, burst) = (self.rate, self.burst);
+        self.buckets.retain(|_, bucket| {
+            bucket.refill(now, rate, burst);
+            bucket.tokens < burst
+        });
+
+        if self.buckets.len() >= self.max_peers {
+            let idle = self
+                .buckets
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.seen_at)
+                .map(|(peer, _)| peer.clone());
+            if let Some(peer) = idle {
+                self.buckets.remove(&peer);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<A: AccessControl> AccessControl for RateLimitAccessControl<A> {
+    async fn msg_is_authorized(&mut self, local_msg: &LocalMessage) -> Result<bool> {
+        let inner_allows = self.inner.msg_is_authorized(local_msg).await?;
+        Ok(self.decide(local_msg, inner_allows, Instant::now()))
+    }
+
+    fn msg_is_authorized_sync(&mut self, local_msg: &LocalMessage) -> Option<Result<bool>> {
+        // Buckets are left alone until the inner decision is known, whichever way it's made
+        let inner_allows = match self.inner.msg_is_authorized_sync(local_msg)? {
+            Ok(inner_allows) => inner_allows,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(Ok(self.decide(local_msg, inner_allows, Instant::now())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::EntityAccessControlBuilder;
+    use core::time::Duration;
+    use ockam_core::compat::vec::Vec;
+    use ockam_core::{route, TransportMessage};
+
+    fn message_from(peer: Option<&ProfileIdentifier>) -> LocalMessage {
+        let local_info = peer
+            .map(|peer| {
+                EntitySecureChannelLocalInfo::new(peer.clone())
+                    .to_local_info()
+                    .unwrap()
+            })
+            .into_iter()
+            .collect();
+        LocalMessage::new(
+            TransportMessage::v1(route!["receiver"], route!["sender"], Vec::new()),
+            local_info,
+        )
+    }
+
+    fn burst<A: AccessControl>(
+        access_control: &mut RateLimitAccessControl<A>,
+        peer: &ProfileIdentifier,
+        messages: usize,
+        now: Instant,
+    ) -> Vec<bool> {
+        let msg = message_from(Some(peer));
+        (0..messages)
+            .map(|_| access_control.decide(&msg, true, now))
+            .collect()
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let alice = ProfileIdentifier::random();
+        let bob = ProfileIdentifier::random();
+        let mut access_control =
+            RateLimitAccessControl::new(EntityAccessControlBuilder::new_with_any_id(), 10, 3);
+        let start = Instant::now();
+
+        // Bursts up to the limit pass, the rest is denied until tokens come back
+        assert_eq!(
+            burst(&mut access_control, &alice, 5, start),
+            [true, true, true, false, false]
+        );
+        // One peer going over the limit doesn't affect another
+        assert_eq!(
+            burst(&mut access_control, &bob, 3, start),
+            [true, true, true]
+        );
+        // 10 per second is a token every 100ms
+        let later = start + Duration::from_millis(250);
+        assert_eq!(
+            burst(&mut access_control, &alice, 3, later),
+            [true, true, false]
+        );
+        // The bucket refills up to the burst, not beyond
+        let much_later = later + Duration::from_secs(60);
+        assert_eq!(
+            burst(&mut access_control, &alice, 4, much_later),
+            [true, true, true, false]
+        );
+
+        // Denied by the inner access control, which doesn't take a token
+        let msg = message_from(Some(&bob));
+        assert!(!access_control.decide(&msg, false, much_later));
+        assert_eq!(
+            burst(&mut access_control, &bob, 4, much_later),
+            [true, true, true, false]
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_unidentified() {
+        let msg = message_from(None);
+        let mut access_control =
+            RateLimitAccessControl::new(EntityAccessControlBuilder::new_with_any_id(), 1, 1);
+        let now = Instant::now();
+        assert!((0..5).all(|_| access_control.decide(&msg, true, now)));
+
+        let mut access_control =
+            RateLimitAccessControl::new(EntityAccessControlBuilder::new_with_any_id(), 1, 1)
+                .deny_unidentified();
+        assert!(!access_control.decide(&msg, true, now));
+    }
+
+    #[test]
+    fn test_rate_limit_evicts_idle_peers() {
+        let peers: Vec<_> = (0..3).map(|_| ProfileIdentifier::random()).collect();
+        let mut access_control =
+            RateLimitAccessControl::new(EntityAccessControlBuilder::new_with_any_id(), 1, 2)
+                .with_max_peers(2);
+        let start = Instant::now();
+
+        burst(&mut access_control, &peers[0], 2, start);
+        burst(
+            &mut access_control,
+            &peers[1],
+            2,
+            start + Duration::from_millis(100),
+        );
+        // Both are still refilling, so the one seen least recently goes
+        burst(
+            &mut access_control,
+            &peers[2],
+            2,
+            start + Duration::from_millis(200),
+        );
+        assert_eq!(access_control.buckets.len(), 2);
+        assert!(!access_control.buckets.contains_key(&peers[0]));
+
+        // Full buckets go first
+        let later = start + Duration::from_secs(10);
+        burst(&mut access_control, &peers[0], 1, later);
+        assert_eq!(access_control.buckets.len(), 1);
+        assert!(access_control.buckets.contains_key(&peers[0]));
+    }
+
+    #[test]
+    fn test_rate_limit_sync() -> Result<()> {
+        let alice = ProfileIdentifier::random();
+        let msg = message_from(Some(&alice));
+        let mut access_control =
+            RateLimitAccessControl::new(EntityAccessControlBuilder::new_with_any_id(), 1, 2);
+
+        let decisions: Vec<bool> = (0..3)
+            .map(|_| access_control.msg_is_authorized_sync(&msg).unwrap())
+            .collect::<Result<_>>()?;
+        assert_eq!(decisions, [true, true, false]);
+
+        Ok(())
+    }
+}
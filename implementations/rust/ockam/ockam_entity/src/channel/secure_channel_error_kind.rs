@@ -0,0 +1,252 @@
+use crate::EntityError;
+use ockam_channel::SecureChannelError;
+use ockam_core::Error;
+
+/// Width of the code range of an error domain, see [`EntityError::DOMAIN_CODE`]
+const DOMAIN_WIDTH: u32 = 1_000;
+
+/// Error domains of transports, which this crate doesn't depend on
+const TRANSPORT_DOMAIN_CODES: [u32; 2] = [
+    15_000, // OCKAM_TRANSPORT_CORE
+    21_000, // OCKAM_TRANSPORT_WEBSOCKET
+];
+
+/// Error domains of the key exchanges underneath a channel, besides [`SecureChannelError`]
+const KEY_EXCHANGE_DOMAIN_CODES: [u32; 2] = [
+    14_000, // OCKAM_KEX_XX
+    18_000, // OCKAM_KEX_X3DH
+];
+
+const TRANSPORT_ERRORS: &[EntityError] = &[EntityError::SecureChannelReconnectFailed];
+
+const TRUST_ERRORS: &[EntityError] = &[
+    EntityError::SecureChannelTrustCheckFailed,
+    EntityError::SecureChannelVerificationFailed,
+    EntityError::SecureChannelCannotBeAuthenticated,
+    EntityError::SecureChannelTrustNotEnforced,
+    EntityError::InheritedTrustUnavailable,
+    EntityError::AnonymousSecureChannelRejected,
+    EntityError::UnknownSecureChannelService,
+    EntityError::ContactVerificationFailed,
+    EntityError::CredentialTrustCheckFailed,
+];
+
+const PROTOCOL_ERRORS: &[EntityError] = &[
+    EntityError::KeyExchangePatternMismatch,
+    EntityError::MalformedHandshakeMessage,
+    EntityError::SecureChannelHandshakeMessageTooLarge,
+    EntityError::SecureChannelProtocolVersionTooOld,
+    EntityError::InvalidSecureChannelService,
+    EntityError::UnknownChannelMsgDestination,
+    EntityError::UnknownChannelMsgOrigin,
+    EntityError::InvalidSecureChannelInternalState,
+    EntityError::MalformedCompressedPayload,
+    EntityError::CborDecodeFailed,
+];
+
+const TIMEOUT_ERRORS: &[EntityError] = &[
+    EntityError::SecureChannelTimeout,
+    EntityError::SecureChannelKeepaliveTimeout,
+];
+
+const CAPACITY_ERRORS: &[EntityError] = &[
+    EntityError::SecureChannelListenerAtCapacity,
+    EntityError::SecureChannelListenerDraining,
+    EntityError::SecureChannelWouldBlock,
+];
+
+/// What a failed secure channel operation ran into, to decide between retrying and giving up.
+/// Secure channel APIs return [`ockam_core::Error`]s, which convert into their kind:
+///
+/// ```
+/// # use ockam_entity::{EntityError, SecureChannelErrorKind};
+/// let err = ockam_core::Error::from(EntityError::SecureChannelListenerAtCapacity);
+/// let kind = SecureChannelErrorKind::from(&err);
+/// assert_eq!(kind, SecureChannelErrorKind::Capacity);
+/// assert!(kind.is_retryable());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecureChannelErrorKind {
+    /// The transport failed, or the channel couldn't be re-established over it
+    Transport,
+    /// The trust policy, or the one of the other side, rejected the peer, or the peer couldn't
+    /// prove its identity
+    Trust,
+    /// The other side sent something it shouldn't have, or speaks an unsupported protocol
+    Protocol,
+    /// The other side didn't answer in time
+    Timeout,
+    /// The other side, or the channel itself, doesn't take more right now
+    Capacity,
+    /// Anything else, e.g. a channel that was closed already or a local misconfiguration
+    Other,
+}
+
+impl SecureChannelErrorKind {
+    /// Failures that may pass on their own, so that the operation is worth trying again.
+    /// Trust and protocol failures repeat until either side changes
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::Transport | Self::Timeout | Self::Capacity)
+    }
+
+    fn of_entity_error(code: u32) -> Self {
+        let is_any = |errors: &[EntityError]| {
+            errors
+                .iter()
+                .any(|err| EntityError::DOMAIN_CODE + *err as u32 == code)
+        };
+
+        if is_any(TRANSPORT_ERRORS) {
+            Self::Transport
+        } else if is_any(TRUST_ERRORS) {
+            Self::Trust
+        } else if is_any(PROTOCOL_ERRORS) {
+            Self::Protocol
+        } else if is_any(TIMEOUT_ERRORS) {
+            Self::Timeout
+        } else if is_any(CAPACITY_ERRORS) {
+            Self::Capacity
+        } else {
+            Self::Other
+        }
+    }
+}
+
+impl From<&Error> for SecureChannelErrorKind {
+    fn from(err: &Error) -> Self {
+        let code = err.code();
+        let domain = code - code % DOMAIN_WIDTH;
+
+        if domain == EntityError::DOMAIN_CODE {
+            Self::of_entity_error(code)
+        } else if TRANSPORT_DOMAIN_CODES.contains(&domain) {
+            Self::Transport
+        } else if domain == SecureChannelError::DOMAIN_CODE
+            || KEY_EXCHANGE_DOMAIN_CODES.contains(&domain)
+        {
+            Self::Protocol
+        } else {
+            Self::Other
+        }
+    }
+}
+
+impl From<Error> for SecureChannelErrorKind {
+    fn from(err: Error) -> Self {
+        Self::from(&err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        Entity, Identity, SecureChannelOptions, TrustEveryonePolicy, TrustIdentifierPolicy,
+    };
+    use core::time::Duration;
+    use ockam_core::{route, Result};
+    use ockam_node::{Context, NullWorker};
+    use ockam_transport_core::TransportError;
+    use ockam_vault_sync_core::Vault;
+
+    fn kind(err: impl Into<Error>) -> SecureChannelErrorKind {
+        SecureChannelErrorKind::from(err.into())
+    }
+
+    #[test]
+    fn test_kinds() {
+        use SecureChannelErrorKind::*;
+
+        for err in [TransportError::ConnectionDrop, TransportError::PeerNotFound] {
+            assert_eq!(kind(err), Transport);
+        }
+        assert_eq!(kind(EntityError::SecureChannelReconnectFailed), Transport);
+
+        for err in [
+            EntityError::SecureChannelTrustCheckFailed,
+            EntityError::SecureChannelVerificationFailed,
+            EntityError::AnonymousSecureChannelRejected,
+        ] {
+            assert_eq!(kind(err), Trust);
+        }
+
+        for err in [
+            EntityError::KeyExchangePatternMismatch,
+            EntityError::MalformedHandshakeMessage,
+            EntityError::SecureChannelProtocolVersionTooOld,
+        ] {
+            assert_eq!(kind(err), Protocol);
+        }
+        assert_eq!(kind(SecureChannelError::ReplayedMessage), Protocol);
+
+        assert_eq!(kind(EntityError::SecureChannelTimeout), Timeout);
+        assert_eq!(kind(EntityError::SecureChannelKeepaliveTimeout), Timeout);
+
+        assert_eq!(kind(EntityError::SecureChannelListenerAtCapacity), Capacity);
+        assert_eq!(kind(EntityError::SecureChannelWouldBlock), Capacity);
+
+        assert_eq!(kind(EntityError::SecureChannelNotFound), Other);
+        assert_eq!(kind(Error::new(42, "SOMEWHERE_ELSE")), Other);
+
+        assert!(Transport.is_retryable() && Timeout.is_retryable() && Capacity.is_retryable());
+        assert!(!Trust.is_retryable() && !Protocol.is_retryable() && !Other.is_retryable());
+    }
+
+    #[ockam_macros::test]
+    async fn test_handshake_failures(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let mut eve = Entity::create(ctx, &vault).await?;
+
+        bob.create_secure_channel_listener_with_max_channels(
+            "bob_listener",
+            TrustIdentifierPolicy::new(alice.identifier().await?),
+            1,
+        )
+        .await?;
+
+        let err = eve
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            SecureChannelErrorKind::from(err),
+            SecureChannelErrorKind::Trust
+        );
+        // Bob releases the slot of Eve meanwhile
+        ctx.sleep(Duration::from_millis(250)).await;
+
+        alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        let err = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            SecureChannelErrorKind::from(err),
+            SecureChannelErrorKind::Capacity
+        );
+
+        ctx.start_worker("black_hole", NullWorker).await?;
+        let err = alice
+            .create_secure_channel_with_options(
+                route!["black_hole"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_timeout(Duration::from_millis(250)),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            SecureChannelErrorKind::from(err),
+            SecureChannelErrorKind::Timeout
+        );
+
+        ctx.stop().await
+    }
+}
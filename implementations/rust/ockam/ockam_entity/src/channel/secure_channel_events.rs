@@ -0,0 +1,67 @@
+use crate::ProfileIdentifier;
+use core::time::Duration;
+use ockam_core::compat::string::String;
+use ockam_core::{Address, Message};
+use ockam_node::Context;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Secure channel handshake lifecycle event, sent to the address given with
+/// [`SecureChannelOptions::with_events_address`](crate::SecureChannelOptions::with_events_address)
+#[derive(Serialize, Deserialize, Message, Debug, Clone, PartialEq)]
+pub enum SecureChannelEvent {
+    /// Initiator started the handshake
+    HandshakeStarted,
+    /// Handshake finished and the peer is trusted.
+    /// Duration is zero without `std`
+    HandshakeCompleted {
+        duration: Duration,
+        peer_id: ProfileIdentifier,
+    },
+    /// Handshake failed or timed out
+    HandshakeFailed { reason: String },
+}
+
+/// Sends events to an optional address. Failing to deliver an event doesn't fail the handshake
+pub(crate) struct SecureChannelEvents {
+    address: Option<Address>,
+}
+
+impl SecureChannelEvents {
+    pub fn new(address: Option<Address>) -> Self {
+        Self { address }
+    }
+
+    pub async fn send(&self, ctx: &Context, event: SecureChannelEvent) {
+        if let Some(address) = &self.address {
+            if let Err(err) = ctx.send(address.clone(), event).await {
+                warn!("{} sending SecureChannel event to {}", err, address);
+            }
+        }
+    }
+}
+
+/// Measures handshake duration where a clock is available
+pub(crate) struct Stopwatch {
+    #[cfg(feature = "std")]
+    started_at: std::time::Instant,
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        Self {
+            #[cfg(feature = "std")]
+            started_at: std::time::Instant::now(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub fn elapsed(&self) -> Duration {
+        Duration::default()
+    }
+}
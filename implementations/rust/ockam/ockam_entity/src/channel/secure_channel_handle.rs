@@ -0,0 +1,134 @@
+use crate::{IdentityRequest, ProfileIdentifier, SecureChannelCipherSuite};
+use core::time::Duration;
+use ockam_core::Address;
+use ockam_node::Context;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Secure channel held by an [`Entity`](crate::Entity), see [`Entity::secure_channels`](crate::Entity::secure_channels)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SecureChannelHandle {
+    address: Address,
+    their_profile_id: ProfileIdentifier,
+    is_initiator: bool,
+    created_at: Duration,
+    cipher_suite: SecureChannelCipherSuite,
+    protocol_version: u16,
+    listener: Option<Address>,
+}
+
+impl SecureChannelHandle {
+    pub(crate) fn new(
+        address: Address,
+        their_profile_id: ProfileIdentifier,
+        is_initiator: bool,
+        cipher_suite: SecureChannelCipherSuite,
+        protocol_version: u16,
+        listener: Option<Address>,
+    ) -> Self {
+        Self {
+            address,
+            their_profile_id,
+            is_initiator,
+            created_at: now(),
+            cipher_suite,
+            protocol_version,
+            listener,
+        }
+    }
+
+    /// Local address to send messages through the channel
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// Identifier of the peer
+    pub fn their_profile_id(&self) -> &ProfileIdentifier {
+        &self.their_profile_id
+    }
+
+    /// Whether this side created the channel, rather than accepted it with a listener
+    pub fn is_initiator(&self) -> bool {
+        self.is_initiator
+    }
+
+    /// Time since UNIX epoch the handshake completed at. Zero without `std`
+    pub fn created_at(&self) -> Duration {
+        self.created_at
+    }
+
+    /// Primitives negotiated during the handshake
+    pub fn cipher_suite(&self) -> &SecureChannelCipherSuite {
+        &self.cipher_suite
+    }
+
+    /// Protocol version negotiated during the handshake, the highest one both sides speak
+    pub fn protocol_version(&self) -> u16 {
+        self.protocol_version
+    }
+
+    /// Listener that accepted the channel, none if this side created it
+    pub fn listener(&self) -> Option<&Address> {
+        self.listener.as_ref()
+    }
+}
+
+#[cfg(feature = "std")]
+fn now() -> Duration {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+#[cfg(not(feature = "std"))]
+fn now() -> Duration {
+    Duration::default()
+}
+
+/// Keeps the list of channels of the [`EntityWorker`](crate::EntityWorker) that started them up to date
+#[derive(Clone)]
+pub(crate) struct SecureChannelRegistry {
+    address: Address,
+    registered: bool,
+}
+
+impl SecureChannelRegistry {
+    pub fn new(address: Address) -> Self {
+        Self {
+            address,
+            registered: false,
+        }
+    }
+
+    pub async fn register(
+        &mut self,
+        ctx: &Context,
+        profile_id: ProfileIdentifier,
+        handle: SecureChannelHandle,
+    ) {
+        self.registered = true;
+        self.send(
+            ctx,
+            IdentityRequest::RegisterSecureChannel(profile_id, handle),
+        )
+        .await
+    }
+
+    /// Does nothing unless the channel was registered
+    pub async fn deregister(&mut self, ctx: &Context, address: Address) {
+        if self.registered {
+            self.registered = false;
+            self.send(ctx, IdentityRequest::DeregisterSecureChannel(address))
+                .await
+        }
+    }
+
+    async fn send(&self, ctx: &Context, req: IdentityRequest) {
+        if let Err(err) = ctx.send(self.address.clone(), req).await {
+            warn!(
+                "{} updating SecureChannel registry at {}",
+                err, self.address
+            );
+        }
+    }
+}
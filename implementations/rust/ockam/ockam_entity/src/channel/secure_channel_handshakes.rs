@@ -0,0 +1,40 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use ockam_channel::PendingHandshakes;
+use ockam_core::compat::sync::Arc;
+use ockam_core::{Address, Result};
+use ockam_node::Context;
+
+/// Handshakes a listener has in progress. Shared by the listener with the entity worker,
+/// which cancels them when the listener is stopped, and stops new ones when it's drained
+#[derive(Clone, Default)]
+pub(crate) struct SecureChannelHandshakes {
+    /// Responders that didn't trust the initiator yet
+    pub responders: PendingHandshakes,
+    /// Regular SecureChannels of the handshakes, until their responder trusts the initiator
+    pub key_exchanges: PendingHandshakes,
+    draining: Arc<AtomicBool>,
+}
+
+impl SecureChannelHandshakes {
+    /// Stop the responders first, so that none of them starts a handshake meanwhile.
+    /// Returns once all of them are stopped
+    pub async fn cancel(&self, ctx: &Context) -> Result<()> {
+        self.responders.cancel(ctx).await?;
+        self.key_exchanges.cancel(ctx).await
+    }
+
+    /// The handshake of given responder is complete, so it's no longer cancelled with the listener
+    pub fn complete(&self, responder: &Address, key_exchange: &Address) {
+        self.responders.remove(responder);
+        self.key_exchanges.remove(key_exchange);
+    }
+
+    /// Reject new handshakes from now on, those in progress still complete
+    pub fn drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,661 @@
+use crate::{
+    AuthorityCredential, KeyExchangePattern, PreSharedKey, DEFAULT_SECURE_CHANNEL_TIMEOUT,
+};
+use core::time::Duration;
+use ockam_channel::{RekeyOptions, DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE, DEFAULT_REPLAY_WINDOW};
+use ockam_core::compat::string::String;
+use ockam_core::Address;
+use serde::{Deserialize, Serialize};
+
+/// Default time a partial batch waits for more messages before it's sent
+pub const DEFAULT_MAX_BATCH_DELAY: Duration = Duration::from_millis(10);
+
+/// Version of the secure channel protocol spoken by this side. Each side speaks every
+/// version down to [`MIN_SECURE_CHANNEL_PROTOCOL_VERSION`], and the handshake settles on
+/// the highest one both speak, see [`SecureChannelHandle::protocol_version`](crate::SecureChannelHandle::protocol_version)
+pub const SECURE_CHANNEL_PROTOCOL_VERSION: u16 = 2;
+
+/// Oldest version of the secure channel protocol still spoken by this side. The first version
+/// predates the version in the key exchange header, and the messages of the handshake changed since
+pub const MIN_SECURE_CHANNEL_PROTOCOL_VERSION: u16 = 2;
+
+/// Options for creating a secure channel with [`Entity::create_secure_channel_with_options`](crate::Entity::create_secure_channel_with_options)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SecureChannelOptions {
+    timeout: Duration,
+    rekey: RekeyOptions,
+    replay_window: u16,
+    max_batch: usize,
+    max_batch_delay: Duration,
+    events_address: Option<Address>,
+    reconnect: Option<ReconnectOptions>,
+    handshake_retry: Option<HandshakeRetryOptions>,
+    key_exchange: KeyExchangePattern,
+    untagged_key_exchange: bool,
+    credential: Option<AuthorityCredential>,
+    service: Option<String>,
+    backpressure: Option<BackpressureOptions>,
+    keepalive: Option<KeepaliveOptions>,
+    strict_trust: bool,
+    inherited_trust: bool,
+    anonymous: bool,
+    compression: bool,
+    min_protocol_version: u16,
+    /// Offered instead of [`SECURE_CHANNEL_PROTOCOL_VERSION`], to act as an older or newer peer
+    protocol_version: u16,
+    max_handshake_message_size: usize,
+    psk: Option<PreSharedKey>,
+    unstamped_local_hops: bool,
+    transport: Option<Address>,
+}
+
+/// How an initiator re-establishes a channel after its transport failed
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ReconnectOptions {
+    max_attempts: u8,
+    backoff: Duration,
+}
+
+impl ReconnectOptions {
+    /// Make up to `max_attempts` attempts, waiting `backoff` before the first one
+    /// and doubling the wait after every failed attempt
+    pub fn new(max_attempts: u8, backoff: Duration) -> Self {
+        Self {
+            max_attempts,
+            backoff,
+        }
+    }
+
+    pub fn max_attempts(&self) -> u8 {
+        self.max_attempts
+    }
+
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
+}
+
+/// How an initiator retries a handshake that timed out or hit a transport error
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct HandshakeRetryOptions {
+    max_attempts: u8,
+    base_delay: Duration,
+}
+
+impl HandshakeRetryOptions {
+    /// Make up to `max_attempts` attempts, waiting `base_delay` after the first failed one
+    /// and doubling the wait after every further one
+    pub fn new(max_attempts: u8, base_delay: Duration) -> Self {
+        Self {
+            max_attempts,
+            base_delay,
+        }
+    }
+
+    pub fn max_attempts(&self) -> u8 {
+        self.max_attempts
+    }
+
+    pub fn base_delay(&self) -> Duration {
+        self.base_delay
+    }
+}
+
+/// How many messages an initiator lets pile up while the other side doesn't keep up
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct BackpressureOptions {
+    max_in_flight: usize,
+    max_queued: usize,
+}
+
+impl BackpressureOptions {
+    /// Hold messages once `max_in_flight` messages weren't acknowledged by the other side yet,
+    /// and drop them once `max_queued` messages are held
+    pub fn new(max_in_flight: usize, max_queued: usize) -> Self {
+        Self {
+            max_in_flight: max_in_flight.max(1),
+            max_queued,
+        }
+    }
+
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    pub fn max_queued(&self) -> usize {
+        self.max_queued
+    }
+}
+
+/// How an initiator detects that the other side went away without closing the channel
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct KeepaliveOptions {
+    interval: Duration,
+    deadline: Duration,
+}
+
+impl KeepaliveOptions {
+    /// Ping the other side every `interval`, and stop the channel if a ping
+    /// isn't answered within `deadline`
+    pub fn new(interval: Duration, deadline: Duration) -> Self {
+        Self { interval, deadline }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    pub fn deadline(&self) -> Duration {
+        self.deadline
+    }
+}
+
+impl Default for SecureChannelOptions {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_SECURE_CHANNEL_TIMEOUT,
+            rekey: RekeyOptions::default(),
+            replay_window: DEFAULT_REPLAY_WINDOW,
+            max_batch: 1,
+            max_batch_delay: DEFAULT_MAX_BATCH_DELAY,
+            events_address: None,
+            reconnect: None,
+            handshake_retry: None,
+            key_exchange: KeyExchangePattern::default(),
+            untagged_key_exchange: false,
+            credential: None,
+            service: None,
+            backpressure: None,
+            keepalive: None,
+            strict_trust: false,
+            inherited_trust: false,
+            anonymous: false,
+            compression: false,
+            min_protocol_version: MIN_SECURE_CHANNEL_PROTOCOL_VERSION,
+            protocol_version: SECURE_CHANNEL_PROTOCOL_VERSION,
+            max_handshake_message_size: DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE,
+            psk: None,
+            unstamped_local_hops: false,
+            transport: None,
+        }
+    }
+}
+
+impl SecureChannelOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time to wait for the handshake to complete
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Agree on a new encryption key with the other side after given number of messages
+    pub fn with_rekey_after_messages(mut self, messages: u16) -> Self {
+        self.rekey = self.rekey.with_after_messages(messages);
+        self
+    }
+
+    /// Replace the encryption key after given time. Ignored without `std`
+    pub fn with_rekey_interval(mut self, interval: Duration) -> Self {
+        self.rekey = self.rekey.with_interval(interval);
+        self
+    }
+
+    /// Number of recent messages from the other side remembered to detect replays.
+    /// Messages delivered out of order by more than that are dropped as well
+    pub fn with_replay_window(mut self, replay_window: u16) -> Self {
+        self.replay_window = replay_window;
+        self
+    }
+
+    /// Coalesce up to given number of outgoing messages into one encrypted frame.
+    /// Values below 2 disable batching, which is the default
+    pub fn with_max_batch(mut self, max_batch: usize) -> Self {
+        self.max_batch = max_batch;
+        self
+    }
+
+    /// Time a partial batch waits for more messages before it's sent
+    pub fn with_max_batch_delay(mut self, max_batch_delay: Duration) -> Self {
+        self.max_batch_delay = max_batch_delay;
+        self
+    }
+
+    /// Send [`SecureChannelEvent`](crate::SecureChannelEvent)s about the handshake to given address
+    pub fn with_events_address(mut self, events_address: impl Into<Address>) -> Self {
+        self.events_address = Some(events_address.into());
+        self
+    }
+
+    /// Re-run the handshake over the same route when the transport fails, keeping the
+    /// channel address. Messages the transport didn't take are sent again once reconnected,
+    /// messages sent meanwhile wait until then, so that the order is kept
+    pub fn with_reconnect(mut self, max_attempts: u8, backoff: Duration) -> Self {
+        self.reconnect = Some(ReconnectOptions::new(max_attempts, backoff));
+        self
+    }
+
+    /// Run the handshake again if it timed out or the transport failed, up to `max_attempts`
+    /// times in total. Each attempt waits for the timeout of [`SecureChannelOptions::with_timeout`],
+    /// and starts over with fresh keys and addresses, so the other side never continues a
+    /// failed attempt. Failures like a rejected profile aren't retried
+    pub fn with_handshake_retry(mut self, max_attempts: u8, base_delay: Duration) -> Self {
+        self.handshake_retry = Some(HandshakeRetryOptions::new(max_attempts, base_delay));
+        self
+    }
+
+    /// Key agreement pattern of the regular SecureChannel underneath
+    pub fn with_key_exchange(mut self, key_exchange: KeyExchangePattern) -> Self {
+        self.key_exchange = key_exchange;
+        self
+    }
+
+    /// Don't tag the first key exchange message, for listeners that predate [`KeyExchangePattern`]
+    /// or were created with [`SecureChannelListenerOptions::with_untagged_key_exchange`].
+    /// Only Noise XX works that way, and neither a service, inherited trust nor anonymity can be
+    /// asked for. Otherwise the channel fails with [`EntityError::KeyExchangePatternMismatch`](crate::EntityError::KeyExchangePatternMismatch).
+    /// No protocol version is offered either, the listener picks its own
+    pub fn with_untagged_key_exchange(mut self) -> Self {
+        self.untagged_key_exchange = true;
+        self
+    }
+
+    /// Present given credential to the other side during the handshake
+    pub fn with_credential(mut self, credential: AuthorityCredential) -> Self {
+        self.credential = Some(credential);
+        self
+    }
+
+    /// Reach given service of the listener, which checks the trust policy registered for it
+    /// with [`Entity::add_secure_channel_service`](crate::Entity::add_secure_channel_service).
+    /// The name is sent in the clear, before the key exchange
+    pub fn with_service(mut self, service: impl Into<String>) -> Self {
+        self.service = Some(service.into());
+        self
+    }
+
+    /// Bound the messages sent to the other side that it didn't acknowledge yet, see
+    /// [`BackpressureOptions::new`]. Messages over both bounds are dropped with
+    /// [`EntityError::SecureChannelWouldBlock`](crate::EntityError::SecureChannelWouldBlock),
+    /// senders avoid that with [`BackpressureContext::wait_for_capacity`](crate::BackpressureContext::wait_for_capacity)
+    pub fn with_backpressure(mut self, max_in_flight: usize, max_queued: usize) -> Self {
+        self.backpressure = Some(BackpressureOptions::new(max_in_flight, max_queued));
+        self
+    }
+
+    /// Ping the other side over the channel, see [`KeepaliveOptions::new`]. Once a ping
+    /// isn't answered in time the channel stops, and senders waiting for
+    /// [`BackpressureContext::wait_for_capacity`](crate::BackpressureContext::wait_for_capacity) get
+    /// [`EntityError::SecureChannelKeepaliveTimeout`](crate::EntityError::SecureChannelKeepaliveTimeout)
+    pub fn with_keepalive(mut self, interval: Duration, deadline: Duration) -> Self {
+        self.keepalive = Some(KeepaliveOptions::new(interval, deadline));
+        self
+    }
+
+    /// Declare that we check a meaningful trust policy, and fail the handshake with
+    /// [`EntityError::SecureChannelTrustNotEnforced`](crate::EntityError::SecureChannelTrustNotEnforced)
+    /// unless the other side declares the same. Listeners opt in with
+    /// [`Entity::create_secure_channel_listener_with_strict_trust`](crate::Entity::create_secure_channel_listener_with_strict_trust)
+    pub fn with_strict_trust(mut self) -> Self {
+        self.strict_trust = true;
+        self
+    }
+
+    /// Create the channel over an existing channel of the same profile, which the route
+    /// starts with, and require the other side to be the peer of that channel.
+    /// Its identity is still proven over the new channel, but the listener checks its trust
+    /// policy before the key exchange completes, which saves the confirmation message.
+    /// The credential, if any, isn't seen by that policy.
+    /// Fails with [`EntityError::InheritedTrustUnavailable`](crate::EntityError::InheritedTrustUnavailable)
+    /// if the route doesn't start with such a channel
+    pub fn with_inherited_trust(mut self) -> Self {
+        self.inherited_trust = true;
+        self
+    }
+
+    /// Only encrypt, without either side proving an identity or checking a trust policy.
+    /// Messages are delivered with [`EntitySecureChannelLocalInfo::is_anonymous`](crate::EntitySecureChannelLocalInfo::is_anonymous)
+    /// set, and the channel isn't listed in [`Entity::secure_channels`](crate::Entity::secure_channels).
+    /// Listeners opt in with [`Entity::create_secure_channel_listener_with_anonymous`](crate::Entity::create_secure_channel_listener_with_anonymous),
+    /// others reject it with [`EntityError::AnonymousSecureChannelRejected`](crate::EntityError::AnonymousSecureChannelRejected).
+    /// Can't be combined with [`SecureChannelOptions::with_inherited_trust`]
+    pub fn with_anonymous(mut self) -> Self {
+        self.anonymous = true;
+        self
+    }
+
+    /// Compress messages before encrypting them, if the other side supports it. Messages that
+    /// wouldn't get smaller are sent as they are. Meant for slow transports and compressible
+    /// payloads such as JSON or CBOR. Ignored for anonymous channels, and without the
+    /// `compression` feature.
+    ///
+    /// **Warning:** the length of a compressed message depends on its content, which encryption
+    /// doesn't hide. If a message mixes secrets with data an attacker controls, e.g. a token
+    /// next to a reflected query, the attacker can recover the secret by watching message sizes,
+    /// as with CRIME and BREACH. Only compress channels whose messages never do that
+    pub fn with_compression(mut self) -> Self {
+        self.compression = true;
+        self
+    }
+
+    /// Fail the handshake with
+    /// [`EntityError::SecureChannelProtocolVersionTooOld`](crate::EntityError::SecureChannelProtocolVersionTooOld)
+    /// if the other side doesn't speak at least given protocol version.
+    /// Newer versions than ours are fine, the other side falls back to ours.
+    /// Ignored for anonymous channels, which don't negotiate a version
+    pub fn with_min_protocol_version(mut self, min_protocol_version: u16) -> Self {
+        self.min_protocol_version = min_protocol_version;
+        self
+    }
+
+    /// Fail the handshake with
+    /// [`EntityError::SecureChannelHandshakeMessageTooLarge`](crate::EntityError::SecureChannelHandshakeMessageTooLarge)
+    /// if the other side sends a handshake message larger than given size, in bytes, instead of
+    /// [`DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE`]. It's checked before the message is parsed
+    pub fn with_max_handshake_message_size(mut self, max_handshake_message_size: usize) -> Self {
+        self.max_handshake_message_size = max_handshake_message_size;
+        self
+    }
+
+    /// Mix given pre-shared key into the keys of the channel, on top of the key exchange.
+    /// The listener has to hold the same key, see
+    /// [`Entity::create_secure_channel_listener_with_psk`](crate::Entity::create_secure_channel_listener_with_psk),
+    /// which lets channels created with stolen identity keys alone fail. Both sides confirm
+    /// they derived the same keys, if they differ the handshake fails with
+    /// [`EntityError::PreSharedKeyMismatch`](crate::EntityError::PreSharedKeyMismatch) on
+    /// both sides, neither learning whose key was wrong. If only the listener has one, the
+    /// initiator fails with [`EntityError::SecureChannelTimeout`](crate::EntityError::SecureChannelTimeout)
+    pub fn with_psk(mut self, psk: impl Into<PreSharedKey>) -> Self {
+        self.psk = Some(psk.into());
+        self
+    }
+
+    /// Deliver messages from the other side without [`EntitySecureChannelLocalInfo`](crate::EntitySecureChannelLocalInfo)
+    /// if their next hop is a worker of this node, which saves encoding it for every message.
+    /// Only for local pipelines that trust every message of the channel: access controls
+    /// checking the peer, like [`EntityIdAccessControl`](crate::EntityIdAccessControl), reject
+    /// those messages, and channels created over this one can't inherit trust from it.
+    /// Messages whose next hop is a transport are still stamped
+    pub fn with_unstamped_local_hops(mut self) -> Self {
+        self.unstamped_local_hops = true;
+        self
+    }
+
+    /// Send every message of the channel, the handshake included, through the transport worker
+    /// of this node at given address, e.g. the sender of one of several adapters, instead of
+    /// the one the route picks. The worker gets the messages with its own address as their next
+    /// hop, like the connection workers of transports do. If no worker runs at the address,
+    /// the channel fails with
+    /// [`EntityError::SecureChannelTransportNotFound`](crate::EntityError::SecureChannelTransportNotFound)
+    pub fn with_transport(mut self, transport: impl Into<Address>) -> Self {
+        self.transport = Some(transport.into());
+        self
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_protocol_version(mut self, protocol_version: u16) -> Self {
+        self.protocol_version = protocol_version;
+        self
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn rekey(&self) -> &RekeyOptions {
+        &self.rekey
+    }
+
+    pub fn replay_window(&self) -> u16 {
+        self.replay_window
+    }
+
+    pub fn max_batch(&self) -> usize {
+        self.max_batch
+    }
+
+    pub fn max_batch_delay(&self) -> Duration {
+        self.max_batch_delay
+    }
+
+    pub fn events_address(&self) -> Option<&Address> {
+        self.events_address.as_ref()
+    }
+
+    pub fn reconnect(&self) -> Option<&ReconnectOptions> {
+        self.reconnect.as_ref()
+    }
+
+    pub fn handshake_retry(&self) -> Option<&HandshakeRetryOptions> {
+        self.handshake_retry.as_ref()
+    }
+
+    /// Longest a handshake can take, with all of its attempts and the waits between them
+    pub(crate) fn handshake_duration(&self) -> Duration {
+        let retry = match &self.handshake_retry {
+            Some(retry) if retry.max_attempts > 1 => retry,
+            _ => return self.timeout,
+        };
+        let retries = u32::from(retry.max_attempts - 1);
+        let waits = retry
+            .base_delay
+            .checked_mul(2u32.saturating_pow(retries) - 1);
+        let attempts = self.timeout.checked_mul(retries + 1);
+
+        match (attempts, waits) {
+            (Some(attempts), Some(waits)) => attempts.checked_add(waits).unwrap_or(Duration::MAX),
+            _ => Duration::MAX,
+        }
+    }
+
+    pub fn key_exchange(&self) -> KeyExchangePattern {
+        self.key_exchange
+    }
+
+    pub fn untagged_key_exchange(&self) -> bool {
+        self.untagged_key_exchange
+    }
+
+    pub fn credential(&self) -> Option<&AuthorityCredential> {
+        self.credential.as_ref()
+    }
+
+    pub fn service(&self) -> Option<&str> {
+        self.service.as_deref()
+    }
+
+    pub fn backpressure(&self) -> Option<&BackpressureOptions> {
+        self.backpressure.as_ref()
+    }
+
+    pub fn keepalive(&self) -> Option<&KeepaliveOptions> {
+        self.keepalive.as_ref()
+    }
+
+    pub fn strict_trust(&self) -> bool {
+        self.strict_trust
+    }
+
+    pub fn inherited_trust(&self) -> bool {
+        self.inherited_trust
+    }
+
+    pub fn anonymous(&self) -> bool {
+        self.anonymous
+    }
+
+    pub fn compression(&self) -> bool {
+        self.compression
+    }
+
+    pub fn min_protocol_version(&self) -> u16 {
+        self.min_protocol_version
+    }
+
+    pub(crate) fn protocol_version(&self) -> u16 {
+        self.protocol_version
+    }
+
+    pub fn max_handshake_message_size(&self) -> usize {
+        self.max_handshake_message_size
+    }
+
+    pub fn psk(&self) -> Option<&PreSharedKey> {
+        self.psk.as_ref()
+    }
+
+    pub fn unstamped_local_hops(&self) -> bool {
+        self.unstamped_local_hops
+    }
+
+    pub fn transport(&self) -> Option<&Address> {
+        self.transport.as_ref()
+    }
+}
+
+/// Options for creating a secure channel listener with [`Entity::create_secure_channel_listener_with_options`](crate::Entity::create_secure_channel_listener_with_options)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SecureChannelListenerOptions {
+    max_channels: Option<usize>,
+    strict_trust: bool,
+    allow_anonymous: bool,
+    max_handshake_message_size: usize,
+    psk: Option<PreSharedKey>,
+    unstamped_local_hops: bool,
+    handshake_timeout: Duration,
+    untagged_key_exchange: bool,
+    min_protocol_version: u16,
+}
+
+impl Default for SecureChannelListenerOptions {
+    fn default() -> Self {
+        Self {
+            max_channels: None,
+            strict_trust: false,
+            allow_anonymous: false,
+            max_handshake_message_size: DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE,
+            psk: None,
+            unstamped_local_hops: false,
+            handshake_timeout: DEFAULT_SECURE_CHANNEL_TIMEOUT,
+            untagged_key_exchange: false,
+            min_protocol_version: MIN_SECURE_CHANNEL_PROTOCOL_VERSION,
+        }
+    }
+}
+
+impl SecureChannelListenerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep at most given number of channels open. Further handshakes fail with
+    /// [`EntityError::SecureChannelListenerAtCapacity`](crate::EntityError::SecureChannelListenerAtCapacity)
+    /// until some of the channels are closed
+    pub fn with_max_channels(mut self, max_channels: usize) -> Self {
+        self.max_channels = Some(max_channels);
+        self
+    }
+
+    /// Only accept initiators declaring they check a trust policy as well, with
+    /// [`SecureChannelOptions::with_strict_trust`]. Others fail with
+    /// [`EntityError::SecureChannelTrustNotEnforced`](crate::EntityError::SecureChannelTrustNotEnforced)
+    pub fn with_strict_trust(mut self) -> Self {
+        self.strict_trust = true;
+        self
+    }
+
+    /// Also accept anonymous channels, created with [`SecureChannelOptions::with_anonymous`].
+    /// Those skip the trust policy, so access controls have to tell them apart, e.g. by
+    /// [`EntityAnyIdAccessControl::allow_anonymous`](crate::EntityAnyIdAccessControl::allow_anonymous)
+    pub fn with_anonymous(mut self) -> Self {
+        self.allow_anonymous = true;
+        self
+    }
+
+    /// Reject handshake messages larger than given size, in bytes, instead of
+    /// [`DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE`]. They are rejected before they are parsed, with
+    /// [`EntityError::SecureChannelHandshakeMessageTooLarge`](crate::EntityError::SecureChannelHandshakeMessageTooLarge)
+    /// once the key exchange completed
+    pub fn with_max_handshake_message_size(mut self, max_handshake_message_size: usize) -> Self {
+        self.max_handshake_message_size = max_handshake_message_size;
+        self
+    }
+
+    /// Mix given pre-shared key into the keys of every channel, see [`SecureChannelOptions::with_psk`].
+    /// Initiators without the same key fail the handshake
+    pub fn with_psk(mut self, psk: impl Into<PreSharedKey>) -> Self {
+        self.psk = Some(psk.into());
+        self
+    }
+
+    /// Deliver messages from initiators without [`EntitySecureChannelLocalInfo`](crate::EntitySecureChannelLocalInfo)
+    /// if their next hop is a worker of this node, see [`SecureChannelOptions::with_unstamped_local_hops`]
+    pub fn with_unstamped_local_hops(mut self) -> Self {
+        self.unstamped_local_hops = true;
+        self
+    }
+
+    /// Give up on handshakes that don't complete within given time, instead of
+    /// [`DEFAULT_SECURE_CHANNEL_TIMEOUT`], which releases their slot of
+    /// [`SecureChannelListenerOptions::with_max_channels`]. Otherwise initiators that stop
+    /// answering would hold on to it
+    pub fn with_handshake_timeout(mut self, handshake_timeout: Duration) -> Self {
+        self.handshake_timeout = handshake_timeout;
+        self
+    }
+
+    /// Expect initiators not to tag their first key exchange message, like those that predate
+    /// [`KeyExchangePattern`] or use [`SecureChannelOptions::with_untagged_key_exchange`].
+    /// Only Noise XX is accepted then, without a service, inherited trust or anonymity
+    pub fn with_untagged_key_exchange(mut self) -> Self {
+        self.untagged_key_exchange = true;
+        self
+    }
+
+    /// Reject initiators that don't speak at least given protocol version, which fail the handshake with
+    /// [`EntityError::SecureChannelProtocolVersionTooOld`](crate::EntityError::SecureChannelProtocolVersionTooOld).
+    /// Initiators newer than us fall back to [`SECURE_CHANNEL_PROTOCOL_VERSION`]
+    pub fn with_min_protocol_version(mut self, min_protocol_version: u16) -> Self {
+        self.min_protocol_version = min_protocol_version;
+        self
+    }
+
+    pub fn max_channels(&self) -> Option<usize> {
+        self.max_channels
+    }
+
+    pub fn strict_trust(&self) -> bool {
+        self.strict_trust
+    }
+
+    pub fn allow_anonymous(&self) -> bool {
+        self.allow_anonymous
+    }
+
+    pub fn max_handshake_message_size(&self) -> usize {
+        self.max_handshake_message_size
+    }
+
+    pub fn psk(&self) -> Option<&PreSharedKey> {
+        self.psk.as_ref()
+    }
+
+    pub fn unstamped_local_hops(&self) -> bool {
+        self.unstamped_local_hops
+    }
+
+    pub fn handshake_timeout(&self) -> Duration {
+        self.handshake_timeout
+    }
+
+    pub fn untagged_key_exchange(&self) -> bool {
+        self.untagged_key_exchange
+    }
+
+    pub fn min_protocol_version(&self) -> u16 {
+        self.min_protocol_version
+    }
+}
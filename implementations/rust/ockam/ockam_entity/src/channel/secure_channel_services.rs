@@ -0,0 +1,68 @@
+use ockam_core::compat::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Mutex},
+};
+use ockam_core::Address;
+
+/// Trust policy workers of the services a listener serves, by service name or pattern.
+/// Shared by the listener with the entity worker, which adds services while it runs
+#[derive(Clone, Default)]
+pub(crate) struct SecureChannelServices {
+    services: Arc<Mutex<BTreeMap<String, Address>>>,
+}
+
+impl SecureChannelServices {
+    /// Replaces the trust policy of a service with the same name
+    pub fn add(&self, service: String, trust_policy_address: Address) {
+        self.services
+            .lock()
+            .unwrap()
+            .insert(service, trust_policy_address);
+    }
+
+    /// Trust policy of the service of given name if there's one, otherwise of the most specific
+    /// pattern matching it. Patterns end with `*` and match the names starting with what comes
+    /// before it, so `*` matches every name
+    pub fn get(&self, service: &str) -> Option<Address> {
+        let services = self.services.lock().unwrap();
+        if let Some(address) = services.get(service) {
+            return Some(address.clone());
+        }
+
+        services
+            .iter()
+            .filter_map(|(pattern, address)| {
+                let prefix = pattern.strip_suffix('*')?;
+                service.starts_with(prefix).then(|| (prefix.len(), address))
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, address)| address.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_patterns() {
+        let services = SecureChannelServices::default();
+        services.add("sensor.*".into(), "sensors".into());
+        services.add("sensor.kitchen.*".into(), "kitchen_sensors".into());
+        services.add("sensor.kitchen.fridge".into(), "fridge".into());
+
+        let get = |service: &str| services.get(service);
+        let address = |address: &str| Some(Address::from(address));
+        assert_eq!(get("sensor.garage"), address("sensors"));
+        assert_eq!(get("sensor."), address("sensors"));
+        assert_eq!(get("sensor.kitchen.oven"), address("kitchen_sensors"));
+        assert_eq!(get("sensor.kitchen.fridge"), address("fridge"));
+        assert_eq!(get("sensor"), None);
+        assert_eq!(get("printer"), None);
+
+        services.add("*".into(), "everything_else".into());
+        assert_eq!(get("printer"), address("everything_else"));
+        assert_eq!(get("sensor.garage"), address("sensors"));
+    }
+}
@@ -1,27 +1,79 @@
 use crate::{
-    EntityChannelMessage, EntityError, EntitySecureChannelLocalInfo, Identity, ProfileIdentifier,
-    SecureChannelTrustInfo, TrustPolicy,
+    handshake_digest, AddressGenerator, ChannelSlot, Contact, EntityChannelMessage,
+    EntityChannelVault, EntityError, EntitySecureChannelLocalInfo, Identity, KeyExchangeHeader,
+    KeyExchangePattern, MessagePriority, PreSharedKey, ProfileIdentifier, PskKeyExchanger,
+    PskMismatches, SecureChannelEvent, SecureChannelEvents, SecureChannelHandshakes,
+    SecureChannelOptions, SecureChannelRegistry, SecureChannelTrustInfo, Stopwatch,
+    TaggedInitiator, TrustPolicy,
 };
 use core::future::Future;
 use core::pin::Pin;
+use core::time::Duration;
+#[cfg(feature = "unsafe_channel_key_export")]
+use ockam_channel::ExportedChannelKey;
 use ockam_channel::{
-    CreateResponderChannelMessage, KeyExchangeCompleted, SecureChannel, SecureChannelInfo,
+    CreateResponderChannelMessage, RekeyOptions, SecureChannel, SecureChannelInfo,
 };
 use ockam_core::async_trait;
-use ockam_core::compat::rand::random;
-use ockam_core::compat::{boxed::Box, vec::Vec};
+use ockam_core::compat::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use ockam_core::vault::PublicKey;
 use ockam_core::{
-    route, Address, Any, Decodable, Encodable, LocalMessage, Message, Result, Route, Routed,
-    TransportMessage, Worker,
+    route, Address, Any, AsyncTryClone, Decodable, Encodable, Error, LocalInfo, LocalMessage,
+    Message, Result, Route, Routed, TransportMessage, Worker,
 };
 use ockam_key_exchange_core::NewKeyExchanger;
-use ockam_key_exchange_xx::{XXNewKeyExchanger, XXVault};
-use ockam_node::Context;
+#[cfg(feature = "x3dh")]
+use ockam_key_exchange_x3dh::X3dhNewKeyExchanger;
+use ockam_key_exchange_xx::XXNewKeyExchanger;
+use ockam_node::tokio::time::timeout;
+use ockam_node::{Context, NodeError};
 use serde::{Deserialize, Serialize};
 use tracing::{debug, info, warn};
+#[cfg(feature = "tracing_spans")]
+use tracing::{debug_span, field, info_span, Instrument, Span};
+
+mod backpressure;
+use backpressure::{Acknowledgements, Backpressure, QueuedMessage};
+mod batching;
+use batching::Batching;
+mod handshake;
+use handshake::{Negotiation, ResponderOrigin};
+mod keepalive;
+use keepalive::Keepalive;
+mod probes;
+use probes::Probes;
+mod reconnect;
+use reconnect::{Reconnect, Reconnection};
+
+/// Default time to wait for the secure channel handshake to complete
+pub const DEFAULT_SECURE_CHANNEL_TIMEOUT: Duration = Duration::from_secs(120);
 
 #[derive(Serialize, Deserialize, Message)]
-pub(crate) struct AuthenticationConfirmation(pub Address);
+pub(crate) struct AuthenticationConfirmation(pub Result<(Address, ProfileIdentifier)>);
+
+/// Errors a handshake retry may get past: the other side didn't answer in time, or the next
+/// hop of the route was gone, e.g. a transport reconnecting. Other failures, like a
+/// rejected profile, would fail again
+fn is_transient(err: &Error) -> bool {
+    err.code() == Error::from(EntityError::SecureChannelTimeout).code()
+        || err.code() == NodeError::NO_SUCH_WORKER_CODE
+}
+
+/// `value` for a handshake attempt, cloned unless it's the last attempt
+async fn take_or_clone<C: AsyncTryClone>(value: &mut Option<C>, last: bool) -> Result<C> {
+    match (last, value.as_ref()) {
+        (false, Some(value)) => value.async_try_clone().await,
+        _ => value
+            .take()
+            .ok_or_else(|| EntityError::InvalidSecureChannelInternalState.into()),
+    }
+}
 
 trait StartSecureChannelFuture: Future<Output = Result<SecureChannelInfo>> + Send + 'static {}
 
@@ -30,6 +82,9 @@ impl<T> StartSecureChannelFuture for T where
 {
 }
 
+type ChannelFactory =
+    Box<dyn Fn(Context, Address) -> Pin<Box<dyn StartSecureChannelFuture>> + Send + Sync>;
+
 struct InitiatorStartChannel<I: Identity, T: TrustPolicy> {
     channel_future: Pin<Box<dyn StartSecureChannelFuture>>, // TODO: Replace with generic
     callback_address: Address,
@@ -41,6 +96,11 @@ struct ResponderWaitForKex<I: Identity, T: TrustPolicy> {
     first_responder_address: Address,
     identity: I,
     trust_policy: T,
+    /// Sent to the initiator instead of our profile, if the listener refused the channel
+    rejection: Option<Error>,
+    /// Peer of the channel the key exchange came through, to be checked before the initiator
+    /// proves anything, since it won't wait for a confirmation
+    inherited_trust: Option<SecureChannelTrustInfo>,
 }
 
 struct InitiatorSendProfile<I: Identity, T: TrustPolicy> {
@@ -50,11 +110,22 @@ struct InitiatorSendProfile<I: Identity, T: TrustPolicy> {
     trust_policy: T,
 }
 
+/// Initiator authenticated the responder and waits for it to accept our profile
+struct InitiatorWaitForConfirm<I: Identity> {
+    initialized: Initialized,
+    callback_address: Address,
+    identity: I,
+}
+
 struct ResponderWaitForProfile<I: Identity, T: TrustPolicy> {
     auth_hash: [u8; 32],
     local_secure_channel_address: Address,
     identity: I,
     trust_policy: T,
+    /// Attributes of the peer already verified, if trust is inherited
+    their_attributes: BTreeMap<String, String>,
+    #[cfg(feature = "unsafe_channel_key_export")]
+    exported_key: ExportedChannelKey,
 }
 
 #[derive(Clone)]
@@ -62,60 +133,281 @@ struct Initialized {
     local_secure_channel_address: Address,
     remote_profile_secure_channel_address: Address,
     their_profile_id: ProfileIdentifier,
+    their_public_key: Option<PublicKey>,
+    /// Change history of the other side as verified during the handshake, none if anonymous
+    their_contact: Option<Contact>,
+    their_attributes: BTreeMap<String, String>,
+    /// Advertised by the other side during the handshake
+    their_profile_attributes: BTreeMap<String, String>,
+    /// Negotiated during the handshake, see [`SecureChannelOptions::with_compression`]
+    compression: bool,
+    /// Negotiated during the handshake, ours for anonymous channels
+    protocol_version: u16,
+    /// [`handshake_digest`] of the last handshake message of the other side, which is
+    /// ignored if it arrives again
+    their_last_handshake_message: Option<[u8; 32]>,
+    #[cfg(feature = "unsafe_channel_key_export")]
+    exported_key: ExportedChannelKey,
 }
 
 enum State<I: Identity, T: TrustPolicy> {
     InitiatorStartChannel(InitiatorStartChannel<I, T>),
     ResponderWaitForKex(ResponderWaitForKex<I, T>),
     InitiatorSendProfile(InitiatorSendProfile<I, T>),
+    InitiatorWaitForConfirm(InitiatorWaitForConfirm<I>),
     ResponderWaitForProfile(ResponderWaitForProfile<I, T>),
     Initialized(Initialized),
 }
 
+/// Profile secure channel over a regular [`SecureChannel`].
+///
+/// Messages of one sender are encrypted, and decrypted ones forwarded, in the order they
+/// arrived: each side is a single worker, and each hop of a tunnel is one too, so nothing
+/// runs concurrently that could overtake. Batches are flushed ahead of unbatched messages,
+/// and backpressure holds messages in order. Only [`MessagePriority::High`] overtakes, and
+/// only what's held by backpressure or batched
 pub(crate) struct SecureChannelWorker<I: Identity, T: TrustPolicy> {
     is_initiator: bool,
     self_local_address: Address,
     self_remote_address: Address,
     state: Option<State<I, T>>,
+    negotiation: Negotiation,
+    /// Left empty by initiators
+    origin: ResponderOrigin,
+    batching: Batching,
+    reconnection: Reconnection<I, T>,
+    /// Transport route towards the other side. Reconnects go over the same route
+    their_route: Route,
+    registry: SecureChannelRegistry,
+    backpressure: Option<Backpressure>,
+    acknowledgements: Option<Acknowledgements>,
+    keepalive: Option<Keepalive>,
+    probes: Probes,
+    /// Notified with [`EntityChannelMessage::Close`] when the channel stops
+    close_watchers: Vec<Route>,
+    /// Messages for workers of this node are delivered without [`EntitySecureChannelLocalInfo`]
+    unstamped_local_hops: bool,
+}
+
+/// Addresses of an initiator, generated before it starts
+pub(crate) struct InitiatorAddresses {
+    callback: Address,
+    local: Address,
+    remote: Address,
+    undelivered: Address,
+    addresses: AddressGenerator,
+}
+
+impl InitiatorAddresses {
+    pub fn generate(addresses: &AddressGenerator) -> Self {
+        Self {
+            callback: addresses.generate(),
+            local: addresses.generate(),
+            remote: addresses.generate(),
+            undelivered: addresses.generate(),
+            addresses: addresses.clone(),
+        }
+    }
+
+    /// Fresh local, remote and undelivered address, for retrying the handshake
+    fn renew(&mut self) {
+        self.local = self.addresses.generate();
+        self.remote = self.addresses.generate();
+        self.undelivered = self.addresses.generate();
+    }
+
+    /// Where the initiator waits for the handshake to complete
+    pub fn callback(&self) -> &Address {
+        &self.callback
+    }
+}
+
+/// What a listener hands to every responder it starts
+pub(crate) struct ResponderSetup {
+    /// Regular SecureChannel listener of the requested key exchange pattern
+    pub listener_address: Address,
+    /// Listener slot, or the reason to reject the channel
+    pub slot: Result<ChannelSlot>,
+    pub registry: SecureChannelRegistry,
+    /// Where the responder is tracked until it trusts the initiator
+    pub handshakes: SecureChannelHandshakes,
+    pub strict_trust: bool,
+    /// Pattern the initiator picked
+    pub key_exchange: KeyExchangePattern,
+    /// Peer of the channel the key exchange came through, if the initiator inherits trust from it
+    pub inherited_trust: Option<SecureChannelTrustInfo>,
+    /// The initiator asked to skip the identity exchange, and the listener allows it
+    pub anonymous: bool,
+    /// Settled on from the key exchange header, and the minimum of the listener
+    pub protocol_version: u16,
+    pub min_protocol_version: u16,
+    pub addresses: AddressGenerator,
+    /// Entity listener address and the service the initiator asked for, shown to the trust policy
+    pub listener: Address,
+    pub service: Option<String>,
+    pub max_handshake_message_size: usize,
+    pub unstamped_local_hops: bool,
+    /// See [`SecureChannelListenerOptions::with_handshake_timeout`](crate::SecureChannelListenerOptions::with_handshake_timeout)
+    pub handshake_timeout: Duration,
+    pub psk_mismatches: PskMismatches,
 }
 
 impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
+    #[allow(clippy::too_many_arguments)]
     pub async fn create_initiator(
         ctx: &Context,
         route: Route,
         identity: I,
         trust_policy: T,
-        vault: impl XXVault,
+        vault: impl EntityChannelVault,
+        options: SecureChannelOptions,
+        inherited_from: Option<ProfileIdentifier>,
+        registry: SecureChannelRegistry,
+        mut addresses: InitiatorAddresses,
     ) -> Result<Address> {
-        let child_address = Address::random(0);
+        let child_address = addresses.callback.clone();
         let mut child_ctx = ctx.new_context(child_address.clone()).await?;
 
-        // Generate 2 random fresh address for newly created SecureChannel.
+        let events = SecureChannelEvents::new(options.events_address().cloned());
+        let stopwatch = Stopwatch::start();
+        events.send(ctx, SecureChannelEvent::HandshakeStarted).await;
+
+        let max_attempts = options
+            .handshake_retry()
+            .map_or(1, |retry| retry.max_attempts().max(1));
+        let mut delay = options
+            .handshake_retry()
+            .map_or(Duration::default(), |retry| retry.base_delay());
+        let mut identity = Some(identity);
+        let mut trust_policy = Some(trust_policy);
+        let mut vault = Some(vault);
+        // Initiators of failed attempts, which may still confirm late
+        let mut failed = Vec::new();
+        let mut attempt = 1;
+        let res = loop {
+            let last = attempt == max_attempts;
+            let res = Self::start_initiator_attempt(
+                ctx,
+                &mut child_ctx,
+                route.clone(),
+                take_or_clone(&mut identity, last).await?,
+                take_or_clone(&mut trust_policy, last).await?,
+                take_or_clone(&mut vault, last).await?,
+                &options,
+                inherited_from.clone(),
+                registry.clone(),
+                &addresses,
+                &failed,
+            )
+            .await;
+
+            match res {
+                Err(err) if !last && is_transient(&err) => {
+                    warn!(
+                        "{} creating ProfileSecureChannel Initiator at local: {}, attempt {}",
+                        err, addresses.local, attempt
+                    );
+                    failed.push(addresses.local.clone());
+                    // The other side may still have state of the failed attempt, so
+                    // the next one starts over at fresh addresses
+                    addresses.renew();
+                    ctx.sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                res => break res,
+            }
+        };
+        // Nothing is sent to it once the handshake is over
+        let _ = ctx.stop_worker(child_address).await;
+
+        match res {
+            Ok((address, peer_id)) => {
+                events
+                    .send(
+                        ctx,
+                        SecureChannelEvent::HandshakeCompleted {
+                            duration: stopwatch.elapsed(),
+                            peer_id,
+                        },
+                    )
+                    .await;
+
+                Ok(address)
+            }
+            Err(err) => {
+                events
+                    .send(
+                        ctx,
+                        SecureChannelEvent::HandshakeFailed {
+                            reason: err.to_string(),
+                        },
+                    )
+                    .await;
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Run the handshake once, at the local and remote address of `addresses`
+    #[allow(clippy::too_many_arguments)]
+    async fn start_initiator_attempt<V: EntityChannelVault>(
+        ctx: &Context,
+        child_ctx: &mut Context,
+        route: Route,
+        identity: I,
+        trust_policy: T,
+        vault: V,
+        options: &SecureChannelOptions,
+        inherited_from: Option<ProfileIdentifier>,
+        registry: SecureChannelRegistry,
+        addresses: &InitiatorAddresses,
+        failed: &[Address],
+    ) -> Result<(Address, ProfileIdentifier)> {
+        // 2 fresh addresses for newly created SecureChannel.
         // One for local workers to encrypt their messages
         // Second for remote workers to decrypt their messages
-        let self_local_address: Address = random();
-        let self_remote_address: Address = random();
+        let self_local_address = addresses.local.clone();
+        let self_remote_address = addresses.remote.clone();
+        // Only needed to send messages again after reconnecting
+        let self_undelivered_address = options.reconnect().map(|_| addresses.undelivered.clone());
 
-        let initiator = XXNewKeyExchanger::new(vault.async_try_clone().await?)
-            .initiator()
-            .await?;
         // Create regular secure channel and set self address as first responder
+        let header = KeyExchangeHeader {
+            protocol_version: options.protocol_version(),
+            pattern: options.key_exchange(),
+            service: options.service().map(String::from),
+            inherited_trust: inherited_from.is_some(),
+            anonymous: options.anonymous(),
+            untagged: options.untagged_key_exchange(),
+        };
+        let channel_factory = Self::channel_factory(
+            route.clone(),
+            vault,
+            *options.rekey(),
+            options.replay_window(),
+            header,
+            options.psk().cloned(),
+            self_undelivered_address.clone(),
+        );
         let temp_ctx = ctx.new_context(Address::random(0)).await?;
-        let self_remote_address_clone = self_remote_address.clone();
-        let channel_future = Box::pin(async move {
-            SecureChannel::create_extended(
-                &temp_ctx,
-                route,
-                Some(self_remote_address_clone),
-                initiator,
-                vault,
-            )
-            .await
-        });
+        let channel_future = channel_factory(temp_ctx, self_remote_address.clone());
+
+        let reconnect = match options.reconnect() {
+            Some(reconnect_options) => Some(Reconnect {
+                options: *reconnect_options,
+                timeout: options.timeout(),
+                channel_factory,
+                identity: identity.async_try_clone().await?,
+                trust_policy: trust_policy.async_try_clone().await?,
+            }),
+            None => None,
+        };
 
         let state = State::InitiatorStartChannel(InitiatorStartChannel {
             channel_future,
-            callback_address: child_address,
+            callback_address: addresses.callback.clone(),
             identity,
             trust_policy,
         });
@@ -125,38 +417,155 @@ impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
             self_local_address: self_local_address.clone(),
             self_remote_address: self_remote_address.clone(),
             state: Some(state),
+            negotiation: Negotiation {
+                credential: options.credential().cloned(),
+                strict_trust: options.strict_trust(),
+                key_exchange: options.key_exchange(),
+                inherited_from,
+                anonymous: options.anonymous(),
+                compression: cfg!(feature = "compression") && options.compression(),
+                protocol_version: options.protocol_version(),
+                min_protocol_version: options.min_protocol_version(),
+                max_handshake_message_size: options.max_handshake_message_size(),
+            },
+            origin: ResponderOrigin::default(),
+            batching: Batching::new(options.max_batch(), options.max_batch_delay()),
+            reconnection: Reconnection::new(reconnect, self_undelivered_address.clone()),
+            their_route: route,
+            registry,
+            backpressure: options.backpressure().copied().map(Backpressure::new),
+            acknowledgements: None,
+            keepalive: options.keepalive().copied().map(Keepalive::new),
+            probes: Probes::default(),
+            close_watchers: Vec::new(),
+            unstamped_local_hops: options.unstamped_local_hops(),
         };
 
-        ctx.start_worker(
-            vec![self_local_address.clone(), self_remote_address.clone()],
-            worker,
-        )
-        .await?;
+        let mut worker_addresses = vec![self_local_address.clone(), self_remote_address.clone()];
+        worker_addresses.extend(self_undelivered_address);
+        ctx.start_worker(worker_addresses, worker).await?;
 
         debug!(
             "Starting ProfileSecureChannel Initiator at local: {}, remote: {}",
             &self_local_address, &self_remote_address
         );
 
-        let _ = child_ctx
-            .receive_timeout::<AuthenticationConfirmation>(
-                120, /* TODO: What is the correct timeout here? */
-            )
-            .await?;
+        let res = timeout(options.timeout(), async {
+            loop {
+                let confirmation = match child_ctx
+                    .receive_block::<AuthenticationConfirmation>()
+                    .await
+                {
+                    Ok(confirmation) => confirmation.take(),
+                    Err(err) => return Err(err),
+                };
+                // Failed attempts may still confirm late
+                if !failed.contains(&confirmation.return_route().recipient()) {
+                    return confirmation.body().0;
+                }
+            }
+        })
+        .await;
+
+        let res = match res {
+            Ok(res) => res,
+            Err(_) => Err(EntityError::SecureChannelTimeout.into()),
+        };
+        if res.is_err() {
+            // Don't leave a half-initialized channel registered
+            let _ = ctx.stop_worker(self_local_address).await;
+        }
 
-        Ok(self_local_address)
+        res
+    }
+
+    fn channel_factory<V: EntityChannelVault>(
+        route: Route,
+        vault: V,
+        rekey_options: RekeyOptions,
+        replay_window: u16,
+        header: KeyExchangeHeader,
+        psk: Option<PreSharedKey>,
+        undelivered_address: Option<Address>,
+    ) -> ChannelFactory {
+        let vault = Arc::new(vault);
+        Box::new(move |temp_ctx: Context, first_responder_address: Address| {
+            let route = route.clone();
+            let vault = vault.clone();
+            let header = header.clone();
+            let psk = psk.clone();
+            let undelivered_address = undelivered_address.clone();
+            let channel_future: Pin<Box<dyn StartSecureChannelFuture>> = Box::pin(async move {
+                let vault = V::async_try_clone(&vault).await?;
+                let mismatches = PskMismatches::default();
+                let channel = match header.pattern {
+                    KeyExchangePattern::Xx => {
+                        let initiator = XXNewKeyExchanger::new(vault.async_try_clone().await?)
+                            .initiator()
+                            .await?;
+                        SecureChannel::create_extended_with_undelivered_address(
+                            &temp_ctx,
+                            route,
+                            Some(first_responder_address),
+                            PskKeyExchanger::new(
+                                TaggedInitiator::new(initiator, &header)?,
+                                vault.async_try_clone().await?,
+                                psk,
+                                mismatches.clone(),
+                            ),
+                            vault,
+                            rekey_options,
+                            replay_window,
+                            undelivered_address,
+                        )
+                        .await
+                    }
+                    #[cfg(feature = "x3dh")]
+                    KeyExchangePattern::X3dh => {
+                        let initiator = X3dhNewKeyExchanger::new(vault.async_try_clone().await?)
+                            .initiator()
+                            .await?;
+                        SecureChannel::create_extended_with_undelivered_address(
+                            &temp_ctx,
+                            route,
+                            Some(first_responder_address),
+                            PskKeyExchanger::new(
+                                TaggedInitiator::new(initiator, &header)?,
+                                vault.async_try_clone().await?,
+                                psk,
+                                mismatches.clone(),
+                            ),
+                            vault,
+                            rekey_options,
+                            replay_window,
+                            undelivered_address,
+                        )
+                        .await
+                    }
+                }?;
+
+                if mismatches.take(&channel.auth_hash()) {
+                    temp_ctx.stop_worker(channel.address()).await?;
+                    return Err(EntityError::PreSharedKeyMismatch.into());
+                }
+
+                Ok(channel)
+            });
+            channel_future
+        })
     }
 
     pub(crate) async fn create_responder(
         ctx: &Context,
         identity: I,
         trust_policy: T,
-        listener_address: Address,
         msg: Routed<CreateResponderChannelMessage>,
+        payload: &[u8],
+        setup: ResponderSetup,
     ) -> Result<()> {
         let mut onward_route = msg.onward_route();
         onward_route.step()?;
-        onward_route.modify().prepend(listener_address);
+        onward_route.modify().prepend(setup.listener_address);
 
         let return_route = msg.return_route();
         let body = msg.body();
@@ -169,21 +578,28 @@ impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
         // Generate 2 random fresh address for newly created SecureChannel.
         // One for local workers to encrypt their messages
         // Second for remote workers to decrypt their messages
-        let self_local_address: Address = random();
-        let self_remote_address: Address = random();
+        let self_local_address = setup.addresses.generate();
+        let self_remote_address = setup.addresses.generate();
 
         // Change completed callback address and forward message for regular key exchange to happen
-        let body = CreateResponderChannelMessage::new(
-            body.payload().to_vec(),
-            Some(self_local_address.clone()),
-        );
+        let body =
+            CreateResponderChannelMessage::new(payload.to_vec(), Some(self_local_address.clone()));
+
+        let msg = TransportMessage::v1(onward_route, return_route.clone(), body.encode()?);
 
-        let msg = TransportMessage::v1(onward_route, return_route, body.encode()?);
+        // The key exchange still happens when rejecting, so that the initiator
+        // learns the reason over an encrypted channel
+        let (slot, rejection) = match setup.slot {
+            Ok(slot) => (Some(slot), None),
+            Err(err) => (None, Some(err)),
+        };
 
         let state = State::ResponderWaitForKex(ResponderWaitForKex {
             first_responder_address,
             identity,
             trust_policy,
+            rejection,
+            inherited_trust: setup.inherited_trust.clone(),
         });
 
         let worker = SecureChannelWorker {
@@ -191,8 +607,42 @@ impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
             self_local_address: self_local_address.clone(),
             self_remote_address: self_remote_address.clone(),
             state: Some(state),
+            negotiation: Negotiation {
+                credential: None,
+                strict_trust: setup.strict_trust,
+                key_exchange: setup.key_exchange,
+                inherited_from: setup
+                    .inherited_trust
+                    .map(|trust_info| trust_info.their_profile_id().clone()),
+                anonymous: setup.anonymous,
+                compression: cfg!(feature = "compression"),
+                protocol_version: setup.protocol_version,
+                min_protocol_version: setup.min_protocol_version,
+                max_handshake_message_size: setup.max_handshake_message_size,
+            },
+            origin: ResponderOrigin {
+                _slot: slot,
+                handshakes: Some(setup.handshakes.clone()),
+                handshake_timeout: Some(setup.handshake_timeout),
+                psk_mismatches: Some(setup.psk_mismatches),
+                listener: Some((setup.listener, setup.service)),
+            },
+            batching: Batching::new(1, Duration::default()),
+            reconnection: Reconnection::new(None, None),
+            their_route: return_route,
+            registry: setup.registry,
+            backpressure: None,
+            acknowledgements: None,
+            keepalive: None,
+            probes: Probes::default(),
+            close_watchers: Vec::new(),
+            unstamped_local_hops: setup.unstamped_local_hops,
         };
 
+        setup
+            .handshakes
+            .responders
+            .insert(self_local_address.clone());
         ctx.start_worker(
             vec![self_local_address.clone(), self_remote_address.clone()],
             worker,
@@ -209,211 +659,341 @@ impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
         Ok(())
     }
 
-    async fn handle_kex_done(
-        &mut self,
-        ctx: &mut <Self as Worker>::Context,
-        msg: Routed<<Self as Worker>::Message>,
-        mut state: ResponderWaitForKex<I, T>,
-    ) -> Result<()> {
-        let kex_msg = KeyExchangeCompleted::decode(msg.payload())?;
-
-        // Prove we posses Profile key
-        let proof = state
-            .identity
-            .create_auth_proof(&kex_msg.auth_hash())
-            .await?;
-        let msg = EntityChannelMessage::Request {
-            contact: state.identity.as_contact().await?,
-            proof,
-        };
-        ctx.send_from_address(
-            route![kex_msg.address().clone(), state.first_responder_address],
-            msg,
-            self.self_remote_address.clone(),
-        )
-        .await?;
-        debug!("Sent Authentication request");
-
-        self.state = Some(State::ResponderWaitForProfile(ResponderWaitForProfile {
-            auth_hash: kex_msg.auth_hash(),
-            local_secure_channel_address: kex_msg.address().clone(),
-            identity: state.identity,
-            trust_policy: state.trust_policy,
-        }));
-
-        Ok(())
+    /// Check if message is meant for the channel itself, rather than to be forwarded through it
+    fn is_addressed_to_channel(onward_route: &Route) -> bool {
+        let mut onward_route = onward_route.clone();
+        onward_route.step().is_ok() && onward_route.next().is_err()
     }
 
-    async fn handle_send_profile(
+    /// Handle message sent by the other side to the channel itself
+    async fn handle_remote_control(
         &mut self,
         ctx: &mut <Self as Worker>::Context,
         msg: Routed<<Self as Worker>::Message>,
-        mut state: InitiatorSendProfile<I, T>,
+        state: Initialized,
     ) -> Result<()> {
         let return_route = msg.return_route();
+        let local_info = msg.local_message().local_info().to_vec();
 
-        // Ensure message came from dedicated SecureChannel
-        if return_route.next()? != &state.channel.address() {
-            return Err(EntityError::UnknownChannelMsgDestination.into());
-        }
-
-        let body = EntityChannelMessage::decode(msg.payload())?;
-
-        // Wait for responder to send us his Profile and Profile Proof.
-        // In case of using Noise XX this is m4 message.
-        if let EntityChannelMessage::Request { contact, proof } = body {
-            debug!("Received Authentication request");
-
-            let their_contact = contact;
-            let their_profile_id = their_contact.identifier().clone();
+        match EntityChannelMessage::decode(msg.payload())? {
+            EntityChannelMessage::Close => {
+                info!(
+                    "ProfileSecureChannel closed by the other side at local: {}, remote: {}",
+                    &self.self_local_address, &self.self_remote_address
+                );
 
-            let contact_result = state.identity.get_contact(&their_profile_id).await?;
+                // Leave no state, so that shutdown doesn't notify the other side back
+                self.state = None;
 
-            if contact_result.is_some() {
-                // TODO: We're creating SecureChannel with known Profile. Need to update their Profile.
-            } else {
-                state.identity.verify_and_add_contact(their_contact).await?;
+                ctx.stop_worker(state.local_secure_channel_address).await?;
+                ctx.stop_worker(self.self_local_address.clone()).await
+            }
+            // Confirmation of a reconnect, which doesn't wait for it
+            EntityChannelMessage::Confirm => Ok(()),
+            EntityChannelMessage::Request { .. } | EntityChannelMessage::Response { .. }
+                if state.their_last_handshake_message == Some(handshake_digest(msg.payload())) =>
+            {
+                debug!(
+                    "ProfileSecureChannel at local: {} ignored repeated handshake message",
+                    &self.self_local_address
+                );
+                Ok(())
+            }
+            EntityChannelMessage::EnableAcks => {
+                self.acknowledgements = Some(Acknowledgements::default());
+                Ok(())
+            }
+            EntityChannelMessage::Ping(id) => {
+                ctx.send_from_address(
+                    route![
+                        state.local_secure_channel_address,
+                        state.remote_profile_secure_channel_address
+                    ],
+                    EntityChannelMessage::Pong(id),
+                    self.self_remote_address.clone(),
+                )
+                .await
             }
+            EntityChannelMessage::Pong(id) => {
+                if let Some(keepalive) = &mut self.keepalive {
+                    keepalive.answered(id);
+                }
+                Ok(())
+            }
+            EntityChannelMessage::ProbeRequest(id) => {
+                ctx.send_from_address(
+                    route![
+                        state.local_secure_channel_address,
+                        state.remote_profile_secure_channel_address
+                    ],
+                    EntityChannelMessage::ProbeResponse(id),
+                    self.self_remote_address.clone(),
+                )
+                .await
+            }
+            // Given up on, or answered already, if there's no prober
+            EntityChannelMessage::ProbeResponse(id) => match self.probes.answered(id) {
+                Some(prober) => ctx.send(prober, EntityChannelMessage::Probed).await,
+                None => Ok(()),
+            },
+            EntityChannelMessage::Ack(received) => {
+                let mut state = state;
+                if let Some(backpressure) = &mut self.backpressure {
+                    backpressure.acknowledged(received);
+                }
+                self.send_queued(ctx, &mut state).await
+            }
+            EntityChannelMessage::Batch(messages) => {
+                debug!(
+                    "ProfileSecureChannel at local: {} received batch of {} messages",
+                    &self.self_local_address,
+                    messages.len()
+                );
 
-            // Verify responder posses their Profile key
-            let verified = state
-                .identity
-                .verify_auth_proof(&state.channel.auth_hash(), &their_profile_id, &proof)
-                .await?;
+                for message in messages {
+                    self.forward_decrypted(
+                        ctx,
+                        &state,
+                        message.onward_route,
+                        return_route.clone(),
+                        local_info.clone(),
+                        message.payload,
+                    )
+                    .await?;
+                    self.acknowledge_received(ctx).await?;
+                }
 
-            if !verified {
-                return Err(EntityError::SecureChannelVerificationFailed.into());
+                Ok(())
             }
-            info!(
-                "Initiator verified SecureChannel from: {}",
-                their_profile_id
-            );
+            _ => Err(EntityError::InvalidSecureChannelInternalState.into()),
+        }
+    }
 
-            // Check our TrustPolicy
-            let trust_info = SecureChannelTrustInfo::new(their_profile_id.clone());
-            let trusted = state.trust_policy.check(&trust_info).await?;
-            if !trusted {
-                return Err(EntityError::SecureChannelTrustCheckFailed.into());
+    /// Handle message sent by local workers to the channel itself
+    async fn handle_local_control(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        msg: Routed<<Self as Worker>::Message>,
+        mut state: Initialized,
+    ) -> Result<()> {
+        let res = match EntityChannelMessage::decode(msg.payload()) {
+            Ok(EntityChannelMessage::FlushBatch(id)) => {
+                // The batch may have been sent already
+                if self.batching.is_pending(id) {
+                    self.flush_batch(ctx, &mut state).await
+                } else {
+                    Ok(())
+                }
             }
-            info!(
-                "Initiator checked trust policy for SecureChannel from: {}",
-                &their_profile_id
-            );
-
-            // Prove we posses our Profile key
-            let contact = state.identity.as_contact().await?;
-            let proof = state
-                .identity
-                .create_auth_proof(&state.channel.auth_hash())
-                .await?;
-
-            let auth_msg = EntityChannelMessage::Response { contact, proof };
+            Ok(EntityChannelMessage::SendAck) => self.send_ack(ctx, &state).await,
+            Ok(EntityChannelMessage::KeepaliveTick) => self.send_ping(ctx, &state).await,
+            Ok(EntityChannelMessage::KeepaliveDeadline(id)) => {
+                let expired = self
+                    .keepalive
+                    .as_ref()
+                    .map_or(false, |keepalive| keepalive.is_expired(id));
+                if expired {
+                    // Leaves no state behind
+                    return self.keepalive_expired(ctx, state).await;
+                }
+                Ok(())
+            }
+            Ok(EntityChannelMessage::ReserveCapacity) => {
+                self.reserve_capacity(ctx, msg.return_route()).await
+            }
+            Ok(EntityChannelMessage::WatchClose) => {
+                let return_route = msg.return_route();
+                self.close_watchers.push(return_route.clone());
+                ctx.send(return_route, EntityChannelMessage::WatchingClose)
+                    .await
+            }
+            Ok(EntityChannelMessage::Probe) => {
+                self.send_probe(ctx, &state, msg.return_route()).await
+            }
+            Ok(EntityChannelMessage::AwaitReady) => {
+                ctx.send(
+                    msg.return_route(),
+                    EntityChannelMessage::Ready(state.their_profile_id.clone()),
+                )
+                .await
+            }
+            Ok(EntityChannelMessage::GetPeerHistory) => {
+                let reply = match &state.their_contact {
+                    Some(contact) => EntityChannelMessage::PeerHistory(contact.clone()),
+                    None => EntityChannelMessage::Reject(EntityError::ContactNotFound.into()),
+                };
+                ctx.send(msg.return_route(), reply).await
+            }
+            Ok(EntityChannelMessage::FinishRecovery(id)) => {
+                self.finish_recovery(ctx, &mut state, Some(id)).await
+            }
+            #[cfg(feature = "unsafe_channel_key_export")]
+            Ok(EntityChannelMessage::ExportKey) => {
+                warn!(
+                    "Exporting key material of ProfileSecureChannel at local: {}",
+                    &self.self_local_address
+                );
+                ctx.send(
+                    msg.return_route(),
+                    EntityChannelMessage::ExportedKey(state.exported_key.clone()),
+                )
+                .await
+            }
+            Ok(_) => Err(EntityError::InvalidSecureChannelInternalState.into()),
+            Err(err) => Err(err),
+        };
 
-            let remote_profile_secure_channel_address = return_route.recipient();
+        self.state = Some(State::Initialized(state));
 
-            ctx.send_from_address(return_route, auth_msg, self.self_remote_address.clone())
-                .await?;
-            debug!("Sent Authentication response");
+        res
+    }
 
-            self.state = Some(State::Initialized(Initialized {
-                local_secure_channel_address: state.channel.address(),
-                remote_profile_secure_channel_address,
-                their_profile_id,
-            }));
+    fn to_peer_message(
+        &self,
+        state: &Initialized,
+        mut onward_route: Route,
+        mut return_route: Route,
+        payload: Vec<u8>,
+    ) -> LocalMessage {
+        // Send to the other party using local regular SecureChannel
+        let onward_route = onward_route
+            .modify()
+            .prepend(state.remote_profile_secure_channel_address.clone())
+            .prepend(state.local_secure_channel_address.clone());
 
-            info!(
-                "Initialized ProfileSecureChannel Initiator at local: {}, remote: {}",
-                &self.self_local_address, &self.self_remote_address
-            );
+        let return_route = return_route
+            .modify()
+            .prepend(self.self_remote_address.clone());
 
-            ctx.send(
-                state.callback_address,
-                AuthenticationConfirmation(self.self_local_address.clone()),
-            )
-            .await?;
+        let transport_msg = TransportMessage::v1(onward_route, return_route, payload);
 
-            Ok(())
-        } else {
-            Err(EntityError::InvalidSecureChannelInternalState.into())
-        }
+        LocalMessage::new(transport_msg, Vec::new())
     }
 
-    async fn handle_receive_profile(
+    async fn send_encrypted(
         &mut self,
-        _ctx: &mut <Self as Worker>::Context,
-        msg: Routed<<Self as Worker>::Message>,
-        mut state: ResponderWaitForProfile<I, T>,
+        ctx: &mut <Self as Worker>::Context,
+        state: &mut Initialized,
+        onward_route: Route,
+        return_route: Route,
+        payload: Vec<u8>,
     ) -> Result<()> {
-        let return_route = msg.return_route();
-
-        // Ensure message came from dedicated SecureChannel
-        if return_route.next()? != &state.local_secure_channel_address {
-            return Err(EntityError::UnknownChannelMsgDestination.into());
-        }
+        // Keep the message in case it needs to be resent after reconnecting
+        let retry = if self.reconnection.is_enabled() {
+            Some((onward_route.clone(), return_route.clone(), payload.clone()))
+        } else {
+            None
+        };
 
-        let body = EntityChannelMessage::decode(msg.payload())?;
+        let err = match ctx
+            .forward(self.to_peer_message(state, onward_route, return_route, payload))
+            .await
+        {
+            Ok(()) => return Ok(()),
+            Err(err) => err,
+        };
 
-        // Wait for responder to send us his Profile and Profile Proof.
-        // In case of using Noise XX this is m4 message.
-        if let EntityChannelMessage::Response { contact, proof } = body {
-            debug!("Received Authentication response");
+        let (onward_route, return_route, payload) = match retry {
+            Some(retry) => retry,
+            None => return Err(err),
+        };
 
-            let their_contact = contact;
-            let their_profile_id = their_contact.identifier().clone();
+        // Regular SecureChannel is gone
+        warn!(
+            "{} sending through ProfileSecureChannel at local: {}, reconnecting",
+            err, self.self_local_address
+        );
+        let _ = ctx
+            .stop_worker(state.local_secure_channel_address.clone())
+            .await;
 
-            let contact_result = state.identity.get_contact(&their_profile_id).await?;
+        match self.reconnect(ctx).await {
+            Ok(initialized) => {
+                self.reconnected(ctx, state, initialized).await?;
 
-            if contact_result.is_some() {
-                // TODO: We're creating SecureChannel with known Profile. Need to update their Profile.
-            } else {
-                state
-                    .identity
-                    .verify_and_add_contact(their_contact.clone())
-                    .await?;
+                ctx.forward(self.to_peer_message(state, onward_route, return_route, payload))
+                    .await
             }
+            Err(err) => {
+                // Give up, so that following sends to this channel fail
+                self.state = None;
+                ctx.stop_worker(self.self_local_address.clone()).await?;
 
-            // Verify initiator posses their Profile key
-            let verified = state
-                .identity
-                .verify_auth_proof(&state.auth_hash, &their_profile_id, &proof)
-                .await?;
-
-            if !verified {
-                return Err(EntityError::SecureChannelVerificationFailed.into());
+                Err(err)
             }
+        }
+    }
 
-            info!(
-                "Responder verified SecureChannel from: {}",
-                &their_profile_id
-            );
+    /// Have given message delivered to the channel itself after a delay
+    async fn schedule(
+        &self,
+        ctx: &Context,
+        delay: Duration,
+        msg: EntityChannelMessage,
+    ) -> Result<()> {
+        let child_ctx = ctx.new_context(Address::random(0)).await?;
+        let self_local_address = self.self_local_address.clone();
 
-            // Check our TrustPolicy
-            let trust_info = SecureChannelTrustInfo::new(their_profile_id.clone());
-            let trusted = state.trust_policy.check(&trust_info).await?;
-            if !trusted {
-                return Err(EntityError::SecureChannelTrustCheckFailed.into());
-            }
-            info!(
-                "Responder checked trust policy for SecureChannel from: {}",
-                &their_profile_id
-            );
+        ctx.runtime().spawn(async move {
+            child_ctx.sleep(delay).await;
 
-            let remote_profile_secure_channel_address = return_route.recipient();
+            // Fails if the channel was stopped in the meantime
+            let _ = child_ctx.send(route![self_local_address], msg).await;
+        });
 
-            self.state = Some(State::Initialized(Initialized {
-                local_secure_channel_address: state.local_secure_channel_address,
-                remote_profile_secure_channel_address,
-                their_profile_id,
-            }));
+        Ok(())
+    }
 
-            info!(
-                "Initialized ProfileSecureChannel Responder at local: {}, remote: {}",
-                &self.self_local_address, &self.self_remote_address
-            );
+    async fn forward_decrypted(
+        &self,
+        ctx: &mut <Self as Worker>::Context,
+        state: &Initialized,
+        onward_route: Route,
+        mut return_route: Route,
+        mut local_info: Vec<LocalInfo>,
+        payload: Vec<u8>,
+    ) -> Result<()> {
+        let return_route = return_route
+            .modify()
+            .pop_front()
+            .pop_front()
+            .prepend(self.self_local_address.clone());
 
-            Ok(())
+        #[cfg(feature = "compression")]
+        let payload = if state.compression {
+            crate::decompress_payload(&payload)?
         } else {
-            Err(EntityError::InvalidSecureChannelInternalState.into())
+            payload
+        };
+        // Transports are told apart from workers of this node by their address type
+        let local_hop = onward_route.next().map_or(false, |next| next.tt == 0);
+        let transport_msg = TransportMessage::v1(onward_route, return_route, payload);
+
+        if !(self.unstamped_local_hops && local_hop) {
+            let info = if self.negotiation.anonymous {
+                EntitySecureChannelLocalInfo::anonymous()
+            } else {
+                EntitySecureChannelLocalInfo::new_with_public_key(
+                    state.their_profile_id.clone(),
+                    state.their_public_key.clone(),
+                )
+                .with_attributes(state.their_attributes.clone())
+                .with_profile_attributes(state.their_profile_attributes.clone())
+            };
+            local_info.push(info.with_route(self.their_route.clone()).to_local_info()?);
+        }
+
+        let msg = LocalMessage::new(transport_msg, local_info);
+
+        match ctx.forward(msg).await {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                warn!(
+                    "{} forwarding decrypted message from {}",
+                    err, self.self_local_address
+                );
+                Ok(())
+            }
         }
     }
 
@@ -429,7 +1009,7 @@ impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
         &mut self,
         ctx: &mut <Self as Worker>::Context,
         msg: Routed<<Self as Worker>::Message>,
-        state: Initialized,
+        mut state: Initialized,
     ) -> Result<()> {
         debug!(
             "ProfileSecureChannel {} received Encrypt",
@@ -440,29 +1020,74 @@ impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
             }
         );
 
+        if Self::is_addressed_to_channel(&msg.onward_route()) {
+            return self.handle_local_control(ctx, msg, state).await;
+        }
+
         self.state = Some(State::Initialized(state.clone()));
 
         let mut onward_route = msg.onward_route();
-        let mut return_route = msg.return_route();
-        let payload = msg.payload().to_vec();
-
-        // Send to the other party using local regular SecureChannel
         let _ = onward_route.step()?;
-        let onward_route = onward_route
-            .modify()
-            .prepend(state.remote_profile_secure_channel_address)
-            .prepend(state.local_secure_channel_address);
+        let message = QueuedMessage {
+            onward_route,
+            return_route: msg.return_route(),
+            payload: msg.payload().to_vec(),
+            priority: MessagePriority::find(msg.local_message()),
+        };
 
-        let return_route = return_route
-            .modify()
-            .prepend(self.self_remote_address.clone());
+        let message = match &mut self.backpressure {
+            Some(backpressure) => match backpressure.admit(message)? {
+                Some(message) => message,
+                None => return Ok(()),
+            },
+            None => message,
+        };
 
-        let transport_msg = TransportMessage::v1(onward_route, return_route, payload);
+        self.send_message(ctx, &mut state, message).await
+    }
 
-        ctx.forward(LocalMessage::new(transport_msg, Vec::new()))
-            .await?;
+    /// Send a message of a local worker, batching it if enabled.
+    /// High priority messages go out right away, ahead of the batch
+    async fn send_message(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        state: &mut Initialized,
+        message: QueuedMessage,
+    ) -> Result<()> {
+        // Keep the order with messages the old channel still returns
+        let QueuedMessage {
+            onward_route,
+            return_route,
+            payload,
+            priority,
+        } = match self.reconnection.hold(message) {
+            Some(message) => message,
+            None => return Ok(()),
+        };
 
-        Ok(())
+        #[cfg(feature = "compression")]
+        let payload = if state.compression {
+            crate::compress_payload(payload)
+        } else {
+            payload
+        };
+
+        if priority == MessagePriority::High {
+            return self
+                .send_encrypted(ctx, state, onward_route, return_route, payload)
+                .await;
+        }
+
+        if self.batching.takes(&payload) {
+            return self
+                .add_to_batch(ctx, state, onward_route, return_route, payload)
+                .await;
+        }
+
+        // Keep the order with already batched messages
+        self.flush_batch(ctx, state).await?;
+        self.send_encrypted(ctx, state, onward_route, return_route, payload)
+            .await
     }
 
     async fn handle_decrypt(
@@ -483,100 +1108,169 @@ impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
         self.state = Some(State::Initialized(state.clone()));
 
         let mut onward_route = msg.onward_route();
-        let mut return_route = msg.return_route();
+        let return_route = msg.return_route();
 
         // Ensure message came from dedicated SecureChannel
         if return_route.next()? != &state.local_secure_channel_address {
             return Err(EntityError::UnknownChannelMsgDestination.into());
         }
 
+        if Self::is_addressed_to_channel(&onward_route) {
+            return self.handle_remote_control(ctx, msg, state).await;
+        }
+
         let local_msg = msg.into_local_message();
-        let mut local_info = local_msg.local_info().to_vec();
+        let local_info = local_msg.local_info().to_vec();
         let payload = local_msg.into_transport_message().payload;
 
         // Forward to local workers
         let _ = onward_route.step()?;
 
-        let return_route = return_route
-            .modify()
-            .pop_front()
-            .pop_front()
-            .prepend(self.self_local_address.clone());
-
-        let transport_msg = TransportMessage::v1(onward_route, return_route, payload);
-
-        local_info.push(
-            EntitySecureChannelLocalInfo::new(state.their_profile_id.clone()).to_local_info()?,
-        );
-
-        let msg = LocalMessage::new(transport_msg, local_info);
-
-        match ctx.forward(msg).await {
-            Ok(_) => Ok(()),
-            Err(err) => {
-                warn!(
-                    "{} forwarding decrypted message from {}",
-                    err, self.self_local_address
-                );
-                Ok(())
-            }
-        }
+        self.forward_decrypted(ctx, &state, onward_route, return_route, local_info, payload)
+            .await?;
+        self.acknowledge_received(ctx).await
     }
 }
 
-#[async_trait]
-impl<I: Identity, T: TrustPolicy> Worker for SecureChannelWorker<I, T> {
-    type Message = Any;
-    type Context = Context;
-
-    async fn initialize(&mut self, _ctx: &mut Self::Context) -> Result<()> {
-        if self.is_initiator {
-            match self.take_state()? {
-                State::InitiatorStartChannel(s) => {
-                    let channel = s.channel_future.await?;
-
-                    self.state = Some(State::InitiatorSendProfile(InitiatorSendProfile {
-                        channel,
-                        callback_address: s.callback_address,
-                        identity: s.identity,
-                        trust_policy: s.trust_policy,
-                    }));
-                }
-                _ => return Err(EntityError::InvalidSecureChannelInternalState.into()),
+impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
+    /// Span around handling a message in the current state. Only addresses and
+    /// identifiers go into it, never key material
+    #[cfg(feature = "tracing_spans")]
+    fn span(&self, msg_addr: Option<&Address>) -> Span {
+        let channel_addr = field::display(&self.self_local_address);
+        let (step, peer_id) = match &self.state {
+            Some(State::Initialized(state)) => {
+                let peer_id = field::display(&state.their_profile_id);
+                return if msg_addr == Some(&self.self_local_address) {
+                    debug_span!("secure_channel_encrypt", channel_addr, peer_id)
+                } else {
+                    debug_span!("secure_channel_decrypt", channel_addr, peer_id)
+                };
             }
-        }
+            Some(State::InitiatorStartChannel(_)) => (
+                "initiator_start_channel",
+                self.negotiation.inherited_from.as_ref(),
+            ),
+            Some(State::ResponderWaitForKex(_)) => (
+                "responder_wait_for_kex",
+                self.negotiation.inherited_from.as_ref(),
+            ),
+            Some(State::InitiatorSendProfile(_)) => (
+                "initiator_send_profile",
+                self.negotiation.inherited_from.as_ref(),
+            ),
+            Some(State::InitiatorWaitForConfirm(state)) => (
+                "initiator_wait_for_confirm",
+                Some(&state.initialized.their_profile_id),
+            ),
+            Some(State::ResponderWaitForProfile(_)) => (
+                "responder_wait_for_profile",
+                self.negotiation.inherited_from.as_ref(),
+            ),
+            None => return Span::none(),
+        };
 
-        Ok(())
+        // Unknown until the other side proved it, unless trust is inherited
+        let span = info_span!(
+            "secure_channel_handshake",
+            channel_addr,
+            step,
+            peer_id = field::Empty
+        );
+        if let Some(peer_id) = peer_id {
+            span.record("peer_id", &field::display(peer_id));
+        }
+        span
     }
 
-    async fn handle_message(
-        &mut self,
-        ctx: &mut Self::Context,
-        msg: Routed<Self::Message>,
-    ) -> Result<()> {
+    /// Handle a message according to the state of the channel
+    async fn dispatch(&mut self, ctx: &mut Context, msg: Routed<Any>) -> Result<()> {
         let msg_addr = msg.msg_addr();
 
+        // Nothing to send again before the channel is up
+        if self.reconnection.undelivered_address.as_ref() == Some(&msg_addr)
+            && !matches!(self.state, Some(State::Initialized(_)))
+        {
+            debug!(
+                "ProfileSecureChannel at local: {} dropped message returned during handshake",
+                &self.self_local_address
+            );
+            return Ok(());
+        }
+
         match self.take_state()? {
             State::InitiatorStartChannel(_) => {
                 return Err(EntityError::InvalidSecureChannelInternalState.into())
             }
             State::ResponderWaitForKex(s) => {
                 if msg_addr == self.self_local_address {
-                    self.handle_kex_done(ctx, msg, s).await?;
+                    if let Err(err) = self.handle_kex_done(ctx, msg, s).await {
+                        warn!(
+                            "{} starting SecureChannel Responder at local: {}",
+                            err, self.self_local_address
+                        );
+                        // Releases the listener slot
+                        ctx.stop_worker(self.self_local_address.clone()).await?;
+                    }
                 } else {
                     return Err(EntityError::UnknownChannelMsgDestination.into());
                 }
             }
             State::InitiatorSendProfile(s) => {
                 if msg_addr == self.self_remote_address {
-                    self.handle_send_profile(ctx, msg, s).await?;
+                    let callback_address = s.callback_address.clone();
+                    if let Err(err) = self.handle_send_profile(ctx, msg, s).await {
+                        warn!(
+                            "{} authenticating SecureChannel Initiator at local: {}",
+                            err, self.self_local_address
+                        );
+                        ctx.send(callback_address, AuthenticationConfirmation(Err(err)))
+                            .await?;
+                    }
+                } else {
+                    return Err(EntityError::UnknownChannelMsgDestination.into());
+                }
+            }
+            State::InitiatorWaitForConfirm(s) => {
+                if msg_addr == self.self_remote_address {
+                    let callback_address = s.callback_address.clone();
+                    let local_secure_channel_address =
+                        s.initialized.local_secure_channel_address.clone();
+                    if let Err(err) = self.handle_wait_for_confirm(ctx, msg, s).await {
+                        warn!(
+                            "{} authenticating SecureChannel Initiator at local: {}",
+                            err, self.self_local_address
+                        );
+                        ctx.stop_worker(local_secure_channel_address).await?;
+                        ctx.send(callback_address, AuthenticationConfirmation(Err(err)))
+                            .await?;
+                    }
                 } else {
                     return Err(EntityError::UnknownChannelMsgDestination.into());
                 }
             }
             State::ResponderWaitForProfile(s) => {
                 if msg_addr == self.self_remote_address {
-                    self.handle_receive_profile(ctx, msg, s).await?;
+                    let local_secure_channel_address = s.local_secure_channel_address.clone();
+                    let return_route = msg.return_route();
+                    if let Err(err) = self.handle_receive_profile(ctx, msg, s).await {
+                        warn!(
+                            "{} authenticating SecureChannel Responder at local: {}",
+                            err, self.self_local_address
+                        );
+                        // Let the initiator know, unless the message didn't come through our channel
+                        if return_route.next().ok() == Some(&local_secure_channel_address) {
+                            ctx.send_from_address(
+                                return_route,
+                                EntityChannelMessage::Reject(err.into()),
+                                self.self_remote_address.clone(),
+                            )
+                            .await?;
+                        }
+                        // Releases the listener slot
+                        ctx.stop_worker(local_secure_channel_address).await?;
+                        ctx.stop_worker(self.self_local_address.clone()).await?;
+                    }
                 } else {
                     return Err(EntityError::UnknownChannelMsgDestination.into());
                 }
@@ -586,6 +1280,8 @@ impl<I: Identity, T: TrustPolicy> Worker for SecureChannelWorker<I, T> {
                     self.handle_encrypt(ctx, msg, s).await?;
                 } else if msg_addr == self.self_remote_address {
                     self.handle_decrypt(ctx, msg, s).await?;
+                } else if self.reconnection.undelivered_address.as_ref() == Some(&msg_addr) {
+                    self.handle_undelivered(ctx, msg, s).await?;
                 } else {
                     return Err(EntityError::UnknownChannelMsgDestination.into());
                 }
@@ -595,3 +1291,157 @@ impl<I: Identity, T: TrustPolicy> Worker for SecureChannelWorker<I, T> {
         Ok(())
     }
 }
+
+#[async_trait]
+impl<I: Identity, T: TrustPolicy> Worker for SecureChannelWorker<I, T> {
+    type Message = Any;
+    type Context = Context;
+
+    async fn initialize(&mut self, ctx: &mut Self::Context) -> Result<()> {
+        if self.is_initiator {
+            #[cfg(feature = "tracing_spans")]
+            let span = self.span(None);
+            match self.take_state()? {
+                State::InitiatorStartChannel(s) => {
+                    let channel = s.channel_future;
+                    #[cfg(feature = "tracing_spans")]
+                    let channel = channel.instrument(span);
+                    let channel = match channel.await {
+                        Ok(channel) => channel,
+                        Err(err) => {
+                            ctx.send(s.callback_address, AuthenticationConfirmation(Err(err)))
+                                .await?;
+                            return Ok(());
+                        }
+                    };
+
+                    self.state = Some(State::InitiatorSendProfile(InitiatorSendProfile {
+                        channel,
+                        callback_address: s.callback_address,
+                        identity: s.identity,
+                        trust_policy: s.trust_policy,
+                    }));
+                }
+                _ => return Err(EntityError::InvalidSecureChannelInternalState.into()),
+            }
+        } else if let Some(timeout) = self.origin.handshake_timeout {
+            self.schedule_handshake_deadline(ctx, timeout).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn shutdown(&mut self, ctx: &mut Self::Context) -> Result<()> {
+        if let Some(handshakes) = &self.origin.handshakes {
+            handshakes.responders.remove(&self.self_local_address);
+        }
+
+        let state = match self.state.take() {
+            // Handshake was cancelled, e.g. by stopping the listener
+            Some(State::ResponderWaitForProfile(state)) => {
+                let _ = ctx.stop_worker(state.local_secure_channel_address).await;
+                None
+            }
+            // Initiator handshake was cancelled, e.g. by the caller giving up on the channel
+            Some(State::InitiatorSendProfile(state)) => {
+                let _ = ctx.stop_worker(state.channel.address()).await;
+                None
+            }
+            Some(State::InitiatorWaitForConfirm(state)) => {
+                let _ = ctx
+                    .stop_worker(state.initialized.local_secure_channel_address)
+                    .await;
+                None
+            }
+            Some(State::Initialized(state)) => Some(state),
+            _ => None,
+        };
+
+        if let Some(mut state) = state {
+            // Don't try to reconnect while stopping
+            self.reconnection.disable();
+
+            // Messages held while reconnecting were sent before those held by backpressure
+            if let Err(err) = self.finish_recovery(ctx, &mut state, None).await {
+                warn!(
+                    "{} sending messages held while reconnecting SecureChannel at local: {}",
+                    err, self.self_local_address
+                );
+            }
+
+            // Messages held by backpressure go out as well, waiting senders find the channel gone
+            self.send_held(ctx, &mut state).await;
+
+            if let Err(err) = self.flush_batch(ctx, &mut state).await {
+                warn!(
+                    "{} sending pending batch of SecureChannel at local: {}",
+                    err, self.self_local_address
+                );
+            }
+
+            // Goes through the regular SecureChannel after everything that was sent before
+            if let Err(err) = ctx
+                .send_from_address(
+                    route![
+                        state.local_secure_channel_address.clone(),
+                        state.remote_profile_secure_channel_address
+                    ],
+                    EntityChannelMessage::Close,
+                    self.self_remote_address.clone(),
+                )
+                .await
+            {
+                warn!(
+                    "{} notifying the other side about closing SecureChannel at local: {}",
+                    err, self.self_local_address
+                );
+            }
+
+            ctx.stop_worker(state.local_secure_channel_address).await?;
+
+            info!(
+                "Stopped ProfileSecureChannel at local: {}, remote: {}",
+                &self.self_local_address, &self.self_remote_address
+            );
+        }
+
+        for prober in self.probes.drain() {
+            let _ = ctx
+                .send(
+                    prober,
+                    EntityChannelMessage::Reject(EntityError::SecureChannelNotFound.into()),
+                )
+                .await;
+        }
+
+        // Also covers channels closed by the other side, which leave no state
+        for watcher in self.close_watchers.drain(..) {
+            let _ = ctx
+                .send_from_address(
+                    watcher,
+                    EntityChannelMessage::Close,
+                    self.self_local_address.clone(),
+                )
+                .await;
+        }
+
+        self.registry
+            .deregister(ctx, self.self_local_address.clone())
+            .await;
+
+        Ok(())
+    }
+
+    async fn handle_message(
+        &mut self,
+        ctx: &mut Self::Context,
+        msg: Routed<Self::Message>,
+    ) -> Result<()> {
+        #[cfg(feature = "tracing_spans")]
+        let span = self.span(Some(&msg.msg_addr()));
+        let res = self.dispatch(ctx, msg);
+        #[cfg(feature = "tracing_spans")]
+        let res = res.instrument(span);
+        res.await
+    }
+}
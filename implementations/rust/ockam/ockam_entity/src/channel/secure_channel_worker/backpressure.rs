@@ -0,0 +1,297 @@
+use super::{Initialized, SecureChannelWorker};
+use crate::{
+    BackpressureOptions, EntityChannelMessage, EntityError, Identity, MessagePriority, TrustPolicy,
+};
+use ockam_core::compat::{collections::VecDeque, vec::Vec};
+use ockam_core::{route, Result, Route, Worker};
+use ockam_node::Context;
+use tracing::warn;
+
+/// High priority messages held by backpressure, over this they're dropped
+const MAX_HIGH_PRIORITY_QUEUED: usize = 16;
+
+/// Messages sent to the other side and not acknowledged yet, see [`BackpressureOptions`]
+pub(super) struct Backpressure {
+    options: BackpressureOptions,
+    sent: u64,
+    acked: u64,
+    /// Messages held until the other side catches up
+    queue: VecDeque<QueuedMessage>,
+    /// [`MessagePriority::High`] messages held, sent before the [`Backpressure::queue`]
+    high_priority: VecDeque<QueuedMessage>,
+    /// Return routes of senders waiting for [`EntityChannelMessage::CapacityReserved`]
+    waiters: VecDeque<Route>,
+    /// Senders told to go ahead, whose message didn't arrive yet
+    reserved: usize,
+}
+
+/// Message of a local worker, as held by backpressure or while reconnecting
+pub(super) struct QueuedMessage {
+    pub(super) onward_route: Route,
+    pub(super) return_route: Route,
+    pub(super) payload: Vec<u8>,
+    pub(super) priority: MessagePriority,
+}
+
+impl Backpressure {
+    pub(super) fn new(options: BackpressureOptions) -> Self {
+        Self {
+            options,
+            sent: 0,
+            acked: 0,
+            queue: VecDeque::new(),
+            high_priority: VecDeque::new(),
+            waiters: VecDeque::new(),
+            reserved: 0,
+        }
+    }
+
+    fn in_flight(&self) -> usize {
+        (self.sent - self.acked) as usize
+    }
+
+    /// Whether a message can be sent without overtaking the queued ones
+    fn can_send(&self) -> bool {
+        self.queue.is_empty() && self.can_send_high_priority()
+    }
+
+    /// Whether a high priority message can be sent, overtaking the normal queue
+    fn can_send_high_priority(&self) -> bool {
+        self.high_priority.is_empty() && self.in_flight() < self.options.max_in_flight()
+    }
+
+    fn queued(&self) -> usize {
+        self.queue.len() + self.high_priority.len()
+    }
+
+    /// Held messages in the order they have to be sent
+    fn into_queued(self) -> impl Iterator<Item = QueuedMessage> {
+        self.high_priority.into_iter().chain(self.queue)
+    }
+
+    /// Whether another message would be sent right away, counting the reserved ones
+    fn has_capacity(&self) -> bool {
+        self.in_flight() + self.queued() + self.reserved < self.options.max_in_flight()
+    }
+
+    /// Give back a message of a local worker if it can be sent right away, counting it as
+    /// in flight. Otherwise hold it, unless too many are held already
+    pub(super) fn admit(&mut self, message: QueuedMessage) -> Result<Option<QueuedMessage>> {
+        self.reserved = self.reserved.saturating_sub(1);
+        let (can_send, queue, max_queued) = match message.priority {
+            MessagePriority::Normal => {
+                (self.can_send(), &mut self.queue, self.options.max_queued())
+            }
+            MessagePriority::High => (
+                self.can_send_high_priority(),
+                &mut self.high_priority,
+                MAX_HIGH_PRIORITY_QUEUED,
+            ),
+        };
+        if !can_send {
+            if queue.len() >= max_queued {
+                return Err(EntityError::SecureChannelWouldBlock.into());
+            }
+            queue.push_back(message);
+            return Ok(None);
+        }
+        self.sent += 1;
+
+        Ok(Some(message))
+    }
+
+    /// The other side received that many messages since acknowledgements were enabled
+    pub(super) fn acknowledged(&mut self, received: u64) {
+        self.acked = received.clamp(self.acked, self.sent);
+    }
+}
+
+/// Messages received from the other side, since it enabled acknowledgements
+#[derive(Default)]
+pub(super) struct Acknowledgements {
+    received: u64,
+    /// Whether [`EntityChannelMessage::SendAck`] is on its way already
+    scheduled: bool,
+}
+
+impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
+    /// Let the sender at `return_route` go ahead once the channel sends its next message right away
+    pub(super) async fn reserve_capacity(
+        &mut self,
+        ctx: &Context,
+        return_route: Route,
+    ) -> Result<()> {
+        match &mut self.backpressure {
+            Some(backpressure) if !backpressure.has_capacity() => {
+                backpressure.waiters.push_back(return_route);
+                Ok(())
+            }
+            Some(backpressure) => {
+                backpressure.reserved += 1;
+                ctx.send(return_route, EntityChannelMessage::CapacityReserved)
+                    .await
+            }
+            None => {
+                ctx.send(return_route, EntityChannelMessage::CapacityReserved)
+                    .await
+            }
+        }
+    }
+
+    /// Send the messages held by backpressure that fit now, then let waiting senders go ahead
+    pub(super) async fn send_queued(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        state: &mut Initialized,
+    ) -> Result<()> {
+        loop {
+            let message = match &mut self.backpressure {
+                Some(backpressure)
+                    if backpressure.in_flight() < backpressure.options.max_in_flight() =>
+                {
+                    match backpressure
+                        .high_priority
+                        .pop_front()
+                        .or_else(|| backpressure.queue.pop_front())
+                    {
+                        Some(message) => {
+                            backpressure.sent += 1;
+                            message
+                        }
+                        None => break,
+                    }
+                }
+                _ => break,
+            };
+
+            self.send_message(ctx, state, message).await?;
+        }
+
+        while let Some(backpressure) = &mut self.backpressure {
+            if !backpressure.has_capacity() {
+                break;
+            }
+            let waiter = match backpressure.waiters.pop_front() {
+                Some(waiter) => waiter,
+                None => break,
+            };
+            backpressure.reserved += 1;
+            // The sender may have given up waiting
+            let _ = ctx
+                .send(waiter, EntityChannelMessage::CapacityReserved)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    /// Send every message held by backpressure when the channel stops. Waiting senders
+    /// are let go, and find the channel gone
+    pub(super) async fn send_held(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        state: &mut Initialized,
+    ) {
+        let mut backpressure = match self.backpressure.take() {
+            Some(backpressure) => backpressure,
+            None => return,
+        };
+
+        let waiters = core::mem::take(&mut backpressure.waiters);
+        for message in backpressure.into_queued() {
+            if let Err(err) = self.send_message(ctx, state, message).await {
+                warn!(
+                    "{} sending held message of SecureChannel at local: {}",
+                    err, self.self_local_address
+                );
+            }
+        }
+        for waiter in waiters {
+            let _ = ctx
+                .send(waiter, EntityChannelMessage::CapacityReserved)
+                .await;
+        }
+    }
+
+    /// Drop the messages held by backpressure when the channel fails, and tell waiting senders why
+    pub(super) async fn reject_held(&mut self, ctx: &Context, reason: EntityError) {
+        let backpressure = match self.backpressure.take() {
+            Some(backpressure) => backpressure,
+            None => return,
+        };
+
+        if backpressure.queued() > 0 {
+            warn!(
+                "Dropping {} held messages of ProfileSecureChannel at local: {}",
+                backpressure.queued(),
+                &self.self_local_address
+            );
+        }
+        for waiter in backpressure.waiters {
+            let _ = ctx
+                .send(waiter, EntityChannelMessage::Reject(reason.into()))
+                .await;
+        }
+    }
+
+    /// Have the other side acknowledge what it receives, so that backpressure can tell
+    /// what's still in flight. The other side counts from zero again after a reconnect
+    pub(super) async fn enable_acks(&mut self, ctx: &Context, state: &Initialized) -> Result<()> {
+        let backpressure = match &mut self.backpressure {
+            Some(backpressure) => backpressure,
+            None => return Ok(()),
+        };
+        backpressure.sent = 0;
+        backpressure.acked = 0;
+
+        ctx.send_from_address(
+            route![
+                state.local_secure_channel_address.clone(),
+                state.remote_profile_secure_channel_address.clone()
+            ],
+            EntityChannelMessage::EnableAcks,
+            self.self_remote_address.clone(),
+        )
+        .await
+    }
+
+    /// Count a message from the other side. The acknowledgement is sent once the channel
+    /// processed the messages already waiting, so that one covers them all
+    pub(super) async fn acknowledge_received(&mut self, ctx: &Context) -> Result<()> {
+        let acknowledgements = match &mut self.acknowledgements {
+            Some(acknowledgements) => acknowledgements,
+            None => return Ok(()),
+        };
+        acknowledgements.received += 1;
+        if acknowledgements.scheduled {
+            return Ok(());
+        }
+        acknowledgements.scheduled = true;
+
+        ctx.send(
+            route![self.self_local_address.clone()],
+            EntityChannelMessage::SendAck,
+        )
+        .await
+    }
+
+    pub(super) async fn send_ack(&mut self, ctx: &Context, state: &Initialized) -> Result<()> {
+        let received = match &mut self.acknowledgements {
+            Some(acknowledgements) => {
+                acknowledgements.scheduled = false;
+                acknowledgements.received
+            }
+            None => return Ok(()),
+        };
+
+        ctx.send_from_address(
+            route![
+                state.local_secure_channel_address.clone(),
+                state.remote_profile_secure_channel_address.clone()
+            ],
+            EntityChannelMessage::Ack(received),
+            self.self_remote_address.clone(),
+        )
+        .await
+    }
+}
@@ -0,0 +1,128 @@
+use super::{Initialized, SecureChannelWorker};
+use crate::{BatchedMessage, EntityChannelMessage, Identity, TrustPolicy};
+use core::time::Duration;
+use ockam_core::compat::vec::Vec;
+use ockam_core::{Encodable, Result, Route, Worker};
+use tracing::debug;
+
+/// Messages bigger than that are never batched, so that the frame fits into a transport message
+const MAX_BATCH_PAYLOAD_SIZE: usize = 16 * 1024;
+
+/// Outgoing messages waiting to be sent in one frame
+struct PendingBatch {
+    id: u64,
+    return_route: Route,
+    messages: Vec<BatchedMessage>,
+    size: usize,
+}
+
+/// Batching of outgoing messages, see [`SecureChannelOptions::with_max_batch`](crate::SecureChannelOptions::with_max_batch)
+pub(super) struct Batching {
+    /// A batch is sent once it has that many messages, never batching if it's 1
+    max_batch: usize,
+    /// Or once its first message waited that long
+    max_delay: Duration,
+    pending: Option<PendingBatch>,
+    next_id: u64,
+}
+
+impl Batching {
+    pub(super) fn new(max_batch: usize, max_delay: Duration) -> Self {
+        Self {
+            max_batch,
+            max_delay,
+            pending: None,
+            next_id: 0,
+        }
+    }
+
+    /// Whether given payload goes into a batch, rather than being sent right away
+    pub(super) fn takes(&self, payload: &[u8]) -> bool {
+        self.max_batch > 1 && payload.len() <= MAX_BATCH_PAYLOAD_SIZE
+    }
+
+    /// Whether batch `id` is still waiting, rather than sent already
+    pub(super) fn is_pending(&self, id: u64) -> bool {
+        self.pending.as_ref().map(|batch| batch.id) == Some(id)
+    }
+}
+
+impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
+    pub(super) async fn add_to_batch(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        state: &mut Initialized,
+        onward_route: Route,
+        return_route: Route,
+        payload: Vec<u8>,
+    ) -> Result<()> {
+        // A batch never mixes return routes, and never outgrows a transport message
+        let fits = match &self.batching.pending {
+            Some(batch) => {
+                batch.return_route == return_route
+                    && batch.size + payload.len() <= MAX_BATCH_PAYLOAD_SIZE
+            }
+            None => true,
+        };
+        if !fits {
+            self.flush_batch(ctx, state).await?;
+        }
+
+        if self.batching.pending.is_none() {
+            let id = self.batching.next_id;
+            self.batching.next_id = self.batching.next_id.wrapping_add(1);
+            self.schedule(
+                ctx,
+                self.batching.max_delay,
+                EntityChannelMessage::FlushBatch(id),
+            )
+            .await?;
+
+            self.batching.pending = Some(PendingBatch {
+                id,
+                return_route,
+                messages: Vec::new(),
+                size: 0,
+            });
+        }
+
+        let is_full = if let Some(batch) = &mut self.batching.pending {
+            batch.size += payload.len();
+            batch.messages.push(BatchedMessage {
+                onward_route,
+                payload,
+            });
+            batch.messages.len() >= self.batching.max_batch
+        } else {
+            false
+        };
+
+        if is_full {
+            self.flush_batch(ctx, state).await?;
+        }
+
+        Ok(())
+    }
+
+    pub(super) async fn flush_batch(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        state: &mut Initialized,
+    ) -> Result<()> {
+        let batch = match self.batching.pending.take() {
+            Some(batch) => batch,
+            None => return Ok(()),
+        };
+
+        debug!(
+            "ProfileSecureChannel at local: {} sending batch of {} messages",
+            &self.self_local_address,
+            batch.messages.len()
+        );
+
+        // Addressed to the channel on the other side, which splits it back
+        let payload = EntityChannelMessage::Batch(batch.messages).encode()?;
+        self.send_encrypted(ctx, state, Route::new().into(), batch.return_route, payload)
+            .await
+    }
+}
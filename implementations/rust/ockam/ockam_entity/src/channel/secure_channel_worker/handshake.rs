@@ -0,0 +1,745 @@
+use super::{
+    AuthenticationConfirmation, Initialized, InitiatorSendProfile, InitiatorWaitForConfirm,
+    ResponderWaitForKex, ResponderWaitForProfile, SecureChannelWorker, State,
+};
+use crate::{
+    auth_proof_data, decode_bounded, handshake_digest, AuthorityCredential, ChannelSlot, Contact,
+    EntityChannelMessage, EntityError, Identity, KeyExchangePattern, ProfileIdentifier,
+    PskMismatches, SecureChannelCipherSuite, SecureChannelHandle, SecureChannelHandshakes,
+    SecureChannelTrustInfo, TrustDecision, TrustPolicy,
+};
+use core::time::Duration;
+use ockam_channel::{KeyExchangeCompleted, SecureChannelInfo};
+use ockam_core::compat::{collections::BTreeMap, string::String};
+use ockam_core::{route, Address, Decodable, Result, Route, Routed, Worker};
+use ockam_node::Context;
+use tracing::{debug, info, warn};
+
+/// What a channel asks for and offers during the handshake
+pub(super) struct Negotiation {
+    /// Presented to the other side during the handshake
+    pub(super) credential: Option<AuthorityCredential>,
+    /// Both sides have to declare they check a trust policy
+    pub(super) strict_trust: bool,
+    /// Pattern of the regular SecureChannel underneath
+    pub(super) key_exchange: KeyExchangePattern,
+    /// Peer of the channel this one was created over, which the other side has to prove to be.
+    /// Set when trust is inherited from that channel, see
+    /// [`SecureChannelOptions::with_inherited_trust`](crate::SecureChannelOptions::with_inherited_trust)
+    pub(super) inherited_from: Option<ProfileIdentifier>,
+    /// Neither side proves an identity, see
+    /// [`SecureChannelOptions::with_anonymous`](crate::SecureChannelOptions::with_anonymous)
+    pub(super) anonymous: bool,
+    /// Offered to the other side, which ends up in [`Initialized::compression`] if it supports it
+    pub(super) compression: bool,
+    /// Highest protocol version the initiator offers, the one the responder settled on
+    pub(super) protocol_version: u16,
+    /// The handshake fails if the other side doesn't speak that version
+    pub(super) min_protocol_version: u16,
+    /// Larger handshake messages from the other side fail the handshake before they are parsed
+    pub(super) max_handshake_message_size: usize,
+}
+
+/// The listener a responder was started by, none of it is set for initiators
+#[derive(Default)]
+pub(super) struct ResponderOrigin {
+    /// Listener slot taken by a responder, released when the worker is dropped
+    pub(super) _slot: Option<ChannelSlot>,
+    /// Handshakes of the listener that started this responder
+    pub(super) handshakes: Option<SecureChannelHandshakes>,
+    /// Responders stop if they don't trust the initiator within that time
+    pub(super) handshake_timeout: Option<Duration>,
+    /// Key confirmations that failed at the listener of a responder
+    pub(super) psk_mismatches: Option<PskMismatches>,
+    /// Entity listener that started this responder and the requested service
+    pub(super) listener: Option<(Address, Option<String>)>,
+}
+
+/// The initiator only learns why it was rejected if the reason is disclosed, so keep it here
+fn log_rejection(trust_info: &SecureChannelTrustInfo, decision: &TrustDecision) {
+    if let Some(reason) = decision.reason() {
+        warn!(
+            "Responder rejected SecureChannel from {}: {}",
+            trust_info.their_profile_id(),
+            reason
+        );
+    }
+}
+
+impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
+    /// Add what our trust policy gets to know about our side of the channel
+    async fn with_our_side(
+        &self,
+        identity: &I,
+        trust_info: SecureChannelTrustInfo,
+    ) -> Result<SecureChannelTrustInfo> {
+        let trust_info = trust_info
+            .with_our_profile_id(identity.identifier().await?)
+            .with_their_route(self.their_route.clone());
+        Ok(match &self.origin.listener {
+            Some((listener, service)) => {
+                trust_info.with_listener(listener.clone(), service.clone())
+            }
+            None => trust_info,
+        })
+    }
+
+    pub(super) async fn handle_kex_done(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        msg: Routed<<Self as Worker>::Message>,
+        mut state: ResponderWaitForKex<I, T>,
+    ) -> Result<()> {
+        let kex_msg = KeyExchangeCompleted::decode(msg.payload())?;
+
+        // Nothing the initiator sends could be decrypted
+        let mismatch = self
+            .origin
+            .psk_mismatches
+            .as_ref()
+            .map_or(false, |mismatches| mismatches.take(&kex_msg.auth_hash()));
+        if mismatch {
+            ctx.stop_worker(kex_msg.address().clone()).await?;
+            return Err(EntityError::PreSharedKeyMismatch.into());
+        }
+
+        let mut rejection = state.rejection.take();
+        let mut their_attributes = BTreeMap::new();
+        if rejection.is_none() {
+            if let Some(trust_info) = &state.inherited_trust {
+                let trust_info = self
+                    .with_our_side(&state.identity, trust_info.clone())
+                    .await?;
+                let decision = state.trust_policy.decide(&trust_info).await?;
+                if !decision.is_trusted() {
+                    log_rejection(&trust_info, &decision);
+                    rejection = Some(decision.to_error(true));
+                }
+                their_attributes = trust_info.inherited_attributes().clone();
+                their_attributes.extend(decision.verified_attributes().clone());
+            }
+        }
+
+        if let Some(rejection) = rejection {
+            ctx.send_from_address(
+                route![kex_msg.address().clone(), state.first_responder_address],
+                EntityChannelMessage::Reject(rejection.into()),
+                self.self_remote_address.clone(),
+            )
+            .await?;
+            debug!("Sent SecureChannel rejection");
+
+            ctx.stop_worker(kex_msg.address().clone()).await?;
+            return ctx.stop_worker(self.self_local_address.clone()).await;
+        }
+
+        if self.negotiation.anonymous {
+            return self
+                .initialize_anonymous_responder(ctx, kex_msg, state)
+                .await;
+        }
+
+        // Prove we posses Profile key, and set the attributes we advertise
+        let attributes = state.identity.get_attributes().await?;
+        let proof = state
+            .identity
+            .create_auth_proof(&auth_proof_data(&kex_msg.auth_hash(), &attributes)?)
+            .await?;
+        let msg = EntityChannelMessage::Request {
+            contact: state.identity.as_contact().await?,
+            proof,
+            credential: self.negotiation.credential.clone(),
+            attributes,
+            strict_trust: self.negotiation.strict_trust,
+            compression: self.negotiation.compression,
+            protocol_version: self.negotiation.protocol_version,
+        };
+        ctx.send_from_address(
+            route![kex_msg.address().clone(), state.first_responder_address],
+            msg,
+            self.self_remote_address.clone(),
+        )
+        .await?;
+        debug!("Sent Authentication request");
+
+        self.state = Some(State::ResponderWaitForProfile(ResponderWaitForProfile {
+            auth_hash: kex_msg.auth_hash(),
+            local_secure_channel_address: kex_msg.address().clone(),
+            identity: state.identity,
+            trust_policy: state.trust_policy,
+            their_attributes,
+            #[cfg(feature = "unsafe_channel_key_export")]
+            exported_key: kex_msg.exported_key().clone(),
+        }));
+
+        Ok(())
+    }
+
+    /// Accept the channel without either side proving anything
+    async fn initialize_anonymous_responder(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        kex_msg: KeyExchangeCompleted,
+        state: ResponderWaitForKex<I, T>,
+    ) -> Result<()> {
+        let local_secure_channel_address = kex_msg.address().clone();
+        ctx.send_from_address(
+            route![
+                local_secure_channel_address.clone(),
+                state.first_responder_address.clone()
+            ],
+            EntityChannelMessage::Confirm,
+            self.self_remote_address.clone(),
+        )
+        .await?;
+
+        if let Some(handshakes) = self.origin.handshakes.take() {
+            handshakes.complete(&self.self_local_address, &local_secure_channel_address);
+        }
+
+        self.state = Some(State::Initialized(Initialized {
+            local_secure_channel_address,
+            remote_profile_secure_channel_address: state.first_responder_address,
+            their_profile_id: ProfileIdentifier::default(),
+            their_public_key: None,
+            their_contact: None,
+            their_attributes: BTreeMap::new(),
+            their_profile_attributes: BTreeMap::new(),
+            compression: false,
+            protocol_version: self.negotiation.protocol_version,
+            their_last_handshake_message: None,
+            #[cfg(feature = "unsafe_channel_key_export")]
+            exported_key: kex_msg.exported_key().clone(),
+        }));
+
+        info!(
+            "Initialized anonymous ProfileSecureChannel Responder at local: {}, remote: {}",
+            &self.self_local_address, &self.self_remote_address
+        );
+
+        Ok(())
+    }
+
+    pub(super) async fn handle_send_profile(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        msg: Routed<<Self as Worker>::Message>,
+        mut state: InitiatorSendProfile<I, T>,
+    ) -> Result<()> {
+        let return_route = msg.return_route();
+
+        // Ensure message came from dedicated SecureChannel
+        if return_route.next()? != &state.channel.address() {
+            return Err(EntityError::UnknownChannelMsgDestination.into());
+        }
+
+        // Sent before the other side is authenticated
+        let body = self.decode_handshake_message(msg.payload())?;
+
+        let mut initialized = self
+            .authenticate_responder(
+                ctx,
+                &mut state.identity,
+                &state.trust_policy,
+                &state.channel,
+                return_route,
+                body,
+            )
+            .await?;
+        initialized.their_last_handshake_message = Some(handshake_digest(msg.payload()));
+
+        // The responder checked its trust policy already, or doesn't have any identity to check
+        if self.negotiation.inherited_from.is_some() || self.negotiation.anonymous {
+            return self
+                .initialize_initiator(ctx, initialized, state.callback_address, state.identity)
+                .await;
+        }
+
+        self.state = Some(State::InitiatorWaitForConfirm(InitiatorWaitForConfirm {
+            initialized,
+            callback_address: state.callback_address,
+            identity: state.identity,
+        }));
+
+        Ok(())
+    }
+
+    pub(super) async fn handle_wait_for_confirm(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        msg: Routed<<Self as Worker>::Message>,
+        state: InitiatorWaitForConfirm<I>,
+    ) -> Result<()> {
+        let return_route = msg.return_route();
+
+        // Ensure message came from dedicated SecureChannel
+        if return_route.next()? != &state.initialized.local_secure_channel_address {
+            return Err(EntityError::UnknownChannelMsgDestination.into());
+        }
+
+        // The responder's profile, delivered again
+        if state.initialized.their_last_handshake_message == Some(handshake_digest(msg.payload())) {
+            debug!("Ignored repeated Authentication request");
+            self.state = Some(State::InitiatorWaitForConfirm(state));
+            return Ok(());
+        }
+
+        match self.decode_handshake_message(msg.payload())? {
+            EntityChannelMessage::Confirm => {}
+            EntityChannelMessage::Reject(err) => return Err(err.into()),
+            _ => return Err(EntityError::InvalidSecureChannelInternalState.into()),
+        }
+        debug!("Received Authentication confirmation");
+
+        self.initialize_initiator(
+            ctx,
+            state.initialized,
+            state.callback_address,
+            state.identity,
+        )
+        .await
+    }
+
+    async fn initialize_initiator(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        initialized: Initialized,
+        callback_address: Address,
+        identity: I,
+    ) -> Result<()> {
+        let their_profile_id = initialized.their_profile_id.clone();
+        let protocol_version = initialized.protocol_version;
+
+        self.enable_acks(ctx, &initialized).await?;
+        if let Some(keepalive) = &self.keepalive {
+            self.schedule(
+                ctx,
+                keepalive.options.interval(),
+                EntityChannelMessage::KeepaliveTick,
+            )
+            .await?;
+        }
+        self.state = Some(State::Initialized(initialized));
+
+        info!(
+            "Initialized ProfileSecureChannel Initiator at local: {}, remote: {}",
+            &self.self_local_address, &self.self_remote_address
+        );
+
+        // Anonymous channels have no peer to be listed under
+        if !self.negotiation.anonymous {
+            self.registry
+                .register(
+                    ctx,
+                    identity.identifier().await?,
+                    SecureChannelHandle::new(
+                        self.self_local_address.clone(),
+                        their_profile_id.clone(),
+                        true,
+                        SecureChannelCipherSuite::new(self.negotiation.key_exchange),
+                        protocol_version,
+                        None,
+                    ),
+                )
+                .await;
+        }
+
+        ctx.send(
+            callback_address,
+            AuthenticationConfirmation(Ok((self.self_local_address.clone(), their_profile_id))),
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// Verify the responder and prove our Profile to it, over a freshly created regular SecureChannel
+    pub(super) async fn authenticate_responder(
+        &self,
+        ctx: &Context,
+        identity: &mut I,
+        trust_policy: &T,
+        channel: &SecureChannelInfo,
+        return_route: Route,
+        body: EntityChannelMessage,
+    ) -> Result<Initialized> {
+        if let EntityChannelMessage::Reject(err) = body {
+            return Err(err.into());
+        }
+
+        if self.negotiation.anonymous {
+            return match body {
+                EntityChannelMessage::Confirm => Ok(Initialized {
+                    local_secure_channel_address: channel.address(),
+                    remote_profile_secure_channel_address: return_route.recipient(),
+                    their_profile_id: ProfileIdentifier::default(),
+                    their_public_key: None,
+                    their_contact: None,
+                    their_attributes: BTreeMap::new(),
+                    their_profile_attributes: BTreeMap::new(),
+                    compression: false,
+                    protocol_version: self.negotiation.protocol_version,
+                    their_last_handshake_message: None,
+                    #[cfg(feature = "unsafe_channel_key_export")]
+                    exported_key: channel.exported_key().clone(),
+                }),
+                _ => Err(EntityError::InvalidSecureChannelInternalState.into()),
+            };
+        }
+
+        // Wait for responder to send us his Profile and Profile Proof.
+        // In case of using Noise XX this is m4 message.
+        if let EntityChannelMessage::Request {
+            contact,
+            proof,
+            credential,
+            attributes: their_profile_attributes,
+            strict_trust,
+            compression,
+            protocol_version,
+        } = body
+        {
+            debug!("Received Authentication request");
+
+            if self.negotiation.strict_trust && !strict_trust {
+                return Err(EntityError::SecureChannelTrustNotEnforced.into());
+            }
+
+            // The listener picks one of the versions we offered in the key exchange header
+            if protocol_version > self.negotiation.protocol_version {
+                return Err(EntityError::MalformedHandshakeMessage.into());
+            }
+            if protocol_version < self.negotiation.min_protocol_version {
+                return Err(EntityError::SecureChannelProtocolVersionTooOld.into());
+            }
+
+            let their_contact = contact;
+            let their_profile_id = their_contact.identifier().clone();
+            self.check_inherited_from(&their_profile_id)?;
+
+            // The proof is verified against the key of the stored contact,
+            // which is brought up to date first if they rotated their key
+            let their_contact = Self::add_or_update_contact(identity, their_contact).await?;
+            let their_public_key = their_contact.get_profile_update_public_key().ok();
+
+            // Verify responder posses their Profile key
+            let verified = identity
+                .verify_auth_proof(
+                    &auth_proof_data(&channel.auth_hash(), &their_profile_attributes)?,
+                    &their_profile_id,
+                    &proof,
+                )
+                .await?;
+
+            if !verified {
+                return Err(EntityError::SecureChannelVerificationFailed.into());
+            }
+            info!(
+                "Initiator verified SecureChannel from: {}",
+                their_profile_id
+            );
+
+            // Check our TrustPolicy
+            let trust_info = SecureChannelTrustInfo::new_with_public_key(
+                their_profile_id.clone(),
+                their_public_key.clone(),
+            )
+            .with_credential(credential)
+            .with_profile_attributes(their_profile_attributes.clone());
+            let trust_info = self.with_our_side(identity, trust_info).await?;
+            let decision = trust_policy.decide(&trust_info).await?;
+            if !decision.is_trusted() {
+                return Err(decision.to_error(false));
+            }
+            info!(
+                "Initiator checked trust policy for SecureChannel from: {}",
+                &their_profile_id
+            );
+
+            // Prove we posses our Profile key, and set the attributes we advertise
+            let contact = identity.as_contact().await?;
+            let attributes = identity.get_attributes().await?;
+            let proof = identity
+                .create_auth_proof(&auth_proof_data(&channel.auth_hash(), &attributes)?)
+                .await?;
+
+            let compression = self.negotiation.compression && compression;
+            let auth_msg = EntityChannelMessage::Response {
+                contact,
+                proof,
+                credential: self.negotiation.credential.clone(),
+                attributes,
+                strict_trust: self.negotiation.strict_trust,
+                compression,
+                protocol_version,
+            };
+
+            let remote_profile_secure_channel_address = return_route.recipient();
+
+            ctx.send_from_address(return_route, auth_msg, self.self_remote_address.clone())
+                .await?;
+            debug!("Sent Authentication response");
+
+            Ok(Initialized {
+                local_secure_channel_address: channel.address(),
+                remote_profile_secure_channel_address,
+                their_profile_id,
+                their_public_key,
+                their_contact: Some(their_contact),
+                their_attributes: decision.verified_attributes().clone(),
+                their_profile_attributes,
+                compression,
+                protocol_version,
+                their_last_handshake_message: None,
+                #[cfg(feature = "unsafe_channel_key_export")]
+                exported_key: channel.exported_key().clone(),
+            })
+        } else {
+            Err(EntityError::InvalidSecureChannelInternalState.into())
+        }
+    }
+
+    /// Decode a message the other side sends before it's authenticated, rejecting it
+    /// without looking inside if it's larger than we accept
+    pub(super) fn decode_handshake_message(&self, payload: &[u8]) -> Result<EntityChannelMessage> {
+        if payload.len() > self.negotiation.max_handshake_message_size {
+            return Err(EntityError::SecureChannelHandshakeMessageTooLarge.into());
+        }
+
+        decode_bounded(payload)
+    }
+
+    /// The identity of the other side is proven over this channel, and has to be the one
+    /// verified by the channel trust is inherited from
+    fn check_inherited_from(&self, their_profile_id: &ProfileIdentifier) -> Result<()> {
+        match &self.negotiation.inherited_from {
+            Some(inherited_from) if inherited_from != their_profile_id => {
+                Err(EntityError::SecureChannelVerificationFailed.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Store their contact if it's new. If it's known, apply the changes they made since,
+    /// which are only accepted if they extend the known history with valid events
+    async fn add_or_update_contact(identity: &mut I, their_contact: Contact) -> Result<Contact> {
+        let their_profile_id = their_contact.identifier().clone();
+        let known_contact = match identity.get_contact(&their_profile_id).await? {
+            Some(known_contact) => known_contact,
+            None => {
+                identity
+                    .verify_and_add_contact(their_contact.clone())
+                    .await?;
+                return Ok(their_contact);
+            }
+        };
+
+        let known_events = known_contact.change_events();
+        let their_events = their_contact.change_events();
+        if their_events.len() <= known_events.len() {
+            return Ok(known_contact);
+        }
+
+        let extends_known = known_events
+            .iter()
+            .zip(their_events)
+            .all(|(known, theirs)| known.identifier() == theirs.identifier());
+        if !extends_known
+            || !identity
+                .verify_and_update_contact(&their_profile_id, &their_events[known_events.len()..])
+                .await?
+        {
+            return Err(EntityError::SecureChannelVerificationFailed.into());
+        }
+
+        identity
+            .get_contact(&their_profile_id)
+            .await?
+            .ok_or_else(|| EntityError::ContactNotFound.into())
+    }
+
+    pub(super) async fn handle_receive_profile(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        msg: Routed<<Self as Worker>::Message>,
+        mut state: ResponderWaitForProfile<I, T>,
+    ) -> Result<()> {
+        let return_route = msg.return_route();
+
+        // Ensure message came from dedicated SecureChannel
+        if return_route.next()? != &state.local_secure_channel_address {
+            return Err(EntityError::UnknownChannelMsgDestination.into());
+        }
+
+        // Sent before the other side is authenticated
+        let body = self.decode_handshake_message(msg.payload())?;
+
+        // Wait for responder to send us his Profile and Profile Proof.
+        // In case of using Noise XX this is m4 message.
+        if let EntityChannelMessage::Response {
+            contact,
+            proof,
+            credential,
+            attributes: their_profile_attributes,
+            strict_trust,
+            compression,
+            protocol_version,
+        } = body
+        {
+            debug!("Received Authentication response");
+
+            if self.negotiation.strict_trust && !strict_trust {
+                return Err(EntityError::SecureChannelTrustNotEnforced.into());
+            }
+
+            // The initiator confirms the version we settled on
+            if protocol_version != self.negotiation.protocol_version {
+                return Err(EntityError::MalformedHandshakeMessage.into());
+            }
+
+            let their_contact = contact;
+            let their_profile_id = their_contact.identifier().clone();
+            self.check_inherited_from(&their_profile_id)?;
+
+            // The proof is verified against the key of the stored contact,
+            // which is brought up to date first if they rotated their key
+            let their_contact =
+                Self::add_or_update_contact(&mut state.identity, their_contact).await?;
+            let their_public_key = their_contact.get_profile_update_public_key().ok();
+
+            // Verify initiator posses their Profile key
+            let verified = state
+                .identity
+                .verify_auth_proof(
+                    &auth_proof_data(&state.auth_hash, &their_profile_attributes)?,
+                    &their_profile_id,
+                    &proof,
+                )
+                .await?;
+
+            if !verified {
+                return Err(EntityError::SecureChannelVerificationFailed.into());
+            }
+
+            info!(
+                "Responder verified SecureChannel from: {}",
+                &their_profile_id
+            );
+
+            let remote_profile_secure_channel_address = return_route.recipient();
+
+            // Inherited trust was checked before the key exchange completed,
+            // and the initiator doesn't wait for a confirmation then
+            let mut their_attributes = state.their_attributes;
+            if self.negotiation.inherited_from.is_none() {
+                // Check our TrustPolicy
+                let trust_info = SecureChannelTrustInfo::new_with_public_key(
+                    their_profile_id.clone(),
+                    their_public_key.clone(),
+                )
+                .with_credential(credential)
+                .with_profile_attributes(their_profile_attributes.clone());
+                let trust_info = self.with_our_side(&state.identity, trust_info).await?;
+                let decision = state.trust_policy.decide(&trust_info).await?;
+                if !decision.is_trusted() {
+                    log_rejection(&trust_info, &decision);
+                    return Err(decision.to_error(true));
+                }
+                info!(
+                    "Responder checked trust policy for SecureChannel from: {}",
+                    &their_profile_id
+                );
+                their_attributes = decision.verified_attributes().clone();
+
+                // The initiator doesn't consider the channel established until we accept it
+                ctx.send_from_address(
+                    return_route,
+                    EntityChannelMessage::Confirm,
+                    self.self_remote_address.clone(),
+                )
+                .await?;
+                debug!("Sent Authentication confirmation");
+            }
+
+            if let Some(handshakes) = self.origin.handshakes.take() {
+                handshakes.complete(
+                    &self.self_local_address,
+                    &state.local_secure_channel_address,
+                );
+            }
+
+            self.registry
+                .register(
+                    ctx,
+                    state.identity.identifier().await?,
+                    SecureChannelHandle::new(
+                        self.self_local_address.clone(),
+                        their_profile_id.clone(),
+                        false,
+                        SecureChannelCipherSuite::new(self.negotiation.key_exchange),
+                        protocol_version,
+                        self.origin
+                            .listener
+                            .as_ref()
+                            .map(|(listener, _)| listener.clone()),
+                    ),
+                )
+                .await;
+
+            self.state = Some(State::Initialized(Initialized {
+                local_secure_channel_address: state.local_secure_channel_address,
+                remote_profile_secure_channel_address,
+                their_profile_id,
+                their_public_key,
+                their_contact: Some(their_contact),
+                their_attributes,
+                their_profile_attributes,
+                compression,
+                protocol_version,
+                their_last_handshake_message: Some(handshake_digest(msg.payload())),
+                #[cfg(feature = "unsafe_channel_key_export")]
+                exported_key: state.exported_key,
+            }));
+
+            info!(
+                "Initialized ProfileSecureChannel Responder at local: {}, remote: {}",
+                &self.self_local_address, &self.self_remote_address
+            );
+
+            Ok(())
+        } else {
+            Err(EntityError::InvalidSecureChannelInternalState.into())
+        }
+    }
+
+    /// Stop the responder if it's still pending after `timeout`, so that an initiator that
+    /// stops answering doesn't hold on to its listener slot
+    pub(super) async fn schedule_handshake_deadline(
+        &self,
+        ctx: &Context,
+        timeout: Duration,
+    ) -> Result<()> {
+        let handshakes = match &self.origin.handshakes {
+            Some(handshakes) => handshakes.clone(),
+            None => return Ok(()),
+        };
+        let child_ctx = ctx.new_context(Address::random(0)).await?;
+        let self_local_address = self.self_local_address.clone();
+
+        ctx.runtime().spawn(async move {
+            child_ctx.sleep(timeout).await;
+
+            // No longer pending once the handshake completed or the responder stopped
+            if handshakes.responders.remove(&self_local_address) {
+                warn!(
+                    "Handshake of SecureChannel Responder at local: {} timed out",
+                    self_local_address
+                );
+                // Releases the listener slot
+                let _ = child_ctx.stop_worker(self_local_address).await;
+            }
+        });
+
+        Ok(())
+    }
+}
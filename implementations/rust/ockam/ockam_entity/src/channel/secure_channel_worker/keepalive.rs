@@ -0,0 +1,101 @@
+use super::{Initialized, SecureChannelWorker};
+use crate::{EntityChannelMessage, EntityError, Identity, KeepaliveOptions, TrustPolicy};
+use ockam_core::{route, Result};
+use ockam_node::Context;
+use tracing::warn;
+
+/// Pings sent to the other side, see [`KeepaliveOptions`]
+pub(super) struct Keepalive {
+    pub(super) options: KeepaliveOptions,
+    sent: u64,
+    /// Highest ping answered by the other side
+    answered: u64,
+}
+
+impl Keepalive {
+    pub(super) fn new(options: KeepaliveOptions) -> Self {
+        Self {
+            options,
+            sent: 0,
+            answered: 0,
+        }
+    }
+
+    /// The other side answered ping `id`
+    pub(super) fn answered(&mut self, id: u64) {
+        self.answered = id.clamp(self.answered, self.sent);
+    }
+
+    /// Whether ping `id` is still unanswered
+    pub(super) fn is_expired(&self, id: u64) -> bool {
+        self.answered < id
+    }
+
+    /// Pings sent over a replaced channel are never answered, so they are not waited for
+    pub(super) fn forget_sent(&mut self) {
+        self.answered = self.sent;
+    }
+}
+
+impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
+    /// Ping the other side, and check for the answer once the deadline passed
+    pub(super) async fn send_ping(&mut self, ctx: &Context, state: &Initialized) -> Result<()> {
+        let (id, options) = match &mut self.keepalive {
+            Some(keepalive) => {
+                keepalive.sent += 1;
+                (keepalive.sent, keepalive.options)
+            }
+            None => return Ok(()),
+        };
+
+        self.schedule(
+            ctx,
+            options.deadline(),
+            EntityChannelMessage::KeepaliveDeadline(id),
+        )
+        .await?;
+        self.schedule(ctx, options.interval(), EntityChannelMessage::KeepaliveTick)
+            .await?;
+
+        // A ping that can't be sent is never answered, which the deadline takes care of
+        if let Err(err) = ctx
+            .send_from_address(
+                route![
+                    state.local_secure_channel_address.clone(),
+                    state.remote_profile_secure_channel_address.clone()
+                ],
+                EntityChannelMessage::Ping(id),
+                self.self_remote_address.clone(),
+            )
+            .await
+        {
+            warn!(
+                "{} sending keepalive of ProfileSecureChannel at local: {}",
+                err, self.self_local_address
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Stop the channel once the other side didn't answer a ping in time. It's not told about it,
+    /// as it's most likely gone. Senders waiting for capacity learn why
+    pub(super) async fn keepalive_expired(
+        &mut self,
+        ctx: &Context,
+        state: Initialized,
+    ) -> Result<()> {
+        warn!(
+            "Keepalive of ProfileSecureChannel at local: {} wasn't answered in time, stopping",
+            &self.self_local_address
+        );
+
+        self.state = None;
+        self.reconnection.disable();
+        self.reject_held(ctx, EntityError::SecureChannelKeepaliveTimeout)
+            .await;
+
+        ctx.stop_worker(state.local_secure_channel_address).await?;
+        ctx.stop_worker(self.self_local_address.clone()).await
+    }
+}
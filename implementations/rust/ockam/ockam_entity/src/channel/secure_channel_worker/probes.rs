@@ -0,0 +1,63 @@
+use super::{Initialized, SecureChannelWorker};
+use crate::{EntityChannelMessage, Identity, TrustPolicy};
+use ockam_core::compat::collections::VecDeque;
+use ockam_core::{route, Result, Route};
+use ockam_node::Context;
+
+/// Probes a channel keeps waiting for an answer to. Older ones are given up on
+const MAX_PENDING_PROBES: usize = 16;
+
+/// Probes sent to the other side, see [`crate::Entity::probe_secure_channel`]
+#[derive(Default)]
+pub(super) struct Probes {
+    sent: u64,
+    /// Probe ids and who to tell once they are answered, oldest first
+    pending: VecDeque<(u64, Route)>,
+}
+
+impl Probes {
+    /// Who to tell that probe `id` was answered, none if it was given up on or answered already
+    pub(super) fn answered(&mut self, id: u64) -> Option<Route> {
+        let index = self.pending.iter().position(|(probe, _)| *probe == id)?;
+        self.pending.remove(index).map(|(_, prober)| prober)
+    }
+
+    /// Senders of the probes still waiting for an answer
+    pub(super) fn drain(&mut self) -> impl Iterator<Item = Route> + '_ {
+        self.pending.drain(..).map(|(_, prober)| prober)
+    }
+}
+
+impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
+    /// Send a probe to the other side, whose answer is passed on to `prober`
+    pub(super) async fn send_probe(
+        &mut self,
+        ctx: &Context,
+        state: &Initialized,
+        prober: Route,
+    ) -> Result<()> {
+        let res = ctx
+            .send_from_address(
+                route![
+                    state.local_secure_channel_address.clone(),
+                    state.remote_profile_secure_channel_address.clone()
+                ],
+                EntityChannelMessage::ProbeRequest(self.probes.sent + 1),
+                self.self_remote_address.clone(),
+            )
+            .await;
+        if let Err(err) = res {
+            return ctx
+                .send(prober, EntityChannelMessage::Reject(err.into()))
+                .await;
+        }
+
+        self.probes.sent += 1;
+        if self.probes.pending.len() >= MAX_PENDING_PROBES {
+            self.probes.pending.pop_front();
+        }
+        self.probes.pending.push_back((self.probes.sent, prober));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,334 @@
+use super::{ChannelFactory, Initialized, QueuedMessage, SecureChannelWorker, State};
+use crate::{
+    handshake_digest, EntityChannelMessage, EntityError, Identity, ReconnectOptions, TrustPolicy,
+};
+use core::time::Duration;
+use ockam_channel::UndeliveredMessage;
+use ockam_core::compat::collections::VecDeque;
+use ockam_core::{route, Address, Any, Decodable, Result, Routed, Worker};
+use ockam_node::tokio::time::timeout;
+use ockam_node::Context;
+use tracing::{debug, info, warn};
+
+/// What an initiator needs to re-run the handshake after the transport failed
+pub(super) struct Reconnect<I: Identity, T: TrustPolicy> {
+    pub(super) options: ReconnectOptions,
+    pub(super) timeout: Duration,
+    pub(super) channel_factory: ChannelFactory,
+    pub(super) identity: I,
+    pub(super) trust_policy: T,
+}
+
+/// Regular SecureChannel replaced by a reconnect, which still returns the messages its
+/// transport didn't take. New messages are held until it returned all of them, so that
+/// they don't overtake those sent again
+struct Recovery {
+    id: u64,
+    old_channel: Address,
+    /// Messages are sent again as they are, so only if both channels compress alike
+    compression: bool,
+    held: VecDeque<QueuedMessage>,
+}
+
+/// Reconnecting an initiator whose transport failed, see
+/// [`SecureChannelOptions::with_reconnect`](crate::SecureChannelOptions::with_reconnect)
+pub(super) struct Reconnection<I: Identity, T: TrustPolicy> {
+    /// None if the channel doesn't reconnect, or stopped doing so
+    reconnect: Option<Reconnect<I, T>>,
+    /// Where regular SecureChannels of a reconnecting initiator return what they couldn't send
+    pub(super) undelivered_address: Option<Address>,
+    recovery: Option<Recovery>,
+    next_recovery_id: u64,
+}
+
+impl<I: Identity, T: TrustPolicy> Reconnection<I, T> {
+    pub(super) fn new(
+        reconnect: Option<Reconnect<I, T>>,
+        undelivered_address: Option<Address>,
+    ) -> Self {
+        Self {
+            reconnect,
+            undelivered_address,
+            recovery: None,
+            next_recovery_id: 0,
+        }
+    }
+
+    pub(super) fn is_enabled(&self) -> bool {
+        self.reconnect.is_some()
+    }
+
+    /// Don't reconnect anymore, e.g. while stopping
+    pub(super) fn disable(&mut self) {
+        self.reconnect = None;
+    }
+
+    /// Hold a message of a local worker while the replaced regular SecureChannel still returns
+    /// what was sent before. Otherwise it's given back, to be sent right away
+    pub(super) fn hold(&mut self, message: QueuedMessage) -> Option<QueuedMessage> {
+        match &mut self.recovery {
+            Some(recovery) => {
+                recovery.held.push_back(message);
+                None
+            }
+            None => Some(message),
+        }
+    }
+}
+
+impl<I: Identity, T: TrustPolicy> SecureChannelWorker<I, T> {
+    /// Re-run the handshake over the original route, keeping our addresses
+    pub(super) async fn reconnect(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+    ) -> Result<Initialized> {
+        let mut reconnect = match self.reconnection.reconnect.take() {
+            Some(reconnect) => reconnect,
+            None => return Err(EntityError::SecureChannelReconnectFailed.into()),
+        };
+
+        let mut backoff = reconnect.options.backoff();
+        let mut res = Err(EntityError::SecureChannelReconnectFailed.into());
+        for attempt in 1..=reconnect.options.max_attempts() {
+            ctx.sleep(backoff).await;
+
+            res = match timeout(
+                reconnect.timeout,
+                self.reconnect_attempt(ctx, &mut reconnect),
+            )
+            .await
+            {
+                Ok(res) => res,
+                Err(_) => Err(EntityError::SecureChannelTimeout.into()),
+            };
+
+            match &res {
+                Ok(_) => {
+                    info!(
+                        "Reconnected ProfileSecureChannel at local: {}, remote: {}",
+                        &self.self_local_address, &self.self_remote_address
+                    );
+                    break;
+                }
+                Err(err) => warn!(
+                    "{} reconnecting ProfileSecureChannel at local: {}, attempt {}",
+                    err, self.self_local_address, attempt
+                ),
+            }
+
+            backoff *= 2;
+        }
+
+        self.reconnection.reconnect = Some(reconnect);
+
+        res
+    }
+
+    async fn reconnect_attempt(
+        &self,
+        ctx: &Context,
+        reconnect: &mut Reconnect<I, T>,
+    ) -> Result<Initialized> {
+        // The responder talks to a temporary address, since this worker is busy
+        let temp_ctx = ctx.new_context(Address::random(0)).await?;
+        let mut responder_ctx = ctx.new_context(Address::random(0)).await?;
+
+        let channel = (reconnect.channel_factory)(temp_ctx, responder_ctx.address()).await?;
+
+        let msg = responder_ctx.receive_block::<Any>().await?.take();
+        let return_route = msg.return_route();
+
+        // Ensure message came from dedicated SecureChannel
+        if return_route.next()? != &channel.address() {
+            return Err(EntityError::UnknownChannelMsgDestination.into());
+        }
+
+        let mut initialized = self
+            .authenticate_responder(
+                ctx,
+                &mut reconnect.identity,
+                &reconnect.trust_policy,
+                &channel,
+                return_route,
+                self.decode_handshake_message(msg.payload())?,
+            )
+            .await?;
+        initialized.their_last_handshake_message = Some(handshake_digest(msg.payload()));
+
+        Ok(initialized)
+    }
+
+    /// Continue over the regular SecureChannel of a reconnect
+    pub(super) async fn reconnected(
+        &mut self,
+        ctx: &Context,
+        state: &mut Initialized,
+        initialized: Initialized,
+    ) -> Result<()> {
+        *state = initialized;
+        self.state = Some(State::Initialized(state.clone()));
+        self.enable_acks(ctx, state).await?;
+        // Pings sent over the old channel are never answered
+        if let Some(keepalive) = &mut self.keepalive {
+            keepalive.forget_sent();
+        }
+
+        Ok(())
+    }
+
+    /// Handle a message the regular SecureChannel couldn't send to the other side.
+    /// The first one of the current channel starts a reconnect
+    pub(super) async fn handle_undelivered(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        msg: Routed<<Self as Worker>::Message>,
+        mut state: Initialized,
+    ) -> Result<()> {
+        let from = msg.return_route().next()?.clone();
+        let undelivered = UndeliveredMessage::decode(msg.payload())?;
+
+        if from == state.local_secure_channel_address {
+            warn!(
+                "Transport of ProfileSecureChannel at local: {} failed, reconnecting",
+                self.self_local_address
+            );
+            if let Err(err) = self.start_recovery(ctx, &mut state).await {
+                // Give up, so that following sends to this channel fail
+                self.state = None;
+                let _ = ctx.stop_worker(state.local_secure_channel_address).await;
+                ctx.stop_worker(self.self_local_address.clone()).await?;
+
+                return Err(err);
+            }
+        }
+
+        let res = self
+            .send_undelivered(ctx, &mut state, from, undelivered)
+            .await;
+
+        self.state = Some(State::Initialized(state));
+
+        res
+    }
+
+    /// Send a message returned by the replaced regular SecureChannel over the current one
+    async fn send_undelivered(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        state: &mut Initialized,
+        from: Address,
+        undelivered: UndeliveredMessage,
+    ) -> Result<()> {
+        let recovery = match &self.reconnection.recovery {
+            Some(recovery) if recovery.old_channel == from => recovery,
+            _ => {
+                debug!(
+                    "ProfileSecureChannel at local: {} dropped message returned by stopped SecureChannel",
+                    &self.self_local_address
+                );
+                return Ok(());
+            }
+        };
+        if recovery.compression != state.compression {
+            warn!(
+                "ProfileSecureChannel at local: {} reconnected with other compression, dropping returned message",
+                &self.self_local_address
+            );
+            return Ok(());
+        }
+
+        // Past the addresses of the remote profile channel and ours, see to_peer_message
+        let (mut onward_route, mut return_route, payload) = undelivered.into_parts();
+        let _ = onward_route.step()?;
+        let _ = return_route.step()?;
+
+        if onward_route.next().is_err() {
+            // Addressed to the channel on the other side
+            match EntityChannelMessage::decode(&payload)? {
+                EntityChannelMessage::Batch(_) => {}
+                // Sent last over the old channel, so nothing else comes back
+                EntityChannelMessage::Close => {
+                    let id = recovery.id;
+                    return self.finish_recovery(ctx, state, Some(id)).await;
+                }
+                // Acks and pings of the old channel mean nothing to the new one
+                _ => return Ok(()),
+            }
+        }
+
+        ctx.forward(self.to_peer_message(state, onward_route, return_route, payload))
+            .await
+    }
+
+    /// Reconnect, and hold new messages until the old regular SecureChannel returned those
+    /// sent to it before
+    async fn start_recovery(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        state: &mut Initialized,
+    ) -> Result<()> {
+        // The channel replaced before won't return anything anymore
+        self.finish_recovery(ctx, state, None).await?;
+
+        let old = state.clone();
+        let initialized = self.reconnect(ctx).await?;
+        self.reconnected(ctx, state, initialized).await?;
+
+        // Returned once the old channel returned everything sent before
+        ctx.send_from_address(
+            route![
+                old.local_secure_channel_address.clone(),
+                old.remote_profile_secure_channel_address
+            ],
+            EntityChannelMessage::Close,
+            self.self_remote_address.clone(),
+        )
+        .await?;
+
+        let id = self.reconnection.next_recovery_id;
+        self.reconnection.next_recovery_id = self.reconnection.next_recovery_id.wrapping_add(1);
+        // Unless the transport came back and delivered it, then give up waiting after a while
+        let timeout = self
+            .reconnection
+            .reconnect
+            .as_ref()
+            .map(|reconnect| reconnect.timeout)
+            .unwrap_or_default();
+        self.schedule(ctx, timeout, EntityChannelMessage::FinishRecovery(id))
+            .await?;
+
+        self.reconnection.recovery = Some(Recovery {
+            id,
+            old_channel: old.local_secure_channel_address,
+            compression: old.compression,
+            held: VecDeque::new(),
+        });
+
+        Ok(())
+    }
+
+    /// Stop the replaced regular SecureChannel and send the messages held meanwhile,
+    /// unless recovery `id` is over already
+    pub(super) async fn finish_recovery(
+        &mut self,
+        ctx: &mut <Self as Worker>::Context,
+        state: &mut Initialized,
+        id: Option<u64>,
+    ) -> Result<()> {
+        let recovery = match self.reconnection.recovery.take() {
+            Some(recovery) if id.map_or(true, |id| id == recovery.id) => recovery,
+            recovery => {
+                self.reconnection.recovery = recovery;
+                return Ok(());
+            }
+        };
+
+        let _ = ctx.stop_worker(recovery.old_channel).await;
+
+        for message in recovery.held {
+            self.send_message(ctx, state, message).await?;
+        }
+
+        Ok(())
+    }
+}
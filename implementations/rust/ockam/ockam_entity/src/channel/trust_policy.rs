@@ -1,38 +1,271 @@
-use crate::ProfileIdentifier;
+use crate::{AuthorityCredential, EntityError, ProfileIdentifier};
+use ockam_core::compat::{collections::BTreeMap, string::String};
+use ockam_core::vault::PublicKey;
 use ockam_core::{async_trait, compat::boxed::Box};
-use ockam_core::{AsyncTryClone, Result};
+use ockam_core::{Address, AsyncTryClone, Result, Route};
 use serde::{Deserialize, Serialize};
 
 mod trust_identifier_policy;
 pub use trust_identifier_policy::*;
+mod trust_multi_identifier_policy;
+pub use trust_multi_identifier_policy::*;
 mod all_trust_policy;
 pub use all_trust_policy::*;
 mod any_trust_policy;
 pub use any_trust_policy::*;
 mod trust_everyone_policy;
 pub use trust_everyone_policy::*;
+mod trust_public_key_policy;
+pub use trust_public_key_policy::*;
+mod trust_worker_policy;
+pub use trust_worker_policy::*;
+mod trust_observing_policy;
+pub use trust_observing_policy::*;
+mod trust_credential_policy;
+pub use trust_credential_policy::*;
+mod trust_attribute_policy;
+pub use trust_attribute_policy::*;
+#[cfg(feature = "std")]
+mod trust_until_policy;
+#[cfg(feature = "std")]
+pub use trust_until_policy::*;
+#[cfg(test)]
+mod counting_trust_policy;
+#[cfg(test)]
+pub(crate) use counting_trust_policy::*;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct SecureChannelTrustInfo {
     their_profile_id: ProfileIdentifier,
-    // TODO: credentials:
+    their_public_key: Option<PublicKey>,
+    their_credential: Option<AuthorityCredential>,
+    their_profile_attributes: BTreeMap<String, String>,
+    inherited_attributes: BTreeMap<String, String>,
+    our_profile_id: Option<ProfileIdentifier>,
+    listener: Option<Address>,
+    service: Option<String>,
+    their_route: Option<Route>,
 }
 
 impl SecureChannelTrustInfo {
     pub fn their_profile_id(&self) -> &ProfileIdentifier {
         &self.their_profile_id
     }
+
+    /// Profile public key the peer proved possession of during the handshake
+    pub fn their_public_key(&self) -> Option<&PublicKey> {
+        self.their_public_key.as_ref()
+    }
+
+    /// Credential the peer presented during the handshake. Not verified,
+    /// see [`TrustCredentialPolicy`]
+    pub fn their_credential(&self) -> Option<&AuthorityCredential> {
+        self.their_credential.as_ref()
+    }
+
+    /// Attributes the peer advertised during the handshake, see [`Identity::set_attribute`](crate::Identity::set_attribute).
+    /// Covered by the proof of its profile key, but set by the peer itself
+    pub fn their_profile_attributes(&self) -> &BTreeMap<String, String> {
+        &self.their_profile_attributes
+    }
+
+    /// Attribute the peer advertised with given key
+    pub fn their_profile_attribute(&self, key: &str) -> Option<&str> {
+        self.their_profile_attributes.get(key).map(String::as_str)
+    }
+
+    /// Attributes of the peer verified on the channel this one inherits trust from, see
+    /// [`SecureChannelOptions::with_inherited_trust`](crate::SecureChannelOptions::with_inherited_trust).
+    /// The channel keeps them along with the ones the trust policy verifies
+    pub fn inherited_attributes(&self) -> &BTreeMap<String, String> {
+        &self.inherited_attributes
+    }
+
+    /// Profile of our side of the channel
+    pub fn our_profile_id(&self) -> Option<&ProfileIdentifier> {
+        self.our_profile_id.as_ref()
+    }
+
+    /// Listener that accepted the channel, on the responder side
+    pub fn listener(&self) -> Option<&Address> {
+        self.listener.as_ref()
+    }
+
+    /// Service the initiator asked for with [`SecureChannelOptions::with_service`](crate::SecureChannelOptions::with_service)
+    pub fn service(&self) -> Option<&str> {
+        self.service.as_deref()
+    }
+
+    /// Transport route towards the peer, as in [`EntitySecureChannelLocalInfo::their_route`](crate::EntitySecureChannelLocalInfo::their_route)
+    pub fn their_route(&self) -> Option<&Route> {
+        self.their_route.as_ref()
+    }
 }
 
 impl SecureChannelTrustInfo {
     pub fn new(their_profile_id: ProfileIdentifier) -> Self {
-        Self { their_profile_id }
+        Self::new_with_public_key(their_profile_id, None)
+    }
+
+    pub fn new_with_public_key(
+        their_profile_id: ProfileIdentifier,
+        their_public_key: Option<PublicKey>,
+    ) -> Self {
+        Self {
+            their_profile_id,
+            their_public_key,
+            their_credential: None,
+            their_profile_attributes: BTreeMap::new(),
+            inherited_attributes: BTreeMap::new(),
+            our_profile_id: None,
+            listener: None,
+            service: None,
+            their_route: None,
+        }
+    }
+
+    pub fn with_credential(mut self, their_credential: Option<AuthorityCredential>) -> Self {
+        self.their_credential = their_credential;
+        self
+    }
+
+    pub fn with_profile_attributes(
+        mut self,
+        their_profile_attributes: BTreeMap<String, String>,
+    ) -> Self {
+        self.their_profile_attributes = their_profile_attributes;
+        self
+    }
+
+    pub fn with_inherited_attributes(
+        mut self,
+        inherited_attributes: BTreeMap<String, String>,
+    ) -> Self {
+        self.inherited_attributes = inherited_attributes;
+        self
+    }
+
+    pub fn with_our_profile_id(mut self, our_profile_id: ProfileIdentifier) -> Self {
+        self.our_profile_id = Some(our_profile_id);
+        self
+    }
+
+    pub fn with_listener(mut self, listener: Address, service: Option<String>) -> Self {
+        self.listener = Some(listener);
+        self.service = service;
+        self
+    }
+
+    pub fn with_their_route(mut self, their_route: Route) -> Self {
+        self.their_route = Some(their_route);
+        self
+    }
+}
+
+/// Outcome of a [`TrustPolicy`], which can say why the peer was rejected
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustDecision {
+    trusted: bool,
+    reason: Option<String>,
+    disclose: bool,
+    verified_attributes: BTreeMap<String, String>,
+}
+
+impl TrustDecision {
+    pub fn trusted() -> Self {
+        Self {
+            trusted: true,
+            reason: None,
+            disclose: false,
+            verified_attributes: BTreeMap::new(),
+        }
+    }
+
+    pub fn rejected() -> Self {
+        Self {
+            trusted: false,
+            reason: None,
+            disclose: false,
+            verified_attributes: BTreeMap::new(),
+        }
+    }
+
+    /// Reject for given reason. The reason is in the error of our side of the handshake,
+    /// but only sent to the peer if [`TrustDecision::disclosed`]
+    pub fn rejected_because<S: Into<String>>(reason: S) -> Self {
+        Self {
+            trusted: false,
+            reason: Some(reason.into()),
+            disclose: false,
+            verified_attributes: BTreeMap::new(),
+        }
+    }
+
+    /// Send the reason to the peer, in the error its handshake fails with.
+    /// Only for reasons that tell a peer nothing it shouldn't know, e.g. an expired credential
+    pub fn disclosed(mut self) -> Self {
+        self.disclose = true;
+        self
+    }
+
+    pub fn is_trusted(&self) -> bool {
+        self.trusted
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    pub fn is_disclosed(&self) -> bool {
+        self.disclose
+    }
+
+    /// Attributes of the peer the policy verified, e.g. those of the credential it checked.
+    /// The channel reports them in [`EntitySecureChannelLocalInfo::their_attributes`](crate::EntitySecureChannelLocalInfo::their_attributes)
+    pub fn with_verified_attributes(
+        mut self,
+        verified_attributes: BTreeMap<String, String>,
+    ) -> Self {
+        self.verified_attributes = verified_attributes;
+        self
+    }
+
+    pub fn verified_attributes(&self) -> &BTreeMap<String, String> {
+        &self.verified_attributes
+    }
+
+    /// Error the handshake fails with on rejection. The one for the peer only has the
+    /// reason if it's disclosed
+    pub(crate) fn to_error(&self, for_peer: bool) -> ockam_core::Error {
+        let err: ockam_core::Error = EntityError::SecureChannelTrustCheckFailed.into();
+        match &self.reason {
+            Some(reason) if self.disclose || !for_peer => err.with_reason(reason.clone()),
+            _ => err,
+        }
+    }
+}
+
+impl From<bool> for TrustDecision {
+    fn from(trusted: bool) -> Self {
+        if trusted {
+            Self::trusted()
+        } else {
+            Self::rejected()
+        }
     }
 }
 
 #[async_trait]
 pub trait TrustPolicy: AsyncTryClone + Send + Sync + 'static {
+    /// Decide whether to trust the other side of a channel. Besides the peer, `trust_info`
+    /// describes our side: our profile, the listener and the transport route
     async fn check(&self, trust_info: &SecureChannelTrustInfo) -> Result<bool>;
+
+    /// Like [`TrustPolicy::check`], but can tell why the peer was rejected.
+    /// Secure channels ask this one, policies only need it for giving reasons
+    async fn decide(&self, trust_info: &SecureChannelTrustInfo) -> Result<TrustDecision> {
+        Ok(self.check(trust_info).await?.into())
+    }
 }
 
 pub trait ConjunctionTrustPolicy: TrustPolicy {
@@ -50,3 +283,157 @@ pub trait DisjunctionTrustPolicy: TrustPolicy {
 }
 
 impl<T> DisjunctionTrustPolicy for T where T: TrustPolicy {}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Entity, EntityError, Identity, SecureChannelOptions, SecureChannelTrustInfo, TrustDecision,
+        TrustEveryonePolicy, TrustPolicy,
+    };
+    use ockam_core::compat::{sync::Arc, vec::Vec};
+    use ockam_core::{async_trait, compat::boxed::Box};
+    use ockam_core::{route, Address, Result};
+    use ockam_node::Context;
+    use ockam_vault_sync_core::Vault;
+    use std::sync::Mutex;
+
+    /// Trusts everyone but ourselves, remembering what it was asked
+    #[derive(Clone, Default)]
+    struct TrustOthersPolicy {
+        checked: Arc<Mutex<Vec<SecureChannelTrustInfo>>>,
+    }
+
+    #[async_trait]
+    impl TrustPolicy for TrustOthersPolicy {
+        async fn check(&self, trust_info: &SecureChannelTrustInfo) -> Result<bool> {
+            self.checked.lock().unwrap().push(trust_info.clone());
+            Ok(trust_info.our_profile_id() != Some(trust_info.their_profile_id()))
+        }
+    }
+
+    #[ockam_macros::test]
+    async fn test_trust_info_our_side(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let alice_id = alice.identifier().await?;
+        let bob_id = bob.identifier().await?;
+
+        let listener_policy = TrustOthersPolicy::default();
+        let service_policy = TrustOthersPolicy::default();
+        bob.create_secure_channel_listener("bob_listener", listener_policy.clone())
+            .await?;
+        bob.add_secure_channel_service("bob_listener", "printer", service_policy.clone())
+            .await?;
+
+        let initiator_policy = TrustOthersPolicy::default();
+        alice
+            .create_secure_channel(route!["bob_listener"], initiator_policy.clone())
+            .await?;
+        alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_service("printer"),
+            )
+            .await?;
+
+        // Bob refuses a channel to himself
+        assert!(bob
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .is_err());
+
+        let checked = initiator_policy.checked.lock().unwrap().clone();
+        assert_eq!(checked.len(), 1);
+        assert_eq!(checked[0].our_profile_id(), Some(&alice_id));
+        assert_eq!(checked[0].their_profile_id(), &bob_id);
+        assert_eq!(checked[0].their_route(), Some(&route!["bob_listener"]));
+        assert!(checked[0].listener().is_none());
+
+        let checked = listener_policy.checked.lock().unwrap().clone();
+        assert_eq!(checked.len(), 2);
+        assert!(checked
+            .iter()
+            .all(|info| info.our_profile_id() == Some(&bob_id)
+                && info.listener() == Some(&Address::from("bob_listener"))
+                && info.service().is_none()));
+        assert_eq!(checked[0].their_profile_id(), &alice_id);
+        assert_eq!(checked[1].their_profile_id(), &bob_id);
+
+        let checked = service_policy.checked.lock().unwrap().clone();
+        assert_eq!(checked.len(), 1);
+        assert_eq!(checked[0].service(), Some("printer"));
+        assert_eq!(checked[0].their_profile_id(), &alice_id);
+
+        ctx.stop().await
+    }
+
+    #[derive(Clone)]
+    struct RejectingPolicy(TrustDecision);
+
+    #[async_trait]
+    impl TrustPolicy for RejectingPolicy {
+        async fn check(&self, _trust_info: &SecureChannelTrustInfo) -> Result<bool> {
+            Ok(false)
+        }
+
+        async fn decide(&self, _trust_info: &SecureChannelTrustInfo) -> Result<TrustDecision> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[ockam_macros::test]
+    async fn test_rejection_reason(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let code = ockam_core::Error::from(EntityError::SecureChannelTrustCheckFailed).code();
+
+        bob.create_secure_channel_listener(
+            "disclosing_listener",
+            RejectingPolicy(TrustDecision::rejected_because("credential expired").disclosed()),
+        )
+        .await?;
+        bob.create_secure_channel_listener(
+            "secretive_listener",
+            RejectingPolicy(TrustDecision::rejected_because("on the deny list")),
+        )
+        .await?;
+
+        let err = alice
+            .create_secure_channel(route!["disclosing_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), code);
+        assert_eq!(err.reason(), Some("credential expired"));
+
+        // The peer only learns that it was rejected
+        let err = alice
+            .create_secure_channel(route!["secretive_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), code);
+        assert!(err.reason().is_none());
+
+        // Our own rejections keep their reason
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+        let err = alice
+            .create_secure_channel(
+                route!["bob_listener"],
+                RejectingPolicy(TrustDecision::rejected_because("unknown issuer")),
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), code);
+        assert_eq!(err.reason(), Some("unknown issuer"));
+
+        ctx.stop().await
+    }
+}
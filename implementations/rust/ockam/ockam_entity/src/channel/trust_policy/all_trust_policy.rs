@@ -1,4 +1,4 @@
-use crate::{SecureChannelTrustInfo, TrustPolicy};
+use crate::{SecureChannelTrustInfo, TrustDecision, TrustPolicy};
 use ockam_core::{async_trait, compat::boxed::Box};
 use ockam_core::{AsyncTryClone, Result};
 
@@ -9,6 +9,9 @@ pub struct AllTrustPolicy<F: TrustPolicy, S: TrustPolicy> {
     second: S,
 }
 
+/// Alias matching the name of the [`ConjunctionTrustPolicy::and`](crate::ConjunctionTrustPolicy::and) combinator
+pub type AndTrustPolicy<F, S> = AllTrustPolicy<F, S>;
+
 impl<F: TrustPolicy, S: TrustPolicy> AllTrustPolicy<F, S> {
     pub fn new(first: F, second: S) -> Self {
         AllTrustPolicy { first, second }
@@ -18,13 +21,32 @@ impl<F: TrustPolicy, S: TrustPolicy> AllTrustPolicy<F, S> {
 #[async_trait]
 impl<F: TrustPolicy, S: TrustPolicy> TrustPolicy for AllTrustPolicy<F, S> {
     async fn check(&self, trust_info: &SecureChannelTrustInfo) -> Result<bool> {
-        Ok(self.first.check(trust_info).await? && self.second.check(trust_info).await?)
+        Ok(self.decide(trust_info).await?.is_trusted())
+    }
+
+    /// Gives the reason of the first policy that rejects, or the attributes both verified
+    async fn decide(&self, trust_info: &SecureChannelTrustInfo) -> Result<TrustDecision> {
+        let first = self.first.decide(trust_info).await?;
+        if !first.is_trusted() {
+            return Ok(first);
+        }
+        let second = self.second.decide(trust_info).await?;
+        if !second.is_trusted() {
+            return Ok(second);
+        }
+
+        let mut verified_attributes = first.verified_attributes().clone();
+        verified_attributes.extend(second.verified_attributes().clone());
+        Ok(second.with_verified_attributes(verified_attributes))
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{ConjunctionTrustPolicy, ProfileIdentifier, SecureChannelTrustInfo, TrustPolicy};
+    use crate::{
+        ConjunctionTrustPolicy, CountingTrustPolicy, ProfileIdentifier, SecureChannelTrustInfo,
+        TrustPolicy,
+    };
     use ockam_core::Result;
     use ockam_core::{async_trait, compat::boxed::Box};
 
@@ -64,4 +86,22 @@ mod test {
             .await
             .unwrap());
     }
+
+    #[tokio::test]
+    async fn test_short_circuit() {
+        let id = ProfileIdentifier::random();
+        let trust_info = SecureChannelTrustInfo::new(id);
+
+        let first = CountingTrustPolicy::new(false);
+        let second = CountingTrustPolicy::new(true);
+
+        assert!(!first
+            .clone()
+            .and(second.clone())
+            .check(&trust_info)
+            .await
+            .unwrap());
+        assert_eq!(first.count(), 1);
+        assert_eq!(second.count(), 0);
+    }
 }
@@ -1,5 +1,6 @@
-use crate::{SecureChannelTrustInfo, TrustPolicy};
-use ockam_core::{async_trait, compat::boxed::Box};
+use crate::{SecureChannelTrustInfo, TrustDecision, TrustPolicy};
+use ockam_core::async_trait;
+use ockam_core::compat::{boxed::Box, format};
 use ockam_core::{AsyncTryClone, Result};
 
 #[derive(AsyncTryClone)]
@@ -9,6 +10,9 @@ pub struct AnyTrustPolicy<F: TrustPolicy, S: TrustPolicy> {
     second: S,
 }
 
+/// Alias matching the name of the [`DisjunctionTrustPolicy::or`](crate::DisjunctionTrustPolicy::or) combinator
+pub type OrTrustPolicy<F, S> = AnyTrustPolicy<F, S>;
+
 impl<F: TrustPolicy, S: TrustPolicy> AnyTrustPolicy<F, S> {
     pub fn new(first: F, second: S) -> Self {
         AnyTrustPolicy { first, second }
@@ -18,13 +22,42 @@ impl<F: TrustPolicy, S: TrustPolicy> AnyTrustPolicy<F, S> {
 #[async_trait]
 impl<F: TrustPolicy, S: TrustPolicy> TrustPolicy for AnyTrustPolicy<F, S> {
     async fn check(&self, trust_info: &SecureChannelTrustInfo) -> Result<bool> {
-        Ok(self.first.check(trust_info).await? || self.second.check(trust_info).await?)
+        Ok(self.decide(trust_info).await?.is_trusted())
+    }
+
+    /// Gives the reasons of both policies, disclosed unless one of them wasn't
+    async fn decide(&self, trust_info: &SecureChannelTrustInfo) -> Result<TrustDecision> {
+        let first = self.first.decide(trust_info).await?;
+        if first.is_trusted() {
+            return Ok(first);
+        }
+        let second = self.second.decide(trust_info).await?;
+        if second.is_trusted() {
+            return Ok(second);
+        }
+
+        let decision = match (first.reason(), second.reason()) {
+            (Some(first), Some(second)) => {
+                TrustDecision::rejected_because(format!("{}, {}", first, second))
+            }
+            (Some(reason), None) | (None, Some(reason)) => TrustDecision::rejected_because(reason),
+            (None, None) => TrustDecision::rejected(),
+        };
+        let disclosed = |d: &TrustDecision| d.reason().is_none() || d.is_disclosed();
+        Ok(if disclosed(&first) && disclosed(&second) {
+            decision.disclosed()
+        } else {
+            decision
+        })
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{DisjunctionTrustPolicy, ProfileIdentifier, SecureChannelTrustInfo, TrustPolicy};
+    use crate::{
+        CountingTrustPolicy, DisjunctionTrustPolicy, ProfileIdentifier, SecureChannelTrustInfo,
+        TrustPolicy,
+    };
     use ockam_core::Result;
     use ockam_core::{async_trait, compat::boxed::Box};
 
@@ -64,4 +97,22 @@ mod test {
             .await
             .unwrap());
     }
+
+    #[tokio::test]
+    async fn test_short_circuit() {
+        let id = ProfileIdentifier::random();
+        let trust_info = SecureChannelTrustInfo::new(id);
+
+        let first = CountingTrustPolicy::new(true);
+        let second = CountingTrustPolicy::new(true);
+
+        assert!(first
+            .clone()
+            .or(second.clone())
+            .check(&trust_info)
+            .await
+            .unwrap());
+        assert_eq!(first.count(), 1);
+        assert_eq!(second.count(), 0);
+    }
 }
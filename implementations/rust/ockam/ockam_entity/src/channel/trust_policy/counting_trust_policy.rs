@@ -0,0 +1,34 @@
+use crate::{SecureChannelTrustInfo, TrustPolicy};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use ockam_core::compat::sync::Arc;
+use ockam_core::Result;
+use ockam_core::{async_trait, compat::boxed::Box};
+
+/// Test policy with a fixed answer, which counts how often it was checked.
+/// Clones share the count
+#[derive(Clone)]
+pub(crate) struct CountingTrustPolicy {
+    res: bool,
+    count: Arc<AtomicUsize>,
+}
+
+impl CountingTrustPolicy {
+    pub(crate) fn new(res: bool) -> Self {
+        Self {
+            res,
+            count: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl TrustPolicy for CountingTrustPolicy {
+    async fn check(&self, _trust_info: &SecureChannelTrustInfo) -> Result<bool> {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        Ok(self.res)
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{SecureChannelTrustInfo, TrustPolicy};
+use ockam_core::compat::{string::String, sync::Arc, vec::Vec};
+use ockam_core::Result;
+use ockam_core::{async_trait, compat::boxed::Box};
+
+type AttributePredicate = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Trust policy that allows peers whose advertised profile attributes, see
+/// [`SecureChannelTrustInfo::their_profile_attributes`], satisfy every requirement.
+/// Peers set their attributes themselves, so this says what a peer claims to be, not who
+/// it is. Combine it with an identifier or credential policy to restrict that
+#[derive(Clone, Default)]
+pub struct TrustAttributePolicy {
+    required: Vec<(String, AttributePredicate)>,
+}
+
+impl TrustAttributePolicy {
+    /// Policy without requirements, which allows everyone
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require the peer to advertise an attribute of given value
+    pub fn with_required_attribute(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        let value = value.into();
+        self.with_attribute_matching(key, move |advertised| advertised == value)
+    }
+
+    /// Require the peer to advertise an attribute with given key, of any value
+    pub fn with_attribute_present(self, key: impl Into<String>) -> Self {
+        self.with_attribute_matching(key, |_| true)
+    }
+
+    /// Require the peer to advertise an attribute whose value satisfies `predicate`
+    pub fn with_attribute_matching(
+        mut self,
+        key: impl Into<String>,
+        predicate: impl Fn(&str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.required.push((key.into(), Arc::new(predicate)));
+        self
+    }
+}
+
+#[async_trait]
+impl TrustPolicy for TrustAttributePolicy {
+    async fn check(&self, trust_info: &SecureChannelTrustInfo) -> Result<bool> {
+        Ok(self.required.iter().all(|(key, predicate)| {
+            trust_info
+                .their_profile_attribute(key)
+                .map_or(false, |value| predicate(value))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        Entity, EntityError, EntitySecureChannelLocalInfo, Identity, ProfileIdentifier,
+        TrustEveryonePolicy,
+    };
+    use ockam_core::compat::{collections::BTreeMap, string::ToString};
+    use ockam_core::route;
+    use ockam_node::Context;
+    use ockam_vault_sync_core::Vault;
+
+    fn trust_info(attributes: &[(&str, &str)]) -> SecureChannelTrustInfo {
+        let attributes: BTreeMap<String, String> = attributes
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        SecureChannelTrustInfo::new(ProfileIdentifier::random()).with_profile_attributes(attributes)
+    }
+
+    #[tokio::test]
+    async fn test() {
+        let policy = TrustAttributePolicy::new()
+            .with_required_attribute("role", "sensor")
+            .with_attribute_present("site")
+            .with_attribute_matching("firmware", |version| version >= "2.0");
+
+        let allowed = trust_info(&[("role", "sensor"), ("site", "lab"), ("firmware", "2.1")]);
+        assert!(policy.check(&allowed).await.unwrap());
+
+        for rejected in [
+            trust_info(&[("role", "actuator"), ("site", "lab"), ("firmware", "2.1")]),
+            trust_info(&[("role", "sensor"), ("firmware", "2.1")]),
+            trust_info(&[("role", "sensor"), ("site", "lab"), ("firmware", "1.9")]),
+            trust_info(&[]),
+        ] {
+            assert!(!policy.check(&rejected).await.unwrap());
+        }
+
+        assert!(TrustAttributePolicy::new()
+            .check(&trust_info(&[]))
+            .await
+            .unwrap());
+    }
+
+    #[ockam_macros::test]
+    async fn test_advertised_attributes(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut sensor = Entity::create(ctx, &vault).await?;
+        let mut actuator = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        sensor
+            .set_attribute("role".to_string(), "sensor".to_string())
+            .await?;
+        actuator
+            .set_attribute("role".to_string(), "actuator".to_string())
+            .await?;
+        assert_eq!(
+            sensor.get_attribute("role").await?.as_deref(),
+            Some("sensor")
+        );
+        assert_eq!(sensor.get_attribute("site").await?, None);
+
+        bob.create_secure_channel_listener(
+            "bob_listener",
+            TrustAttributePolicy::new().with_required_attribute("role", "sensor"),
+        )
+        .await?;
+
+        let channel = sensor
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        ctx.send(route![channel, ctx.address()], "Hello, Bob!".to_string())
+            .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let info = EntitySecureChannelLocalInfo::find_info(msg.local_message())?;
+        assert_eq!(info.their_profile_attribute("role"), Some("sensor"));
+        assert_eq!(msg.body(), "Hello, Bob!");
+
+        let err = actuator
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelTrustCheckFailed).code()
+        );
+
+        // Attributes are stored with the profile
+        let exported = sensor.export().await?;
+        let mut restored = Entity::create(ctx, &vault).await?;
+        let profile = restored.import_profile(&vault, &exported).await?;
+        assert_eq!(
+            profile.get_attribute("role").await?.as_deref(),
+            Some("sensor")
+        );
+
+        ctx.stop().await
+    }
+}
@@ -0,0 +1,220 @@
+use crate::authentication::Authentication;
+use crate::{
+    AuthorityCredential, ProfileVault, SecureChannelTrustInfo, TrustDecision, TrustPolicy,
+};
+use ockam_core::compat::{collections::BTreeMap, string::String};
+use ockam_core::vault::PublicKey;
+use ockam_core::{async_trait, compat::boxed::Box};
+use ockam_core::{AsyncTryClone, Result};
+use tracing::warn;
+
+/// Trust policy that allows peers presenting an [`AuthorityCredential`] issued to them
+/// by the authority with given public key, carrying all required attributes.
+/// Denies peers without a credential. The attributes of credentials it allows are reported in
+/// [`EntitySecureChannelLocalInfo::their_attributes`](crate::EntitySecureChannelLocalInfo::their_attributes)
+pub struct TrustCredentialPolicy<V: ProfileVault + Sync> {
+    vault: V,
+    authority_public_key: PublicKey,
+    required_attributes: BTreeMap<String, String>,
+}
+
+impl<V: ProfileVault + Sync> TrustCredentialPolicy<V> {
+    pub fn new(vault: V, authority_public_key: PublicKey) -> Self {
+        Self {
+            vault,
+            authority_public_key,
+            required_attributes: BTreeMap::new(),
+        }
+    }
+
+    /// Require the credential to carry an attribute of given value
+    pub fn with_required_attribute(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.required_attributes.insert(key.into(), value.into());
+        self
+    }
+
+    async fn verify(
+        &self,
+        trust_info: &SecureChannelTrustInfo,
+        credential: &AuthorityCredential,
+    ) -> Result<bool> {
+        // Otherwise anyone holding a credential could present it
+        if credential.subject() != trust_info.their_profile_id() {
+            return Ok(false);
+        }
+
+        let data = AuthorityCredential::signed_data(credential.subject(), credential.attributes())?;
+        let mut vault = self.vault.async_try_clone().await?;
+        if !Authentication::verify_proof(
+            &data,
+            &self.authority_public_key,
+            credential.proof(),
+            &mut vault,
+        )
+        .await?
+        {
+            return Ok(false);
+        }
+
+        Ok(self
+            .required_attributes
+            .iter()
+            .all(|(key, value)| credential.attribute(key) == Some(value.as_str())))
+    }
+}
+
+#[async_trait]
+impl<V: ProfileVault + Sync> AsyncTryClone for TrustCredentialPolicy<V> {
+    async fn async_try_clone(&self) -> Result<Self> {
+        Ok(Self {
+            vault: self.vault.async_try_clone().await?,
+            authority_public_key: self.authority_public_key.clone(),
+            required_attributes: self.required_attributes.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl<V: ProfileVault + Sync> TrustPolicy for TrustCredentialPolicy<V> {
+    async fn check(&self, trust_info: &SecureChannelTrustInfo) -> Result<bool> {
+        let credential = match trust_info.their_credential() {
+            Some(credential) => credential,
+            None => return Ok(false),
+        };
+
+        match self.verify(trust_info, credential).await {
+            Ok(res) => Ok(res),
+            Err(err) => {
+                warn!(
+                    "{} verifying credential of {}",
+                    err,
+                    trust_info.their_profile_id()
+                );
+                Ok(false)
+            }
+        }
+    }
+
+    async fn decide(&self, trust_info: &SecureChannelTrustInfo) -> Result<TrustDecision> {
+        match trust_info.their_credential() {
+            Some(credential) if self.check(trust_info).await? => {
+                Ok(TrustDecision::trusted()
+                    .with_verified_attributes(credential.attributes().clone()))
+            }
+            _ => Ok(TrustDecision::rejected()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        AuthorityCredential, Entity, EntityError, Identity, SecureChannelOptions,
+        TrustCredentialPolicy, TrustEveryonePolicy,
+    };
+    use ockam_core::compat::{
+        collections::BTreeMap,
+        string::{String, ToString},
+    };
+    use ockam_core::{route, Encodable, Result};
+    use ockam_node::Context;
+    use ockam_vault_sync_core::{Vault, VaultSync};
+
+    fn attributes(role: &str) -> BTreeMap<String, String> {
+        let mut attributes = BTreeMap::new();
+        attributes.insert("role".to_string(), role.to_string());
+        attributes
+    }
+
+    #[ockam_macros::test]
+    async fn test(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut authority = Entity::create(ctx, &vault).await?;
+        let mut unknown_authority = Entity::create(ctx, &vault).await?;
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let alice_id = alice.identifier().await?;
+        let bob_id = bob.identifier().await?;
+
+        let policy = TrustCredentialPolicy::new(
+            VaultSync::create_with_worker(ctx, &vault).await?,
+            authority.get_root_public_key().await?,
+        )
+        .with_required_attribute("role", "member");
+        bob.create_secure_channel_listener("bob_listener", policy)
+            .await?;
+
+        let trust_check_failed =
+            ockam_core::Error::from(EntityError::SecureChannelTrustCheckFailed).code();
+
+        let credential = authority
+            .issue_authority_credential(&alice_id, attributes("member"))
+            .await?;
+        let alice_channel = alice
+            .create_secure_channel_with_options(
+                route!["bob_listener"],
+                TrustEveryonePolicy,
+                SecureChannelOptions::new().with_credential(credential),
+            )
+            .await?;
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+
+        let rejected = [
+            // Signed by an unknown key
+            Some(
+                unknown_authority
+                    .issue_authority_credential(&alice_id, attributes("member"))
+                    .await?,
+            ),
+            // Missing the required attribute
+            Some(
+                authority
+                    .issue_authority_credential(&alice_id, attributes("guest"))
+                    .await?,
+            ),
+            // Issued to someone else
+            Some(
+                authority
+                    .issue_authority_credential(&bob_id, attributes("member"))
+                    .await?,
+            ),
+            // Signed by the authority, but not as a credential
+            Some(AuthorityCredential::new(
+                alice_id.clone(),
+                attributes("member"),
+                authority
+                    .create_auth_proof(&(&alice_id, &attributes("member")).encode()?)
+                    .await?,
+            )),
+            None,
+        ];
+        for credential in rejected {
+            let mut options = SecureChannelOptions::new();
+            if let Some(credential) = credential {
+                options = options.with_credential(credential);
+            }
+            let err = alice
+                .create_secure_channel_with_options(
+                    route!["bob_listener"],
+                    TrustEveryonePolicy,
+                    options,
+                )
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(err.code(), trust_check_failed);
+        }
+
+        ctx.stop().await
+    }
+}
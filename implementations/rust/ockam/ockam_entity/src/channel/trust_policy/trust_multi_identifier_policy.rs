@@ -0,0 +1,208 @@
+use crate::{ProfileIdentifier, SecureChannelTrustInfo, TrustPolicy};
+use ockam_core::compat::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    vec::Vec,
+};
+use ockam_core::Result;
+use ockam_core::{async_trait, compat::boxed::Box};
+
+/// Trust policies whose allowed identifiers can be listed and changed while a listener
+/// or channel uses them. Changes apply to the handshakes after them, channels already
+/// established stay open
+pub trait TrustedIdentifiers {
+    /// Identifiers currently allowed, in no particular order
+    fn trusted_identifiers(&self) -> Vec<ProfileIdentifier>;
+
+    /// Allow given identifier. Returns `false` if it was allowed already
+    fn trust_identifier(&self, their_profile_id: ProfileIdentifier) -> bool;
+
+    /// Stop allowing given identifier. Returns `false` if it wasn't allowed
+    fn distrust_identifier(&self, their_profile_id: &ProfileIdentifier) -> bool;
+}
+
+/// Trust policy that allows any peer whose identifier is in the given set.
+/// An empty set denies everyone.
+///
+/// Clones share the set, so a clone kept after handing the policy to a listener changes
+/// who the listener trusts, see [`TrustedIdentifiers`]
+#[derive(Clone)]
+pub struct TrustMultiIdentifierPolicy {
+    their_profile_ids: Arc<Mutex<HashSet<ProfileIdentifier>>>,
+}
+
+impl TrustMultiIdentifierPolicy {
+    /// Allow the peers with given identifiers. Duplicates are ignored
+    pub fn new(their_profile_ids: Vec<ProfileIdentifier>) -> Self {
+        Self {
+            their_profile_ids: Arc::new(Mutex::new(their_profile_ids.into_iter().collect())),
+        }
+    }
+
+    /// Add an identifier to the allowed set
+    pub fn add(self, their_profile_id: ProfileIdentifier) -> Self {
+        self.trust_identifier(their_profile_id);
+        self
+    }
+}
+
+impl TrustedIdentifiers for TrustMultiIdentifierPolicy {
+    fn trusted_identifiers(&self) -> Vec<ProfileIdentifier> {
+        self.their_profile_ids
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    fn trust_identifier(&self, their_profile_id: ProfileIdentifier) -> bool {
+        self.their_profile_ids
+            .lock()
+            .unwrap()
+            .insert(their_profile_id)
+    }
+
+    fn distrust_identifier(&self, their_profile_id: &ProfileIdentifier) -> bool {
+        self.their_profile_ids
+            .lock()
+            .unwrap()
+            .remove(their_profile_id)
+    }
+}
+
+#[async_trait]
+impl TrustPolicy for TrustMultiIdentifierPolicy {
+    async fn check(&self, trust_info: &SecureChannelTrustInfo) -> Result<bool> {
+        Ok(self
+            .their_profile_ids
+            .lock()
+            .unwrap()
+            .contains(trust_info.their_profile_id()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Entity, Identity, ProfileIdentifier, SecureChannelTrustInfo, TrustEveryonePolicy,
+        TrustMultiIdentifierPolicy, TrustPolicy, TrustedIdentifiers,
+    };
+    use ockam_core::compat::string::{String, ToString};
+    use ockam_core::{route, Result};
+    use ockam_node::Context;
+    use ockam_vault_sync_core::Vault;
+
+    #[tokio::test]
+    async fn test() {
+        let alice = ProfileIdentifier::random();
+        let bob = ProfileIdentifier::random();
+        let eve = ProfileIdentifier::random();
+
+        let policy = TrustMultiIdentifierPolicy::new(vec![alice.clone()]).add(bob.clone());
+
+        assert!(policy
+            .check(&SecureChannelTrustInfo::new(alice.clone()))
+            .await
+            .unwrap());
+        assert!(policy
+            .check(&SecureChannelTrustInfo::new(bob))
+            .await
+            .unwrap());
+        assert!(!policy
+            .check(&SecureChannelTrustInfo::new(eve))
+            .await
+            .unwrap());
+
+        let empty_policy = TrustMultiIdentifierPolicy::new(vec![]);
+        assert!(!empty_policy
+            .check(&SecureChannelTrustInfo::new(alice))
+            .await
+            .unwrap());
+    }
+
+    #[ockam_macros::test]
+    async fn test_channel(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let mut carol = Entity::create(ctx, &vault).await?;
+        let mut dave = Entity::create(ctx, &vault).await?;
+
+        let policy = TrustMultiIdentifierPolicy::new(vec![
+            alice.identifier().await?,
+            dave.identifier().await?,
+        ]);
+        bob.create_secure_channel_listener("bob_listener", policy)
+            .await?;
+
+        for allowed in [&mut alice, &mut dave] {
+            let channel = allowed
+                .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+                .await?;
+            ctx.send(route![channel, ctx.address()], "Hello, Bob!".to_string())
+                .await?;
+            assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+        }
+
+        // Carol isn't listed
+        assert!(carol
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .is_err());
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_change_running_listener(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let mut carol = Entity::create(ctx, &vault).await?;
+        let alice_id = alice.identifier().await?;
+        let carol_id = carol.identifier().await?;
+
+        let policy = TrustMultiIdentifierPolicy::new(vec![alice_id.clone()]);
+        bob.create_secure_channel_listener("bob_listener", policy.clone())
+            .await?;
+        assert_eq!(policy.trusted_identifiers(), vec![alice_id.clone()]);
+
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        assert!(carol
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .is_err());
+
+        assert!(policy.trust_identifier(carol_id.clone()));
+        assert!(!policy.trust_identifier(carol_id.clone()));
+        let mut trusted = policy.trusted_identifiers();
+        trusted.sort_by_key(|id| id.to_string());
+        let mut expected = vec![alice_id.clone(), carol_id];
+        expected.sort_by_key(|id| id.to_string());
+        assert_eq!(trusted, expected);
+
+        carol
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        // Alice can't create new channels, but keeps the one she has
+        assert!(policy.distrust_identifier(&alice_id));
+        assert!(alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .is_err());
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+
+        ctx.stop().await
+    }
+}
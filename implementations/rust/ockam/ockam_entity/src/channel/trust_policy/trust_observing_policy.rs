@@ -0,0 +1,171 @@
+use crate::{
+    ProfileIdentifier, SecureChannelTrustInfo, TrustDecision, TrustEveryonePolicy, TrustPolicy,
+};
+use core::future::Future;
+use core::time::Duration;
+use ockam_core::compat::sync::Arc;
+use ockam_core::{async_trait, compat::boxed::Box};
+use ockam_core::{AsyncTryClone, Result};
+use ockam_node::tokio::time::timeout;
+use tracing::warn;
+
+/// Default time the handshake waits for the observer
+pub const DEFAULT_TRUST_OBSERVER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Trust policy that reports every decision of another policy to an observer,
+/// which is called with the peer identifier and whether it was allowed.
+/// The observer can't change the decision, and is abandoned if it doesn't finish in time
+pub struct TrustObservingPolicy<T: TrustPolicy, F> {
+    inner: T,
+    observer: Arc<F>,
+    timeout: Duration,
+}
+
+impl<F, Fut> TrustObservingPolicy<TrustEveryonePolicy, F>
+where
+    F: Fn(ProfileIdentifier, bool) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    /// Allow everyone, reporting who connects
+    pub fn new(observer: F) -> Self {
+        Self::wrap(TrustEveryonePolicy, observer)
+    }
+}
+
+impl<T, F, Fut> TrustObservingPolicy<T, F>
+where
+    T: TrustPolicy,
+    F: Fn(ProfileIdentifier, bool) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    /// Report decisions of given policy
+    pub fn wrap(inner: T, observer: F) -> Self {
+        Self {
+            inner,
+            observer: Arc::new(observer),
+            timeout: DEFAULT_TRUST_OBSERVER_TIMEOUT,
+        }
+    }
+
+    /// Time the handshake waits for the observer
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[async_trait]
+impl<T, F> AsyncTryClone for TrustObservingPolicy<T, F>
+where
+    T: TrustPolicy,
+    F: Send + Sync + 'static,
+{
+    async fn async_try_clone(&self) -> Result<Self> {
+        Ok(Self {
+            inner: self.inner.async_try_clone().await?,
+            observer: self.observer.clone(),
+            timeout: self.timeout,
+        })
+    }
+}
+
+#[async_trait]
+impl<T, F, Fut> TrustPolicy for TrustObservingPolicy<T, F>
+where
+    T: TrustPolicy,
+    F: Fn(ProfileIdentifier, bool) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    async fn check(&self, trust_info: &SecureChannelTrustInfo) -> Result<bool> {
+        Ok(self.decide(trust_info).await?.is_trusted())
+    }
+
+    async fn decide(&self, trust_info: &SecureChannelTrustInfo) -> Result<TrustDecision> {
+        let res = self.inner.decide(trust_info).await;
+        let allowed = matches!(&res, Ok(decision) if decision.is_trusted());
+
+        let their_profile_id = trust_info.their_profile_id().clone();
+        if timeout(self.timeout, (self.observer)(their_profile_id, allowed))
+            .await
+            .is_err()
+        {
+            warn!(
+                "Trust observer timed out for {}",
+                trust_info.their_profile_id()
+            );
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        Entity, Identity, ProfileIdentifier, TrustEveryonePolicy, TrustIdentifierPolicy,
+        TrustObservingPolicy,
+    };
+    use core::time::Duration;
+    use ockam_core::compat::{sync::Arc, vec::Vec};
+    use ockam_core::{route, Result};
+    use ockam_node::Context;
+    use ockam_vault_sync_core::Vault;
+    use std::sync::Mutex;
+
+    #[ockam_macros::test]
+    async fn test(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let mut eve = Entity::create(ctx, &vault).await?;
+        let alice_id = alice.identifier().await?;
+        let eve_id = eve.identifier().await?;
+
+        let observed: Arc<Mutex<Vec<(ProfileIdentifier, bool)>>> = Default::default();
+        let observed_clone = observed.clone();
+        let policy = TrustObservingPolicy::wrap(
+            TrustIdentifierPolicy::new(alice_id.clone()),
+            move |id, allowed| {
+                observed_clone.lock().unwrap().push((id, allowed));
+                async {}
+            },
+        );
+        bob.create_secure_channel_listener("bob_listener", policy)
+            .await?;
+
+        alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        assert!(eve
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .is_err());
+
+        let observed = observed.lock().unwrap().clone();
+        assert_eq!(observed.len(), 2);
+        assert!(observed.contains(&(alice_id, true)));
+        assert!(observed.contains(&(eve_id, false)));
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_observer_timeout(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let policy = TrustObservingPolicy::new(|_, _| core::future::pending())
+            .with_timeout(Duration::from_millis(100));
+        bob.create_secure_channel_listener("bob_listener", policy)
+            .await?;
+
+        alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        ctx.stop().await
+    }
+}
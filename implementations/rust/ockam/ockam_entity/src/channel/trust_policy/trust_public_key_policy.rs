@@ -0,0 +1,67 @@
+use crate::{SecureChannelTrustInfo, TrustPolicy};
+use ockam_core::vault::PublicKey;
+use ockam_core::Result;
+use ockam_core::{async_trait, compat::boxed::Box};
+
+/// Trust policy that allows only the peer that proved possession of the given public key,
+/// regardless of its profile identifier
+#[derive(Clone)]
+pub struct TrustPublicKeyPolicy {
+    their_public_key: PublicKey,
+}
+
+impl TrustPublicKeyPolicy {
+    /// The key type is part of the [`PublicKey`] and must match as well
+    pub fn new(their_public_key: PublicKey) -> Self {
+        Self { their_public_key }
+    }
+}
+
+#[async_trait]
+impl TrustPolicy for TrustPublicKeyPolicy {
+    async fn check(&self, trust_info: &SecureChannelTrustInfo) -> Result<bool> {
+        Ok(trust_info.their_public_key() == Some(&self.their_public_key))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ProfileIdentifier, SecureChannelTrustInfo, TrustPolicy, TrustPublicKeyPolicy};
+    use ockam_core::vault::{PublicKey, SecretType};
+
+    #[tokio::test]
+    async fn test() {
+        let id = ProfileIdentifier::random();
+        let key = PublicKey::new(vec![1; 32], SecretType::Ed25519);
+        let other_key = PublicKey::new(vec![2; 32], SecretType::Ed25519);
+        let other_type_key = PublicKey::new(vec![1; 32], SecretType::X25519);
+
+        let policy = TrustPublicKeyPolicy::new(key.clone());
+
+        assert!(policy
+            .check(&SecureChannelTrustInfo::new_with_public_key(
+                id.clone(),
+                Some(key)
+            ))
+            .await
+            .unwrap());
+        assert!(!policy
+            .check(&SecureChannelTrustInfo::new_with_public_key(
+                id.clone(),
+                Some(other_key)
+            ))
+            .await
+            .unwrap());
+        assert!(!policy
+            .check(&SecureChannelTrustInfo::new_with_public_key(
+                id.clone(),
+                Some(other_type_key)
+            ))
+            .await
+            .unwrap());
+        assert!(!policy
+            .check(&SecureChannelTrustInfo::new(id))
+            .await
+            .unwrap());
+    }
+}
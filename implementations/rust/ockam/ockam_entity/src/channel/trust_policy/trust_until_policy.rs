@@ -0,0 +1,164 @@
+use crate::{SecureChannelTrustInfo, TrustDecision, TrustPolicy};
+use core::time::Duration;
+use ockam_core::{async_trait, compat::boxed::Box};
+use ockam_core::{AsyncTryClone, Result};
+use std::time::Instant;
+
+/// Reason handshakes are rejected for once a [`TrustUntilPolicy`] expired. Disclosed to the peer
+pub const TRUST_EXPIRED_REASON: &str = "trust expired";
+
+/// Trust policy that allows the peers another policy allows, until a deadline.
+/// After it every handshake is rejected with [`TRUST_EXPIRED_REASON`].
+/// Channels established before stay open
+pub struct TrustUntilPolicy<T: TrustPolicy> {
+    inner: T,
+    deadline: Instant,
+}
+
+impl<T: TrustPolicy> TrustUntilPolicy<T> {
+    pub fn new(inner: T, deadline: Instant) -> Self {
+        Self { inner, deadline }
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+}
+
+#[async_trait]
+impl<T: TrustPolicy> AsyncTryClone for TrustUntilPolicy<T> {
+    async fn async_try_clone(&self) -> Result<Self> {
+        Ok(Self {
+            inner: self.inner.async_try_clone().await?,
+            deadline: self.deadline,
+        })
+    }
+}
+
+#[async_trait]
+impl<T: TrustPolicy> TrustPolicy for TrustUntilPolicy<T> {
+    async fn check(&self, trust_info: &SecureChannelTrustInfo) -> Result<bool> {
+        Ok(self.decide(trust_info).await?.is_trusted())
+    }
+
+    async fn decide(&self, trust_info: &SecureChannelTrustInfo) -> Result<TrustDecision> {
+        if Instant::now() >= self.deadline {
+            return Ok(TrustDecision::rejected_because(TRUST_EXPIRED_REASON).disclosed());
+        }
+
+        self.inner.decide(trust_info).await
+    }
+}
+
+/// [`TrustUntilPolicy`] with the deadline given time after the policy is created
+pub struct TrustForDurationPolicy<T: TrustPolicy>(TrustUntilPolicy<T>);
+
+impl<T: TrustPolicy> TrustForDurationPolicy<T> {
+    pub fn new(inner: T, duration: Duration) -> Self {
+        Self(TrustUntilPolicy::new(inner, Instant::now() + duration))
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.0.deadline()
+    }
+}
+
+#[async_trait]
+impl<T: TrustPolicy> AsyncTryClone for TrustForDurationPolicy<T> {
+    async fn async_try_clone(&self) -> Result<Self> {
+        Ok(Self(self.0.async_try_clone().await?))
+    }
+}
+
+#[async_trait]
+impl<T: TrustPolicy> TrustPolicy for TrustForDurationPolicy<T> {
+    async fn check(&self, trust_info: &SecureChannelTrustInfo) -> Result<bool> {
+        self.0.check(trust_info).await
+    }
+
+    async fn decide(&self, trust_info: &SecureChannelTrustInfo) -> Result<TrustDecision> {
+        self.0.decide(trust_info).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        Entity, EntityError, Identity, ProfileIdentifier, TrustEveryonePolicy,
+        TrustIdentifierPolicy,
+    };
+    use ockam_core::compat::string::{String, ToString};
+    use ockam_core::route;
+    use ockam_node::Context;
+    use ockam_vault_sync_core::Vault;
+    use tokio::time::sleep;
+
+    #[tokio::test]
+    async fn test() {
+        let alice = ProfileIdentifier::random();
+        let eve = ProfileIdentifier::random();
+
+        let policy = TrustForDurationPolicy::new(
+            TrustIdentifierPolicy::new(alice.clone()),
+            Duration::from_secs(60),
+        );
+        assert!(policy
+            .check(&SecureChannelTrustInfo::new(alice.clone()))
+            .await
+            .unwrap());
+        // The inner policy still decides before the deadline
+        let decision = policy
+            .decide(&SecureChannelTrustInfo::new(eve))
+            .await
+            .unwrap();
+        assert!(!decision.is_trusted());
+        assert_eq!(decision.reason(), None);
+
+        let policy = TrustUntilPolicy::new(TrustEveryonePolicy, Instant::now());
+        let decision = policy
+            .decide(&SecureChannelTrustInfo::new(alice))
+            .await
+            .unwrap();
+        assert!(!decision.is_trusted());
+        assert_eq!(decision.reason(), Some(TRUST_EXPIRED_REASON));
+    }
+
+    #[ockam_macros::test]
+    async fn test_expiry(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+        bob.create_secure_channel_listener(
+            "bob_listener",
+            TrustUntilPolicy::new(TrustEveryonePolicy, deadline),
+        )
+        .await?;
+
+        let channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        sleep(deadline.saturating_duration_since(Instant::now())).await;
+        let err = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecureChannelTrustCheckFailed).code()
+        );
+        assert_eq!(err.reason(), Some(TRUST_EXPIRED_REASON));
+
+        // The channel established before the deadline still works
+        ctx.send(route![channel, ctx.address()], "Hello, Bob!".to_string())
+            .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+
+        ctx.stop().await
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    SecureChannelTrustInfo, TrustDecision, TrustPolicy, TrustPolicyRequest, TrustPolicyResponse,
+};
+use core::time::Duration;
+use ockam_core::{async_trait, compat::boxed::Box};
+use ockam_core::{Address, AsyncTryClone, Result, Route};
+use ockam_node::tokio::time::timeout;
+use ockam_node::Context;
+use tracing::warn;
+
+/// Default time to wait for the authorization worker to reply
+pub const DEFAULT_TRUST_WORKER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Trust policy that asks an authorization worker, which receives a [`TrustPolicyRequest`]
+/// and replies with a [`TrustPolicyResponse`], which can give a reason for a rejection.
+/// Denies if the worker can't be reached or doesn't reply in time
+pub struct TrustWorkerPolicy {
+    ctx: Context,
+    route: Route,
+    timeout: Duration,
+}
+
+impl TrustWorkerPolicy {
+    pub async fn new(ctx: &Context, route: impl Into<Route>) -> Result<Self> {
+        Self::new_with_timeout(ctx, route, DEFAULT_TRUST_WORKER_TIMEOUT).await
+    }
+
+    pub async fn new_with_timeout(
+        ctx: &Context,
+        route: impl Into<Route>,
+        timeout: Duration,
+    ) -> Result<Self> {
+        Ok(Self {
+            ctx: ctx.new_context(Address::random(0)).await?,
+            route: route.into(),
+            timeout,
+        })
+    }
+
+    async fn ask(&self, trust_info: &SecureChannelTrustInfo) -> Result<TrustDecision> {
+        let mut ctx = self.ctx.new_context(Address::random(0)).await?;
+        ctx.send(
+            self.route.clone(),
+            TrustPolicyRequest {
+                info: trust_info.clone(),
+            },
+        )
+        .await?;
+
+        match timeout(self.timeout, ctx.receive_block::<TrustPolicyResponse>()).await {
+            Ok(response) => Ok(response?.take().body().into()),
+            Err(_) => {
+                warn!("Authorization worker at {} timed out", self.route);
+                Ok(TrustDecision::rejected())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AsyncTryClone for TrustWorkerPolicy {
+    async fn async_try_clone(&self) -> Result<Self> {
+        Ok(Self {
+            ctx: self.ctx.new_context(Address::random(0)).await?,
+            route: self.route.clone(),
+            timeout: self.timeout,
+        })
+    }
+}
+
+#[async_trait]
+impl TrustPolicy for TrustWorkerPolicy {
+    async fn check(&self, trust_info: &SecureChannelTrustInfo) -> Result<bool> {
+        Ok(self.decide(trust_info).await?.is_trusted())
+    }
+
+    async fn decide(&self, trust_info: &SecureChannelTrustInfo) -> Result<TrustDecision> {
+        match self.ask(trust_info).await {
+            Ok(decision) => Ok(decision),
+            Err(err) => {
+                warn!("{} asking authorization worker at {}", err, self.route);
+                Ok(TrustDecision::rejected())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ProfileIdentifier, SecureChannelTrustInfo, TrustDecision, TrustPolicy, TrustPolicyRequest,
+        TrustPolicyResponse, TrustWorkerPolicy,
+    };
+    use core::time::Duration;
+    use ockam_core::{async_trait, Result, Routed, Worker};
+    use ockam_node::Context;
+
+    struct Authorizer {
+        allowed: ProfileIdentifier,
+    }
+
+    #[async_trait]
+    impl Worker for Authorizer {
+        type Message = TrustPolicyRequest;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            ctx: &mut Self::Context,
+            msg: Routed<Self::Message>,
+        ) -> Result<()> {
+            let route = msg.return_route();
+            let res = msg.body().info.their_profile_id() == &self.allowed;
+
+            ctx.send(route, TrustPolicyResponse::from(TrustDecision::from(res)))
+                .await
+        }
+    }
+
+    struct Silent;
+
+    #[async_trait]
+    impl Worker for Silent {
+        type Message = TrustPolicyRequest;
+        type Context = Context;
+
+        async fn handle_message(
+            &mut self,
+            _ctx: &mut Self::Context,
+            _msg: Routed<Self::Message>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[ockam_macros::test]
+    async fn test(ctx: &mut Context) -> Result<()> {
+        let alice = ProfileIdentifier::random();
+        let eve = ProfileIdentifier::random();
+
+        ctx.start_worker(
+            "authorizer",
+            Authorizer {
+                allowed: alice.clone(),
+            },
+        )
+        .await?;
+        ctx.start_worker("silent", Silent).await?;
+
+        let policy = TrustWorkerPolicy::new(ctx, "authorizer").await?;
+        assert!(
+            policy
+                .check(&SecureChannelTrustInfo::new(alice.clone()))
+                .await?
+        );
+        assert!(!policy.check(&SecureChannelTrustInfo::new(eve)).await?);
+
+        let policy =
+            TrustWorkerPolicy::new_with_timeout(ctx, "silent", Duration::from_millis(100)).await?;
+        assert!(
+            !policy
+                .check(&SecureChannelTrustInfo::new(alice.clone()))
+                .await?
+        );
+
+        let policy = TrustWorkerPolicy::new(ctx, "unknown").await?;
+        assert!(!policy.check(&SecureChannelTrustInfo::new(alice)).await?);
+
+        ctx.stop().await
+    }
+}
@@ -1,23 +1,133 @@
+use crate::EntityError;
 use crate::EntityError::IdentityApiFailed;
+#[cfg(feature = "unsafe_channel_key_export")]
+use crate::ExportedChannelKey;
 use crate::{
-    profile::Profile, AuthenticationProof, Changes, Contact, EntityBuilder, Identity,
-    IdentityRequest, IdentityResponse, Lease, MaybeContact, ProfileChangeEvent, ProfileIdentifier,
-    TrustPolicy, TrustPolicyImpl, TTL,
+    profile::Profile, request_channel, AuthenticationProof, AuthorityCredential, Changes, Contact,
+    EntityBuilder, EntityChannelMessage, Identity, IdentityRequest, IdentityResponse, Lease,
+    MaybeContact, PreSharedKey, ProfileChangeEvent, ProfileEventAttributes, ProfileIdentifier,
+    SecureChannelCipherSuite, SecureChannelHandle, SecureChannelListenerOptions,
+    SecureChannelOptions, Stopwatch, TrustPolicy, TrustPolicyImpl, DEFAULT_SECURE_CHANNEL_TIMEOUT,
+    TTL,
 };
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
 use ockam_core::compat::{
+    collections::BTreeMap,
     string::{String, ToString},
+    sync::Arc,
     vec::Vec,
 };
-use ockam_core::vault::{PublicKey, Secret};
+use ockam_core::vault::{PublicKey, Secret, SecretType, Signature};
 use ockam_core::{async_trait, compat::boxed::Box};
-use ockam_core::{Address, AsyncTryClone, Result, Route};
-use ockam_node::{Context, Handle};
+use ockam_core::{route, Address, AsyncTryClone, Message, Result, Route};
+use ockam_node::tokio;
+use ockam_node::{Context, Handle, DEFAULT_TIMEOUT};
 use IdentityRequest::*;
 use IdentityResponse as Res;
+
+/// How often [`Entity::drain_secure_channel_listener_then_stop`] checks for open channels
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Shuts the entity worker down once the last clone of the `Entity` it was built for is dropped
+struct EntityWorkerOwner {
+    ctx: Option<Context>,
+    entity_worker: Address,
+    shut_down: AtomicBool,
+}
+
+impl Drop for EntityWorkerOwner {
+    fn drop(&mut self) {
+        if self.shut_down.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Some(ctx) = self.ctx.take() {
+            let entity_worker = self.entity_worker.clone();
+            // Drop can't wait, so the workers are stopped in the background
+            ctx.runtime().spawn(async move {
+                let _ = ctx.send(route![entity_worker], Shutdown).await;
+            });
+        }
+    }
+}
+
+/// Context an [`Entity::create_secure_channel`] call waits at. If the call is dropped before
+/// the entity worker answered, the channel and everything started for it are stopped
+struct PendingSecureChannel {
+    ctx: Option<Context>,
+    entity_worker: Address,
+    /// Started for the channel, but not handed to the entity worker yet
+    trust_policy_address: Option<Address>,
+    /// Seconds to wait for the entity worker
+    timeout: u64,
+}
+
+impl PendingSecureChannel {
+    fn ctx(&mut self) -> &mut Context {
+        // Only taken on completion and drop
+        self.ctx.as_mut().unwrap()
+    }
+
+    async fn request(&mut self, request: IdentityRequest) -> Result<IdentityResponse> {
+        let entity_worker = self.entity_worker.clone();
+        let timeout = self.timeout;
+        self.ctx().send(route![entity_worker], request).await?;
+        self.trust_policy_address = None;
+        let res = self
+            .ctx()
+            .receive_timeout::<IdentityResponse>(timeout)
+            .await?;
+
+        // Answered, nothing to clean up
+        if let Some(ctx) = self.ctx.take() {
+            let _ = ctx.stop_worker(ctx.address()).await;
+        }
+
+        Ok(res.take().body())
+    }
+}
+
+impl Drop for PendingSecureChannel {
+    fn drop(&mut self) {
+        if let Some(mut ctx) = self.ctx.take() {
+            let entity_worker = self.entity_worker.clone();
+            let trust_policy_address = self.trust_policy_address.take();
+            let timeout = self.timeout;
+            // Drop can't wait, so the channel is stopped in the background
+            ctx.runtime().spawn(async move {
+                if let Some(trust_policy_address) = trust_policy_address {
+                    let _ = ctx.stop_worker(trust_policy_address).await;
+                }
+                if ctx
+                    .send(route![entity_worker], CancelSecureChannel)
+                    .await
+                    .is_ok()
+                {
+                    // The channel may have been created before the entity worker got the
+                    // cancellation, then its address arrives first
+                    while let Ok(msg) = ctx.receive_timeout::<IdentityResponse>(timeout).await {
+                        match msg.take().body() {
+                            Res::CreateSecureChannel(address) => {
+                                let _ = ctx.stop_worker(address).await;
+                            }
+                            Res::CancelSecureChannel => break,
+                            _ => {}
+                        }
+                    }
+                }
+                let _ = ctx.stop_worker(ctx.address()).await;
+            });
+        }
+    }
+}
+
 #[derive(AsyncTryClone)]
 pub struct Entity {
     pub(crate) handle: Handle,
     current_profile_id: Option<ProfileIdentifier>,
+    /// Only set for entities built by [`EntityBuilder`] and their clones,
+    /// not for those taken from a [`Profile`]
+    owner: Option<Arc<EntityWorkerOwner>>,
 }
 
 impl Entity {
@@ -25,13 +135,50 @@ impl Entity {
         Entity {
             handle,
             current_profile_id: profile_id,
+            owner: None,
         }
     }
 
+    /// Entity whose worker, with everything it started, is shut down when the last clone
+    /// of it is dropped
+    pub(crate) async fn new_owned(handle: Handle) -> Result<Self> {
+        let owner = EntityWorkerOwner {
+            ctx: Some(handle.ctx().new_context(Address::random(0)).await?),
+            entity_worker: handle.address().clone(),
+            shut_down: AtomicBool::new(false),
+        };
+        Ok(Entity {
+            owner: Some(Arc::new(owner)),
+            ..Self::new(handle, None)
+        })
+    }
+
     pub async fn create(ctx: &Context, vault_address: &Address) -> Result<Entity> {
         EntityBuilder::new(ctx, vault_address).await?.build().await
     }
 
+    /// Create an `Entity` whose profile root key is `secret`, a key already in the vault,
+    /// rather than a generated one. See [`EntityBuilder::build_with_key`]
+    pub async fn create_from_existing_vault_key(
+        ctx: &Context,
+        vault_address: &Address,
+        secret: &Secret,
+    ) -> Result<Entity> {
+        EntityBuilder::new(ctx, vault_address)
+            .await?
+            .build_with_key(secret)
+            .await
+    }
+
+    /// Recreate an `Entity` from [`Entity::export`] or [`Entity::export_with_secrets`] output.
+    /// Secret keys missing from the vault are imported into it
+    pub async fn import(ctx: &Context, vault_address: &Address, data: &[u8]) -> Result<Entity> {
+        EntityBuilder::new(ctx, vault_address)
+            .await?
+            .import(data)
+            .await
+    }
+
     pub async fn call(&self, req: IdentityRequest) -> Result<IdentityResponse> {
         self.handle.call(req).await
     }
@@ -39,12 +186,53 @@ impl Entity {
     pub async fn cast(&self, req: IdentityRequest) -> Result<()> {
         self.handle.cast(req).await
     }
+
+    /// Stop the secure channel listeners and channels of this entity, the trust policies
+    /// given to them, and the entity worker itself. Returns once they are stopped, so
+    /// clones of this entity and its profiles can't be used afterwards. Dropping the last
+    /// clone of an entity does the same, without waiting for it
+    pub async fn shutdown(self) -> Result<()> {
+        if let Some(owner) = &self.owner {
+            owner.shut_down.store(true, Ordering::SeqCst);
+        }
+        match self.call(Shutdown).await? {
+            Res::Shutdown => Ok(()),
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
+
+    /// Decommission this entity: stop its workers like [`Entity::shutdown`] does, then destroy
+    /// the secret keys of all of its profiles in the vault, including the ones rotated out,
+    /// which wipes them from memory. Returns once all of it is done. Every step is taken even
+    /// if one fails, and the first failure is returned, so that callers know some key material
+    /// may be left
+    pub async fn delete(self) -> Result<()> {
+        if let Some(owner) = &self.owner {
+            owner.shut_down.store(true, Ordering::SeqCst);
+        }
+        match self.call(Delete).await? {
+            Res::Delete => Ok(()),
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
 }
 
 impl Entity {
     pub fn id(&self) -> ProfileIdentifier {
         self.current_profile_id.as_ref().unwrap().clone()
     }
+
+    /// [`ProfileIdentifier`] of the current profile, without asking the entity worker.
+    /// It stays the same once the profile is created, also when its key is rotated.
+    /// Fails with [`EntityError::ProfileNotFound`](crate::EntityError::ProfileNotFound)
+    /// if the entity has no profile
+    pub fn current_identifier(&self) -> Result<&ProfileIdentifier> {
+        self.current_profile_id
+            .as_ref()
+            .ok_or_else(|| EntityError::ProfileNotFound.into())
+    }
 }
 
 fn err<T>() -> Result<T> {
@@ -53,14 +241,122 @@ fn err<T>() -> Result<T> {
 
 impl Entity {
     pub async fn create_profile(&mut self, vault_address: &Address) -> Result<Profile> {
-        if let Res::CreateProfile(id) = self.call(CreateProfile(vault_address.clone())).await? {
-            // Set current_profile_id, if it's first profile
-            if self.current_profile_id.is_none() {
-                self.current_profile_id = Some(id.clone());
+        self.create_profile_with_key_type(
+            vault_address,
+            SecretType::Ed25519,
+            ProfileEventAttributes::new(),
+        )
+        .await
+    }
+
+    /// Create a profile with a root key of given type, recording `attributes` in its first
+    /// change event. Fails with [`EntityError::UnsupportedProfileKeyType`](crate::EntityError::UnsupportedProfileKeyType)
+    /// for key types that can't sign
+    pub async fn create_profile_with_key_type(
+        &mut self,
+        vault_address: &Address,
+        key_type: SecretType,
+        attributes: ProfileEventAttributes,
+    ) -> Result<Profile> {
+        match self
+            .call(CreateProfile(vault_address.clone(), key_type, attributes))
+            .await?
+        {
+            Res::CreateProfile(id) => {
+                // Set current_profile_id, if it's first profile
+                if self.current_profile_id.is_none() {
+                    self.current_profile_id = Some(id.clone());
+                }
+                Ok(Profile::new(id, self.handle.async_try_clone().await?))
             }
-            Ok(Profile::new(id, self.handle.async_try_clone().await?))
-        } else {
-            err()
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
+
+    /// Create a profile around a key already in the vault, recording `attributes` in its first
+    /// change event. Fails with [`EntityError::SecretKeyNotFound`](crate::EntityError::SecretKeyNotFound)
+    /// if the vault doesn't have the key, and with
+    /// [`EntityError::UnsupportedProfileKeyType`](crate::EntityError::UnsupportedProfileKeyType)
+    /// for key types that can't sign
+    pub async fn create_profile_from_key(
+        &mut self,
+        vault_address: &Address,
+        secret: &Secret,
+        attributes: ProfileEventAttributes,
+    ) -> Result<Profile> {
+        match self
+            .call(CreateProfileFromKey(
+                vault_address.clone(),
+                secret.clone(),
+                attributes,
+            ))
+            .await?
+        {
+            Res::CreateProfile(id) => {
+                // Set current_profile_id, if it's first profile
+                if self.current_profile_id.is_none() {
+                    self.current_profile_id = Some(id.clone());
+                }
+                Ok(Profile::new(id, self.handle.async_try_clone().await?))
+            }
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
+
+    /// Recreate a profile from [`Entity::export`] or [`Entity::export_with_secrets`] output,
+    /// keeping its [`ProfileIdentifier`]
+    pub async fn import_profile(
+        &mut self,
+        vault_address: &Address,
+        data: &[u8],
+    ) -> Result<Profile> {
+        match self
+            .call(ImportProfile(vault_address.clone(), data.to_vec()))
+            .await?
+        {
+            Res::ImportProfile(id) => {
+                // Set current_profile_id, if it's first profile
+                if self.current_profile_id.is_none() {
+                    self.current_profile_id = Some(id.clone());
+                }
+                Ok(Profile::new(id, self.handle.async_try_clone().await?))
+            }
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
+
+    /// Serialize the current profile, so it can be loaded after a restart with [`Entity::import`].
+    /// Secret keys are not included, the vault has to persist them
+    pub async fn export(&self) -> Result<Vec<u8>> {
+        self.export_profile(false).await
+    }
+
+    /// Same as [`Entity::export`], but also includes the current secret keys, exported
+    /// through the vault. Needed with vaults that don't persist secrets, such as the in-memory
+    /// one. The result has to be stored as securely as the keys themselves
+    pub async fn export_with_secrets(&self) -> Result<Vec<u8>> {
+        self.export_profile(true).await
+    }
+
+    async fn export_profile(&self, with_secrets: bool) -> Result<Vec<u8>> {
+        match self.call(ExportProfile(self.id(), with_secrets)).await? {
+            Res::ExportProfile(data) => Ok(data),
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
+
+    /// Replace the root key of the current profile, keeping its [`ProfileIdentifier`].
+    /// The change is signed with the previous key, so peers holding the old history can verify it.
+    /// Secure channels created afterwards authenticate with the new key, existing ones keep working
+    pub async fn rotate_profile_key(&mut self) -> Result<()> {
+        match self.call(RotateProfileKey(self.id())).await? {
+            Res::RotateProfileKey => Ok(()),
+            Res::Error(err) => Err(err),
+            _ => err(),
         }
     }
 
@@ -71,6 +367,40 @@ impl Entity {
         self.cast(RemoveProfile(profile_id.into())).await
     }
 
+    /// Create a secondary profile, which the entity presents instead of the current one
+    /// when given its name, e.g. in [`Entity::create_secure_channel_as`].
+    /// Fails with [`EntityError::ProfileNameInUse`](crate::EntityError::ProfileNameInUse)
+    /// if another profile already has that name
+    pub async fn create_named_profile(
+        &mut self,
+        name: impl Into<String>,
+        vault_address: &Address,
+    ) -> Result<Profile> {
+        let profile = self.create_profile(vault_address).await?;
+        let profile_id = profile.identifier().await?;
+        match self
+            .call(NameProfile(name.into(), profile_id.clone()))
+            .await?
+        {
+            Res::NameProfile => Ok(profile),
+            Res::Error(err) => {
+                self.remove_profile(profile_id).await?;
+                Err(err)
+            }
+            _ => err(),
+        }
+    }
+
+    /// Profile created with [`Entity::create_named_profile`]. Fails with
+    /// [`EntityError::ProfileNotFound`](crate::EntityError::ProfileNotFound) for unknown names
+    pub async fn named_profile(&self, name: impl Into<String>) -> Result<Profile> {
+        match self.call(GetNamedProfile(name.into())).await? {
+            Res::GetNamedProfile(id) => Ok(Profile::new(id, self.handle.async_try_clone().await?)),
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
+
     pub async fn current_profile(&self) -> Result<Option<Profile>> {
         match &self.current_profile_id {
             None => Ok(None),
@@ -85,7 +415,7 @@ impl Entity {
 #[async_trait]
 impl Identity for Entity {
     async fn identifier(&self) -> Result<ProfileIdentifier> {
-        Ok(self.current_profile_id.as_ref().unwrap().clone())
+        self.current_identifier().cloned()
     }
 
     async fn create_key(&mut self, label: String) -> Result<()> {
@@ -261,6 +591,26 @@ impl Identity for Entity {
         }
     }
 
+    async fn set_attribute(&mut self, key: String, value: String) -> Result<()> {
+        if let Res::SetAttribute = self.call(SetAttribute(self.id(), key, value)).await? {
+            Ok(())
+        } else {
+            err()
+        }
+    }
+
+    async fn get_attribute(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.get_attributes().await?.remove(key))
+    }
+
+    async fn get_attributes(&self) -> Result<BTreeMap<String, String>> {
+        if let Res::Attributes(attributes) = self.call(GetAttributes(self.id())).await? {
+            Ok(attributes)
+        } else {
+            err()
+        }
+    }
+
     async fn get_lease(
         &self,
         lease_manager_route: &Route,
@@ -291,10 +641,28 @@ impl Identity for Entity {
 }
 
 impl Entity {
+    /// Create a secure channel listener at given address. Fails with
+    /// [`EntityError::SecureChannelListenerAddressInUse`](crate::EntityError::SecureChannelListenerAddressInUse)
+    /// if a worker is already registered there, until it is stopped
     pub async fn create_secure_channel_listener(
         &mut self,
         address: impl Into<Address>,
         trust_policy: impl TrustPolicy,
+    ) -> Result<()> {
+        self.create_secure_channel_listener_with_options(
+            address,
+            trust_policy,
+            SecureChannelListenerOptions::new(),
+        )
+        .await
+    }
+
+    /// Create a secure channel listener at given address, configured by given options
+    pub async fn create_secure_channel_listener_with_options(
+        &mut self,
+        address: impl Into<Address>,
+        trust_policy: impl TrustPolicy,
+        options: SecureChannelListenerOptions,
     ) -> Result<()> {
         let profile = self
             .current_profile()
@@ -303,43 +671,827 @@ impl Entity {
             .expect("no current profile");
         let ctx = self.handle.ctx();
         let trust_policy_address = TrustPolicyImpl::create_worker(ctx, trust_policy).await?;
-        if let Res::CreateSecureChannelListener = self
+        match self
             .call(CreateSecureChannelListener(
                 profile.identifier().await.expect("couldn't get profile id"),
                 address.into(),
                 trust_policy_address,
+                options,
             ))
             .await?
         {
-            Ok(())
-        } else {
-            err()
+            Res::CreateSecureChannelListener => Ok(()),
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
+
+    /// Create a secure channel listener that keeps at most `max_channels` channels open.
+    /// Further handshakes fail with [`EntityError::SecureChannelListenerAtCapacity`](crate::EntityError::SecureChannelListenerAtCapacity)
+    /// until some of the channels are closed
+    pub async fn create_secure_channel_listener_with_max_channels(
+        &mut self,
+        address: impl Into<Address>,
+        trust_policy: impl TrustPolicy,
+        max_channels: usize,
+    ) -> Result<()> {
+        self.create_secure_channel_listener_with_options(
+            address,
+            trust_policy,
+            SecureChannelListenerOptions::new().with_max_channels(max_channels),
+        )
+        .await
+    }
+
+    /// Create a secure channel listener that only accepts initiators declaring they check
+    /// a trust policy as well, with [`SecureChannelOptions::with_strict_trust`]. Others fail with
+    /// [`EntityError::SecureChannelTrustNotEnforced`](crate::EntityError::SecureChannelTrustNotEnforced)
+    pub async fn create_secure_channel_listener_with_strict_trust(
+        &mut self,
+        address: impl Into<Address>,
+        trust_policy: impl TrustPolicy,
+    ) -> Result<()> {
+        self.create_secure_channel_listener_with_options(
+            address,
+            trust_policy,
+            SecureChannelListenerOptions::new().with_strict_trust(),
+        )
+        .await
+    }
+
+    /// Create a secure channel listener that also accepts anonymous channels, created with
+    /// [`SecureChannelOptions::with_anonymous`]. Those skip the trust policy, so access controls
+    /// have to tell them apart, e.g. by [`EntityAnyIdAccessControl::allow_anonymous`](crate::EntityAnyIdAccessControl::allow_anonymous)
+    pub async fn create_secure_channel_listener_with_anonymous(
+        &mut self,
+        address: impl Into<Address>,
+        trust_policy: impl TrustPolicy,
+    ) -> Result<()> {
+        self.create_secure_channel_listener_with_options(
+            address,
+            trust_policy,
+            SecureChannelListenerOptions::new().with_anonymous(),
+        )
+        .await
+    }
+
+    /// Create a secure channel listener that rejects handshake messages larger than given size,
+    /// in bytes, instead of [`DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE`](crate::DEFAULT_MAX_HANDSHAKE_MESSAGE_SIZE). They are rejected before they
+    /// are parsed, with [`EntityError::SecureChannelHandshakeMessageTooLarge`](crate::EntityError::SecureChannelHandshakeMessageTooLarge)
+    /// once the key exchange completed
+    pub async fn create_secure_channel_listener_with_max_handshake_message_size(
+        &mut self,
+        address: impl Into<Address>,
+        trust_policy: impl TrustPolicy,
+        max_handshake_message_size: usize,
+    ) -> Result<()> {
+        self.create_secure_channel_listener_with_options(
+            address,
+            trust_policy,
+            SecureChannelListenerOptions::new()
+                .with_max_handshake_message_size(max_handshake_message_size),
+        )
+        .await
+    }
+
+    /// Create a secure channel listener that mixes given pre-shared key into the keys of every
+    /// channel, see [`SecureChannelOptions::with_psk`]. Initiators without the same key fail
+    /// the handshake
+    pub async fn create_secure_channel_listener_with_psk(
+        &mut self,
+        address: impl Into<Address>,
+        trust_policy: impl TrustPolicy,
+        psk: impl Into<PreSharedKey>,
+    ) -> Result<()> {
+        self.create_secure_channel_listener_with_options(
+            address,
+            trust_policy,
+            SecureChannelListenerOptions::new().with_psk(psk),
+        )
+        .await
+    }
+
+    /// Create a secure channel listener at the address derived from the identifier of the
+    /// current profile and given label, see [`ProfileIdentifier::derive_address`], and return
+    /// that address. Peers knowing the identifier create channels to it without asking for it
+    pub async fn create_secure_channel_listener_at_derived_address(
+        &mut self,
+        label: &str,
+        trust_policy: impl TrustPolicy,
+    ) -> Result<Address> {
+        let address = self.identifier().await?.derive_address(label);
+        self.create_secure_channel_listener_with_options(
+            address.clone(),
+            trust_policy,
+            SecureChannelListenerOptions::new(),
+        )
+        .await?;
+        Ok(address)
+    }
+
+    /// Serve a service under an existing listener, authorizing initiators that name it with
+    /// [`SecureChannelOptions::with_service`] by given trust policy instead of the listener's one.
+    /// A name ending with `*` is a pattern serving every service whose name starts with what
+    /// comes before it, e.g. `sensor.*` serves `sensor.kitchen`. A service served by name takes
+    /// precedence, then the longest matching pattern, so `*` serves every service the others don't.
+    /// Initiators naming a service the listener doesn't serve are rejected with
+    /// [`EntityError::UnknownSecureChannelService`](crate::EntityError::UnknownSecureChannelService)
+    pub async fn add_secure_channel_service(
+        &mut self,
+        listener: impl Into<Address>,
+        service: impl Into<String>,
+        trust_policy: impl TrustPolicy,
+    ) -> Result<()> {
+        let ctx = self.handle.ctx();
+        let trust_policy_address = TrustPolicyImpl::create_worker(ctx, trust_policy).await?;
+        match self
+            .call(AddSecureChannelService(
+                listener.into(),
+                service.into(),
+                trust_policy_address,
+            ))
+            .await?
+        {
+            Res::AddSecureChannelService => Ok(()),
+            Res::Error(err) => Err(err),
+            _ => err(),
         }
     }
 
+    /// Stop a secure channel listener. Handshakes in progress are cancelled, and their workers
+    /// stopped, before this returns. Channels established already stay open
+    pub async fn stop_secure_channel_listener(
+        &mut self,
+        listener: impl Into<Address>,
+    ) -> Result<()> {
+        match self
+            .call(StopSecureChannelListener(listener.into()))
+            .await?
+        {
+            Res::StopSecureChannelListener => Ok(()),
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
+
+    /// Stop a secure channel listener from accepting channels, e.g. before redeploying.
+    /// New handshakes fail with
+    /// [`EntityError::SecureChannelListenerDraining`](crate::EntityError::SecureChannelListenerDraining),
+    /// those in progress still complete, and channels established already stay open.
+    /// The listener keeps running until it's stopped
+    pub async fn drain_secure_channel_listener(
+        &mut self,
+        listener: impl Into<Address>,
+    ) -> Result<()> {
+        match self
+            .call(DrainSecureChannelListener(listener.into()))
+            .await?
+        {
+            Res::DrainSecureChannelListener => Ok(()),
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
+
+    /// Drain a secure channel listener, see [`Entity::drain_secure_channel_listener`], and wait
+    /// up to `timeout` for the channels it accepted to close. Those still open then are stopped,
+    /// and so is the listener
+    pub async fn drain_secure_channel_listener_then_stop(
+        &mut self,
+        listener: impl Into<Address>,
+        timeout: Duration,
+    ) -> Result<()> {
+        let listener = listener.into();
+        self.drain_secure_channel_listener(listener.clone()).await?;
+
+        // Counted in polls, as there's no clock without std
+        let mut waited = Duration::ZERO;
+        loop {
+            let open: Vec<Address> = self
+                .secure_channels()
+                .await?
+                .into_iter()
+                .filter(|channel| channel.listener() == Some(&listener))
+                .map(|channel| channel.address().clone())
+                .collect();
+            if open.is_empty() {
+                break;
+            }
+            if waited >= timeout {
+                for channel in open {
+                    // Closed on its own meanwhile
+                    let _ = self.stop_secure_channel(&channel).await;
+                }
+                break;
+            }
+            self.handle.ctx().sleep(DRAIN_POLL_INTERVAL).await;
+            waited += DRAIN_POLL_INTERVAL;
+        }
+
+        self.stop_secure_channel_listener(listener).await
+    }
+
+    /// Create a secure channel to the listener at `route`.
+    /// Messages of one sender arrive in the order they were sent, also over channels tunneled
+    /// through other channels, unless they are [`MessagePriority::High`](crate::MessagePriority::High)
+    /// or the transport reorders them.
+    /// Cancellation safe: if the returned future is dropped before it resolves, e.g. on a
+    /// timeout, the handshake is ended and the channel, if it was created meanwhile, is
+    /// stopped in the background.
+    /// Whether a failure is worth retrying is told by its [`SecureChannelErrorKind`](crate::SecureChannelErrorKind)
     pub async fn create_secure_channel(
         &mut self,
         route: impl Into<Route>,
         trust_policy: impl TrustPolicy,
+    ) -> Result<Address> {
+        self.create_secure_channel_with_timeout(route, trust_policy, DEFAULT_SECURE_CHANNEL_TIMEOUT)
+            .await
+    }
+
+    /// Create a secure channel, failing with [`EntityError::SecureChannelTimeout`](crate::EntityError::SecureChannelTimeout)
+    /// if the handshake doesn't complete within the given duration
+    pub async fn create_secure_channel_with_timeout(
+        &mut self,
+        route: impl Into<Route>,
+        trust_policy: impl TrustPolicy,
+        timeout: Duration,
+    ) -> Result<Address> {
+        self.create_secure_channel_with_options(
+            route,
+            trust_policy,
+            SecureChannelOptions::new().with_timeout(timeout),
+        )
+        .await
+    }
+
+    /// Close a secure channel. Messages already sent to the channel are delivered first,
+    /// then the other side is notified and shuts down its end as well.
+    /// Sending to a closed channel fails, as its address no longer exists.
+    pub async fn stop_secure_channel(&self, channel: &Address) -> Result<()> {
+        self.handle.ctx().stop_worker(channel.clone()).await
+    }
+
+    /// Create a secure channel using given [`SecureChannelOptions`]
+    pub async fn create_secure_channel_with_options(
+        &mut self,
+        route: impl Into<Route>,
+        trust_policy: impl TrustPolicy,
+        options: SecureChannelOptions,
     ) -> Result<Address> {
         let profile = self
             .current_profile()
             .await
             .unwrap()
             .expect("no current profile");
+        self.start_secure_channel(profile, route.into(), trust_policy, options)
+            .await
+    }
+
+    /// Create a secure channel presenting the profile created with
+    /// [`Entity::create_named_profile`] instead of the current one
+    pub async fn create_secure_channel_as(
+        &mut self,
+        profile_name: impl Into<String>,
+        route: impl Into<Route>,
+        trust_policy: impl TrustPolicy,
+    ) -> Result<Address> {
+        let profile = self.named_profile(profile_name).await?;
+        self.start_secure_channel(
+            profile,
+            route.into(),
+            trust_policy,
+            SecureChannelOptions::new(),
+        )
+        .await
+    }
+
+    async fn start_secure_channel(
+        &mut self,
+        profile: Profile,
+        route: Route,
+        trust_policy: impl TrustPolicy,
+        options: SecureChannelOptions,
+    ) -> Result<Address> {
         let ctx = self.handle.ctx();
+        let mut pending = PendingSecureChannel {
+            ctx: Some(ctx.new_context(Address::random(0)).await?),
+            entity_worker: self.handle.address().clone(),
+            trust_policy_address: None,
+            // The worker always replies once the handshake is over or timed out,
+            // the extra time only guards against the worker itself being gone
+            timeout: options
+                .handshake_duration()
+                .as_secs()
+                .saturating_add(DEFAULT_TIMEOUT),
+        };
         let trust_policy_address = TrustPolicyImpl::create_worker(ctx, trust_policy).await?;
-        if let Res::CreateSecureChannel(address) = self
-            .call(CreateSecureChannel(
+        pending.trust_policy_address = Some(trust_policy_address.clone());
+
+        match pending
+            .request(CreateSecureChannel(
                 profile.identifier().await.expect("couldn't get profile id"),
-                route.into(),
+                route,
                 trust_policy_address,
+                options,
             ))
             .await?
         {
-            Ok(address)
+            Res::CreateSecureChannel(address) => Ok(address),
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
+
+    /// Secure channel of the current profile to `profile_id`, with the listener at
+    /// `route_to_listener`. It is created on first use, and reused while it stays open,
+    /// also by concurrent calls and clones of this entity. The peer has to present
+    /// `profile_id`, as for [`TrustIdentifierPolicy`](crate::TrustIdentifierPolicy)
+    pub async fn secure_channel_to(
+        &self,
+        profile_id: &ProfileIdentifier,
+        route_to_listener: impl Into<Route>,
+    ) -> Result<Address> {
+        match self
+            .handle
+            .call_timeout(
+                GetSecureChannelTo(self.id(), profile_id.clone(), route_to_listener.into()),
+                DEFAULT_SECURE_CHANNEL_TIMEOUT.as_secs() + DEFAULT_TIMEOUT,
+            )
+            .await?
+        {
+            Res::CreateSecureChannel(address) => Ok(address),
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
+
+    /// Send `msg` from `ctx` to `onward_route` of the peer presenting `profile_id`,
+    /// through the channel of [`Entity::secure_channel_to`]
+    pub async fn send_to<M: Message + Send + 'static>(
+        &self,
+        ctx: &Context,
+        profile_id: &ProfileIdentifier,
+        route_to_listener: impl Into<Route>,
+        onward_route: impl Into<Route>,
+        msg: M,
+    ) -> Result<()> {
+        let channel = self
+            .secure_channel_to(profile_id, route_to_listener)
+            .await?;
+        let mut route: Route = onward_route.into();
+        route.modify().prepend(channel);
+        ctx.send(route, msg).await
+    }
+
+    /// Secure channels of the current profile that completed the handshake and
+    /// weren't closed yet, whether created by this side or accepted by a listener
+    pub async fn secure_channels(&self) -> Result<Vec<SecureChannelHandle>> {
+        if let Res::SecureChannels(channels) = self.call(GetSecureChannels(self.id())).await? {
+            Ok(channels)
         } else {
             err()
         }
     }
+
+    /// Secure channel of the current profile at given local address
+    pub async fn secure_channel_info(
+        &self,
+        address: &Address,
+    ) -> Result<Option<SecureChannelHandle>> {
+        let channels = self.secure_channels().await?;
+        Ok(channels
+            .into_iter()
+            .find(|channel| channel.address() == address))
+    }
+
+    /// Primitives negotiated by the secure channel of the current profile at given local address
+    pub async fn secure_channel_cipher_suite(
+        &self,
+        address: &Address,
+    ) -> Result<Option<SecureChannelCipherSuite>> {
+        Ok(self
+            .secure_channel_info(address)
+            .await?
+            .map(|channel| *channel.cipher_suite()))
+    }
+
+    /// Wait until the secure channel at given local address takes messages, and return the
+    /// identifier of the peer it authenticated. [`Entity::create_secure_channel`] already
+    /// returns once the other side accepted the channel, this is for channels known from
+    /// elsewhere, e.g. accepted by a listener or reconnecting after a transport failure.
+    /// Fails with [`EntityError::SecureChannelTimeout`] if that takes longer than `timeout`
+    pub async fn await_secure_channel_ready(
+        &self,
+        address: &Address,
+        timeout: Duration,
+    ) -> Result<ProfileIdentifier> {
+        let mut ctx =
+            request_channel(self.handle.ctx(), address, EntityChannelMessage::AwaitReady).await?;
+
+        let res = tokio::time::timeout(timeout, ctx.receive_block::<EntityChannelMessage>()).await;
+        match res {
+            Ok(Ok(msg)) => match msg.take().body() {
+                EntityChannelMessage::Ready(their_profile_id) => Ok(their_profile_id),
+                EntityChannelMessage::Reject(err) => Err(err.into()),
+                _ => Err(EntityError::InvalidSecureChannelInternalState.into()),
+            },
+            Ok(Err(err)) => Err(err),
+            Err(_) => Err(EntityError::SecureChannelTimeout.into()),
+        }
+    }
+
+    /// Check that the other side of the secure channel at given local address still answers,
+    /// e.g. from a supervisor, and return how long the round trip took, zero without `std`.
+    /// Neither side delivers anything to its workers for it. Unlike keepalives, see
+    /// [`SecureChannelOptions::with_keepalive`], the channel stays open if there's no answer.
+    /// Fails with [`EntityError::SecureChannelTimeout`] if there's none within `timeout`
+    pub async fn probe_secure_channel(
+        &self,
+        address: &Address,
+        timeout: Duration,
+    ) -> Result<Duration> {
+        if self.secure_channel_info(address).await?.is_none() {
+            return Err(EntityError::SecureChannelNotFound.into());
+        }
+
+        let stopwatch = Stopwatch::start();
+        let mut ctx =
+            request_channel(self.handle.ctx(), address, EntityChannelMessage::Probe).await?;
+
+        let res = tokio::time::timeout(timeout, ctx.receive_block::<EntityChannelMessage>()).await;
+        let _ = ctx.stop_worker(ctx.address()).await;
+        match res {
+            Ok(Ok(msg)) => match msg.take().body() {
+                EntityChannelMessage::Probed => Ok(stopwatch.elapsed()),
+                EntityChannelMessage::Reject(err) => Err(err.into()),
+                _ => Err(EntityError::InvalidSecureChannelInternalState.into()),
+            },
+            Ok(Err(err)) => Err(err),
+            Err(_) => Err(EntityError::SecureChannelTimeout.into()),
+        }
+    }
+
+    /// Change history of the peer of the secure channel of the current profile at given local
+    /// address, as the channel verified it during the handshake. Later changes, e.g. seen by
+    /// other channels to the same peer, aren't included, so it can be pinned or verified again
+    /// with [`Contact::verify`]. Fails with [`EntityError::ContactNotFound`] for anonymous channels
+    pub async fn secure_channel_peer_history(&self, address: &Address) -> Result<Contact> {
+        if self.secure_channel_info(address).await?.is_none() {
+            return Err(EntityError::SecureChannelNotFound.into());
+        }
+
+        let mut ctx = request_channel(
+            self.handle.ctx(),
+            address,
+            EntityChannelMessage::GetPeerHistory,
+        )
+        .await?;
+
+        match ctx.receive::<EntityChannelMessage>().await?.take().body() {
+            EntityChannelMessage::PeerHistory(contact) => Ok(contact),
+            EntityChannelMessage::Reject(err) => Err(err.into()),
+            _ => Err(EntityError::InvalidSecureChannelInternalState.into()),
+        }
+    }
+
+    /// Key material of the secure channel of the current profile at given local address.
+    /// The other side of the channel exports the same key. Read [`ExportedChannelKey`]
+    /// about what using it outside of the channel gives up
+    #[cfg(feature = "unsafe_channel_key_export")]
+    pub async fn export_channel_key(&self, address: &Address) -> Result<ExportedChannelKey> {
+        if self.secure_channel_info(address).await?.is_none() {
+            return Err(EntityError::SecureChannelNotFound.into());
+        }
+
+        let mut ctx =
+            request_channel(self.handle.ctx(), address, EntityChannelMessage::ExportKey).await?;
+
+        match ctx.receive::<EntityChannelMessage>().await?.take().body() {
+            EntityChannelMessage::ExportedKey(key) => Ok(key),
+            EntityChannelMessage::Reject(err) => Err(err.into()),
+            _ => Err(EntityError::InvalidSecureChannelInternalState.into()),
+        }
+    }
+
+    /// Sign attributes of another profile with our root key, acting as an authority.
+    /// The subject presents the credential with [`SecureChannelOptions::with_credential`]
+    pub async fn issue_authority_credential(
+        &mut self,
+        subject: &ProfileIdentifier,
+        attributes: BTreeMap<String, String>,
+    ) -> Result<AuthorityCredential> {
+        let data = AuthorityCredential::signed_data(subject, &attributes)?;
+        let proof = self.create_auth_proof(&data).await?;
+
+        Ok(AuthorityCredential::new(subject.clone(), attributes, proof))
+    }
+
+    /// Sign arbitrary data with the root key of the current profile
+    pub async fn sign(&self, data: &[u8]) -> Result<Signature> {
+        match self.call(Sign(self.id(), data.to_vec())).await? {
+            Res::Sign(signature) => Ok(signature),
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
+
+    /// Verify a signature of [`Entity::sign`] by given profile, which has to be the current
+    /// profile or one of its contacts, see [`Identity::verify_and_add_contact`].
+    /// Keys they rotated away from still verify, since they're part of their verified history
+    pub async fn verify(
+        &self,
+        data: &[u8],
+        signature: &Signature,
+        signer_id: &ProfileIdentifier,
+    ) -> Result<bool> {
+        match self
+            .call(VerifySignature(
+                self.id(),
+                data.to_vec(),
+                signature.clone(),
+                signer_id.clone(),
+            ))
+            .await?
+        {
+            Res::VerifySignature(verified) => Ok(verified),
+            Res::Error(err) => Err(err),
+            _ => err(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Entity, Identity, TrustEveryonePolicy};
+    use core::time::Duration;
+    use ockam_core::compat::string::{String, ToString};
+    use ockam_core::vault::KeyIdVault;
+    use ockam_core::{route, Address, Result};
+    use ockam_node::Context;
+    use ockam_vault_sync_core::{Vault, VaultSync};
+    use tokio::time::sleep;
+
+    #[ockam_macros::test]
+    async fn test_send_to(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let bob_id = bob.identifier().await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        // Both wait for the same handshake
+        let (first, second) = tokio::join!(
+            alice.send_to(
+                ctx,
+                &bob_id,
+                route!["bob_listener"],
+                route![ctx.address()],
+                "1".to_string()
+            ),
+            alice.send_to(
+                ctx,
+                &bob_id,
+                route!["bob_listener"],
+                route![ctx.address()],
+                "2".to_string()
+            ),
+        );
+        first?;
+        second?;
+
+        let mut received = vec![
+            ctx.receive::<String>().await?.take().body(),
+            ctx.receive::<String>().await?.take().body(),
+        ];
+        received.sort();
+        assert_eq!(received, ["1", "2"]);
+        assert_eq!(alice.secure_channels().await?.len(), 1);
+        assert_eq!(bob.secure_channels().await?.len(), 1);
+
+        // Later ones reuse it
+        alice
+            .send_to(
+                ctx,
+                &bob_id,
+                route!["bob_listener"],
+                route![ctx.address()],
+                "3".to_string(),
+            )
+            .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "3");
+        assert_eq!(
+            alice
+                .secure_channel_to(&bob_id, route!["bob_listener"])
+                .await?,
+            alice.secure_channels().await?[0].address().clone()
+        );
+        assert_eq!(alice.secure_channels().await?.len(), 1);
+
+        // The peer has to be the one asked for
+        let carol_id = Entity::create(ctx, &vault).await?.identifier().await?;
+        assert!(alice
+            .secure_channel_to(&carol_id, route!["bob_listener"])
+            .await
+            .is_err());
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_shutdown(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+        alice
+            .create_secure_channel_listener("alice_listener", TrustEveryonePolicy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        let bob_channel = bob
+            .create_secure_channel(route!["alice_listener"], TrustEveryonePolicy)
+            .await?;
+        ctx.send(
+            route![bob_channel, ctx.address()],
+            "Hello, Alice!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let alice_accepted_channel = msg.return_route().next()?.clone();
+
+        let alice_worker = alice.handle.address().clone();
+        alice.shutdown().await?;
+
+        let workers = ctx.list_workers().await?;
+        for address in [
+            alice_worker,
+            Address::from("alice_listener"),
+            alice_channel,
+            alice_accepted_channel,
+        ]
+        .iter()
+        {
+            assert!(!workers.contains(address));
+        }
+        assert!(workers.contains(&Address::from("bob_listener")));
+
+        // Dropping an entity stops its workers in the background
+        let bob_worker = bob.handle.address().clone();
+        drop(bob);
+        sleep(Duration::from_millis(200)).await;
+
+        let workers = ctx.list_workers().await?;
+        assert!(!workers.contains(&bob_worker));
+        assert!(!workers.contains(&Address::from("bob_listener")));
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_delete(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+        let mut vault_sync = VaultSync::create_with_worker(ctx, &vault).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+        alice
+            .create_secure_channel_listener("alice_listener", TrustEveryonePolicy)
+            .await?;
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+
+        // Keys rotated out are destroyed as well
+        let mut key_ids = Vec::new();
+        for _ in 0..2 {
+            let public_key = alice.get_root_public_key().await?;
+            key_ids.push(
+                vault_sync
+                    .compute_key_id_for_public_key(&public_key)
+                    .await?,
+            );
+            alice.rotate_profile_key().await?;
+        }
+        let public_key = alice.get_root_public_key().await?;
+        key_ids.push(
+            vault_sync
+                .compute_key_id_for_public_key(&public_key)
+                .await?,
+        );
+        for key_id in &key_ids {
+            vault_sync.get_secret_by_key_id(key_id).await?;
+        }
+
+        let alice_worker = alice.handle.address().clone();
+        alice.delete().await?;
+
+        for key_id in &key_ids {
+            assert!(vault_sync.get_secret_by_key_id(key_id).await.is_err());
+        }
+        // Bob shares the vault, his keys stay
+        let public_key = bob.get_root_public_key().await?;
+        let key_id = vault_sync
+            .compute_key_id_for_public_key(&public_key)
+            .await?;
+        vault_sync.get_secret_by_key_id(&key_id).await?;
+
+        let workers = ctx.list_workers().await?;
+        for address in [alice_worker, Address::from("alice_listener"), alice_channel].iter() {
+            assert!(!workers.contains(address));
+        }
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_await_secure_channel_ready(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+        let alice_id = alice.identifier().await?;
+        let bob_id = bob.identifier().await?;
+
+        for i in 0..5 {
+            let alice_channel = alice
+                .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+                .await?;
+            let peer = alice
+                .await_secure_channel_ready(&alice_channel, Duration::from_secs(5))
+                .await?;
+            assert_eq!(peer, bob_id);
+
+            // The first message makes it
+            ctx.send(route![alice_channel, ctx.address()], i.to_string())
+                .await?;
+            let msg = ctx.receive::<String>().await?.take();
+            let bob_channel = msg.return_route().next()?.clone();
+            assert_eq!(msg.body(), i.to_string());
+
+            let peer = bob
+                .await_secure_channel_ready(&bob_channel, Duration::from_secs(5))
+                .await?;
+            assert_eq!(peer, alice_id);
+        }
+
+        // Closed channels never get ready
+        let channels = alice.secure_channels().await?;
+        let alice_channel = channels[0].address();
+        alice.stop_secure_channel(alice_channel).await?;
+        assert!(alice
+            .await_secure_channel_ready(alice_channel, Duration::from_secs(5))
+            .await
+            .is_err());
+
+        ctx.stop().await
+    }
+
+    #[cfg(feature = "unsafe_channel_key_export")]
+    #[ockam_macros::test]
+    async fn test_export_channel_key(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+            .await?;
+
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        ctx.send(
+            route![alice_channel.clone(), ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        let msg = ctx.receive::<String>().await?.take();
+        let bob_channel = msg.return_route().next()?.clone();
+
+        let alice_key = alice.export_channel_key(&alice_channel).await?;
+        let bob_key = bob.export_channel_key(&bob_channel).await?;
+        assert_eq!(alice_key, bob_key);
+        assert_eq!(alice_key.as_bytes().len(), 32);
+
+        // Only channels of the entity's own profile
+        assert_eq!(
+            bob.export_channel_key(&alice_channel)
+                .await
+                .err()
+                .unwrap()
+                .code(),
+            ockam_core::Error::from(crate::EntityError::SecureChannelNotFound).code()
+        );
+
+        ctx.stop().await
+    }
 }
@@ -1,4 +1,7 @@
-use crate::{Entity, EntityWorker};
+use crate::{AddressGenerator, Entity, EntityWorker, ProfileEventAttributes};
+use ockam_core::compat::rand::RngCore;
+use ockam_core::compat::string::String;
+use ockam_core::vault::{Secret, SecretType};
 use ockam_core::{Address, Result};
 use ockam_node::{Context, Handle};
 
@@ -6,6 +9,9 @@ use ockam_node::{Context, Handle};
 pub struct EntityBuilder {
     ctx: Context,
     vault: Address,
+    key_type: SecretType,
+    attributes: ProfileEventAttributes,
+    addresses: AddressGenerator,
 }
 
 impl EntityBuilder {
@@ -15,20 +21,81 @@ impl EntityBuilder {
         Ok(Self {
             ctx: child_ctx,
             vault: vault.clone(),
+            key_type: SecretType::Ed25519,
+            attributes: ProfileEventAttributes::new(),
+            addresses: AddressGenerator::default(),
         })
     }
 
+    /// Type of the profile root key, Ed25519 by default. X25519 keys sign with XEdDSA.
+    /// Types that can't sign fail [`EntityBuilder::build`] with
+    /// [`EntityError::UnsupportedProfileKeyType`](crate::EntityError::UnsupportedProfileKeyType)
+    pub fn with_key_type(mut self, key_type: SecretType) -> Self {
+        self.key_type = key_type;
+        self
+    }
+
+    /// Attribute recorded in the first change event of the profile
+    pub fn with_attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.into(), value.into());
+        self
+    }
+
+    /// Generate the addresses of the entity worker, its listeners and its channels with given RNG.
+    /// Meant for tests, which reproduce a run by passing an RNG seeded the same way.
+    /// Keys are still generated by the vault
+    pub fn with_rng(mut self, rng: impl RngCore + Send + 'static) -> Self {
+        self.addresses = AddressGenerator::new(rng);
+        self
+    }
+
     // TODO: enable_credentials_signing_key
 
     pub async fn build(self) -> Result<Entity> {
-        let address = Address::random(0);
+        let address = self.addresses.generate();
+        self.ctx
+            .start_worker(&address, EntityWorker::new(self.addresses))
+            .await?;
+
+        let mut entity = Entity::new_owned(Handle::new(self.ctx, address)).await?;
+
+        let _ = entity
+            .create_profile_with_key_type(&self.vault, self.key_type, self.attributes)
+            .await?;
+
+        Ok(entity)
+    }
+
+    /// Build an `Entity` whose profile root key is `secret`, which is already in the vault,
+    /// e.g. written there during provisioning. The key type follows from the key, so
+    /// [`EntityBuilder::with_key_type`] doesn't apply. The [`ProfileIdentifier`](crate::ProfileIdentifier)
+    /// is derived from the key, so the same key always gives the same one
+    pub async fn build_with_key(self, secret: &Secret) -> Result<Entity> {
+        let address = self.addresses.generate();
+        self.ctx
+            .start_worker(&address, EntityWorker::new(self.addresses))
+            .await?;
+
+        let mut entity = Entity::new_owned(Handle::new(self.ctx, address)).await?;
+
+        let _ = entity
+            .create_profile_from_key(&self.vault, secret, self.attributes)
+            .await?;
+
+        Ok(entity)
+    }
+
+    /// Build an `Entity` around a previously exported profile, see [`Entity::import`].
+    /// The key type and attributes are those of the exported profile
+    pub async fn import(self, data: &[u8]) -> Result<Entity> {
+        let address = self.addresses.generate();
         self.ctx
-            .start_worker(&address, EntityWorker::default())
+            .start_worker(&address, EntityWorker::new(self.addresses))
             .await?;
 
-        let mut entity = Entity::new(Handle::new(self.ctx, address), None);
+        let mut entity = Entity::new_owned(Handle::new(self.ctx, address)).await?;
 
-        let _ = entity.create_profile(&self.vault).await?;
+        let _ = entity.import_profile(&self.vault, data).await?;
 
         Ok(entity)
     }
@@ -36,8 +103,22 @@ impl EntityBuilder {
 
 #[cfg(test)]
 mod test {
-    use crate::EntityBuilder;
-    use ockam_vault_sync_core::Vault;
+    use crate::{
+        Entity, EntityBuilder, EntityError, Identity, ProfileChangeType, ProfileIdentifier,
+        TrustEveryonePolicy, TrustIdentifierPolicy,
+    };
+    use ockam_core::compat::string::{String, ToString};
+    use ockam_core::compat::vec::Vec;
+    use ockam_core::vault::{
+        SecretAttributes, SecretPersistence, SecretType, SecretVault, AES256_SECRET_LENGTH,
+        CURVE25519_SECRET_LENGTH,
+    };
+    use ockam_core::{route, Address, Result};
+    use ockam_node::Context;
+    use ockam_vault::KeyIdVault;
+    use ockam_vault_sync_core::{Vault, VaultSync};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
 
     #[test]
     fn test_builder() {
@@ -51,4 +132,204 @@ mod test {
         })
         .unwrap();
     }
+
+    /// Addresses of the channel both sides end up with, on a fresh node
+    fn seeded_channel_addresses(seed: u64) -> Vec<Address> {
+        let (mut ctx, mut ex) = ockam_node::start_node();
+        ex.execute(async move {
+            let res: Result<Vec<Address>> = async {
+                let vault = Vault::create(&ctx).await?;
+                let mut alice = EntityBuilder::new(&ctx, &vault)
+                    .await?
+                    .with_rng(XorShiftRng::seed_from_u64(seed))
+                    .build()
+                    .await?;
+                let mut bob = EntityBuilder::new(&ctx, &vault)
+                    .await?
+                    .with_rng(XorShiftRng::seed_from_u64(seed + 1))
+                    .build()
+                    .await?;
+
+                bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+                    .await?;
+                let alice_channel = alice
+                    .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+                    .await?;
+
+                // Bob registered the channel by the time it delivers a message
+                ctx.send(
+                    route![alice_channel.clone(), ctx.address()],
+                    "Hello, Bob!".to_string(),
+                )
+                .await?;
+                ctx.receive::<String>().await?;
+                let bob_channel = bob.secure_channels().await?[0].address().clone();
+
+                Ok(vec![alice_channel, bob_channel])
+            }
+            .await;
+
+            ctx.stop().await.unwrap();
+            res.unwrap()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_builder_rng() {
+        let addresses = seeded_channel_addresses(42);
+        assert_eq!(seeded_channel_addresses(42), addresses);
+        assert_ne!(seeded_channel_addresses(7), addresses);
+    }
+
+    #[ockam_macros::test]
+    async fn test_builder_key_type(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        for (i, key_type) in [SecretType::Ed25519, SecretType::X25519].iter().enumerate() {
+            let mut alice = EntityBuilder::new(ctx, &vault)
+                .await?
+                .with_key_type(*key_type)
+                .with_attribute("name", "alice")
+                .build()
+                .await?;
+            let mut bob = EntityBuilder::new(ctx, &vault)
+                .await?
+                .with_key_type(*key_type)
+                .build()
+                .await?;
+
+            assert_eq!(alice.get_root_public_key().await?.stype(), *key_type);
+            let changes = alice.get_changes().await?;
+            let create_key = changes[0].change_block().change();
+            assert!(matches!(
+                create_key.change_type(),
+                ProfileChangeType::CreateKey(_)
+            ));
+            assert_eq!(
+                create_key.attributes().get("name").map(String::as_str),
+                Some("alice")
+            );
+
+            let listener = format!("bob_listener_{}", i);
+            bob.create_secure_channel_listener(
+                listener.as_str(),
+                TrustIdentifierPolicy::new(alice.identifier().await?),
+            )
+            .await?;
+            let alice_channel = alice
+                .create_secure_channel(
+                    route![listener.as_str()],
+                    TrustIdentifierPolicy::new(bob.identifier().await?),
+                )
+                .await?;
+
+            ctx.send(
+                route![alice_channel, ctx.address()],
+                "Hello, Bob!".to_string(),
+            )
+            .await?;
+            assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+        }
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_create_from_existing_vault_key(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        // Provisioned out of band
+        let mut vault_sync = VaultSync::create_with_worker(ctx, &vault).await?;
+        let secret = vault_sync
+            .secret_generate(SecretAttributes::new(
+                SecretType::Ed25519,
+                SecretPersistence::Persistent,
+                CURVE25519_SECRET_LENGTH,
+            ))
+            .await?;
+        let public_key = vault_sync.secret_public_key_get(&secret).await?;
+        let expected_id = ProfileIdentifier::from_key_id(
+            vault_sync
+                .compute_key_id_for_public_key(&public_key)
+                .await?,
+        );
+
+        let mut alice = Entity::create_from_existing_vault_key(ctx, &vault, &secret).await?;
+        assert_eq!(alice.identifier().await?, expected_id);
+        assert_eq!(alice.get_root_public_key().await?, public_key);
+        assert_eq!(alice.get_changes().await?.len(), 1);
+        assert!(alice.verify_changes().await?);
+
+        let again = EntityBuilder::new(ctx, &vault)
+            .await?
+            .with_attribute("name", "alice")
+            .build_with_key(&secret)
+            .await?;
+        assert_eq!(again.identifier().await?, expected_id);
+
+        // The adopted key authenticates channels
+        let mut bob = Entity::create(ctx, &vault).await?;
+        bob.create_secure_channel_listener(
+            "bob_listener",
+            TrustIdentifierPolicy::new(expected_id.clone()),
+        )
+        .await?;
+        let alice_channel = alice
+            .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+            .await?;
+        ctx.send(
+            route![alice_channel, ctx.address()],
+            "Hello, Bob!".to_string(),
+        )
+        .await?;
+        assert_eq!(ctx.receive::<String>().await?.take().body(), "Hello, Bob!");
+
+        let aes = vault_sync
+            .secret_generate(SecretAttributes::new(
+                SecretType::Aes,
+                SecretPersistence::Ephemeral,
+                AES256_SECRET_LENGTH,
+            ))
+            .await?;
+        let err = Entity::create_from_existing_vault_key(ctx, &vault, &aes)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::UnsupportedProfileKeyType).code()
+        );
+
+        vault_sync.secret_destroy(aes.clone()).await?;
+        let err = Entity::create_from_existing_vault_key(ctx, &vault, &aes)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::SecretKeyNotFound).code()
+        );
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_builder_unsupported_key_type(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let err = EntityBuilder::new(ctx, &vault)
+            .await?
+            .with_key_type(SecretType::Aes)
+            .build()
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.code(),
+            ockam_core::Error::from(EntityError::UnsupportedProfileKeyType).code()
+        );
+
+        ctx.stop().await
+    }
 }
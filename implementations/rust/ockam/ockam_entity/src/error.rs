@@ -40,6 +40,36 @@ pub enum EntityError {
     IssuerInvalidMessage,
     PresenterInvalidMessage,
     VerifierInvalidMessage,
+    SecureChannelTimeout,
+    SecureChannelReconnectFailed,
+    KeyExchangePatternMismatch,
+    SecretKeyNotFound,
+    SecureChannelListenerAtCapacity,
+    CborEncodeFailed,
+    CborDecodeFailed,
+    InvalidProfileIdHex,
+    InvalidSecureChannelService,
+    UnknownSecureChannelService,
+    SecureChannelListenerNotFound,
+    SecureChannelWouldBlock,
+    UnsupportedProfileKeyType,
+    SecureChannelKeepaliveTimeout,
+    ProfileNameInUse,
+    LocalInfoMissing,
+    LocalInfoMalformed,
+    SecureChannelTrustNotEnforced,
+    MalformedHandshakeMessage,
+    InheritedTrustUnavailable,
+    SecureChannelListenerAddressInUse,
+    AnonymousSecureChannelRejected,
+    MalformedCompressedPayload,
+    SecureChannelNotFound,
+    SecureChannelProtocolVersionTooOld,
+    SecureChannelHandshakeMessageTooLarge,
+    SecureChannelCancelled,
+    SecureChannelTransportNotFound,
+    SecureChannelListenerDraining,
+    PreSharedKeyMismatch,
 }
 
 impl EntityError {
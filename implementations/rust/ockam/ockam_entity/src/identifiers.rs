@@ -2,11 +2,13 @@ use crate::profile::Profile;
 use crate::EntityError;
 use core::convert::TryFrom;
 use core::fmt::{Display, Formatter};
+use core::str::FromStr;
 use ockam_core::compat::string::String;
 use ockam_core::hex::encode;
 use ockam_core::vault::{Hasher, KeyId};
-use ockam_core::{Error, Result};
+use ockam_core::{Address, Error, Result};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 /// An identifier of a Profile.
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Default)]
@@ -17,6 +19,10 @@ pub type ProfileIdentifier = EntityIdentifier;
 /// Unique [`crate::Profile`] identifier, computed as SHA256 of root public key
 impl EntityIdentifier {
     pub const PREFIX: &'static str = "P";
+    /// Hashed ahead of the identifier and label by [`EntityIdentifier::derive_address`]
+    const ADDRESS_DOMAIN: &'static [u8] = b"OCKAM_ENTITY_DERIVED_ADDRESS";
+    /// Length of the hex key id, a SHA256
+    const KEY_ID_LEN: usize = 64;
     /// Create a EntityIdentifier from a KeyId
     pub fn from_key_id(key_id: KeyId) -> Self {
         Self { 0: key_id }
@@ -25,6 +31,23 @@ impl EntityIdentifier {
     pub fn key_id(&self) -> &KeyId {
         &self.0
     }
+
+    /// Local address of the service of this profile with given label, which peers knowing
+    /// the identifier can route to without asking for it. The same identifier and label always
+    /// give the same address, different ones give different addresses: it's the first 16 bytes
+    /// of a SHA256 over both, hex encoded
+    pub fn derive_address(&self, label: &str) -> Address {
+        let mut hasher = Sha256::new();
+        hasher.update(Self::ADDRESS_DOMAIN);
+        // Length prefixes keep ("ab", "c") apart from ("a", "bc")
+        for part in [self.0.as_bytes(), label.as_bytes()] {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+        let hash = hasher.finalize();
+
+        Address::from(encode(&hash[..16]))
+    }
 }
 
 impl Display for EntityIdentifier {
@@ -40,15 +63,41 @@ impl From<EntityIdentifier> for String {
     }
 }
 
+/// Parses the `P` prefix followed by the hex key id. Surrounding whitespace and the case
+/// of both are ignored, so that every spelling of the same identifier compares equal.
+/// Fails with [`EntityError::InvalidProfileId`] without the prefix or with a key id that isn't
+/// 64 characters long, and with [`EntityError::InvalidProfileIdHex`] if the key id isn't hex
 impl TryFrom<&str> for EntityIdentifier {
     type Error = Error;
 
     fn try_from(value: &str) -> Result<Self> {
-        if let Some(str) = value.strip_prefix(Self::PREFIX) {
-            Ok(Self::from_key_id(str.into()))
-        } else {
-            Err(EntityError::InvalidProfileId.into())
+        let value = value.trim();
+        let key_id = match value.get(..Self::PREFIX.len()) {
+            Some(prefix) if prefix.eq_ignore_ascii_case(Self::PREFIX) => {
+                &value[Self::PREFIX.len()..]
+            }
+            _ => return Err(EntityError::InvalidProfileId.into()),
+        };
+
+        if key_id.is_empty() {
+            return Err(EntityError::InvalidProfileId.into());
         }
+        if !key_id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(EntityError::InvalidProfileIdHex.into());
+        }
+        if key_id.len() != Self::KEY_ID_LEN {
+            return Err(EntityError::InvalidProfileId.into());
+        }
+
+        Ok(Self::from_key_id(key_id.to_ascii_lowercase()))
+    }
+}
+
+impl FromStr for EntityIdentifier {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::try_from(s)
     }
 }
 
@@ -96,7 +145,9 @@ mod test {
 
     impl EntityIdentifier {
         pub fn random() -> EntityIdentifier {
-            EntityIdentifier(format!("{:x}", thread_rng().next_u64()))
+            let mut key_id = [0u8; 32];
+            thread_rng().fill_bytes(&mut key_id);
+            EntityIdentifier(encode(key_id))
         }
     }
 
@@ -115,4 +166,77 @@ mod test {
         let id2: EntityIdentifier = str.try_into().unwrap();
         assert_eq!(id1, id2);
     }
+
+    #[test]
+    fn test_derive_address() {
+        let alice: EntityIdentifier =
+            "P79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b6"
+                .parse()
+                .unwrap();
+        let bob = EntityIdentifier::random();
+
+        // Stable across runs
+        let address = alice.derive_address("listener");
+        assert_eq!(address, alice.derive_address("listener"));
+        assert_eq!(address.to_string(), "0#651c1963a5b0901b135e02e2f1e958a4");
+
+        let addresses = [
+            address,
+            alice.derive_address("service"),
+            alice.derive_address(""),
+            bob.derive_address("listener"),
+            EntityIdentifier::from_key_id(
+                "79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b".into(),
+            )
+            .derive_address("6listener"),
+        ];
+        for (i, a) in addresses.iter().enumerate() {
+            assert!(addresses[i + 1..].iter().all(|b| a != b));
+        }
+    }
+
+    #[test]
+    fn test_parse_normalizes() {
+        let id: EntityIdentifier =
+            "P79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b6"
+                .try_into()
+                .unwrap();
+
+        for spelling in [
+            "P79B26BA2EA5AD9B54ABE5BEBBCCE7C446BEDA8C948AFC0DE293250090E5270B6",
+            "P79b26BA2ea5AD9b54abe5bebbcce7c446beda8c948afc0de293250090e5270B6",
+            "  P79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b6\n",
+            "\tp79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b6 ",
+        ] {
+            let parsed: EntityIdentifier = spelling.parse().unwrap();
+            assert_eq!(parsed, id);
+            assert_eq!(EntityIdentifier::try_from(spelling).unwrap(), id);
+        }
+        assert_eq!(
+            id.to_string(),
+            "P79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b6"
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed() {
+        let code = |res: Result<EntityIdentifier>| res.err().unwrap().code();
+        let invalid = Error::from(EntityError::InvalidProfileId).code();
+        let invalid_hex = Error::from(EntityError::InvalidProfileIdHex).code();
+
+        assert_eq!(code("79b26ba2ea5a".parse()), invalid);
+        assert_eq!(code("".parse()), invalid);
+        assert_eq!(code(" P ".parse()), invalid);
+        assert_eq!(code("Pab".parse()), invalid);
+        assert_eq!(
+            code("P79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b".parse()),
+            invalid
+        );
+        assert_eq!(
+            code("P79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b6a".parse()),
+            invalid
+        );
+        assert_eq!(code("Pxyz-not-a-key".parse()), invalid_hex);
+        assert_eq!(code("P79b2 6ba2".parse()), invalid_hex);
+    }
 }
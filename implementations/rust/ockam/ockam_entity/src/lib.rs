@@ -20,6 +20,7 @@ extern crate alloc;
 
 use cfg_if::cfg_if;
 
+pub(crate) use address_generator::*;
 pub use change::*;
 pub use channel::*;
 pub use contact::*;
@@ -40,6 +41,7 @@ pub use worker::*;
 
 use crate::EntityError;
 
+mod address_generator;
 mod authentication;
 mod change;
 pub mod change_history;
@@ -137,7 +139,7 @@ mod test {
     use super::*;
     use ockam_core::Error;
     use ockam_node::Context;
-    use ockam_vault_sync_core::Vault;
+    use ockam_vault_sync_core::{Vault, VaultSync};
 
     fn test_error<S: Into<String>>(msg: S) -> Result<()> {
         Err(Error::new(0, msg.into()))
@@ -219,6 +221,191 @@ mod test {
         Ok(())
     }
 
+    #[ockam_macros::test]
+    async fn test_profile_key_rotation(ctx: &mut Context) -> Result<()> {
+        let alice_vault = Vault::create(ctx).await.expect("failed to create vault");
+        let bob_vault = Vault::create(ctx).await.expect("failed to create vault");
+
+        let mut alice = Entity::create(ctx, &alice_vault).await?;
+        let mut bob = Entity::create(ctx, &bob_vault).await?;
+        let alice_id = alice.identifier().await?;
+
+        bob.verify_and_add_contact(alice.as_contact().await?)
+            .await?;
+
+        alice.rotate_profile_key().await?;
+        assert_eq!(alice.identifier().await?, alice_id);
+        assert!(alice.verify_changes().await?);
+
+        let changes = alice.get_changes().await?;
+        assert!(
+            bob.verify_contact(Contact::new(alice_id.clone(), changes.clone()))
+                .await?
+        );
+        let rotation = changes.last().unwrap().clone();
+
+        // Previous key signature replaced with the new key's own signature
+        let self_signature = rotation
+            .signatures()
+            .iter()
+            .find(|s| s.stype() == &SignatureType::SelfSign)
+            .unwrap()
+            .data()
+            .clone();
+        let forged_signatures = rotation
+            .signatures()
+            .iter()
+            .map(|s| match s.stype() {
+                SignatureType::PrevSign => {
+                    Signature::new(SignatureType::PrevSign, self_signature.clone())
+                }
+                _ => s.clone(),
+            })
+            .collect();
+        let forged = ProfileChangeEvent::new(
+            rotation.identifier().clone(),
+            rotation.change_block().clone(),
+            forged_signatures,
+        );
+        let mut tampered_changes = changes.clone();
+        *tampered_changes.last_mut().unwrap() = forged.clone();
+        assert!(
+            !bob.verify_contact(Contact::new(alice_id.clone(), tampered_changes))
+                .await?
+        );
+        assert!(!bob.verify_and_update_contact(&alice_id, &[forged]).await?);
+
+        // Previous key signature missing
+        let stripped_signatures = rotation
+            .signatures()
+            .iter()
+            .filter(|s| s.stype() != &SignatureType::PrevSign)
+            .cloned()
+            .collect();
+        let stripped = ProfileChangeEvent::new(
+            rotation.identifier().clone(),
+            rotation.change_block().clone(),
+            stripped_signatures,
+        );
+        assert!(
+            !bob.verify_and_update_contact(&alice_id, &[stripped])
+                .await?
+        );
+
+        assert!(
+            bob.verify_and_update_contact(&alice_id, &[rotation])
+                .await?
+        );
+        let contact = bob.get_contact(&alice_id).await?.unwrap();
+        assert_eq!(
+            contact.get_profile_update_public_key()?,
+            alice.get_root_public_key().await?
+        );
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_sign_and_verify(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let mut bob = Entity::create(ctx, &vault).await?;
+        let alice_id = alice.identifier().await?;
+        let bob_id = bob.identifier().await?;
+
+        bob.verify_and_add_contact(alice.as_contact().await?)
+            .await?;
+
+        let data = b"config blob";
+        let signature = alice.sign(data).await?;
+        assert!(alice.verify(data, &signature, &alice_id).await?);
+        assert!(bob.verify(data, &signature, &alice_id).await?);
+
+        // Tampered payload
+        assert!(!bob.verify(b"config blog", &signature, &alice_id).await?);
+
+        // Someone else's signature
+        alice
+            .verify_and_add_contact(bob.as_contact().await?)
+            .await?;
+        assert!(!alice.verify(data, &signature, &bob_id).await?);
+
+        // Unknown signer
+        let carol = Entity::create(ctx, &vault).await?;
+        assert!(carol.verify(data, &signature, &alice_id).await.is_err());
+
+        // Signed before the key was rotated, which bob learns about
+        alice.rotate_profile_key().await?;
+        let rotation = alice.get_changes().await?.last().unwrap().clone();
+        assert!(
+            bob.verify_and_update_contact(&alice_id, &[rotation])
+                .await?
+        );
+        assert!(bob.verify(data, &signature, &alice_id).await?);
+        let signature = alice.sign(data).await?;
+        assert!(bob.verify(data, &signature, &alice_id).await?);
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_sign_is_domain_separated(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+        let mut vault_sync = VaultSync::create_with_worker(ctx, &vault).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let alice_id = alice.identifier().await?;
+
+        // Signing the id of a change event doesn't give a signature of that event
+        alice.rotate_profile_key().await?;
+        let event_id = alice
+            .get_changes()
+            .await?
+            .last()
+            .unwrap()
+            .identifier()
+            .clone();
+        let signature = alice.sign(event_id.as_ref()).await?;
+        assert!(
+            alice
+                .verify(event_id.as_ref(), &signature, &alice_id)
+                .await?
+        );
+
+        let public_key = alice.get_root_public_key().await?;
+        assert!(
+            !vault_sync
+                .verify(&signature, &public_key, event_id.as_ref())
+                .await?
+        );
+
+        ctx.stop().await
+    }
+
+    #[ockam_macros::test]
+    async fn test_current_identifier(ctx: &mut Context) -> Result<()> {
+        let vault = Vault::create(ctx).await?;
+
+        let mut alice = Entity::create(ctx, &vault).await?;
+        let alice_id = alice.identifier().await?;
+
+        let (first, second) = (&alice, &alice);
+        let (first_id, second_id) = (first.current_identifier()?, second.current_identifier()?);
+        assert_eq!(first_id, &alice_id);
+        assert_eq!(second_id, &alice_id);
+
+        let (first_id, second_id) =
+            ockam_core::compat::try_join!(first.identifier(), second.identifier())?;
+        assert_eq!(first_id, alice_id);
+        assert_eq!(second_id, alice_id);
+
+        alice.rotate_profile_key().await?;
+        assert_eq!(alice.current_identifier()?, &alice_id);
+
+        ctx.stop().await
+    }
+
     #[ockam_macros::test]
     async fn async_tests(ctx: &mut Context) -> Result<()> {
         let alice_vault = Vault::create(ctx).await.expect("failed to create vault");
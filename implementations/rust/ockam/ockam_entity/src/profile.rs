@@ -21,7 +21,7 @@ use crate::{
     AuthenticationProof, Changes, Contact, Entity, Identity, IdentityRequest, IdentityResponse,
     Lease, ProfileChangeEvent, ProfileIdentifier, TTL,
 };
-use ockam_core::compat::{string::String, vec::Vec};
+use ockam_core::compat::{collections::BTreeMap, string::String, vec::Vec};
 use ockam_core::{async_trait, compat::boxed::Box, AsyncTryClone};
 use ockam_core::{Result, Route};
 use ockam_node::Handle;
@@ -168,6 +168,18 @@ impl Identity for Profile {
             .await
     }
 
+    async fn set_attribute(&mut self, key: String, value: String) -> Result<()> {
+        self.entity().await?.set_attribute(key, value).await
+    }
+
+    async fn get_attribute(&self, key: &str) -> Result<Option<String>> {
+        self.entity().await?.get_attribute(key).await
+    }
+
+    async fn get_attributes(&self) -> Result<BTreeMap<String, String>> {
+        self.entity().await?.get_attributes().await
+    }
+
     async fn get_lease(
         &self,
         lease_manager_route: &Route,
@@ -10,13 +10,22 @@ use crate::{
 use cfg_if::cfg_if;
 use ockam_core::compat::rand::{thread_rng, CryptoRng, RngCore};
 use ockam_core::compat::{
+    collections::BTreeMap,
     string::{String, ToString},
     vec::Vec,
 };
-use ockam_core::vault::{SecretPersistence, SecretType, SecretVault, CURVE25519_SECRET_LENGTH};
-use ockam_core::{allow, deny, Address, AsyncTryClone, Result, Route};
-use ockam_vault::{KeyIdVault, PublicKey, Secret, SecretAttributes};
+use ockam_core::vault::{
+    Hasher, SecretKey, SecretPersistence, SecretType, SecretVault, Signature, Signer, Verifier,
+    CURVE25519_SECRET_LENGTH,
+};
+use ockam_core::{allow, deny, Address, AsyncTryClone, Decodable, Encodable, Result, Route};
+use ockam_vault::{KeyIdVault, PublicKey, Secret, SecretAttributes, VaultError};
 use ockam_vault_sync_core::VaultSync;
+use serde::{Deserialize, Serialize};
+
+/// Hashed with the data of [`ProfileState::sign`], so that the root key never signs anything
+/// that could pass for a change event, a channel authentication proof or a credential
+const APP_SIGNATURE_LABEL: &[u8] = b"OCKAM_ENTITY_APP_SIGNATURE";
 
 cfg_if! {
     if #[cfg(feature = "credentials")] {
@@ -30,6 +39,8 @@ pub struct ProfileState {
     id: ProfileIdentifier,
     change_history: ProfileChangeHistory,
     contacts: Contacts,
+    /// Advertised to peers during secure channel handshakes
+    attributes: BTreeMap<String, String>,
     pub(crate) vault: VaultSync,
     #[cfg(feature = "credentials")]
     pub(crate) rand_msg: Message,
@@ -53,6 +64,7 @@ impl ProfileState {
             id: identifier,
             change_history: ProfileChangeHistory::new(change_events),
             contacts,
+            attributes: BTreeMap::new(),
             vault,
             #[cfg(feature = "credentials")]
             rand_msg: Message::random(rng),
@@ -70,24 +82,69 @@ impl ProfileState {
         self.vault.address()
     }
 
-    /// Create ProfileState
-    pub(crate) async fn create(mut vault: VaultSync) -> Result<Self> {
+    /// Create ProfileState with a root key of given type, which must be able to sign.
+    /// Rotated keys keep the type
+    pub(crate) async fn create(
+        vault: VaultSync,
+        key_type: SecretType,
+        attributes: ProfileEventAttributes,
+    ) -> Result<Self> {
+        Self::check_root_key_type(key_type)?;
+
+        let secret_attributes = SecretAttributes::new(
+            key_type,
+            SecretPersistence::Persistent,
+            CURVE25519_SECRET_LENGTH,
+        );
+
+        Self::create_with_root_key(vault, None, secret_attributes, attributes).await
+    }
+
+    /// Create ProfileState around a root key that is already in the vault, e.g. written there
+    /// during provisioning. Fails with [`EntityError::SecretKeyNotFound`] if the vault doesn't
+    /// have the key, and with [`EntityError::UnsupportedProfileKeyType`] if it can't sign.
+    /// The [`ProfileIdentifier`] follows from the public key, so the same key always gives the same one
+    pub(crate) async fn create_from_existing_key(
+        mut vault: VaultSync,
+        secret: &Secret,
+        attributes: ProfileEventAttributes,
+    ) -> Result<Self> {
+        let secret_attributes = vault
+            .secret_attributes_get(secret)
+            .await
+            .map_err(|_| EntityError::SecretKeyNotFound)?;
+        Self::check_root_key_type(secret_attributes.stype())?;
+
+        Self::create_with_root_key(vault, Some(secret), secret_attributes, attributes).await
+    }
+
+    fn check_root_key_type(key_type: SecretType) -> Result<()> {
+        match key_type {
+            SecretType::Ed25519 | SecretType::X25519 => Ok(()),
+            _ => Err(EntityError::UnsupportedProfileKeyType.into()),
+        }
+    }
+
+    /// Create ProfileState whose change history starts with the root key, which is generated
+    /// with given attributes unless `secret` is set
+    async fn create_with_root_key(
+        mut vault: VaultSync,
+        secret: Option<&Secret>,
+        secret_attributes: SecretAttributes,
+        attributes: ProfileEventAttributes,
+    ) -> Result<Self> {
         let initial_event_id = EventIdentifier::initial(&mut vault).await;
 
         let key_attribs = KeyAttributes::new(
             Profile::ROOT_LABEL.to_string(),
-            MetaKeyAttributes::SecretAttributes(SecretAttributes::new(
-                SecretType::Ed25519,
-                SecretPersistence::Persistent,
-                CURVE25519_SECRET_LENGTH,
-            )),
+            MetaKeyAttributes::SecretAttributes(secret_attributes),
         );
 
         let create_key_event = Self::make_create_key_event_static(
-            None,
+            secret,
             initial_event_id,
-            key_attribs.clone(),
-            ProfileEventAttributes::new(),
+            key_attribs,
+            attributes,
             None,
             &mut vault,
         )
@@ -108,6 +165,23 @@ impl ProfileState {
         Ok(profile)
     }
 
+    /// Destroy the secret keys of the change history still in the vault, the ones rotated out
+    /// included, which wipes them from memory. The profile can't sign anything afterwards
+    pub(crate) async fn destroy_secrets(&mut self) -> Result<()> {
+        let not_found = ockam_core::Error::from(VaultError::SecretNotFound).code();
+        for event in self.change_history.as_ref() {
+            let secret = match Self::get_secret_key_from_event(event, &mut self.vault).await {
+                Ok(secret) => secret,
+                // Destroyed already, or never imported
+                Err(err) if err.code() == not_found => continue,
+                Err(err) => return Err(err),
+            };
+            self.vault.secret_destroy(secret).await?;
+        }
+
+        Ok(())
+    }
+
     pub(crate) async fn get_secret_key_from_event(
         event: &ProfileChangeEvent,
         vault: &mut impl ProfileVault,
@@ -128,6 +202,118 @@ impl ProfileState {
     }
 }
 
+/// [`ProfileState`] in binary form, used to store a profile and load it after a restart
+#[derive(Serialize, Deserialize)]
+struct ExportedProfile {
+    id: ProfileIdentifier,
+    change_history: ProfileChangeHistory,
+    contacts: Vec<Contact>,
+    secrets: Vec<ExportedSecret>,
+    attributes: BTreeMap<String, String>,
+}
+
+/// Current secret key for a label, as exported from the vault
+#[derive(Serialize, Deserialize)]
+struct ExportedSecret {
+    label: String,
+    key: SecretKey,
+    attributes: SecretAttributes,
+}
+
+/// Labels of all keys in the change history, each listed once
+fn key_labels(events: &[ProfileChangeEvent]) -> Vec<&str> {
+    let mut labels: Vec<&str> = Vec::new();
+    for event in events {
+        let label = event.change_block().change().label();
+        if !labels.contains(&label) {
+            labels.push(label);
+        }
+    }
+    labels
+}
+
+impl ProfileState {
+    /// Serialize identifier, change history, contacts and attributes. Current secret keys are
+    /// only included if `with_secrets` is set, otherwise the vault has to keep them
+    pub(crate) async fn export(&mut self, with_secrets: bool) -> Result<Vec<u8>> {
+        let mut secrets = Vec::new();
+        if with_secrets {
+            for label in key_labels(self.change_history.as_ref()) {
+                let event =
+                    ProfileChangeHistory::find_last_key_event(self.change_history.as_ref(), label)?;
+                let secret = Self::get_secret_key_from_event(event, &mut self.vault).await?;
+                secrets.push(ExportedSecret {
+                    label: label.to_string(),
+                    key: self.vault.secret_export(&secret).await?,
+                    attributes: self.vault.secret_attributes_get(&secret).await?,
+                });
+            }
+        }
+
+        let exported = ExportedProfile {
+            id: self.id.clone(),
+            change_history: self.change_history.clone(),
+            contacts: self.contacts.values().cloned().collect(),
+            secrets,
+            attributes: self.attributes.clone(),
+        };
+
+        exported.encode().map_err(|_| EntityError::BareError.into())
+    }
+
+    /// Recreate ProfileState from [`ProfileState::export`] output. Secret keys already
+    /// present in the vault are used as is, missing ones are imported from the data
+    pub(crate) async fn import(mut vault: VaultSync, data: &[u8]) -> Result<Self> {
+        let exported = ExportedProfile::decode(data).map_err(|_| EntityError::BareError)?;
+
+        for label in key_labels(exported.change_history.as_ref()) {
+            let event =
+                ProfileChangeHistory::find_last_key_event(exported.change_history.as_ref(), label)?;
+            if Self::get_secret_key_from_event(event, &mut vault)
+                .await
+                .is_ok()
+            {
+                continue;
+            }
+
+            let secret = exported
+                .secrets
+                .iter()
+                .find(|s| s.label == label)
+                .ok_or(EntityError::SecretKeyNotFound)?;
+            vault
+                .secret_import(secret.key.as_ref(), secret.attributes)
+                .await?;
+
+            // Imported key must be the one the change history refers to
+            Self::get_secret_key_from_event(event, &mut vault)
+                .await
+                .map_err(|_| EntityError::SecretKeyNotFound)?;
+        }
+
+        let contacts = exported
+            .contacts
+            .into_iter()
+            .map(|contact| (contact.identifier().clone(), contact))
+            .collect();
+
+        let mut profile = Self::new(
+            exported.id,
+            exported.change_history.as_ref().to_vec(),
+            contacts,
+            vault,
+            thread_rng(),
+        );
+        profile.attributes = exported.attributes;
+
+        if !profile.verify_changes().await? {
+            return Err(EntityError::VerifyFailed.into());
+        }
+
+        Ok(profile)
+    }
+}
+
 impl ProfileState {
     pub async fn identifier(&self) -> Result<ProfileIdentifier> {
         Ok(self.id.clone())
@@ -216,6 +402,50 @@ impl ProfileState {
         .await
     }
 
+    /// Sign data with the current root key. What's signed is `SHA256(label || data)`, see
+    /// [`APP_SIGNATURE_LABEL`]
+    pub async fn sign(&mut self, data: &[u8]) -> Result<Signature> {
+        let root_secret = self.get_root_secret_key().await?;
+        let digest = self.app_signature_digest(data).await?;
+
+        self.vault.sign(&root_secret, &digest).await
+    }
+
+    async fn app_signature_digest(&mut self, data: &[u8]) -> Result<[u8; 32]> {
+        let mut labeled = APP_SIGNATURE_LABEL.to_vec();
+        labeled.extend_from_slice(data);
+
+        self.vault.sha256(&labeled).await
+    }
+
+    /// Verify a signature of given [`Profile`], which is us or a known contact.
+    /// Signatures of root keys rotated since are valid, as long as the key is in the verified history
+    pub async fn verify_signature(
+        &mut self,
+        data: &[u8],
+        signature: &Signature,
+        signer_id: &ProfileIdentifier,
+    ) -> Result<bool> {
+        let events = if signer_id == &self.id {
+            self.change_history.as_ref().to_vec()
+        } else {
+            self.get_contact(signer_id)
+                .await?
+                .ok_or(EntityError::ContactNotFound)?
+                .change_events()
+                .to_vec()
+        };
+        let digest = self.app_signature_digest(data).await?;
+
+        for public_key in ProfileChangeHistory::get_root_public_keys(&events) {
+            if self.vault.verify(signature, &public_key, &digest).await? {
+                return allow();
+            }
+        }
+
+        deny()
+    }
+
     pub async fn add_change(&mut self, change_event: ProfileChangeEvent) -> Result<()> {
         let slice = core::slice::from_ref(&change_event);
         if ProfileChangeHistory::check_consistency(self.change_history.as_ref(), slice) {
@@ -268,14 +498,20 @@ impl ProfileState {
         ))
     }
 
+    pub(crate) fn set_attribute(&mut self, key: String, value: String) {
+        self.attributes.insert(key, value);
+    }
+
+    pub(crate) fn attributes(&self) -> &BTreeMap<String, String> {
+        &self.attributes
+    }
+
     pub async fn get_contact(&mut self, id: &ProfileIdentifier) -> Result<Option<Contact>> {
         Ok(self.contacts.get(id).cloned())
     }
 
     pub async fn verify_contact(&mut self, contact: Contact) -> Result<bool> {
-        contact.verify(&mut self.vault).await?;
-
-        allow()
+        contact.verify(&mut self.vault).await
     }
 
     pub async fn verify_and_add_contact(&mut self, contact: Contact) -> Result<bool> {
@@ -1,5 +1,5 @@
 use crate::{Changes, Contact, Lease, ProfileChangeEvent, ProfileIdentifier, TTL};
-use ockam_core::compat::{string::String, vec::Vec};
+use ockam_core::compat::{collections::BTreeMap, string::String, vec::Vec};
 use ockam_core::vault::{PublicKey, Secret};
 use ockam_core::{async_trait, compat::boxed::Box, AsyncTryClone};
 use ockam_core::{Result, Route};
@@ -75,6 +75,16 @@ pub trait Identity: AsyncTryClone + Send + Sync + 'static {
         change_events: &[ProfileChangeEvent],
     ) -> Result<bool>;
 
+    /// Set attribute advertised to peers during secure channel handshakes, replacing
+    /// the previous value of the key
+    async fn set_attribute(&mut self, key: String, value: String) -> Result<()>;
+
+    /// Return attribute with given key
+    async fn get_attribute(&self, key: &str) -> Result<Option<String>>;
+
+    /// Return all attributes
+    async fn get_attributes(&self) -> Result<BTreeMap<String, String>>;
+
     async fn get_lease(
         &self,
         lease_manager_route: &Route,
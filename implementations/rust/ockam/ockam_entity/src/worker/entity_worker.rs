@@ -1,22 +1,67 @@
 use crate::{
-    EntityError::IdentityApiFailed, IdentityRequest, IdentityRequest::*, IdentityResponse as Res,
-    MaybeContact, Profile, ProfileChannelListener, ProfileIdentifier, ProfileState,
-    SecureChannelWorker, TrustPolicyImpl,
+    AddressGenerator, AuthenticationConfirmation, EntityError, EntityError::IdentityApiFailed,
+    IdentityRequest, IdentityRequest::*, IdentityResponse as Res, InitiatorAddresses, MaybeContact,
+    Profile, ProfileChannelListener, ProfileIdentifier, ProfileState, SecureChannelHandle,
+    SecureChannelHandshakes, SecureChannelOptions, SecureChannelRegistry, SecureChannelServices,
+    SecureChannelWorker, TrustIdentifierPolicy, TrustPolicyImpl,
 };
 use core::result::Result::Ok;
 use ockam_core::{
-    async_trait::async_trait, compat::boxed::Box, compat::collections::HashMap, Address, Result,
-    Routed, Worker,
+    async_trait::async_trait, compat::boxed::Box, compat::collections::HashMap,
+    compat::string::String, compat::vec::Vec, route, Address, Message, Result, Route, Routed,
+    Worker,
 };
-use ockam_node::{Context, Handle};
+use ockam_node::{Context, Handle, NodeError};
 use ockam_vault_sync_core::VaultSync;
 
 #[cfg(feature = "lease_proto_json")]
 use crate::lease::json_proto::{LeaseProtocolRequest, LeaseProtocolResponse};
 
+/// Channel to a peer, shared by the [`Entity::send_to`](crate::Entity::send_to) calls
+enum ChannelTo {
+    /// Being created, the routes wait for its address
+    Pending(Vec<Route>),
+    Open(Address),
+}
+
+/// Channel an [`Entity::create_secure_channel`](crate::Entity::create_secure_channel) call waits for
+struct PendingChannel {
+    reply: Route,
+    /// Where the initiator waits for the handshake to complete
+    callback: Address,
+    trust_policy_address: Address,
+    /// The call was dropped, so the channel is stopped once it's created
+    cancelled: bool,
+}
+
 #[derive(Default)]
 pub struct EntityWorker {
     profiles: HashMap<ProfileIdentifier, ProfileState>,
+    /// Secondary profiles, by the name they were given
+    profile_names: HashMap<String, ProfileIdentifier>,
+    /// Channels of every profile, kept up to date by the channels themselves
+    secure_channels: Vec<(ProfileIdentifier, SecureChannelHandle)>,
+    /// Services of every listener, by listener address
+    listener_services: HashMap<Address, SecureChannelServices>,
+    /// Handshakes every listener has in progress, by listener address
+    listener_handshakes: HashMap<Address, SecureChannelHandshakes>,
+    /// Channels [`Entity::send_to`](crate::Entity::send_to) goes through, by our and their profile
+    channels_to: HashMap<(ProfileIdentifier, ProfileIdentifier), ChannelTo>,
+    /// Channels being created, by the address of the call waiting for them
+    pending_channels: HashMap<Address, PendingChannel>,
+    /// Trust policy workers started for the listeners, services and channels, stopped
+    /// on shutdown
+    trust_policy_workers: Vec<Address>,
+    addresses: AddressGenerator,
+}
+
+impl EntityWorker {
+    pub(crate) fn new(addresses: AddressGenerator) -> Self {
+        Self {
+            addresses,
+            ..Default::default()
+        }
+    }
 }
 
 impl EntityWorker {
@@ -25,6 +70,58 @@ impl EntityWorker {
             .get_mut(profile_id)
             .expect("default profile invalid")
     }
+
+    /// Start a secure channel initiator in the background, which sends the outcome
+    /// of the handshake to `reply`, as made by `respond`
+    #[allow(clippy::too_many_arguments)]
+    async fn start_initiator<M, F>(
+        &mut self,
+        ctx: &Context,
+        profile_id: ProfileIdentifier,
+        route: Route,
+        trust_policy_address: Address,
+        options: SecureChannelOptions,
+        inherited_from: Option<ProfileIdentifier>,
+        reply: Route,
+        respond: F,
+    ) -> Result<Address>
+    where
+        M: Message + Send + 'static,
+        F: FnOnce(Result<Address>) -> M + Send + 'static,
+    {
+        let trust_policy = TrustPolicyImpl::new(Handle::new(
+            ctx.new_context(Address::random(0)).await?,
+            trust_policy_address,
+        ));
+        let vault_address = self.profile(&profile_id).vault_address();
+        let handle = Handle::new(ctx.new_context(Address::random(0)).await?, ctx.address());
+        let profile = Profile::new(profile_id, handle);
+        let registry = SecureChannelRegistry::new(ctx.address());
+        // Before spawning, so that they don't depend on the order tasks run in
+        let addresses = InitiatorAddresses::generate(&self.addresses);
+        let callback = addresses.callback().clone();
+
+        let child_ctx = ctx.new_context(Address::random(0)).await?;
+        let rt = ctx.runtime();
+        rt.spawn(async move {
+            let vault = VaultSync::create_with_worker(&child_ctx, &vault_address).await?;
+            let res = SecureChannelWorker::create_initiator(
+                &child_ctx,
+                route,
+                profile,
+                trust_policy,
+                vault,
+                options,
+                inherited_from,
+                registry,
+                addresses,
+            )
+            .await;
+            child_ctx.send(reply, respond(res)).await
+        });
+
+        Ok(callback)
+    }
 }
 
 fn err<T>() -> Result<T> {
@@ -44,14 +141,16 @@ impl Worker for EntityWorker {
         let reply = msg.return_route();
         let req = msg.body();
         match req {
-            CreateProfile(vault_address) => {
+            CreateProfile(vault_address, key_type, attributes) => {
                 let vault_sync = VaultSync::create_with_worker(ctx, &vault_address)
                     .await
                     .expect("couldn't create profile vault");
 
-                let profile_state = ProfileState::create(vault_sync)
-                    .await
-                    .expect("failed to create ProfileState");
+                let profile_state =
+                    match ProfileState::create(vault_sync, key_type, attributes).await {
+                        Ok(profile_state) => profile_state,
+                        Err(err) => return ctx.send(reply, Res::Error(err)).await,
+                    };
 
                 let id = profile_state
                     .identifier()
@@ -64,7 +163,61 @@ impl Worker for EntityWorker {
 
                 ctx.send(reply, Res::CreateProfile(id)).await
             }
+            CreateProfileFromKey(vault_address, secret, attributes) => {
+                let vault_sync = VaultSync::create_with_worker(ctx, &vault_address).await?;
+
+                let res =
+                    match ProfileState::create_from_existing_key(vault_sync, &secret, attributes)
+                        .await
+                    {
+                        Ok(profile_state) => {
+                            let id = profile_state.identifier().await?;
+                            self.add_profile_state(profile_state).await?;
+                            Res::CreateProfile(id)
+                        }
+                        Err(err) => Res::Error(err),
+                    };
+                ctx.send(reply, res).await
+            }
             RemoveProfile(profile_id) => self.remove_profile(profile_id),
+            NameProfile(name, profile_id) => {
+                let res = if !self.profiles.contains_key(&profile_id) {
+                    Res::Error(EntityError::ProfileNotFound.into())
+                } else if self.profile_names.contains_key(&name) {
+                    Res::Error(EntityError::ProfileNameInUse.into())
+                } else {
+                    self.profile_names.insert(name, profile_id);
+                    Res::NameProfile
+                };
+                ctx.send(reply, res).await
+            }
+            GetNamedProfile(name) => {
+                let res = match self.profile_names.get(&name) {
+                    Some(profile_id) => Res::GetNamedProfile(profile_id.clone()),
+                    None => Res::Error(EntityError::ProfileNotFound.into()),
+                };
+                ctx.send(reply, res).await
+            }
+            ExportProfile(profile_id, with_secrets) => {
+                let res = match self.profile(&profile_id).export(with_secrets).await {
+                    Ok(data) => Res::ExportProfile(data),
+                    Err(err) => Res::Error(err),
+                };
+                ctx.send(reply, res).await
+            }
+            ImportProfile(vault_address, data) => {
+                let vault_sync = VaultSync::create_with_worker(ctx, &vault_address).await?;
+
+                let res = match ProfileState::import(vault_sync, &data).await {
+                    Ok(profile_state) => {
+                        let id = profile_state.identifier().await?;
+                        self.add_profile_state(profile_state).await?;
+                        Res::ImportProfile(id)
+                    }
+                    Err(err) => Res::Error(err),
+                };
+                ctx.send(reply, res).await
+            }
             CreateKey(profile_id, label) => {
                 let profile = self.profile(&profile_id);
 
@@ -82,6 +235,13 @@ impl Worker for EntityWorker {
 
                 profile.rotate_root_secret_key().await
             }
+            RotateProfileKey(profile_id) => {
+                let res = match self.profile(&profile_id).rotate_root_secret_key().await {
+                    Ok(()) => Res::RotateProfileKey,
+                    Err(err) => Res::Error(err),
+                };
+                ctx.send(reply, res).await
+            }
             GetProfilePublicKey(profile_id) => {
                 if let Ok(public_key) = self.profile(&profile_id).get_root_public_key().await {
                     ctx.send(reply, Res::GetProfilePublicKey(public_key)).await
@@ -133,6 +293,24 @@ impl Worker for EntityWorker {
                     err()
                 }
             }
+            Sign(profile_id, data) => {
+                let res = match self.profile(&profile_id).sign(data.as_slice()).await {
+                    Ok(signature) => Res::Sign(signature),
+                    Err(err) => Res::Error(err),
+                };
+                ctx.send(reply, res).await
+            }
+            VerifySignature(profile_id, data, signature, signer_id) => {
+                let res = match self
+                    .profile(&profile_id)
+                    .verify_signature(data.as_slice(), &signature, &signer_id)
+                    .await
+                {
+                    Ok(verified) => Res::VerifySignature(verified),
+                    Err(err) => Res::Error(err),
+                };
+                ctx.send(reply, res).await
+            }
             AddChange(profile_id, change) => self.profile(&profile_id).add_change(change).await,
             GetChanges(profile_id) => {
                 let changes = self
@@ -169,6 +347,14 @@ impl Worker for EntityWorker {
                     .await?;
                 ctx.send(reply, Res::VerifyAndUpdateContact(verified)).await
             }
+            SetAttribute(profile_id, key, value) => {
+                self.profile(&profile_id).set_attribute(key, value);
+                ctx.send(reply, Res::SetAttribute).await
+            }
+            GetAttributes(profile_id) => {
+                let attributes = self.profile(&profile_id).attributes().clone();
+                ctx.send(reply, Res::Attributes(attributes)).await
+            }
             GetContact(profile_id, contact_id) => {
                 let contact = self.profile(&profile_id).get_contact(&contact_id).await?;
                 let message = match contact {
@@ -177,7 +363,16 @@ impl Worker for EntityWorker {
                 };
                 ctx.send(reply, Res::GetContact(message)).await
             }
-            CreateSecureChannelListener(profile_id, address, trust_policy_address) => {
+            CreateSecureChannelListener(profile_id, address, trust_policy_address, options) => {
+                self.trust_policy_workers.push(trust_policy_address.clone());
+                if self.listener_handshakes.contains_key(&address) {
+                    return ctx
+                        .send(
+                            reply,
+                            Res::Error(EntityError::SecureChannelListenerAddressInUse.into()),
+                        )
+                        .await;
+                }
                 let trust_policy = TrustPolicyImpl::new(Handle::new(
                     ctx.new_context(Address::random(0)).await?,
                     trust_policy_address,
@@ -186,38 +381,288 @@ impl Worker for EntityWorker {
                 let handle = Handle::new(ctx.new_context(Address::random(0)).await?, ctx.address());
                 let profile = Profile::new(profile_id, handle);
                 let vault = VaultSync::create_with_worker(ctx, &vault_address).await?;
-                let listener = ProfileChannelListener::new(trust_policy, profile, vault);
-                ctx.start_worker(address, listener).await?;
-                ctx.send(reply, Res::CreateSecureChannelListener).await
+                let registry = SecureChannelRegistry::new(ctx.address());
+                let services = SecureChannelServices::default();
+                let handshakes = SecureChannelHandshakes::default();
+                let listener = ProfileChannelListener::new(
+                    trust_policy,
+                    profile,
+                    vault,
+                    options,
+                    registry,
+                    services.clone(),
+                    handshakes.clone(),
+                    self.addresses.clone(),
+                );
+                let res = match ctx.start_worker(address.clone(), listener).await {
+                    Ok(()) => {
+                        self.listener_services.insert(address.clone(), services);
+                        self.listener_handshakes.insert(address, handshakes);
+                        Res::CreateSecureChannelListener
+                    }
+                    Err(err)
+                        if err.code()
+                            == ockam_core::Error::from(NodeError::WorkerExists(
+                                address.clone(),
+                            ))
+                            .code() =>
+                    {
+                        Res::Error(EntityError::SecureChannelListenerAddressInUse.into())
+                    }
+                    Err(err) => Res::Error(err),
+                };
+                ctx.send(reply, res).await
             }
-            CreateSecureChannel(profile_id, route, trust_policy_address) => {
-                let trust_policy = TrustPolicyImpl::new(Handle::new(
-                    ctx.new_context(Address::random(0)).await?,
-                    trust_policy_address,
-                ));
-                let vault_address = self.profile(&profile_id).vault_address();
-                let handle = Handle::new(ctx.new_context(Address::random(0)).await?, ctx.address());
-                let profile = Profile::new(profile_id.clone(), handle);
-
-                let child_ctx = ctx.new_context(Address::random(0)).await?;
-                let rt = ctx.runtime();
-                rt.spawn(async move {
-                    let vault = VaultSync::create_with_worker(&child_ctx, &vault_address).await?;
-                    let address = SecureChannelWorker::create_initiator(
-                        &child_ctx,
+            AddSecureChannelService(listener_address, service, trust_policy_address) => {
+                self.trust_policy_workers.push(trust_policy_address.clone());
+                let res = match self.listener_services.get(&listener_address) {
+                    Some(services) => {
+                        services.add(service, trust_policy_address);
+                        Res::AddSecureChannelService
+                    }
+                    None => Res::Error(EntityError::SecureChannelListenerNotFound.into()),
+                };
+                ctx.send(reply, res).await
+            }
+            StopSecureChannelListener(listener_address) => {
+                self.listener_services.remove(&listener_address);
+                let res = match self.listener_handshakes.remove(&listener_address) {
+                    Some(handshakes) => match ctx.stop_worker(listener_address).await {
+                        // The listener cancels them as well, but only once it shut down
+                        Ok(()) => match handshakes.cancel(ctx).await {
+                            Ok(()) => Res::StopSecureChannelListener,
+                            Err(err) => Res::Error(err),
+                        },
+                        Err(err) => Res::Error(err),
+                    },
+                    None => Res::Error(EntityError::SecureChannelListenerNotFound.into()),
+                };
+                ctx.send(reply, res).await
+            }
+            DrainSecureChannelListener(listener_address) => {
+                let res = match self.listener_handshakes.get(&listener_address) {
+                    Some(handshakes) => {
+                        handshakes.drain();
+                        Res::DrainSecureChannelListener
+                    }
+                    None => Res::Error(EntityError::SecureChannelListenerNotFound.into()),
+                };
+                ctx.send(reply, res).await
+            }
+            CreateSecureChannel(profile_id, mut route, trust_policy_address, options) => {
+                self.trust_policy_workers.push(trust_policy_address.clone());
+                // Anonymous channels have no identity to inherit trust for, and channels inheriting
+                // trust go through the transport of the outer channel
+                if options.inherited_trust()
+                    && (options.anonymous() || options.transport().is_some())
+                {
+                    return ctx
+                        .send(reply, Res::Error(EntityError::InvalidParameter.into()))
+                        .await;
+                }
+                // The other side has to be the peer of the channel the route starts with
+                let inherited_from = if options.inherited_trust() {
+                    let outer = route.next().ok().and_then(|address| {
+                        self.secure_channels
+                            .iter()
+                            .find(|(id, handle)| id == &profile_id && handle.address() == address)
+                    });
+                    match outer {
+                        Some((_, handle)) => Some(handle.their_profile_id().clone()),
+                        None => {
+                            return ctx
+                                .send(
+                                    reply,
+                                    Res::Error(EntityError::InheritedTrustUnavailable.into()),
+                                )
+                                .await
+                        }
+                    }
+                } else {
+                    None
+                };
+
+                if let Some(transport) = options.transport() {
+                    let running =
+                        transport.tt == 0 && ctx.list_workers().await?.contains(transport);
+                    if !running {
+                        return ctx
+                            .send(
+                                reply,
+                                Res::Error(EntityError::SecureChannelTransportNotFound.into()),
+                            )
+                            .await;
+                    }
+                    route.modify().prepend(transport.clone());
+                }
+
+                // The result comes back here first, in case the call is dropped meanwhile
+                let waiting = reply.recipient();
+                let callback = self
+                    .start_initiator(
+                        ctx,
+                        profile_id,
                         route,
-                        profile,
-                        trust_policy,
-                        vault,
+                        trust_policy_address.clone(),
+                        options,
+                        inherited_from,
+                        route![ctx.address()],
+                        {
+                            let waiting = waiting.clone();
+                            move |res| SecureChannelCreated(waiting, res)
+                        },
                     )
                     .await?;
-                    child_ctx
-                        .send(reply, Res::CreateSecureChannel(address))
-                        .await
-                });
+                self.pending_channels.insert(
+                    waiting,
+                    PendingChannel {
+                        reply,
+                        callback,
+                        trust_policy_address,
+                        cancelled: false,
+                    },
+                );
+                Ok(())
+            }
+            SecureChannelCreated(waiting, res) => {
+                let pending = match self.pending_channels.remove(&waiting) {
+                    Some(pending) => pending,
+                    // Shut down meanwhile
+                    None => return Ok(()),
+                };
+                if !pending.cancelled {
+                    let res = match res {
+                        Ok(address) => Res::CreateSecureChannel(address),
+                        Err(err) => Res::Error(err),
+                    };
+                    return ctx.send(pending.reply, res).await;
+                }
 
+                if let Ok(address) = res {
+                    let _ = ctx.stop_worker(address).await;
+                }
+                self.trust_policy_workers
+                    .retain(|address| address != &pending.trust_policy_address);
+                let _ = ctx.stop_worker(pending.trust_policy_address).await;
+                // Once everything the call started is gone
+                ctx.send(pending.reply, Res::CancelSecureChannel).await
+            }
+            CancelSecureChannel => {
+                let pending = match self.pending_channels.get_mut(&reply.recipient()) {
+                    Some(pending) => pending,
+                    // Answered already
+                    None => return ctx.send(reply, Res::CancelSecureChannel).await,
+                };
+                pending.cancelled = true;
+                // Ends the handshake right away, unless it completes first. The initiator
+                // may not be waiting yet, then it's stopped once the handshake is over
+                let _ = ctx
+                    .send(
+                        pending.callback.clone(),
+                        AuthenticationConfirmation(Err(EntityError::SecureChannelCancelled.into())),
+                    )
+                    .await;
                 Ok(())
             }
+            GetSecureChannelTo(profile_id, their_profile_id, route) => {
+                let key = (profile_id.clone(), their_profile_id.clone());
+                match self.channels_to.get_mut(&key) {
+                    Some(ChannelTo::Open(address)) => {
+                        return ctx
+                            .send(reply, Res::CreateSecureChannel(address.clone()))
+                            .await
+                    }
+                    Some(ChannelTo::Pending(waiting)) => {
+                        waiting.push(reply);
+                        return Ok(());
+                    }
+                    None => {}
+                }
+                self.channels_to
+                    .insert(key, ChannelTo::Pending(vec![reply]));
+
+                let trust_policy = TrustIdentifierPolicy::new(their_profile_id.clone());
+                let trust_policy_address =
+                    TrustPolicyImpl::create_worker(ctx, trust_policy).await?;
+                self.trust_policy_workers.push(trust_policy_address.clone());
+                self.start_initiator(
+                    ctx,
+                    profile_id.clone(),
+                    route,
+                    trust_policy_address,
+                    SecureChannelOptions::new(),
+                    None,
+                    route![ctx.address()],
+                    move |res| SecureChannelToCreated(profile_id, their_profile_id, res),
+                )
+                .await?;
+                Ok(())
+            }
+            SecureChannelToCreated(profile_id, their_profile_id, res) => {
+                let key = (profile_id, their_profile_id);
+                let waiting = match self.channels_to.remove(&key) {
+                    Some(ChannelTo::Pending(waiting)) => waiting,
+                    // Shut down meanwhile
+                    _ => return Ok(()),
+                };
+                if let Ok(address) = &res {
+                    self.channels_to
+                        .insert(key, ChannelTo::Open(address.clone()));
+                }
+                for reply in waiting {
+                    let res = match &res {
+                        Ok(address) => Res::CreateSecureChannel(address.clone()),
+                        Err(err) => Res::Error(err.clone()),
+                    };
+                    ctx.send(reply, res).await?;
+                }
+                Ok(())
+            }
+            RegisterSecureChannel(profile_id, handle) => {
+                self.secure_channels.push((profile_id, handle));
+                Ok(())
+            }
+            DeregisterSecureChannel(address) => {
+                self.secure_channels
+                    .retain(|(_, handle)| handle.address() != &address);
+                self.channels_to.retain(
+                    |_, channel| !matches!(channel, ChannelTo::Open(open) if open == &address),
+                );
+                Ok(())
+            }
+            GetSecureChannels(profile_id) => {
+                let channels = self
+                    .secure_channels
+                    .iter()
+                    .filter(|(id, _)| id == &profile_id)
+                    .map(|(_, handle)| handle.clone())
+                    .collect();
+                ctx.send(reply, Res::SecureChannels(channels)).await
+            }
+            Shutdown => {
+                self.stop_workers(ctx).await?;
+
+                // Nobody waits for the reply when the entity was dropped
+                let _ = ctx.send(reply, Res::Shutdown).await;
+                ctx.stop_worker(ctx.address()).await
+            }
+            Delete => {
+                // Every step is taken even if one fails, so that as little as possible is left
+                let mut first_err = self.stop_workers(ctx).await.err();
+                for (_, mut profile) in self.profiles.drain() {
+                    if let Err(err) = profile.destroy_secrets().await {
+                        first_err.get_or_insert(err);
+                    }
+                }
+                self.profile_names.clear();
+
+                let res = match first_err {
+                    Some(err) => Res::Error(err),
+                    None => Res::Delete,
+                };
+                ctx.send(reply, res).await?;
+                ctx.stop_worker(ctx.address()).await
+            }
             GetLease(lease_manager_route, profile_id, org_id, bucket, ttl) => {
                 let profile = self.profile(&profile_id);
                 if let Ok(lease) = profile
@@ -507,9 +952,49 @@ impl EntityWorker {
     }
 
     fn remove_profile<I: Into<ProfileIdentifier>>(&mut self, profile_id: I) -> Result<()> {
+        let profile_id = profile_id.into();
         self.profiles
-            .remove(&profile_id.into())
+            .remove(&profile_id)
             .expect("remove_profile failed");
+        self.profile_names.retain(|_, id| id != &profile_id);
         Ok(())
     }
+
+    /// Stop the listeners, channels and trust policy workers of the entity. Channels being
+    /// created fail with [`EntityError::SecureChannelCancelled`]. Every worker is stopped even
+    /// if one fails, the first error is returned
+    async fn stop_workers(&mut self, ctx: &Context) -> Result<()> {
+        let mut first_err = None;
+        // Listeners first, so that they don't accept channels meanwhile
+        self.listener_services.clear();
+        for (listener_address, handshakes) in self.listener_handshakes.drain() {
+            // Ignore the error in case the listener was stopped already
+            let _ = ctx.stop_worker(listener_address).await;
+            if let Err(err) = handshakes.cancel(ctx).await {
+                first_err.get_or_insert(err);
+            }
+        }
+        self.channels_to.clear();
+        for (_, pending) in self.pending_channels.drain() {
+            let err = EntityError::SecureChannelCancelled;
+            let _ = ctx
+                .send(
+                    pending.callback,
+                    AuthenticationConfirmation(Err(err.into())),
+                )
+                .await;
+            let _ = ctx.send(pending.reply, Res::Error(err.into())).await;
+        }
+        for (_, handle) in self.secure_channels.drain(..) {
+            let _ = ctx.stop_worker(handle.address().clone()).await;
+        }
+        for trust_policy_address in self.trust_policy_workers.drain(..) {
+            let _ = ctx.stop_worker(trust_policy_address).await;
+        }
+
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
 }
@@ -1,10 +1,12 @@
 use crate::{
-    AuthenticationProof, Changes, Contact, Lease, ProfileChangeEvent, ProfileIdentifier, TTL,
+    AuthenticationProof, Changes, Contact, Lease, ProfileChangeEvent, ProfileEventAttributes,
+    ProfileIdentifier, SecureChannelHandle, SecureChannelListenerOptions, SecureChannelOptions,
+    TTL,
 };
 use cfg_if::cfg_if;
 use ockam_core::compat::{string::String, vec::Vec};
-use ockam_core::vault::Secret;
-use ockam_core::{Address, Message, Route};
+use ockam_core::vault::{Secret, SecretType, Signature};
+use ockam_core::{Address, Message, Result, Route};
 use serde::{Deserialize, Serialize};
 
 pub type EventAttribute = (String, String);
@@ -15,7 +17,8 @@ pub type Id = ProfileIdentifier;
 #[allow(clippy::large_enum_variant)]
 #[derive(Clone, Serialize, Deserialize, Message)]
 pub enum IdentityRequest {
-    CreateProfile(Address),
+    CreateProfile(Address, SecretType, ProfileEventAttributes),
+    CreateProfileFromKey(Address, Secret, ProfileEventAttributes),
     CreateAuthenticationProof(Id, ByteVec),
     CreateKey(Id, String),
     AddKey(Id, String, Secret),
@@ -27,15 +30,36 @@ pub enum IdentityRequest {
     GetContacts(Id),
     GetContact(Id, Id),
     RotateKey(Id),
+    RotateProfileKey(Id),
     AddChange(Id, ProfileChangeEvent),
     VerifyAuthenticationProof(Id, ByteVec, Id, AuthenticationProof),
+    Sign(Id, ByteVec),
+    VerifySignature(Id, ByteVec, Signature, Id),
     VerifyChanges(Id),
     VerifyAndAddContact(Id, Contact),
     VerifyContact(Id, Contact),
     VerifyAndUpdateContact(Id, Id, Changes),
+    SetAttribute(Id, String, String),
+    GetAttributes(Id),
     RemoveProfile(Id),
-    CreateSecureChannelListener(Id, Address, Address),
-    CreateSecureChannel(Id, Route, Address),
+    NameProfile(String, Id),
+    GetNamedProfile(String),
+    ExportProfile(Id, bool),
+    ImportProfile(Address, ByteVec),
+    CreateSecureChannelListener(Id, Address, Address, SecureChannelListenerOptions),
+    AddSecureChannelService(Address, String, Address),
+    StopSecureChannelListener(Address),
+    DrainSecureChannelListener(Address),
+    CreateSecureChannel(Id, Route, Address, SecureChannelOptions),
+    SecureChannelCreated(Address, Result<Address>),
+    CancelSecureChannel,
+    RegisterSecureChannel(Id, SecureChannelHandle),
+    DeregisterSecureChannel(Address),
+    GetSecureChannels(Id),
+    GetSecureChannelTo(Id, Id, Route),
+    SecureChannelToCreated(Id, Id, Result<Address>),
+    Shutdown,
+    Delete,
     GetLease(Route, Id, String, String, TTL),
     RevokeLease(Route, Id, Lease),
     #[cfg(feature = "credentials")]
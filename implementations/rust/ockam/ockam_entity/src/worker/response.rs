@@ -1,7 +1,8 @@
-use crate::{AuthenticationProof, Changes, Contact, Lease, ProfileIdentifier};
+use crate::{AuthenticationProof, Changes, Contact, Lease, ProfileIdentifier, SecureChannelHandle};
 use cfg_if::cfg_if;
-use ockam_core::compat::vec::Vec;
-use ockam_core::{Address, Message};
+use ockam_core::compat::{collections::BTreeMap, string::String, vec::Vec};
+use ockam_core::vault::Signature;
+use ockam_core::{Address, Error, Message};
 use ockam_vault::{PublicKey, Secret};
 use serde::{Deserialize, Serialize};
 
@@ -14,7 +15,12 @@ pub enum MaybeContact {
 #[derive(Serialize, Deserialize, Message)]
 pub enum IdentityResponse {
     AddKey,
+    RotateProfileKey,
     CreateProfile(ProfileIdentifier),
+    ExportProfile(Vec<u8>),
+    ImportProfile(ProfileIdentifier),
+    NameProfile,
+    GetNamedProfile(ProfileIdentifier),
     CreateAuthenticationProof(AuthenticationProof),
     GetPublicKey(PublicKey),
     GetProfilePublicKey(PublicKey),
@@ -24,13 +30,25 @@ pub enum IdentityResponse {
     Contacts(Vec<Contact>),
     GetContact(MaybeContact),
     VerifyAuthenticationProof(bool),
+    Sign(Signature),
+    VerifySignature(bool),
     VerifyContact(bool),
     VerifyAndUpdateContact(bool),
     VerifyChanges(bool),
     VerifyAndAddContact(bool),
+    SetAttribute,
+    Attributes(BTreeMap<String, String>),
     CreateSecureChannelListener,
+    AddSecureChannelService,
+    StopSecureChannelListener,
+    DrainSecureChannelListener,
     CreateSecureChannel(Address),
+    CancelSecureChannel,
+    SecureChannels(Vec<SecureChannelHandle>),
+    Shutdown,
+    Delete,
     Lease(Lease),
+    Error(Error),
     #[cfg(feature = "credentials")]
     CredentialResponse(IdentityCredentialResponse),
 }
@@ -123,13 +123,23 @@ impl ProfileState {
         let self_signature = self.vault.sign(&secret_key, event_id.as_ref()).await?;
         let self_signature = Signature::new(SignatureType::SelfSign, self_signature);
 
+        // Links the new key to the one it replaces
+        let prev_signature = self
+            .vault
+            .sign(&last_key_in_chain, event_id.as_ref())
+            .await?;
+        let prev_signature = Signature::new(SignatureType::PrevSign, prev_signature);
+
         let root_key = self.get_root_secret_key().await?;
 
         let root_signature = self.vault.sign(&root_key, event_id.as_ref()).await?;
         let root_signature = Signature::new(SignatureType::RootSign, root_signature);
 
-        let signed_change_event =
-            ProfileChangeEvent::new(event_id, change_block, vec![self_signature, root_signature]);
+        let signed_change_event = ProfileChangeEvent::new(
+            event_id,
+            change_block,
+            vec![self_signature, prev_signature, root_signature],
+        );
 
         Ok(signed_change_event)
     }
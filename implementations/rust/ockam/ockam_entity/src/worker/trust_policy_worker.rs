@@ -1,5 +1,5 @@
-use crate::{SecureChannelTrustInfo, TrustPolicy};
-use ockam_core::compat::boxed::Box;
+use crate::{SecureChannelTrustInfo, TrustDecision, TrustPolicy};
+use ockam_core::compat::{boxed::Box, collections::BTreeMap, string::String};
 use ockam_core::{
     async_trait::async_trait, Address, AsyncTryClone, Message, Result, Routed, Worker,
 };
@@ -41,6 +41,10 @@ impl TrustPolicyImpl {
 #[async_trait]
 impl TrustPolicy for TrustPolicyImpl {
     async fn check(&self, trust_info: &SecureChannelTrustInfo) -> Result<bool> {
+        Ok(self.decide(trust_info).await?.is_trusted())
+    }
+
+    async fn decide(&self, trust_info: &SecureChannelTrustInfo) -> Result<TrustDecision> {
         let response: TrustPolicyResponse = self
             .handle
             .call(TrustPolicyRequest {
@@ -48,7 +52,7 @@ impl TrustPolicy for TrustPolicyImpl {
             })
             .await?;
 
-        Ok(response.res)
+        Ok(response.into())
     }
 }
 
@@ -70,6 +74,40 @@ pub struct TrustPolicyRequest {
 #[derive(Serialize, Deserialize, Message)]
 pub struct TrustPolicyResponse {
     pub res: bool,
+    /// See [`TrustDecision::reason`]
+    pub reason: Option<String>,
+    /// See [`TrustDecision::is_disclosed`]
+    pub disclose: bool,
+    /// See [`TrustDecision::verified_attributes`]
+    pub verified_attributes: BTreeMap<String, String>,
+}
+
+impl From<TrustDecision> for TrustPolicyResponse {
+    fn from(decision: TrustDecision) -> Self {
+        Self {
+            res: decision.is_trusted(),
+            reason: decision.reason().map(String::from),
+            disclose: decision.is_disclosed(),
+            verified_attributes: decision.verified_attributes().clone(),
+        }
+    }
+}
+
+impl From<TrustPolicyResponse> for TrustDecision {
+    fn from(response: TrustPolicyResponse) -> Self {
+        let decision = match (response.res, response.reason) {
+            (true, _) => {
+                TrustDecision::trusted().with_verified_attributes(response.verified_attributes)
+            }
+            (false, Some(reason)) => TrustDecision::rejected_because(reason),
+            (false, None) => TrustDecision::rejected(),
+        };
+        if response.disclose {
+            decision.disclosed()
+        } else {
+            decision
+        }
+    }
 }
 
 #[async_trait]
@@ -85,8 +123,8 @@ impl<T: TrustPolicy> Worker for TrustPolicyWorker<T> {
         let route = msg.return_route();
         let msg = msg.body();
 
-        let res = self.trust_policy.check(&msg.info).await?;
-        ctx.send(route, TrustPolicyResponse { res }).await?;
+        let decision = self.trust_policy.decide(&msg.info).await?;
+        ctx.send(route, TrustPolicyResponse::from(decision)).await?;
 
         Ok(())
     }
@@ -0,0 +1,94 @@
+//! Checks that the synchronous fast path of entity access controls allocates at least one time
+//! less per message than the async fallback, whose future is boxed for every message. The timing
+//! of both paths is reported by `cargo bench -p ockam_entity --bench access_control`
+
+use futures::executor::block_on;
+use ockam_core::{route, AccessControl, LocalMessage, TransportMessage};
+use ockam_entity::{EntityAccessControlBuilder, EntitySecureChannelLocalInfo, ProfileIdentifier};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::convert::TryInto;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Calls of each path the allocations are counted over
+const CALLS: usize = 1000;
+
+const PROFILE_ID: &str = "P79b26ba2ea5ad9b54abe5bebbcce7c446beda8c948afc0de293250090e5270b6";
+
+/// Counts allocations of the whole process, so this file has a single test
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Message as it leaves a secure channel with given peer
+fn channel_message(their_profile_id: ProfileIdentifier) -> LocalMessage {
+    let local_info = EntitySecureChannelLocalInfo::new(their_profile_id)
+        .to_local_info()
+        .unwrap();
+
+    LocalMessage::new(
+        TransportMessage::v1(route!["receiver"], route!["channel", "sender"], vec![]),
+        vec![local_info],
+    )
+}
+
+fn allocations(mut f: impl FnMut()) -> usize {
+    // The first call may set up thread locals, e.g. the waker of `block_on`
+    f();
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..CALLS {
+        f();
+    }
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn check_allocations<A: AccessControl>(name: &str, mut access_control: A, msg: &LocalMessage) {
+    let sync = allocations(|| {
+        assert!(access_control
+            .msg_is_authorized_sync(msg)
+            .expect("no fast path")
+            .unwrap())
+    });
+    let not_sync =
+        allocations(|| assert!(block_on(access_control.msg_is_authorized(msg)).unwrap()));
+
+    assert!(
+        sync + CALLS <= not_sync,
+        "{}: the fast path doesn't save the future of the async path, {} allocations per \
+         message, {} through the async path",
+        name,
+        sync as f64 / CALLS as f64,
+        not_sync as f64 / CALLS as f64
+    );
+}
+
+#[test]
+fn fast_path_saves_an_allocation() {
+    let their_profile_id: ProfileIdentifier = PROFILE_ID.try_into().unwrap();
+    let msg = channel_message(their_profile_id.clone());
+
+    check_allocations(
+        "id",
+        EntityAccessControlBuilder::new_with_id(their_profile_id),
+        &msg,
+    );
+    check_allocations(
+        "any_id",
+        EntityAccessControlBuilder::new_with_any_id(),
+        &msg,
+    );
+}
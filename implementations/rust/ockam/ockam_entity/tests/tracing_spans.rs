@@ -0,0 +1,150 @@
+#![cfg(feature = "tracing_spans")]
+
+use ockam_core::{route, Result};
+use ockam_entity::{Entity, Identity, TrustEveryonePolicy};
+use ockam_node::Context;
+use ockam_vault_sync_core::Vault;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// Name and fields of a span
+type RecordedSpan = (String, BTreeMap<String, String>);
+
+/// Subscriber keeping every span with its fields, the one at index `n` has id `n + 1`
+#[derive(Clone, Default)]
+struct SpanCollector {
+    spans: Arc<Mutex<Vec<RecordedSpan>>>,
+}
+
+struct FieldVisitor<'a>(&'a mut BTreeMap<String, String>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for SpanCollector {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = BTreeMap::new();
+        span.record(&mut FieldVisitor(&mut fields));
+        let mut spans = self.spans.lock().unwrap();
+        spans.push((span.metadata().name().to_string(), fields));
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        let index = span.into_u64() as usize - 1;
+        values.record(&mut FieldVisitor(&mut spans[index].1));
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+async fn handshake_and_message(ctx: &mut Context) -> Result<(String, String, String)> {
+    let vault = Vault::create(ctx).await?;
+
+    let mut alice = Entity::create(ctx, &vault).await?;
+    let mut bob = Entity::create(ctx, &vault).await?;
+    bob.create_secure_channel_listener("bob_listener", TrustEveryonePolicy)
+        .await?;
+
+    let channel = alice
+        .create_secure_channel(route!["bob_listener"], TrustEveryonePolicy)
+        .await?;
+    ctx.send(
+        route![channel.clone(), ctx.address()],
+        "Hello, Bob!".to_string(),
+    )
+    .await?;
+    ctx.receive::<String>().await?;
+
+    Ok((
+        channel.to_string(),
+        alice.identifier().await?.to_string(),
+        bob.identifier().await?.to_string(),
+    ))
+}
+
+#[test]
+fn test_handshake_spans() {
+    let collector = SpanCollector::default();
+    // Before the node starts, which would install its own subscriber otherwise
+    tracing::subscriber::set_global_default(collector.clone()).unwrap();
+
+    let (mut ctx, mut executor) = ockam_node::start_node();
+    let (channel, alice_id, bob_id) = executor
+        .execute(async move {
+            let res = handshake_and_message(&mut ctx).await;
+            ctx.stop().await?;
+            res
+        })
+        .unwrap()
+        .unwrap();
+
+    let spans = collector.spans.lock().unwrap().clone();
+    let channel_spans: Vec<&RecordedSpan> = spans
+        .iter()
+        .filter(|(name, _)| name.starts_with("secure_channel_"))
+        .collect();
+
+    let steps: Vec<&str> = channel_spans
+        .iter()
+        .filter(|(name, _)| name == "secure_channel_handshake")
+        .map(|(_, fields)| fields["step"].as_str())
+        .collect();
+    for step in [
+        "initiator_start_channel",
+        "responder_wait_for_kex",
+        "initiator_send_profile",
+        "responder_wait_for_profile",
+        "initiator_wait_for_confirm",
+    ]
+    .iter()
+    {
+        assert!(steps.contains(step), "no span for {}", step);
+    }
+
+    // Alice learns who Bob is once he proved it, before he confirms the channel
+    assert!(channel_spans.iter().any(|(name, fields)| {
+        name == "secure_channel_handshake"
+            && fields["step"] == "initiator_wait_for_confirm"
+            && fields["channel_addr"] == channel
+            && fields.get("peer_id") == Some(&bob_id)
+    }));
+    assert!(channel_spans.iter().any(|(name, fields)| {
+        name == "secure_channel_encrypt"
+            && fields["channel_addr"] == channel
+            && fields["peer_id"] == bob_id
+    }));
+    assert!(channel_spans.iter().any(|(name, fields)| {
+        name == "secure_channel_decrypt" && fields["peer_id"] == alice_id
+    }));
+
+    // Nothing but addresses, identifiers and steps
+    for (_, fields) in channel_spans {
+        assert!(fields
+            .keys()
+            .all(|key| ["channel_addr", "peer_id", "step"].contains(&key.as_str())));
+    }
+}
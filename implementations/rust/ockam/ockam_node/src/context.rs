@@ -9,7 +9,7 @@ use crate::{
     error::Error,
     parser,
     relay::{CtrlSignal, ProcessorRelay, RelayMessage, WorkerRelay},
-    router::SenderPair,
+    router::{AccessControlSlot, SenderPair},
     Cancel, NodeMessage, ShutdownType,
 };
 use core::time::Duration;
@@ -43,6 +43,8 @@ pub struct Context {
     rt: Arc<Runtime>,
     mailbox: Receiver<RelayMessage>,
     access_control: Box<dyn AccessControl>,
+    /// Replacement of `access_control`, see [`Context::set_worker_access_control`]
+    access_control_slot: AccessControlSlot,
 }
 
 impl Context {
@@ -64,7 +66,14 @@ impl Context {
             }
 
             if let RelayPayload::Direct(local_msg) = &relay_msg.data {
-                if !self.access_control.msg_is_authorized(local_msg).await? {
+                if let Some(access_control) = self.access_control_slot.take() {
+                    self.access_control = access_control;
+                }
+                let is_authorized = match self.access_control.msg_is_authorized_sync(local_msg) {
+                    Some(is_authorized) => is_authorized?,
+                    None => self.access_control.msg_is_authorized(local_msg).await?,
+                };
+                if !is_authorized {
                     warn!("Message for {} did not pass access control", relay_msg.addr);
                     continue;
                 }
@@ -88,6 +97,7 @@ impl Context {
     ) -> (Self, SenderPair, Receiver<CtrlSignal>) {
         let (mailbox_tx, mailbox) = channel(32);
         let (ctrl_tx, ctrl_rx) = channel(1);
+        let access_control_slot = AccessControlSlot::new();
         (
             Self {
                 rt,
@@ -95,10 +105,12 @@ impl Context {
                 address,
                 mailbox,
                 access_control: Box::new(access_control),
+                access_control_slot: access_control_slot.clone(),
             },
             SenderPair {
                 msgs: mailbox_tx,
                 ctrl: ctrl_tx,
+                access_control: access_control_slot,
             },
             ctrl_rx,
         )
@@ -176,6 +188,26 @@ impl Context {
             .await
     }
 
+    /// Replace the [`AccessControl`] of a running worker, e.g. to revoke a peer.
+    /// Messages the worker takes from its mailbox after this returns are checked by
+    /// `access_control`. Messages it already let through are not checked again
+    pub async fn set_worker_access_control<A, NA>(&self, addr: A, access_control: NA) -> Result<()>
+    where
+        A: Into<Address>,
+        NA: AccessControl,
+    {
+        let (msg, mut rx) = NodeMessage::access_control_request(addr.into());
+        self.sender.send(msg).await.map_err(Error::from)?;
+        let slot = rx
+            .recv()
+            .await
+            .ok_or(Error::InternalIOFailure)??
+            .take_access_control()?;
+        slot.replace(Box::new(access_control));
+
+        Ok(())
+    }
+
     async fn start_worker_impl<NM, NW, NA>(
         &self,
         address: AddressSet,
@@ -195,9 +227,6 @@ impl Context {
             access_control,
         );
 
-        // Then initialise the worker message relay
-        WorkerRelay::<NW, NM>::init(self.rt.as_ref(), worker, ctx, ctrl_rx);
-
         // Send start request to router
         let (msg, mut rx) = NodeMessage::start_worker(address, sender, false);
         self.sender
@@ -206,11 +235,13 @@ impl Context {
             .map_err(|_| Error::FailedStartWorker)?;
 
         // Wait for the actual return code
-        Ok(rx
-            .recv()
-            .await
-            .ok_or(Error::InternalIOFailure)?
-            .map(|_| ())?)
+        rx.recv().await.ok_or(Error::InternalIOFailure)??;
+
+        // Only run the worker once its addresses are registered, so that a
+        // rejected worker never initializes or acknowledges a stop
+        WorkerRelay::<NW, NM>::init(self.rt.as_ref(), worker, ctx, ctrl_rx);
+
+        Ok(())
     }
 
     /// Start a new processor at [`Address`](ockam_core::Address)
@@ -234,9 +265,6 @@ impl Context {
             Passthrough,
         );
 
-        // Initialise the processor relay with the ctrl receiver
-        ProcessorRelay::<P>::init(self.rt.as_ref(), processor, ctx, ctrl_rx);
-
         // Send start request to router
         let (msg, mut rx) = NodeMessage::start_processor(address, senders);
         self.sender
@@ -245,11 +273,12 @@ impl Context {
             .map_err(|_| Error::FailedStartProcessor)?;
 
         // Wait for the actual return code
-        Ok(rx
-            .recv()
-            .await
-            .ok_or(Error::InternalIOFailure)?
-            .map(|_| ())?)
+        rx.recv().await.ok_or(Error::InternalIOFailure)??;
+
+        // Initialise the processor relay with the ctrl receiver
+        ProcessorRelay::<P>::init(self.rt.as_ref(), processor, ctx, ctrl_rx);
+
+        Ok(())
     }
 
     /// Shut down a worker by its primary address
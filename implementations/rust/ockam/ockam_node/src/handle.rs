@@ -1,4 +1,4 @@
-use crate::Context;
+use crate::{Context, DEFAULT_TIMEOUT};
 use ockam_core::{async_trait, compat::boxed::Box};
 use ockam_core::{Address, AsyncTryClone, Message, Result};
 
@@ -33,10 +33,19 @@ impl Handle {
     pub async fn call<I: Message + Send + 'static, O: Message + Send + 'static>(
         &self,
         msg: I,
+    ) -> Result<O> {
+        self.call_timeout(msg, DEFAULT_TIMEOUT).await
+    }
+
+    /// Asynchronously sends and receiving a message using a new `Context`, with explicit timeout
+    pub async fn call_timeout<I: Message + Send + 'static, O: Message + Send + 'static>(
+        &self,
+        msg: I,
+        timeout_secs: u64,
     ) -> Result<O> {
         let mut ctx = self.ctx.new_context(Address::random(0)).await?;
         ctx.send(self.address.clone(), msg).await?;
-        let msg = ctx.receive::<O>().await?;
+        let msg = ctx.receive_timeout::<O>(timeout_secs).await?;
         Ok(msg.take().body())
     }
 }
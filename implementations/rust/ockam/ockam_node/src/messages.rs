@@ -1,5 +1,9 @@
 use crate::tokio::sync::mpsc::{channel, Receiver, Sender};
-use crate::{error::Error, relay::RelayMessage, router::SenderPair};
+use crate::{
+    error::Error,
+    relay::RelayMessage,
+    router::{AccessControlSlot, SenderPair},
+};
 use core::fmt::Formatter;
 use ockam_core::compat::{string::String, vec::Vec};
 use ockam_core::{Address, AddressSet};
@@ -38,6 +42,8 @@ pub enum NodeMessage {
     SenderReq(Address, Sender<NodeReplyResult>),
     /// Register a new router for a route id type
     Router(u8, Address, Sender<NodeReplyResult>),
+    /// Request the access control slot of a worker address
+    AccessControlReq(Address, Sender<NodeReplyResult>),
 }
 
 impl core::fmt::Display for NodeMessage {
@@ -54,6 +60,7 @@ impl core::fmt::Display for NodeMessage {
             NodeMessage::StopAck(_) => write!(f, "StopAck"),
             NodeMessage::SenderReq(_, _) => write!(f, "SenderReq"),
             NodeMessage::Router(_, _, _) => write!(f, "Router"),
+            NodeMessage::AccessControlReq(_, _) => write!(f, "AccessControlReq"),
         }
     }
 }
@@ -128,6 +135,12 @@ impl NodeMessage {
         let (tx, rx) = channel(1);
         (Self::SenderReq(route, tx), rx)
     }
+
+    /// Create an access control request message and reply receiver
+    pub fn access_control_request(address: Address) -> (Self, Receiver<NodeReplyResult>) {
+        let (tx, rx) = channel(1);
+        (Self::AccessControlReq(address, tx), rx)
+    }
 }
 
 /// The reply/result of a Node
@@ -150,6 +163,8 @@ pub enum NodeReply {
         /// with router wrapping.
         wrap: bool,
     },
+    /// Slot to replace the access control of a worker through
+    AccessControl(AccessControlSlot),
 }
 
 /// Failure states from a router command
@@ -167,6 +182,11 @@ pub enum NodeError {
     Rejected(Reason),
 }
 
+impl NodeError {
+    /// Code of the [`ockam_core::Error`] a message to an address nobody has fails with
+    pub const NO_SUCH_WORKER_CODE: u32 = Error::DOMAIN_CODE + Error::UnknownWorker as u32;
+}
+
 /// The reason why a command was rejected
 #[derive(Debug, Copy, Clone)]
 pub enum Reason {
@@ -268,6 +288,19 @@ impl NodeReply {
         }
     }
 
+    /// Return [NodeReply::AccessControl] for the given slot
+    pub fn access_control(slot: AccessControlSlot) -> NodeReplyResult {
+        Ok(NodeReply::AccessControl(slot))
+    }
+
+    /// Consume the wrapper and return [NodeReply::AccessControl]
+    pub fn take_access_control(self) -> Result<AccessControlSlot, Error> {
+        match self {
+            Self::AccessControl(slot) => Ok(slot),
+            _ => Err(Error::InternalIOFailure),
+        }
+    }
+
     /// Consume the wrapper and return [NodeReply::Workers]
     pub fn take_workers(self) -> Result<Vec<Address>, Error> {
         match self {
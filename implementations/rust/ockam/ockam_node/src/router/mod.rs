@@ -16,14 +16,71 @@ use crate::{
     relay::{CtrlSignal, RelayMessage},
     NodeMessage, NodeReply, ShutdownType,
 };
-use ockam_core::compat::collections::BTreeMap;
-use ockam_core::{Address, Result};
+use core::fmt::{self, Debug, Formatter};
+use core::sync::atomic::{AtomicBool, Ordering};
+use ockam_core::compat::{
+    boxed::Box,
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+use ockam_core::{AccessControl, Address, Result};
 
 /// A pair of senders to a worker relay
 #[derive(Debug)]
 pub struct SenderPair {
     pub msgs: Sender<RelayMessage>,
     pub ctrl: Sender<CtrlSignal>,
+    pub access_control: AccessControlSlot,
+}
+
+/// Access control replacing the one of a worker, shared between the
+/// router and the worker's context
+///
+/// The context takes it up before checking the next message from its
+/// mailbox, so messages already let through are not checked again
+#[derive(Clone)]
+pub struct AccessControlSlot {
+    access_control: Arc<Mutex<Option<Box<dyn AccessControl>>>>,
+    // Set once there is a replacement, so that the context only locks when it has to
+    replaced: Arc<AtomicBool>,
+}
+
+impl AccessControlSlot {
+    /// Create an empty slot
+    pub fn new() -> Self {
+        Self {
+            access_control: Arc::new(Mutex::new(None)),
+            replaced: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Replace the access control of the worker, including a replacement
+    /// it didn't take up yet
+    pub fn replace(&self, access_control: Box<dyn AccessControl>) {
+        *self.access_control.lock().unwrap() = Some(access_control);
+        self.replaced.store(true, Ordering::Release);
+    }
+
+    /// Take the replacement, if there is one
+    pub fn take(&self) -> Option<Box<dyn AccessControl>> {
+        if !self.replaced.swap(false, Ordering::Acquire) {
+            return None;
+        }
+
+        self.access_control.lock().unwrap().take()
+    }
+}
+
+impl Default for AccessControlSlot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for AccessControlSlot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "AccessControlSlot")
+    }
 }
 
 /// A combined address type and local worker router
@@ -77,6 +134,7 @@ impl Router {
                 addr.clone().into(),
                 senders.msgs,
                 senders.ctrl,
+                senders.access_control,
                 AddressMeta {
                     processor: false,
                     bare: true,
@@ -211,6 +269,25 @@ impl Router {
                     .expect("Failed to send a message for some reason,,,");
             }
 
+            AccessControlReq(ref addr, ref reply) => {
+                let record = self
+                    .map
+                    .addr_map
+                    .get(addr)
+                    .and_then(|primary| self.map.internal.get(primary));
+                let msg = match record {
+                    // A stopping worker doesn't take messages anymore
+                    Some(record) if record.check() => {
+                        NodeReply::access_control(record.access_control())
+                    }
+                    _ => NodeReply::no_such_worker(addr.clone()),
+                };
+                reply
+                    .send(msg)
+                    .await
+                    .map_err(|_| Error::InternalIOFailure)?
+            }
+
             // Handle route/ sender requests
             SenderReq(ref addr, ref reply) => match determine_type(addr) {
                 RouteType::Internal(ref addr) => utils::resolve(self, addr, reply, false).await?,
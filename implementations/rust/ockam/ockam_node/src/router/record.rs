@@ -1,3 +1,4 @@
+use super::AccessControlSlot;
 use crate::relay::{CtrlSignal, RelayMessage};
 use crate::tokio::sync::mpsc::Sender;
 use crate::{error::Error, NodeError, NodeReply, NodeReplyResult};
@@ -125,6 +126,7 @@ pub struct AddressRecord {
     address_set: AddressSet,
     sender: Option<Sender<RelayMessage>>,
     ctrl_tx: Sender<CtrlSignal>,
+    access_control: AccessControlSlot,
     state: AddressState,
     meta: AddressMeta,
 }
@@ -136,16 +138,21 @@ impl AddressRecord {
     pub fn sender(&self) -> Sender<RelayMessage> {
         self.sender.clone().expect("No such sender!")
     }
+    pub fn access_control(&self) -> AccessControlSlot {
+        self.access_control.clone()
+    }
     pub fn new(
         address_set: AddressSet,
         sender: Sender<RelayMessage>,
         ctrl_tx: Sender<CtrlSignal>,
+        access_control: AccessControlSlot,
         meta: AddressMeta,
     ) -> Self {
         AddressRecord {
             address_set,
             sender: Some(sender),
             ctrl_tx,
+            access_control,
             state: AddressState::Running,
             meta,
         }
@@ -25,13 +25,28 @@ async fn start(
     senders: SenderPair,
     reply: &Sender<NodeReplyResult>,
 ) -> Result<()> {
+    // Never shadow a registered worker
+    if router.map.addr_map.contains_key(&addr) {
+        trace!("StartProcessor command rejected: address '{}' taken", addr);
+        reply
+            .send(NodeReply::worker_exists(addr))
+            .await
+            .map_err(|_| Error::InternalIOFailure)?;
+        return Ok(());
+    }
+
     debug!("Starting new processor '{}'", &addr);
-    let SenderPair { msgs, ctrl } = senders;
+    let SenderPair {
+        msgs,
+        ctrl,
+        access_control,
+    } = senders;
 
     let record = AddressRecord::new(
         addr.clone().into(),
         msgs,
         ctrl,
+        access_control,
         AddressMeta {
             processor: true,
             bare: false,
@@ -27,8 +27,25 @@ async fn start(
     bare: bool,
     reply: &Sender<NodeReplyResult>,
 ) -> Result<()> {
+    // Never shadow a registered worker
+    if let Some(addr) = addrs
+        .iter()
+        .find(|addr| router.map.addr_map.contains_key(*addr))
+    {
+        trace!("StartWorker command rejected: address '{}' taken", addr);
+        reply
+            .send(NodeReply::worker_exists(addr.clone()))
+            .await
+            .map_err(|_| Error::InternalIOFailure)?;
+        return Ok(());
+    }
+
     debug!("Starting new worker '{}'", addrs.first());
-    let SenderPair { msgs, ctrl } = senders;
+    let SenderPair {
+        msgs,
+        ctrl,
+        access_control,
+    } = senders;
 
     // Create an address record and insert it into the internal map
     let primary_addr = addrs.first();
@@ -36,6 +53,7 @@ async fn start(
         addrs.clone(),
         msgs,
         ctrl,
+        access_control,
         AddressMeta {
             processor: false,
             bare,
@@ -36,6 +36,8 @@ pub enum VaultError {
     InvalidBlsSecretLength,
     /// Invalid BLS secret
     InvalidBlsSecret,
+    /// Storage backend failed to load or store secrets
+    StorageFailed,
 }
 
 impl VaultError {
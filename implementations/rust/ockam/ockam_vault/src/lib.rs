@@ -28,6 +28,7 @@ mod key_id_impl;
 mod secret_impl;
 mod signer_impl;
 mod software_vault;
+mod storage;
 mod symmetric_impl;
 mod verifier_impl;
 mod xeddsa;
@@ -44,5 +45,6 @@ pub use key_id_impl::*;
 pub use secret_impl::*;
 pub use signer_impl::*;
 pub use software_vault::*;
+pub use storage::*;
 pub use symmetric_impl::*;
 pub use verifier_impl::*;
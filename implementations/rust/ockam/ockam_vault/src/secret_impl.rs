@@ -144,11 +144,7 @@ impl SecretVault for SoftwareVault {
             }
         };
         let key_id = self.compute_key_id(key.as_ref(), &attributes).await?;
-        self.next_id += 1;
-        self.entries
-            .insert(self.next_id, VaultEntry::new(key_id, attributes, key));
-
-        Ok(Secret::new(self.next_id))
+        self.insert_entry(VaultEntry::new(key_id, attributes, key))
     }
 
     async fn secret_import(
@@ -158,12 +154,11 @@ impl SecretVault for SoftwareVault {
     ) -> Result<Secret> {
         self.check_secret(secret, &attributes)?;
         let key_id_opt = self.compute_key_id(secret, &attributes).await?;
-        self.next_id += 1;
-        self.entries.insert(
-            self.next_id,
-            VaultEntry::new(key_id_opt, attributes, SecretKey::new(secret.to_vec())),
-        );
-        Ok(Secret::new(self.next_id))
+        self.insert_entry(VaultEntry::new(
+            key_id_opt,
+            attributes,
+            SecretKey::new(secret.to_vec()),
+        ))
     }
 
     async fn secret_export(&mut self, context: &Secret) -> Result<SecretKey> {
@@ -217,12 +212,9 @@ impl SecretVault for SoftwareVault {
         }
     }
 
-    /// Remove secret from memory
+    /// Remove secret from memory, and from the storage if it's persistent
     async fn secret_destroy(&mut self, context: Secret) -> Result<()> {
-        match self.entries.remove(&context.index()) {
-            None => Err(VaultError::EntryNotFound.into()),
-            Some(_) => Ok(()),
-        }
+        self.remove_entry(&context)
     }
 }
 
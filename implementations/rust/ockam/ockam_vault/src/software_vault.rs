@@ -1,10 +1,13 @@
-use crate::VaultError;
-use ockam_core::compat::{collections::BTreeMap, string::String};
-use ockam_core::vault::{Secret, SecretAttributes, SecretKey};
+use crate::{MemoryVaultStorage, VaultError, VaultStorage};
+use core::fmt;
+use ockam_core::compat::{boxed::Box, collections::BTreeMap, string::String};
+use ockam_core::vault::{Secret, SecretAttributes, SecretKey, SecretPersistence};
 use ockam_core::Result;
+use serde::{Deserialize, Serialize};
 use tracing::info;
 
 /// Vault implementation that stores secrets in memory and uses software crypto.
+/// Persistent secrets are also kept by a [`VaultStorage`], see [`SoftwareVault::with_storage`].
 ///
 /// # Examples
 /// ```
@@ -32,21 +35,44 @@ use tracing::info;
 ///     Ok(())
 /// }
 /// ```
-#[derive(Debug)]
 pub struct SoftwareVault {
     pub(crate) entries: BTreeMap<usize, VaultEntry>,
     pub(crate) next_id: usize,
+    storage: Box<dyn VaultStorage>,
 }
 
 impl SoftwareVault {
-    /// Create a new SoftwareVault
+    /// Create a new SoftwareVault, which loses all secrets on restart
     pub fn new() -> Self {
         info!("Creating vault");
         Self {
             entries: Default::default(),
             next_id: 0,
+            storage: Box::new(MemoryVaultStorage),
         }
     }
+
+    /// Create a SoftwareVault with the secrets of given storage, which
+    /// keeps the persistent secrets created from now on as well
+    pub fn with_storage(mut storage: impl VaultStorage) -> Result<Self> {
+        let entries = storage.load()?;
+        info!("Creating vault with {} stored secrets", entries.len());
+        let next_id = entries.keys().next_back().copied().unwrap_or(0);
+        Ok(Self {
+            entries,
+            next_id,
+            storage: Box::new(storage),
+        })
+    }
+}
+
+impl fmt::Debug for SoftwareVault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SoftwareVault")
+            .field("entries", &self.entries)
+            .field("next_id", &self.next_id)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Default for SoftwareVault {
@@ -61,28 +87,56 @@ impl SoftwareVault {
             .get(&context.index())
             .ok_or_else(|| VaultError::EntryNotFound.into())
     }
+
+    /// Add a secret, through the storage if it's persistent
+    pub(crate) fn insert_entry(&mut self, entry: VaultEntry) -> Result<Secret> {
+        let index = self.next_id + 1;
+        if entry.key_attributes().persistence() == SecretPersistence::Persistent {
+            self.storage.store(index, &entry)?;
+        }
+        self.next_id = index;
+        self.entries.insert(index, entry);
+
+        Ok(Secret::new(index))
+    }
+
+    /// Remove a secret, through the storage if it's persistent
+    pub(crate) fn remove_entry(&mut self, context: &Secret) -> Result<()> {
+        let entry = self.get_entry(context)?;
+        if entry.key_attributes().persistence() == SecretPersistence::Persistent {
+            self.storage.remove(context.index())?;
+        }
+        self.entries.remove(&context.index());
+
+        Ok(())
+    }
 }
 
-#[derive(Debug, Eq, PartialEq)]
-pub(crate) struct VaultEntry {
+/// Secret held by a [`SoftwareVault`], as given to its [`VaultStorage`]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct VaultEntry {
     key_id: Option<String>,
     key_attributes: SecretAttributes,
     key: SecretKey,
 }
 
 impl VaultEntry {
+    /// Key id, for the secret types that have one
     pub fn key_id(&self) -> &Option<String> {
         &self.key_id
     }
+    /// Secret attributes
     pub fn key_attributes(&self) -> SecretAttributes {
         self.key_attributes
     }
+    /// Secret key
     pub fn key(&self) -> &SecretKey {
         &self.key
     }
 }
 
 impl VaultEntry {
+    /// Constructor
     pub fn new(key_id: Option<String>, key_attributes: SecretAttributes, key: SecretKey) -> Self {
         VaultEntry {
             key_id,
@@ -0,0 +1,173 @@
+use crate::software_vault::VaultEntry;
+use ockam_core::compat::collections::BTreeMap;
+use ockam_core::Result;
+
+/// Backend keeping the [`SecretPersistence::Persistent`](ockam_core::vault::SecretPersistence::Persistent)
+/// secrets of a [`SoftwareVault`](crate::SoftwareVault), so that they survive a restart.
+///
+/// The vault loads everything once when it's created and works on that copy in memory.
+/// New and destroyed secrets are written through the backend before the vault call returns,
+/// so a failing backend fails the call. Ephemeral secrets never reach the backend
+pub trait VaultStorage: Send + Sync + 'static {
+    /// Secrets stored so far, by index
+    fn load(&mut self) -> Result<BTreeMap<usize, VaultEntry>>;
+
+    /// Store a new secret under given index
+    fn store(&mut self, index: usize, entry: &VaultEntry) -> Result<()>;
+
+    /// Remove the secret with given index
+    fn remove(&mut self, index: usize) -> Result<()>;
+}
+
+/// Backend that doesn't keep anything beyond the memory of the vault, so persistent
+/// secrets are lost on restart like ephemeral ones. The default of [`SoftwareVault`](crate::SoftwareVault)
+#[derive(Debug, Default)]
+pub struct MemoryVaultStorage;
+
+impl VaultStorage for MemoryVaultStorage {
+    fn load(&mut self) -> Result<BTreeMap<usize, VaultEntry>> {
+        Ok(BTreeMap::new())
+    }
+
+    fn store(&mut self, _index: usize, _entry: &VaultEntry) -> Result<()> {
+        Ok(())
+    }
+
+    fn remove(&mut self, _index: usize) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+mod file {
+    use super::VaultStorage;
+    use crate::software_vault::VaultEntry;
+    use crate::VaultError;
+    use ockam_core::compat::collections::BTreeMap;
+    use ockam_core::{Decodable, Encodable, Result};
+    use std::fs;
+    use std::io::{ErrorKind, Write};
+    use std::path::PathBuf;
+    use zeroize::Zeroizing;
+
+    /// Backend keeping the secrets in a single file, rewritten on every change.
+    /// Secrets are stored in plain, so the file has to be protected as well as the keys
+    /// themselves. On unix it's only readable by its owner
+    #[derive(Debug)]
+    pub struct FileVaultStorage {
+        path: PathBuf,
+        entries: BTreeMap<usize, VaultEntry>,
+    }
+
+    impl FileVaultStorage {
+        /// Use the file at given path, which is created on the first persistent secret
+        pub fn new(path: impl Into<PathBuf>) -> Self {
+            Self {
+                path: path.into(),
+                entries: BTreeMap::new(),
+            }
+        }
+
+        /// Write to a temporary file first, so that a crash leaves either version behind
+        fn save(&self) -> Result<()> {
+            // Secrets in plain, which must not linger in memory once they're written
+            let data = Zeroizing::new(self.entries.encode()?);
+            let mut tmp_path = self.path.clone().into_os_string();
+            tmp_path.push(".tmp");
+
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+
+            let mut file = options.open(&tmp_path).map_err(|_| failed())?;
+            file.write_all(&data).map_err(|_| failed())?;
+            file.sync_all().map_err(|_| failed())?;
+            fs::rename(&tmp_path, &self.path).map_err(|_| failed())
+        }
+    }
+
+    impl VaultStorage for FileVaultStorage {
+        fn load(&mut self) -> Result<BTreeMap<usize, VaultEntry>> {
+            self.entries = match fs::read(&self.path) {
+                Ok(data) => BTreeMap::decode(&Zeroizing::new(data))?,
+                Err(err) if err.kind() == ErrorKind::NotFound => BTreeMap::new(),
+                Err(_) => return Err(failed()),
+            };
+
+            Ok(self.entries.clone())
+        }
+
+        fn store(&mut self, index: usize, entry: &VaultEntry) -> Result<()> {
+            self.entries.insert(index, entry.clone());
+            self.save()
+        }
+
+        fn remove(&mut self, index: usize) -> Result<()> {
+            if self.entries.remove(&index).is_some() {
+                self.save()?;
+            }
+            Ok(())
+        }
+    }
+
+    fn failed() -> ockam_core::Error {
+        VaultError::StorageFailed.into()
+    }
+}
+
+#[cfg(feature = "std")]
+pub use file::*;
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::{FileVaultStorage, SoftwareVault};
+    use ockam_core::vault::{
+        KeyIdVault, SecretAttributes, SecretPersistence, SecretType, SecretVault,
+        CURVE25519_SECRET_LENGTH,
+    };
+    use ockam_core::Result;
+
+    fn attributes(persistence: SecretPersistence) -> SecretAttributes {
+        SecretAttributes::new(SecretType::X25519, persistence, CURVE25519_SECRET_LENGTH)
+    }
+
+    #[tokio::test]
+    async fn file_storage_survives_restart() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("ockam_vault_{}", rand::random::<u64>()));
+
+        let mut vault = SoftwareVault::with_storage(FileVaultStorage::new(&path))?;
+        let persistent = vault
+            .secret_generate(attributes(SecretPersistence::Persistent))
+            .await?;
+        let rotated = vault
+            .secret_generate(attributes(SecretPersistence::Persistent))
+            .await?;
+        let ephemeral = vault
+            .secret_generate(attributes(SecretPersistence::Ephemeral))
+            .await?;
+        let public_key = vault.secret_public_key_get(&persistent).await?;
+        let key = vault.secret_export(&persistent).await?;
+        vault.secret_destroy(rotated.clone()).await?;
+        drop(vault);
+
+        let mut vault = SoftwareVault::with_storage(FileVaultStorage::new(&path))?;
+        let key_id = vault.compute_key_id_for_public_key(&public_key).await?;
+        assert_eq!(vault.get_secret_by_key_id(&key_id).await?, persistent);
+        assert_eq!(vault.secret_export(&persistent).await?, key);
+        assert!(vault.secret_export(&rotated).await.is_err());
+        assert!(vault.secret_export(&ephemeral).await.is_err());
+
+        // New secrets don't reuse indices of the stored ones
+        let new = vault
+            .secret_generate(attributes(SecretPersistence::Ephemeral))
+            .await?;
+        assert!(new.index() > persistent.index());
+
+        std::fs::remove_file(&path).unwrap();
+        Ok(())
+    }
+}
@@ -12,6 +12,16 @@ impl Vault {
         use ockam_vault::SoftwareVault;
         Self::create_with_inner(ctx, SoftwareVault::default()).await
     }
+    /// Start a Vault with SoftwareVault implementation, which loads its secrets from
+    /// given storage and keeps the persistent ones there.
+    #[cfg(feature = "software_vault")]
+    pub async fn create_with_storage(
+        ctx: &Context,
+        storage: impl ockam_vault::VaultStorage,
+    ) -> Result<Address> {
+        use ockam_vault::SoftwareVault;
+        Self::create_with_inner(ctx, SoftwareVault::with_storage(storage)?).await
+    }
     /// Start a Vault Worker with given implementation.
     pub async fn create_with_inner<V: VaultTrait>(ctx: &Context, inner: V) -> Result<Address> {
         VaultWorker::create_with_inner(ctx, inner).await